@@ -0,0 +1,182 @@
+//! Image adjustments applied right before images are shown
+
+use chrono::NaiveTime;
+use image::{DynamicImage, Rgb};
+
+use crate::config::BrightnessEntry;
+use crate::errors::RahmenResult;
+use crate::pixel::{dither, luma};
+
+/// Brightness and gamma applied to everything shown on the display
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    /// Brightness factor, 1.0 leaves the image unchanged
+    pub brightness: f32,
+    /// Gamma, values larger than 1.0 brighten dark areas
+    pub gamma: f32,
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Self {
+            brightness: 1.,
+            gamma: 1.,
+        }
+    }
+}
+
+impl Level {
+    /// Whether applying this level leaves images unchanged
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Compute a table mapping input channel values to adjusted values
+    pub fn lut(&self) -> [u8; 256] {
+        let mut lut = [0; 256];
+        for (i, v) in lut.iter_mut().enumerate() {
+            let corrected = (i as f32 / 255.).powf(1. / self.gamma) * self.brightness;
+            *v = (corrected * 255.).round().max(0.).min(255.) as u8;
+        }
+        lut
+    }
+}
+
+/// A schedule of levels, each taking effect at a time of day and lasting until the next one
+#[derive(Debug, Clone)]
+pub struct BrightnessSchedule {
+    entries: Vec<(NaiveTime, Level)>,
+}
+
+impl BrightnessSchedule {
+    /// Construct a schedule from configuration entries. Times are given as `HH:MM`.
+    pub fn new<I: Iterator<Item = BrightnessEntry>>(entries: I) -> RahmenResult<Self> {
+        let mut parsed = vec![];
+        for entry in entries {
+            let defaults = Level::default();
+            parsed.push((
+                NaiveTime::parse_from_str(&entry.from, "%H:%M")?,
+                Level {
+                    brightness: entry.brightness.unwrap_or(defaults.brightness),
+                    gamma: entry.gamma.unwrap_or(defaults.gamma),
+                },
+            ));
+        }
+        parsed.sort_by_key(|(from, _)| *from);
+        Ok(Self { entries: parsed })
+    }
+
+    /// Determine the level at a time of day. Before the first entry of the day, the last entry
+    /// of the previous day is still in effect.
+    pub fn level_at(&self, time: NaiveTime) -> Level {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(from, _)| *from <= time)
+            .or_else(|| self.entries.last())
+            .map(|(_, level)| *level)
+            .unwrap_or_default()
+    }
+}
+
+/// Map the color channels of an image through a lookup table
+pub fn apply_lut(img: &DynamicImage, lut: &[u8; 256]) -> DynamicImage {
+    let mut buffer = img.to_rgb8();
+    buffer.iter_mut().for_each(|v| *v = lut[*v as usize]);
+    DynamicImage::ImageRgb8(buffer)
+}
+
+/// A filter giving all images shown the same look
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Show images in grayscale
+    Grayscale,
+    /// Tone images brown, like old photographs
+    Sepia,
+    /// Map dark areas to the first color and light areas to the second, blending in between
+    Duotone([u8; 3], [u8; 3]),
+}
+
+fn channel(value: f32) -> u8 {
+    value.round().max(0.).min(255.) as u8
+}
+
+impl Filter {
+    /// Give an image the look of this filter
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        let mut buffer = img.to_rgb8();
+        match self {
+            Filter::Grayscale => return DynamicImage::ImageLuma8(img.to_luma8()),
+            Filter::Sepia => {
+                for pixel in buffer.pixels_mut() {
+                    let [red, green, blue] = pixel.0;
+                    let (red, green, blue) = (f32::from(red), f32::from(green), f32::from(blue));
+                    *pixel = Rgb([
+                        channel(0.393 * red + 0.769 * green + 0.189 * blue),
+                        channel(0.349 * red + 0.686 * green + 0.168 * blue),
+                        channel(0.272 * red + 0.534 * green + 0.131 * blue),
+                    ]);
+                }
+            }
+            Filter::Duotone(shadows, highlights) => {
+                let palette = (0..=255u16)
+                    .map(|level| {
+                        let weight = f32::from(level) / 255.;
+                        let mut color = [0; 3];
+                        for (c, (dark, light)) in
+                            color.iter_mut().zip(shadows.iter().zip(highlights.iter()))
+                        {
+                            *c = channel(
+                                f32::from(*dark) * (1. - weight) + f32::from(*light) * weight,
+                            );
+                        }
+                        color
+                    })
+                    .collect::<Vec<_>>();
+                for pixel in buffer.pixels_mut() {
+                    pixel.0 = palette[usize::from(luma(pixel.0))];
+                }
+            }
+        }
+        DynamicImage::ImageRgb8(buffer)
+    }
+}
+
+/// Simulate how images would look on another kind of display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Proof {
+    /// Show images in grayscale
+    Grayscale,
+    /// Show images like an e-paper panel with 16 gray levels would
+    Eink,
+}
+
+impl Proof {
+    /// Switch from one proofing mode to the next, where `None` means no proofing
+    pub fn cycle(proof: Option<Self>) -> Option<Self> {
+        match proof {
+            None => Some(Proof::Grayscale),
+            Some(Proof::Grayscale) => Some(Proof::Eink),
+            Some(Proof::Eink) => None,
+        }
+    }
+
+    /// Render an image as it would look in this mode
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        match self {
+            Proof::Grayscale => DynamicImage::ImageLuma8(img.to_luma8()),
+            Proof::Eink => {
+                let mut dithered = dither(&img.to_luma8(), 16);
+                // e-paper neither reaches white nor black
+                let lut = Level {
+                    brightness: 0.7,
+                    gamma: 1.,
+                }
+                .lut();
+                dithered.iter_mut().for_each(|v| *v = 40 + lut[*v as usize]);
+                DynamicImage::ImageLuma8(dithered)
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Dim the backlight of the panel, rather than the images
+//!
+//! Panels with a backlight the kernel controls, such as the official Raspberry Pi touchscreen and
+//! other DSI panels, are dimmed through `/sys/class/backlight`. This keeps the full contrast of
+//! the images, which dimming them in software reduces. The brightness the backlight had is restored
+//! when done.
+
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// The directory holding the backlight devices
+const BACKLIGHTS: &str = "/sys/class/backlight";
+
+/// A backlight device
+#[derive(Debug)]
+pub struct Backlight {
+    /// the directory of the device
+    device: PathBuf,
+    /// the raw value of full brightness
+    max: u32,
+    /// the raw value before we changed it
+    saved: u32,
+}
+
+fn read_value(path: &Path) -> RahmenResult<u32> {
+    std::fs::read_to_string(path)?
+        .trim()
+        .parse()
+        .map_err(|e| RahmenError::DisplayError(format!("{:?}: {}", path, e)))
+}
+
+impl Backlight {
+    /// Open the backlight device `name`, or the first one found
+    pub fn open(name: Option<&str>) -> RahmenResult<Self> {
+        let device = match name {
+            Some(name) => Path::new(BACKLIGHTS).join(name),
+            None => {
+                let mut devices = std::fs::read_dir(BACKLIGHTS)?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                devices.sort();
+                devices.into_iter().next().ok_or_else(|| {
+                    RahmenError::DisplayError(format!("no backlight in {}", BACKLIGHTS))
+                })?
+            }
+        };
+        let max = read_value(&device.join("max_brightness"))?.max(1);
+        let saved = read_value(&device.join("brightness"))?;
+        Ok(Self { device, max, saved })
+    }
+
+    fn write(&self, value: u32) -> RahmenResult<()> {
+        std::fs::write(self.device.join("brightness"), value.to_string())?;
+        Ok(())
+    }
+
+    /// Set the brightness as a factor from 0, which is as dark as the panel goes, to 1
+    pub fn set(&self, brightness: f32) -> RahmenResult<()> {
+        let brightness = f64::from(brightness.max(0.).min(1.));
+        self.write((brightness * f64::from(self.max)).round() as u32)
+    }
+}
+
+impl Drop for Backlight {
+    fn drop(&mut self) {
+        if let Err(e) = self.write(self.saved) {
+            warn!("Failed to restore the backlight: {}", e);
+        }
+    }
+}
@@ -1,34 +1,112 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use clap::{App, Arg};
-use font_kit::loaders::freetype::Font;
-use image::{DynamicImage, GenericImageView};
-use pyo3::{types::PyList, PyTryInto, Python};
+use clap::{App, Arg, ArgMatches};
+use image::{DynamicImage, GenericImageView, Rgb};
 use timely::dataflow::channels::pact::Pipeline;
-use timely::dataflow::operators::capture::Event;
+use timely::dataflow::operators::capture;
 use timely::dataflow::operators::{
-    Branch, Capture, Concat, ConnectLoop, Enter, Filter, Inspect, Leave, LoopVariable, Map,
+    Branch, Capture, Concat, ConnectLoop, Enter, Filter as _, Inspect, Leave, LoopVariable, Map,
     Notificator, Operator, Probe, ResultStream,
 };
 use timely::dataflow::{InputHandle, ProbeHandle, Scope};
 use timely::order::Product;
 use timely::worker::Config;
+use tracing::{error, info, warn};
 
-use rahmen::config::Settings;
-use rahmen::dataflow::{Configuration, FormatText, ResizeImage};
-use rahmen::display::Display;
+use rahmen::adjust::{BrightnessSchedule, Filter, Level, Proof};
+use rahmen::backlight::Backlight;
+use rahmen::check::Problem;
+use rahmen::cleanup::CleanupCache;
+use rahmen::collage::{Collage, Fit, MAX_IMAGES};
+use rahmen::compositor::Compositor;
+use rahmen::config::{
+    Corner, FilterKind, LetterboxFill, LetterboxSettings, Settings, StatusBackground,
+    CONFIG_FILE_NAMES,
+};
+use rahmen::control::{Command, SharedStatus, Status};
+use rahmen::dataflow::{
+    font_canvas_vstretch, AdjustImage, Configuration, FormatText, Letterbox, Marquee, PlaceImage,
+    ResizeImage, StatusLayout, StatusPosition, Texture, DEFAULT_FONT_SIZE, FONT_CANVAS_VSTRETCH,
+};
+use rahmen::dirs::BaseDirectories;
+#[cfg(feature = "terminal")]
+use rahmen::display::GraphicsProtocol;
+#[cfg(feature = "framebuffer")]
+use rahmen::display::Rotation;
+use rahmen::display::{parse_resolution, Display, ScreenSize};
+#[cfg(feature = "drm")]
+use rahmen::display_drm::DrmDisplay;
+#[cfg(feature = "eink")]
+use rahmen::display_eink::EinkDisplay;
 #[cfg(feature = "fltk")]
-use rahmen::display_fltk::FltkDisplay;
+use rahmen::display_fltk::{FltkDisplay, WindowOptions};
+#[cfg(feature = "framebuffer")]
 use rahmen::display_framebuffer::FramebufferDisplay;
-use rahmen::errors::{RahmenError, RahmenResult};
-use rahmen::font::FontRenderer;
-use rahmen::provider::{load_image_from_path, Provider, StatusLineFormatter};
-use rahmen::provider_list::ListProvider;
+#[cfg(feature = "http")]
+use rahmen::display_http::HttpDisplay;
+use rahmen::display_multi::{MultiDisplay, MultiMode};
+use rahmen::display_png::PngDisplay;
+#[cfg(feature = "sdl2")]
+use rahmen::display_sdl2::Sdl2Display;
+#[cfg(feature = "terminal")]
+use rahmen::display_terminal::TerminalDisplay;
+#[cfg(feature = "x11")]
+use rahmen::display_x11::X11Display;
+use rahmen::errors::{ErrorPolicy, RahmenError, RahmenResult};
+use rahmen::events::{Event, EventBus, Publisher};
+use rahmen::favorite::Favorites;
+use rahmen::font::{Alignment, Background, FontRenderer, TextStyle};
+use rahmen::geocode::{Detail, Home, Language, ReverseGeocoder};
+use rahmen::hdr::ToneMapper;
+use rahmen::input_gpio::Button;
+use rahmen::input_motion::MotionSensor;
+use rahmen::light_sensor::{Ambient, Sensor, SensorKind};
+use rahmen::metrics::Metrics;
+use rahmen::mqtt::{Mqtt, MqttConfig};
+use rahmen::pair::Pairing;
+use rahmen::pixel_shift::PixelShift;
+use rahmen::placeholder::Placeholder;
+use rahmen::player::{Effect, Player};
+use rahmen::power::{Power, PowerMethod, PowerSwitch};
+use rahmen::prefetch::Prefetcher;
+use rahmen::presence::Away;
+use rahmen::progress::{BarStyle, CounterStyle, ProgressIndicator};
+use rahmen::provider::{
+    arrived, is_truncated, load_image_from_path, Provider, StatusLineFormatter,
+};
+use rahmen::provider_blacklist::{Blacklist, BlacklistProvider};
+use rahmen::provider_composite::CompositeProvider;
+use rahmen::provider_duplicate::{DuplicateProvider, HashCache};
+use rahmen::provider_duration::DurationProvider;
+use rahmen::provider_list::{ListProvider, Transitions};
+use rahmen::provider_lookahead::LookaheadProvider;
+use rahmen::provider_orientation::{OrientationMode, OrientationProvider};
+use rahmen::provider_playlist::{Opener, PlaylistProvider};
+use rahmen::provider_quality::{QualityGate, QualityProvider};
+use rahmen::provider_requeue::{Requeue, RequeueProvider};
+use rahmen::provider_resolution::ResolutionProvider;
+use rahmen::provider_resume::{ResumeProvider, ResumeState};
+use rahmen::provider_shuffle::ShuffleProvider;
+use rahmen::rotate::{orientation, upright, Rotations};
+use rahmen::schedule::{DailyTime, Schedule, TimeRange};
+use rahmen::store::{Store, StoreBackend};
+use rahmen::straighten::straighten;
+use rahmen::systemd::Notifier;
+use rahmen::transcode::TranscodeCache;
+use rahmen::transition::{self, Crossfade, Easing};
+use rahmen::watch::FileWatcher;
+use rahmen::weather::{Units, WeatherService, WeatherSource};
 
 /// dataflow control, this is used as result R part
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -40,7 +118,7 @@ enum RunControl {
 }
 
 /// error handler for display stuff
-fn fatal_err<T>(result: RahmenResult<Option<T>>) -> RunResult<T> {
+fn fatal_err<T>(result: RahmenResult<Option<T>>, events: &Publisher) -> RunResult<T> {
     match result {
         // empty result means we terminate as planned (e.g. end of list)
         Ok(None) => Err(RunControl::Terminate),
@@ -48,17 +126,19 @@ fn fatal_err<T>(result: RahmenResult<Option<T>>) -> RunResult<T> {
         Ok(Some(t)) => Ok(t),
         // display error and terminate
         Err(e) => {
-            eprintln!("Encountered error, terminating: {}", e);
+            error!("Encountered error, terminating: {}", e);
+            events.publish(Event::ProviderError(e.to_string()));
             Err(RunControl::Terminate)
         }
     }
 }
 
 /// to keep running the dataflow when there's a stream error
-fn suppress_err<T>(result: RahmenResult<T>) -> RunResult<T> {
+fn suppress_err<T>(result: RahmenResult<T>, events: &Publisher) -> RunResult<T> {
     result.map_err(|e| {
         // just notify about error but keep processing
-        eprintln!("Encountered error, suppressing: {}", e);
+        warn!("Encountered error, suppressing: {}", e);
+        events.publish(Event::ProviderError(e.to_string()));
         RunControl::Suppressed
     })
 }
@@ -67,16 +147,273 @@ fn suppress_err<T>(result: RahmenResult<T>) -> RunResult<T> {
 enum Render {
     Image(usize, (u32, u32), Arc<DynamicImage>),
     Blank(usize, u32, u32, u32, u32),
+    /// A new slide of images was loaded, the main one first, with the display duration its
+    /// provider requested and the number of images in the slideshow, if known
+    Slide(Vec<PathBuf>, Option<Duration>, Option<usize>),
+    /// The lines of the status line for displays showing it as text
+    Text(usize, Vec<String>),
 }
 
 type RunResult<T> = Result<T, RunControl>;
 
+/// The commands besides playback, and the state they change
+struct Controls {
+    /// the soft proofing mode, if any
+    proof: Option<Proof>,
+    /// whether clean mode was toggled by command
+    clean_toggled: bool,
+    /// the brightness set by a command, overriding the schedule
+    brightness_override: Option<u8>,
+    /// whether a command toggled the screen, off or on against the schedule
+    screen_off: bool,
+    /// what to do while nobody is home, if presence is detected
+    away: Option<Away>,
+    /// whether someone is home, as far as presence detection knows
+    someone_home: bool,
+    /// how long the screen stays on without motion, if a motion sensor is configured
+    motion_timeout: Option<Duration>,
+    /// when motion was seen last
+    motion_seen_at: Duration,
+    /// whether to start again with the configuration loaded anew
+    reload_due: bool,
+    favorites: Favorites,
+    rotations: Rotations,
+    blacklist: Blacklist,
+    requeue: Requeue<PathBuf>,
+    status: SharedStatus,
+}
+
+impl Controls {
+    /// Apply the `effects` of the player, returning the updates for the dataflow
+    fn apply(&self, effects: Vec<Effect>) -> Vec<Configuration> {
+        let mut requeued = vec![];
+        let updates = effects
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::Advance => Some(Configuration::Tick),
+                Effect::Requeue(path) => {
+                    requeued.push(path);
+                    None
+                }
+            })
+            .collect();
+        // the images of a slide are shown again together, before anything requested earlier
+        self.requeue.push_all(requeued);
+        updates
+    }
+
+    /// Whether nobody is around at `now`, so the screen is blank
+    fn absent(&self, now: Duration) -> bool {
+        self.motion_timeout
+            .map_or(false, |timeout| now >= self.motion_seen_at + timeout)
+            || (self.away == Some(Away::Blank) && !self.someone_home)
+    }
+
+    /// Whether the slideshow waits for someone to come home. This is apart from the player
+    /// being paused, so coming home never resumes a slideshow paused by command.
+    fn held(&self) -> bool {
+        self.away == Some(Away::Pause) && !self.someone_home
+    }
+
+    /// Follow `command`, returning the updates for the dataflow. Quitting, and reloading the
+    /// configuration, end the main loop with `RahmenError::Terminate`.
+    fn handle(
+        &mut self,
+        command: Command,
+        now: Duration,
+        player: &mut Player,
+    ) -> RahmenResult<Vec<Configuration>> {
+        let effects = match command {
+            Command::CycleProof => {
+                self.proof = Proof::cycle(self.proof);
+                info!("Proof: {:?}", self.proof);
+                return Ok(vec![Configuration::Proof(self.proof)]);
+            }
+            Command::ToggleClean => {
+                self.clean_toggled = !self.clean_toggled;
+                vec![]
+            }
+            Command::SetBrightness(percent) => {
+                info!("Brightness: {}%", percent);
+                self.brightness_override = Some(percent);
+                self.status.lock().unwrap().brightness = Some(percent);
+                vec![]
+            }
+            Command::Quit => return Err(RahmenError::Terminate),
+            Command::Reload => {
+                // a broken configuration would keep Rahmen from starting again
+                if !config_fit_for_reload() {
+                    return Ok(vec![]);
+                }
+                info!("Reloading the configuration");
+                self.reload_due = true;
+                return Err(RahmenError::Terminate);
+            }
+            Command::ToggleScreen => {
+                self.screen_off = !self.screen_off;
+                info!("Screen off: {}", self.screen_off);
+                vec![]
+            }
+            Command::Presence(home) => {
+                self.someone_home = home;
+                // a slideshow held while away goes on by itself, with the next image if its time
+                // has come
+                if self.away == Some(Away::Blank) && home {
+                    // welcome with an image not seen yet
+                    player.handle(Command::Next, now)
+                } else {
+                    vec![]
+                }
+            }
+            Command::Motion => {
+                let absent = self
+                    .motion_timeout
+                    .map_or(false, |timeout| now >= self.motion_seen_at + timeout);
+                self.motion_seen_at = now;
+                if absent {
+                    info!("Motion seen, showing the slideshow again");
+                    // welcome with an image not seen yet
+                    player.handle(Command::Next, now)
+                } else {
+                    vec![]
+                }
+            }
+            Command::Favorite => {
+                for path in player.slide_paths() {
+                    match self.favorites.mark(path) {
+                        Ok(file) => info!("Marked {:?} as a favorite in {:?}", path, file),
+                        Err(err) => warn!("Marking {:?} failed: {}", path, err),
+                    }
+                }
+                vec![]
+            }
+            Command::Rotate(clockwise) => match player.slide().map(Path::to_path_buf) {
+                Some(path) => match self.rotations.turn(&path, clockwise) {
+                    Ok(file) => {
+                        info!("Turned {:?}, remembered in {:?}", path, file);
+                        // show the slide again, loaded anew
+                        player
+                            .slide_paths()
+                            .iter()
+                            .cloned()
+                            .map(Effect::Requeue)
+                            .chain(Some(Effect::Advance))
+                            .collect()
+                    }
+                    Err(err) => {
+                        warn!("Turning {:?} failed: {}", path, err);
+                        vec![]
+                    }
+                },
+                None => vec![],
+            },
+            Command::Blacklist => {
+                let paths = player.slide_paths().to_vec();
+                for path in &paths {
+                    match self.blacklist.add(path) {
+                        Ok(()) => info!("Never showing {:?} again", path),
+                        Err(err) => warn!("Blacklisting {:?} failed: {}", path, err),
+                    }
+                }
+                player.forget(&paths, now)
+            }
+            // playback commands
+            command => player.handle(command, now),
+        };
+        Ok(self.apply(effects))
+    }
+}
+
 #[cfg(unix)]
-const SYSTEM_CONFIG_PATH: &str = "/etc/rahmen.toml";
+const SYSTEM_CONFIG_DIR: &str = "/etc";
 
-fn main() -> RahmenResult<()> {
-    // read command line args
-    let matches = App::new("Rahmen client")
+const DEFAULT_FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf";
+
+/// The largest number of pixels of an image kept in memory, unless set otherwise
+const DEFAULT_BUFFER_MAX_SIZE: &str = "16000000";
+
+/// The layer of the status line
+const STATUS_LINE_KEY: usize = 2;
+
+/// How often the metrics are written to their text file
+const METRICS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often the heartbeat file is written
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The display provider used unless another is selected
+#[cfg(feature = "framebuffer")]
+const DEFAULT_DISPLAY: &str = "framebuffer";
+/// The display provider used unless another is selected, a window beyond Linux
+#[cfg(all(
+    not(feature = "framebuffer"),
+    feature = "sdl2",
+    not(target_os = "linux")
+))]
+const DEFAULT_DISPLAY: &str = "sdl2";
+/// The display provider used unless another is selected, which needs no hardware
+#[cfg(not(any(
+    feature = "framebuffer",
+    all(feature = "sdl2", not(target_os = "linux"))
+)))]
+const DEFAULT_DISPLAY: &str = "null";
+
+/// Write the heartbeat to `path`, replacing it atomically, so a watchdog never reads half of it
+fn write_heartbeat(path: &Path, health: &serde_json::Value) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, health.to_string())?;
+    std::fs::rename(&temporary, path)
+}
+
+/// Create a provider reading a list file, or stdin for `-`, or else matching a glob pattern. The
+/// transitions given in list files are recorded in `transitions`.
+fn open_input(input: &str, transitions: &Transitions) -> RahmenResult<Box<dyn Provider<PathBuf>>> {
+    Ok(if input.eq("-") {
+        info!("Reading from stdin");
+        Box::new(ListProvider::new(
+            BufReader::new(std::io::stdin()),
+            transitions.clone(),
+        ))
+    } else if let Ok(file) = File::open(input) {
+        info!("Reading from file {}", input);
+        Box::new(ListProvider::new(BufReader::new(file), transitions.clone()))
+    } else {
+        info!("Reading from pattern {}", input);
+        Box::new(rahmen::provider_glob::create(input)?)
+    })
+}
+
+/// Set every value of `value`, a table or a single value, as an override under the key `key`, so
+/// tables are merged into those of the file rather than replacing them
+fn set_override(c: &mut config::Config, key: &str, value: config::Value) -> RahmenResult<()> {
+    match value.clone().into_table() {
+        Ok(table) => {
+            for (name, value) in table {
+                set_override(c, &format!("{}.{}", key, name), value)?;
+            }
+        }
+        Err(_) => {
+            c.set(key, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Apply the settings of the profile `name`, from the table `profiles.<name>` of the file
+fn apply_profile(c: &mut config::Config, name: &str) -> RahmenResult<()> {
+    let profile = c.get_table(&format!("profiles.{}", name)).map_err(|_| {
+        config::ConfigError::Message(format!("profile {:?} not found in the config file", name))
+    })?;
+    for (key, value) in profile {
+        set_override(c, &key, value)?;
+    }
+    Ok(())
+}
+
+/// The command line interface
+fn app() -> App<'static> {
+    App::new("Rahmen client")
         .arg(
             Arg::new("display")
                 .short('d')
@@ -85,13 +422,34 @@ fn main() -> RahmenResult<()> {
                 .value_name("display")
                 .takes_value(true)
                 .possible_values(&[
+                    #[cfg(feature = "drm")]
+                    "drm",
+                    #[cfg(feature = "eink")]
+                    "eink",
                     #[cfg(feature = "fltk")]
                     "fltk",
+                    #[cfg(feature = "framebuffer")]
                     "framebuffer",
+                    #[cfg(feature = "http")]
+                    "http",
+                    "multi",
+                    "null",
+                    "png",
+                    #[cfg(feature = "sdl2")]
+                    "sdl2",
+                    #[cfg(feature = "terminal")]
+                    "terminal",
+                    #[cfg(feature = "x11")]
+                    "x11",
                 ])
-                .default_value("framebuffer"),
+                .default_value(DEFAULT_DISPLAY),
+        )
+        .arg(
+            Arg::new("input")
+                .takes_value(true)
+                .required_unless_present_any(&["export_impressions", "check_config"])
+                .index(1),
         )
-        .arg(Arg::new("input").takes_value(true).required(true).index(1))
         .arg(
             Arg::new("output")
                 .short('o')
@@ -110,14 +468,14 @@ fn main() -> RahmenResult<()> {
                 .long("buffer_max_size")
                 .takes_value(true)
                 .validator(|v| f64::from_str(v))
-                .default_value(format!("{}", 4000 * 4000).as_str()),
+                .default_value(DEFAULT_BUFFER_MAX_SIZE),
         )
         .arg(
             Arg::new("font")
                 .long("font")
                 .takes_value(true)
                 .validator(|f| File::open(f))
-                .default_value("/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf"),
+                .about("The font for the status line"),
         )
         .arg(
             Arg::new("font_size")
@@ -125,6 +483,29 @@ fn main() -> RahmenResult<()> {
                 .takes_value(true)
                 .validator(|v| f32::from_str(v)),
         )
+        .arg(
+            Arg::new("export_impressions")
+                .long("export_impressions")
+                .takes_value(true)
+                .about("Write how often and how long each image was shown to a CSV or JSON file, and exit"),
+        )
+        .arg(
+            Arg::new("check_config")
+                .long("check-config")
+                .about("Check the configuration, the inputs and the font, and exit"),
+        )
+        .arg(
+            Arg::new("shuffle")
+                .long("shuffle")
+                .about("Show the images in random order"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .about("Seed for a reproducible random order, implies --shuffle")
+                .takes_value(true)
+                .validator(|v| u64::from_str(v)),
+        )
         .arg(
             Arg::new("config")
                 .long("config")
@@ -132,61 +513,335 @@ fn main() -> RahmenResult<()> {
                 .takes_value(true)
                 .validator(|f| File::open(f)),
         )
-        .get_matches();
-
-    // evaluate input arg
-    let input = matches.value_of("input").expect("Input missing");
-    // box is used bec of dynamic typing for provider
-    let mut provider: Box<dyn Provider<_>> = if input.eq("-") {
-        println!("Reading from stdin");
-        Box::new(ListProvider::new(BufReader::new(std::io::stdin())))
-    } else if let Ok(file) = File::open(input) {
-        println!("Reading from file");
-        Box::new(ListProvider::new(BufReader::new(file)))
-    } else {
-        println!("Reading from pattern {}", input);
-        Box::new(rahmen::provider_glob::create(input)?)
-    };
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .short('p')
+                .about("Use the settings of a profile of the configuration file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("set")
+                .long("set")
+                .about("Override a setting of the configuration file, such as font_size=32 or status.position=top")
+                .value_name("key=value")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|v| match v.split_once('=') {
+                    Some((key, _)) if !key.trim().is_empty() => Ok(()),
+                    _ => Err(format!("{:?} is not key=value", v)),
+                }),
+        )
+}
 
+/// Read the settings from the configuration file and the overrides given on the command line
+fn load_settings(matches: &ArgMatches, dirs: &BaseDirectories) -> RahmenResult<Settings> {
     // look for config file
-    let dirs = xdg::BaseDirectories::new().unwrap();
-    let settings: Settings = if let Some(path) = matches
+    let config_path: Option<PathBuf> = matches
         .value_of("config")
         .map(Into::into)
-        .or_else(|| dirs.find_config_file("rahmen.toml"))
+        .or_else(|| {
+            CONFIG_FILE_NAMES
+                .iter()
+                .find_map(|name| dirs.find_config_file(name))
+        })
         .or_else(|| {
             #[cfg(unix)]
-            if std::fs::metadata(SYSTEM_CONFIG_PATH).is_ok() {
-                Some(SYSTEM_CONFIG_PATH.into())
-            } else {
-                None
+            {
+                CONFIG_FILE_NAMES
+                    .iter()
+                    .map(|name| Path::new(SYSTEM_CONFIG_DIR).join(name))
+                    .find(|path| path.is_file())
             }
             #[cfg(not(unix))]
             None
-        }) {
+        });
+    if config_path.is_none() && matches.is_present("profile") {
+        return Err(config::ConfigError::Message("profiles need a config file".into()).into());
+    }
+    // settings given on the command line override those of the file
+    let overrides: Vec<_> = matches.values_of("set").into_iter().flatten().collect();
+    Ok(if config_path.is_some() || !overrides.is_empty() {
         let mut c = config::Config::default();
-        c.merge(config::File::from(path))?;
+        match config_path {
+            Some(path) => {
+                rahmen::config::merge_file(&mut c, &path)?;
+                // a profile overrides the common settings
+                if let Some(profile) = matches.value_of("profile") {
+                    info!("Using profile {}", profile);
+                    apply_profile(&mut c, profile)?;
+                }
+            }
+            None => {
+                warn!("Config file not found, continuing with the settings given by --set");
+                // without a file, the status line is empty like with the default settings
+                c.set_default("status_line", Vec::<config::Value>::new())?;
+            }
+        }
+        for setting in overrides {
+            if let Some((key, value)) = setting.split_once('=') {
+                c.set(key.trim(), value)?;
+            }
+        }
         c.try_into()?
     } else {
-        eprintln!("Config file not found, continuing with default settings");
+        warn!("Config file not found, continuing with default settings");
         Default::default()
+    })
+}
+
+/// Look for problems in the settings, the inputs and the font
+fn check_settings(matches: &ArgMatches, settings: &Settings) -> Vec<Problem> {
+    let font_path = matches
+        .value_of("font")
+        .or_else(|| settings.font.as_ref().and_then(|font| font.path.as_deref()))
+        .unwrap_or(DEFAULT_FONT);
+    let inputs: Vec<_> = matches.value_of("input").into_iter().collect();
+    rahmen::check::check(settings, &inputs, Path::new(font_path))
+}
+
+/// Whether the configuration, as it is now, is fit to start again with. Problems are logged.
+fn config_fit_for_reload() -> bool {
+    let matches = match app().try_get_matches() {
+        Ok(matches) => matches,
+        Err(err) => {
+            error!("Not reloading: {}", err);
+            return false;
+        }
+    };
+    let dirs = BaseDirectories::new().unwrap();
+    let settings = match load_settings(&matches, &dirs) {
+        Ok(settings) => settings,
+        Err(err) => {
+            error!("Not reloading, the configuration is broken: {}", err);
+            return false;
+        }
+    };
+    let problems = check_settings(&matches, &settings);
+    for problem in &problems {
+        error!("{}", problem);
+    }
+    if !problems.is_empty() {
+        error!(
+            "Not reloading, found {} problems in the configuration",
+            problems.len()
+        );
+    }
+    problems.is_empty()
+}
+
+fn main() -> RahmenResult<()> {
+    rahmen::logging::init();
+
+    // read command line args
+    let matches = app().get_matches();
+
+    let dirs = BaseDirectories::new().unwrap();
+    let settings = load_settings(&matches, &dirs)?;
+
+    if matches.is_present("check_config") {
+        let problems = check_settings(&matches, &settings);
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        if !problems.is_empty() {
+            eprintln!("Found {} problems in the configuration", problems.len());
+            std::process::exit(1);
+        }
+        println!("Configuration OK");
+        return Ok(());
+    }
+
+    // persistent state, such as the image to resume from
+    let state = settings.state.unwrap_or_default();
+    let backend = state.backend.unwrap_or(StoreBackend::Json);
+    let state_path = match state.path {
+        Some(path) => path.into(),
+        None => dirs.place_data_file(match backend {
+            StoreBackend::Json => "rahmen/state.json",
+            StoreBackend::Sqlite => "rahmen/state.sqlite",
+        })?,
+    };
+    let store = rahmen::store::open(backend, &state_path)?;
+
+    if let Some(target) = matches.value_of("export_impressions") {
+        info!("Exporting impressions to {}", target);
+        return rahmen::impressions::export(&*store, Path::new(target));
+    }
+    let count_impressions = state.impressions.unwrap_or(false);
+    let resume = state.resume.unwrap_or(false);
+    let mut resumed = if resume {
+        ResumeState::load(&*store)?
+    } else {
+        ResumeState::default()
+    };
+    let blacklist = Blacklist::open(match state.blacklist {
+        Some(path) => path.into(),
+        None => dirs.place_data_file("rahmen/blacklist")?,
+    })?;
+    if !blacklist.is_empty() {
+        info!("Never showing {} blacklisted images", blacklist.len());
+    }
+
+    // evaluate input arg, and additional sources from the config file
+    let input = matches.value_of("input").expect("Input missing");
+    let inputs = std::iter::once((input.to_string(), 1)).chain(
+        settings
+            .source
+            .iter()
+            .flatten()
+            .map(|source| (source.input.clone(), source.weight.unwrap_or(1))),
+    );
+
+    // shuffle the images, reproducibly if a seed is given
+    let seed = matches
+        .value_of("seed")
+        .map(str::parse)
+        .transpose()
+        .unwrap()
+        .or(settings.seed);
+    let shuffle_seed =
+        if matches.is_present("shuffle") || seed.is_some() || settings.shuffle.unwrap_or(false) {
+            // resuming continues in the same order
+            let seed = seed.or(resumed.seed).unwrap_or_else(rand::random);
+            info!("Shuffling with seed {}", seed);
+            Some(seed)
+        } else {
+            None
+        };
+
+    // box is used bec of dynamic typing for provider
+    let mut sources = vec![];
+    let list_transitions = Transitions::default();
+    for (index, (input, weight)) in inputs.enumerate() {
+        let mut provider = open_input(&input, &list_transitions)?;
+        if let Some(seed) = shuffle_seed {
+            // shuffle each source differently
+            let seed = seed.wrapping_add(index as u64);
+            provider = Box::new(ShuffleProvider::<PathBuf, _>::new(provider, seed));
+        }
+        sources.push((provider, weight));
+    }
+    let provider: Box<dyn Provider<_>> = if sources.len() == 1 {
+        sources.remove(0).0
+    } else {
+        Box::new(CompositeProvider::new(sources, settings.max_gap))
     };
+
+    // images can ask to be shown longer or shorter
+    let duration = settings.duration.clone().unwrap_or_default();
+    let provider = DurationProvider::new(
+        provider,
+        duration.sidecar.unwrap_or(true),
+        duration.tag.clone(),
+    );
+
+    let provider = ResumeProvider::new(provider, resumed.position, resumed.image.clone());
+    let positions = provider.positions();
+    resumed.seed = shuffle_seed;
+
+    // change the playlist, the interval or the screen by time of day and weekday, if configured
+    let schedule = Schedule::new(settings.schedule.as_deref().unwrap_or_default())?;
+    let playlists = settings.playlists.clone().unwrap_or_default();
+    for block in schedule.blocks() {
+        if let Some(name) = &block.playlist {
+            if !playlists.contains_key(name) {
+                return Err(RahmenError::ConfigError(Arc::new(
+                    config::ConfigError::Message(format!("unknown playlist {:?}", name)),
+                )));
+            }
+        }
+    }
+    let open_playlist: Opener = {
+        let (sidecar, tag) = (duration.sidecar.unwrap_or(true), duration.tag.clone());
+        let transitions = list_transitions.clone();
+        Box::new(
+            move |input: &str| -> RahmenResult<Box<dyn Provider<PathBuf>>> {
+                let mut provider = open_input(input, &transitions)?;
+                if let Some(seed) = shuffle_seed {
+                    provider = Box::new(ShuffleProvider::<PathBuf, _>::new(provider, seed));
+                }
+                Ok(Box::new(LookaheadProvider::new(DurationProvider::new(
+                    provider,
+                    sidecar,
+                    tag.clone(),
+                ))))
+            },
+        )
+    };
+    let provider =
+        PlaylistProvider::new(LookaheadProvider::new(provider), playlists, open_playlist);
+    let playlist_selection = provider.selection();
+    let mut active_block = schedule.active(chrono::Local::now().naive_local()).cloned();
+    if let Some(block) = &active_block {
+        playlist_selection.select(block.playlist.clone());
+    }
+
+    let provider = BlacklistProvider::new(provider, blacklist.clone());
+    // skip thumbnails and other small images, if configured
+    let screen_size = ScreenSize::default();
+    let provider: Box<dyn Provider<PathBuf>> = match settings.resolution.clone() {
+        Some(resolution) => Box::new(ResolutionProvider::new(
+            provider,
+            resolution.min_pixels,
+            resolution.max_upscale,
+            screen_size.clone(),
+        )),
+        None => Box::new(provider),
+    };
+    // select images of the orientation of the screen, if configured
+    let provider: Box<dyn Provider<PathBuf>> = match settings.orientation.clone() {
+        Some(orientation) => Box::new(OrientationProvider::new(
+            provider,
+            orientation.mode.unwrap_or(OrientationMode::Prefer),
+            orientation.tolerance.unwrap_or(0.1),
+            screen_size.clone(),
+        )),
+        None => Box::new(provider),
+    };
+    // skip accidental shots, if configured
+    let provider: Box<dyn Provider<PathBuf>> = match settings.quality.clone() {
+        Some(quality) => Box::new(QualityProvider::new(
+            provider,
+            QualityGate {
+                min_sharpness: quality.min_sharpness.unwrap_or(50.),
+                min_brightness: quality.min_brightness.unwrap_or(16.),
+                max_brightness: quality.max_brightness.unwrap_or(240.),
+            },
+        )),
+        None => Box::new(provider),
+    };
+    // skip bursts and other near-duplicates, if configured
+    let provider: Box<dyn Provider<PathBuf>> = match settings.duplicates.clone() {
+        Some(duplicates) => {
+            let cache = HashCache::open(match duplicates.cache {
+                Some(path) => path.into(),
+                None => dirs.place_cache_file("rahmen/hashes")?,
+            })?;
+            info!("Skipping near-duplicates, {} hashes cached", cache.len());
+            Box::new(DuplicateProvider::new(
+                provider,
+                cache,
+                duplicates.window.unwrap_or(10),
+                duplicates.threshold.unwrap_or(6),
+            ))
+        }
+        None => Box::new(provider),
+    };
+
+    // images still being written are loaded again as they grow, until they are complete
+    let mut provider = RequeueProvider::<PathBuf, _>::new(provider);
+    let requeue = provider.requeue();
     // Python search path: use the Python system path, and prepend the value(s) from the config file
     // Note: contrary to the documentation, the Python system path will not contain the directory from which we're called,
     // so this has to be indicated in the configuration file
     if let Some(python_paths) = settings.py_path {
-        Python::with_gil(|py| {
-            let syspath: &PyList = py
-                .import("sys")
-                .unwrap()
-                .get("path")
-                .unwrap()
-                .try_into()
-                .unwrap();
-            for path in &python_paths {
-                syspath.insert(0, path).unwrap();
-            }
-        });
+        rahmen::python::prepend_path(&python_paths)?;
+    }
+    // curves from Python can be used by the transitions configured and those in file lists
+    if let Some(module) = &settings.py_easings {
+        rahmen::python::register_easings(module)?;
     }
 
     // build the status line, using the settings from the config file for the individual
@@ -196,8 +851,61 @@ fn main() -> RahmenResult<()> {
     let status_line_formatter = StatusLineFormatter::new(
         settings.status_line.iter().cloned(),
         settings.py_postprocess,
+        settings.lua_postprocess,
+        settings.lua_path.as_deref().unwrap_or_default(),
         settings.separator.unwrap_or_else(|| ", ".to_string()),
     )?;
+    let status_line_formatter = match settings.geocode {
+        Some(geocode) => {
+            info!("Loading places from {}", geocode.cities);
+            let geocoder = ReverseGeocoder::load(
+                Path::new(&geocode.cities),
+                geocode.admin1.as_deref().map(Path::new),
+                geocode.countries.as_deref().map(Path::new),
+                geocode.min_population.unwrap_or(0),
+                &Language {
+                    locale: geocode.language,
+                    alternate_names: geocode.alternate_names.map(PathBuf::from),
+                    ascii: geocode.ascii.unwrap_or(false),
+                },
+                geocode
+                    .detail
+                    .unwrap_or_else(|| vec![Detail::City, Detail::Country]),
+                geocode.separator.unwrap_or_else(|| ", ".to_string()),
+            )?;
+            let geocoder = if geocode.cache.unwrap_or(true) {
+                let cache_path = match geocode.cache_path {
+                    Some(path) => path.into(),
+                    None => dirs.place_data_file(match backend {
+                        StoreBackend::Json => "rahmen/places.json",
+                        StoreBackend::Sqlite => "rahmen/places.sqlite",
+                    })?,
+                };
+                geocoder.with_cache(
+                    rahmen::store::open(backend, &cache_path)?,
+                    geocode.cache_decimals.unwrap_or(2),
+                )
+            } else {
+                geocoder
+            };
+            status_line_formatter.with_geocoder(geocoder)
+        }
+        None => status_line_formatter,
+    };
+    let status_line_formatter = match settings.camera_timezone {
+        Some(timezone) => {
+            status_line_formatter.with_camera_timezone(rahmen::date::parse_timezone(&timezone)?)
+        }
+        None => status_line_formatter,
+    };
+    let status_line_formatter = match settings.home {
+        Some(home) => status_line_formatter.with_home(Home {
+            position: (home.latitude, home.longitude),
+            units: home.units.unwrap_or(Units::Metric),
+            decimals: home.decimals.unwrap_or(1),
+        }),
+        None => status_line_formatter,
+    };
 
     // continue evaluating the command line args
     let buffer_max_size: usize = matches
@@ -206,8 +914,259 @@ fn main() -> RahmenResult<()> {
         .parse()
         .unwrap();
 
-    let font = Font::from_path(matches.value_of("font").unwrap(), 0).unwrap();
-    let font_renderer = FontRenderer::with_font(font);
+    // transcode images in formats we can't decode ourselves
+    let mut transcode_cache_limit = None;
+    let transcode_cache = settings
+        .transcode
+        .map(|transcode| -> RahmenResult<_> {
+            let dir: PathBuf = match transcode.cache_dir {
+                Some(dir) => dir.into(),
+                None => dirs.create_cache_directory("rahmen/transcoded")?,
+            };
+            transcode_cache_limit = transcode
+                .max_cache_size
+                .map(|megabytes| (dir.clone(), megabytes * 1024 * 1024));
+            TranscodeCache::new(
+                dir,
+                transcode.converter,
+                screen_size.clone(),
+                buffer_max_size,
+            )
+        })
+        .transpose()?;
+
+    // remove dust from scanned images
+    let cleanup_cache = settings
+        .cleanup
+        .map(|cleanup| -> RahmenResult<_> {
+            let dir: PathBuf = match cleanup.cache_dir {
+                Some(dir) => dir.into(),
+                None => dirs.create_cache_directory("rahmen/cleaned")?,
+            };
+            CleanupCache::new(
+                dir,
+                &cleanup.patterns.unwrap_or_default(),
+                cleanup.max_speck.unwrap_or(2),
+                cleanup.threshold.unwrap_or(40),
+                cleanup.red_eye.unwrap_or(false),
+            )
+        })
+        .transpose()?;
+
+    // take HDR images down to SDR, unless disabled
+    let hdr = settings.hdr.clone().unwrap_or_default();
+    let tone_mapper = if hdr.tone_map.unwrap_or(true) {
+        Some(ToneMapper {
+            peak: hdr.peak.unwrap_or(1000.).max(1.),
+        })
+    } else {
+        None
+    };
+
+    let straighten_max_angle = settings
+        .straighten
+        .map(|straighten| straighten.max_angle.unwrap_or(3.));
+
+    // count what happens, for monitoring
+    let metrics = Metrics::default();
+    let metrics_settings = settings.metrics.clone().unwrap_or_default();
+    if let Some(address) = &metrics_settings.address {
+        rahmen::metrics::serve(address, metrics.clone())?;
+    }
+    let metrics_textfile = metrics_settings.textfile.map(PathBuf::from);
+
+    let errors = settings.errors.clone().unwrap_or_default();
+    let default_policy = ErrorPolicy::default();
+    let error_policy = ErrorPolicy {
+        on_error: errors.on_error.unwrap_or(default_policy.on_error),
+        retries: errors.retries.unwrap_or(default_policy.retries),
+        backoff: errors.backoff.map_or(default_policy.backoff, |seconds| {
+            Duration::from_secs_f64(seconds.max(0.))
+        }),
+    };
+
+    // load the upcoming images in the background
+    let prefetch_depth = settings.prefetch.unwrap_or(1);
+    let decode_metrics = metrics.clone();
+    let prefetcher = Prefetcher::new(
+        move |path: &Path| {
+            let started = Instant::now();
+            let loaded = match &transcode_cache {
+                Some(cache) => cache.resolve(path),
+                None => Ok(path.to_owned()),
+            }
+            .and_then(|source| {
+                let img = match &cleanup_cache {
+                    Some(cache) if cache.applies_to(path) => {
+                        cache.load(path, &source, Some(buffer_max_size))
+                    }
+                    _ => load_image_from_path(&source, Some(buffer_max_size)),
+                }?;
+                let img = match &tone_mapper {
+                    Some(tone_mapper) => tone_mapper.apply(path, img),
+                    None => img,
+                };
+                // transcoded copies keep no metadata, so the orientation is that of the original
+                Ok(upright(img, orientation(path)))
+            })
+            .map(|img| match straighten_max_angle {
+                Some(max_angle) => straighten(img, max_angle),
+                None => img,
+            });
+            if loaded.is_ok() {
+                decode_metrics.image_decoded(started.elapsed());
+            }
+            loaded
+        },
+        error_policy,
+    );
+    let upcoming_prefetcher = prefetcher.clone();
+
+    let font = settings.font.unwrap_or_default();
+    let font_path = matches
+        .value_of("font")
+        .or_else(|| font.path.as_deref())
+        .unwrap_or(DEFAULT_FONT);
+    let mut font_renderer = FontRenderer::from_path(font_path)?;
+    // show what went wrong in place of images that fail to load, if configured
+    let placeholder = Rc::new(RefCell::new(
+        match (&errors.placeholder_image, errors.placeholder) {
+            (Some(path), _) => Some(Placeholder::image(path, Some(buffer_max_size))?),
+            (None, Some(true)) => Some(Placeholder::Text(FontRenderer::from_path(font_path)?)),
+            _ => None,
+        },
+    ));
+    // a single bad file is skipped, while failures in a row, such as a mount gone, are shown
+    let placeholder_after = errors.placeholder_after.unwrap_or(3).max(1);
+    let black = [0; 3];
+    let status = settings.status.unwrap_or_default();
+    let status_layout = StatusLayout {
+        position: status.position.unwrap_or(StatusPosition::Bottom),
+        margin: status.margin.unwrap_or(0),
+        overlay: status.overlay.unwrap_or(false),
+    };
+    let marquee = if status.marquee.unwrap_or(false) {
+        Some(Marquee {
+            speed: status.marquee_speed.unwrap_or(60.).max(1.),
+            pause: Duration::from_secs_f64(status.marquee_pause.unwrap_or(2.).max(0.)),
+        })
+    } else {
+        None
+    };
+    let min_font_size = status.min_font_size.map(|size| size.max(1.));
+    let background_color = Rgb(status.background_color.unwrap_or(black));
+    let background_opacity =
+        (status.background_opacity.unwrap_or(0.5).max(0.).min(1.) * 255.).round() as u8;
+    let status_style = TextStyle {
+        color: Rgb(font.color.unwrap_or([255; 3])),
+        outline: font
+            .outline
+            .map(|width| (width, Rgb(font.outline_color.unwrap_or(black)))),
+        shadow: font
+            .shadow
+            .map(|offset| (offset, Rgb(font.shadow_color.unwrap_or(black)))),
+        background: match status.background.unwrap_or(StatusBackground::None) {
+            StatusBackground::None => None,
+            StatusBackground::Box => Some(Background::Box(background_color, background_opacity)),
+            StatusBackground::Gradient => Some(Background::Gradient(
+                background_color,
+                background_opacity,
+                status_layout.position == StatusPosition::Bottom,
+            )),
+        },
+        align: status.align.unwrap_or(Alignment::Left),
+        margin: status_layout.margin,
+        max_lines: status.max_lines,
+    };
+    font_renderer.set_style(status_style);
+    // make room for all lines of the status line
+    let font_canvas_vstretch = font_canvas_vstretch(status.max_lines.unwrap_or(1));
+
+    // opacity of the status line and widgets
+    let layers = settings.layers.unwrap_or_default();
+    // the layers describing the photo fade in along with it
+    let slide_layer_opacity = [
+        (STATUS_LINE_KEY, layers.status.unwrap_or(1.)),
+        (4, layers.qr.unwrap_or(1.)),
+    ];
+    let layer_opacity = vec![
+        (STATUS_LINE_KEY, layers.status.unwrap_or(1.)),
+        (3, layers.weather.unwrap_or(1.)),
+        (4, layers.qr.unwrap_or(1.)),
+        (ProgressIndicator::BAR_KEY, layers.progress.unwrap_or(0.5)),
+        (
+            ProgressIndicator::COUNTER_KEY,
+            layers.progress.unwrap_or(0.5),
+        ),
+    ];
+
+    // link to the image shown with a QR code, if configured
+    let qr_code = settings.qr.map(|qr| {
+        (
+            qr.url,
+            qr.strip_prefix.map(PathBuf::from),
+            qr.corner.unwrap_or(Corner::BottomRight),
+            qr.margin.unwrap_or(10),
+            qr.module_size.unwrap_or(4),
+        )
+    });
+
+    // show the weather in a corner, if configured
+    let (weather_sender, weather_updates) = mpsc::channel();
+    let weather_renderer = match settings.weather {
+        Some(weather) => {
+            let margin = weather.margin.unwrap_or(10);
+            let corner = weather.corner.unwrap_or(Corner::TopRight);
+            let mut renderer = FontRenderer::from_path(font_path)?;
+            renderer.set_style(TextStyle {
+                background: None,
+                align: match corner {
+                    Corner::TopLeft | Corner::BottomLeft => Alignment::Left,
+                    Corner::TopRight | Corner::BottomRight => Alignment::Right,
+                },
+                margin,
+                max_lines: None,
+                ..status_style
+            });
+            let layout = StatusLayout {
+                position: match corner {
+                    Corner::TopLeft | Corner::TopRight => StatusPosition::Top,
+                    Corner::BottomLeft | Corner::BottomRight => StatusPosition::Bottom,
+                },
+                margin,
+                overlay: true,
+            };
+            WeatherSource {
+                service: weather.service.unwrap_or(WeatherService::OpenMeteo),
+                latitude: weather.latitude,
+                longitude: weather.longitude,
+                api_key: weather.api_key,
+                units: weather.units.unwrap_or(Units::Metric),
+            }
+            .spawn(
+                Duration::from_secs(weather.interval.unwrap_or(15) * 60),
+                weather_sender,
+            );
+            Some((renderer, layout))
+        }
+        None => None,
+    };
+
+    // reload the font and the images filling in when they change on disk, unlike the
+    // configuration
+    let mut asset_watcher = FileWatcher::new();
+    asset_watcher.watch(font_path);
+    if let Some(path) = &errors.placeholder_image {
+        asset_watcher.watch(path);
+    }
+    if let Some(LetterboxSettings {
+        fill: Some(LetterboxFill::Texture),
+        texture: Some(path),
+        ..
+    }) = &settings.letterbox
+    {
+        asset_watcher.watch(path);
+    }
 
     let duration_millis = (matches
         .value_of("time")
@@ -217,7 +1176,7 @@ fn main() -> RahmenResult<()> {
         .unwrap_or(90.)
         * 1000f64) as u64;
     let delay = Duration::from_millis(duration_millis);
-    println!("Delay: {:?}", delay);
+    info!("Delay: {:?}", delay);
 
     // font size to use (px)
     let font_size_f = matches
@@ -225,19 +1184,208 @@ fn main() -> RahmenResult<()> {
         .map(str::parse)
         .transpose()?
         .or(settings.font_size)
-        .unwrap_or(30.);
+        .unwrap_or(DEFAULT_FONT_SIZE);
+    // with the status line at the top, the image moves down
+    let image_y_shift = if status_layout.position == StatusPosition::Top {
+        status_layout.reserved_height(font_size_f, font_canvas_vstretch)
+    } else {
+        0
+    };
+
+    // show the progress of the slideshow, if configured
+    let mut progress = match settings.progress {
+        Some(progress) => {
+            let color = Rgb(progress.color.unwrap_or([255; 3]));
+            let bar = if progress.bar.unwrap_or(true) {
+                Some(BarStyle {
+                    height: progress.bar_height.unwrap_or(4),
+                    position: progress.bar_position.unwrap_or(StatusPosition::Bottom),
+                    color,
+                })
+            } else {
+                None
+            };
+            let counter = if progress.counter.unwrap_or(false) {
+                let corner = progress.counter_corner.unwrap_or(Corner::TopLeft);
+                let margin = progress.margin.unwrap_or(10);
+                let mut renderer = FontRenderer::from_path(font_path)?;
+                renderer.set_style(TextStyle {
+                    color,
+                    background: None,
+                    align: match corner {
+                        Corner::TopLeft | Corner::BottomLeft => Alignment::Left,
+                        Corner::TopRight | Corner::BottomRight => Alignment::Right,
+                    },
+                    margin,
+                    max_lines: None,
+                    ..status_style
+                });
+                Some(CounterStyle {
+                    renderer,
+                    font_size: font_size_f,
+                    canvas_height: (font_size_f * FONT_CANVAS_VSTRETCH).ceil() as u32,
+                    corner,
+                    margin,
+                })
+            } else {
+                None
+            };
+            Some(ProgressIndicator::new(bar, counter))
+        }
+        None => None,
+    };
 
     let show_time = settings.display_time.unwrap_or(false);
     let time_format = settings.time_format.unwrap_or("%H:%M:%S".into());
 
+    // hide the status line during quiet hours
+    let quiet_hours = settings
+        .quiet_hours
+        .iter()
+        .flatten()
+        .map(TimeRange::new)
+        .collect::<RahmenResult<Vec<_>>>()?;
+
+    // restart daily, if configured
+    let maintenance_time = settings
+        .maintenance
+        .as_ref()
+        .map(|maintenance| DailyTime::new(&maintenance.at))
+        .transpose()?;
+    let exit_for_maintenance = settings
+        .maintenance
+        .as_ref()
+        .and_then(|maintenance| maintenance.exit)
+        .unwrap_or(false);
+    let logs_kept = settings
+        .maintenance
+        .and_then(|maintenance| maintenance.logs_kept)
+        .unwrap_or(7);
+
+    // fill the bars beside images of another aspect ratio than the screen
+    let letterbox_settings = settings.letterbox.unwrap_or_default();
+    let letterbox_brightness = letterbox_settings.brightness.unwrap_or(0.4).max(0.).min(1.);
+    let letterbox = match letterbox_settings.fill {
+        None | Some(LetterboxFill::Black) => Letterbox::Black,
+        Some(LetterboxFill::Color) => Letterbox::Color(letterbox_settings.color.unwrap_or(black)),
+        Some(LetterboxFill::Palette) => Letterbox::Palette(letterbox_brightness),
+        Some(LetterboxFill::Average) => Letterbox::Average(letterbox_brightness),
+        Some(LetterboxFill::Texture) => match &letterbox_settings.texture {
+            Some(path) => Letterbox::Texture(Texture::load(path, Some(buffer_max_size))?),
+            None => {
+                return Err(RahmenError::ConfigError(Arc::new(
+                    config::ConfigError::Message("the texture fill needs a texture image".into()),
+                )))
+            }
+        },
+    };
+    // give all images the same look, if configured
+    let filter_settings = settings.filter.clone().unwrap_or_default();
+    let filter = filter_settings.kind.map(|kind| match kind {
+        FilterKind::Grayscale => Filter::Grayscale,
+        FilterKind::Sepia => Filter::Sepia,
+        FilterKind::Duotone => Filter::Duotone(
+            filter_settings.shadows.unwrap_or([20, 30, 60]),
+            filter_settings.highlights.unwrap_or([240, 225, 190]),
+        ),
+    });
+    // show several images at once, if configured
+    let collage_settings = settings.collage.clone().unwrap_or_default();
+    let collage = settings.collage.as_ref().map(|_| Collage {
+        count: collage_settings.count.unwrap_or(4).max(2).min(MAX_IMAGES),
+        gap: collage_settings.gap.unwrap_or(10),
+        background: collage_settings.background.unwrap_or(black),
+        fit: collage_settings.fit.unwrap_or(Fit::Auto),
+        portrait_fit: collage_settings
+            .portrait_fit
+            .or(collage_settings.fit)
+            .unwrap_or(Fit::Auto),
+    });
+    let caption_separator = collage_settings.separator.unwrap_or_else(|| " | ".into());
+    // join two portraits on a landscape screen, if configured, unless showing collages
+    let pairing = settings
+        .pairs
+        .filter(|_| collage.is_none())
+        .map(|pairs| Pairing {
+            gap: pairs.gap.unwrap_or(20),
+            divider: pairs.divider.unwrap_or(black),
+            tolerance: 0.1,
+        });
+    let slide_screen_size = screen_size.clone();
+    let placeholder_screen_size = screen_size.clone();
+    let loading_placeholder = Rc::clone(&placeholder);
+    // the images that failed to load in a row
+    let mut load_failures = 0;
+    // fade between images, if configured
+    let transition = settings
+        .transition
+        .map(|transition| -> RahmenResult<_> {
+            Ok((
+                transition::duration(transition.duration.unwrap_or(1.))?,
+                match transition.easing {
+                    Some(name) => transition::easing(&name)?,
+                    None => Easing::EaseInOut,
+                },
+            ))
+        })
+        .transpose()?;
+
+    // dim the display depending on the time of day, if configured
+    let brightness_schedule = settings
+        .brightness
+        .map(|entries| BrightnessSchedule::new(entries.into_iter()))
+        .transpose()?;
+    // dim the display with the light in the room, if configured
+    let (light_sender, light_updates) = mpsc::channel();
+    let mut ambient = match settings.light_sensor {
+        Some(light) => {
+            let bus = light.bus.unwrap_or(1);
+            let sensor = match light.sensor {
+                SensorKind::Iio => Sensor::Iio(
+                    light
+                        .device
+                        .unwrap_or_else(|| "/sys/bus/iio/devices/iio:device0".into())
+                        .into(),
+                ),
+                SensorKind::Tsl2561 => Sensor::Tsl2561(bus, light.address.unwrap_or(0x39)),
+                SensorKind::Veml7700 => Sensor::Veml7700(bus, light.address.unwrap_or(0x10)),
+            };
+            sensor.spawn(
+                Duration::from_secs_f64(light.interval.unwrap_or(2.).max(0.1)),
+                light_sender,
+            )?;
+            Some(Ambient::new(
+                light.dark.unwrap_or(5.),
+                light.bright.unwrap_or(300.),
+                light.min_brightness.unwrap_or(0.3),
+            ))
+        }
+        None => None,
+    };
+    // dim the backlight rather than the images, if configured
+    let backlight = settings
+        .backlight
+        .map(|backlight| Backlight::open(backlight.device.as_deref()))
+        .transpose()?;
+
     // initialization for timely dataflow
     let allocator = timely::communication::allocator::Thread::new();
     let mut worker = timely::worker::Worker::new(Config::default(), allocator);
 
     // input: #1 timeline #2 screen resolution
     let mut input_configuration: InputHandle<_, Configuration> = InputHandle::new();
+    // input: the weather, as lines of text
+    let mut input_weather: InputHandle<_, Vec<String>> = InputHandle::new();
     // to gather information about progress
     let mut probe = ProbeHandle::new();
+    // the store is shared by counting impressions and saving the state to resume from
+    let store = RefCell::new(store);
+    // the images shown, and since when
+    let mut shown: Option<(Vec<PathBuf>, Duration)> = None;
+    // subsystems subscribe to what happens in the slideshow, borrowing the above
+    let mut events = EventBus::new();
+    let (next_events, load_events) = (events.publisher(), events.publisher());
+    let (next_metrics, load_metrics) = (metrics.clone(), metrics.clone());
 
     let output = worker.dataflow(|scope| {
         let configuration_stream = input_configuration.to_stream(scope);
@@ -248,13 +1396,144 @@ fn main() -> RahmenResult<()> {
                 .filter(|c| matches!(c, Configuration::Tick))
                 .enter(inner)
                 .concat(&cycle)
-                // obtain next path
-                .map(move |_| fatal_err(provider.next_image()))
+                // obtain next path, and how long to show it
+                .map(move |_| {
+                    let next = provider.next_image();
+                    if let Ok(None) = next {
+                        next_metrics.provider_exhausted();
+                    }
+                    let next = fatal_err(next, &next_events).and_then(|path| {
+                        let duration = provider.duration();
+                        // fill a collage with the images after it, with the size of the screen to
+                        // compose it for
+                        let others = match (&collage, slide_screen_size.get()) {
+                            (Some(collage), Some(screen)) => {
+                                let mut others = vec![];
+                                while others.len() + 1 < collage.count {
+                                    // like for the first image, an error ends the slideshow
+                                    match provider.next_image() {
+                                        Ok(Some(other)) => others.push(other),
+                                        Ok(None) => break,
+                                        Err(e) => return fatal_err(Err(e), &next_events),
+                                    }
+                                }
+                                Some((others, screen))
+                            }
+                            _ => None,
+                        };
+                        // take the next image along if both are portraits, with the height of
+                        // the screen to join them for
+                        let partner = match (&pairing, slide_screen_size.get()) {
+                            (Some(pairing), Some(screen))
+                                if pairing.fits(screen) && pairing.is_portrait(&path) =>
+                            {
+                                match provider.peek(1) {
+                                    Ok(upcoming)
+                                        if upcoming
+                                            .first()
+                                            .map_or(false, |next| pairing.is_portrait(next)) =>
+                                    {
+                                        // like for the first image, an error ends the slideshow
+                                        match provider.next_image() {
+                                            Ok(next) => next.map(|next| (next, screen.1)),
+                                            Err(e) => return fatal_err(Err(e), &next_events),
+                                        }
+                                    }
+                                    _ => None,
+                                }
+                            }
+                            _ => None,
+                        };
+                        Ok((path, partner, others, duration, provider.total()))
+                    });
+                    // a collage shows several images at a time
+                    let slide_images = collage.map_or(1, |collage| collage.count);
+                    // the images of this slide are still loading in the background
+                    let current: Vec<PathBuf> = match &next {
+                        Ok((path, partner, others, _, _)) => std::iter::once(path.clone())
+                            .chain(partner.iter().map(|(partner, _)| partner.clone()))
+                            .chain(others.iter().flat_map(|(others, _)| others.iter().cloned()))
+                            .collect(),
+                        Err(_) => vec![],
+                    };
+                    match provider.peek(prefetch_depth * slide_images) {
+                        Ok(upcoming) => upcoming_prefetcher.prefetch(&current, &upcoming),
+                        Err(e) => warn!("Failed to look ahead: {}", e),
+                    }
+                    next
+                })
                 // Load image
-                .and_then(move |ref path| {
+                .and_then(move |(path, partner, others, duration, total)| {
+                    let loaded = prefetcher.load(&path);
+                    // the images shown besides the first one, for their captions
+                    let mut shown = vec![];
+                    let loaded = match (&pairing, partner) {
+                        (Some(pairing), Some((partner, screen_height))) => {
+                            loaded.map(|img| match prefetcher.load(&partner) {
+                                Ok(right) => {
+                                    info!("Showing {:?} next to it", partner);
+                                    let joined =
+                                        Arc::new(pairing.join(&img, &right, screen_height));
+                                    shown.push((partner, right));
+                                    joined
+                                }
+                                Err(e) => {
+                                    warn!("Failed to load {:?}: {}", partner, e);
+                                    img
+                                }
+                            })
+                        }
+                        _ => loaded,
+                    };
+                    let (loaded, others) = match (&collage, others) {
+                        (Some(collage), Some((others, (width, height)))) => {
+                            let others = others
+                                .into_iter()
+                                .filter_map(|other| match prefetcher.load(&other) {
+                                    Ok(img) => Some((other, img)),
+                                    Err(e) => {
+                                        warn!("Failed to load {:?}: {}", other, e);
+                                        None
+                                    }
+                                })
+                                .collect::<Vec<_>>();
+                            let loaded = loaded.map(|img| {
+                                if others.is_empty() {
+                                    return img;
+                                }
+                                let images = std::iter::once(&*img)
+                                    .chain(others.iter().map(|(_, other)| &**other))
+                                    .collect::<Vec<_>>();
+                                Arc::new(collage.compose(&images, width, height))
+                            });
+                            (loaded, others)
+                        }
+                        _ => (loaded, shown),
+                    };
+                    match &loaded {
+                        Ok(_) => load_events.publish(Event::ImageLoaded(path.clone())),
+                        Err(e) => load_metrics.decode_failed(e),
+                    }
+                    if loaded.is_err() && error_policy.aborts() {
+                        let loaded = loaded.map(|img| Some((path, duration, total, img, others)));
+                        return fatal_err(loaded, &load_events);
+                    }
+                    load_failures = if loaded.is_err() {
+                        load_failures + 1
+                    } else {
+                        0
+                    };
+                    let loaded = match (loaded, loading_placeholder.borrow_mut().as_mut()) {
+                        (Err(e), Some(placeholder)) if load_failures >= placeholder_after => {
+                            warn!("Failed to load {:?}, showing a placeholder: {}", path, e);
+                            load_events.publish(Event::ProviderError(e.to_string()));
+                            Ok(placeholder.frame(&path, &e, placeholder_screen_size.get()))
+                        }
+                        (loaded, _) => loaded,
+                    };
                     suppress_err(
-                        load_image_from_path(path, Some(buffer_max_size))
-                            .map(|img| (path.clone(), Arc::new(img))),
+                        loaded.map(|img| (path, duration, total, img, others)),
+                        &load_events,
                     )
                 })
                 .branch(|_t, d| d.as_ref().err() == Some(&RunControl::Suppressed));
@@ -269,8 +1548,25 @@ fn main() -> RahmenResult<()> {
 
         let mut status_line_stream = img_path_stream
             .ok()
-            .flat_map(move |(p, _img)| status_line_formatter.format(&p).ok())
-            .inspect(|loc| println!("Status line: {}", loc));
+            .flat_map(move |(p, _duration, _total, img, others)| {
+                // an image is shown without its caption, and the captions of a collage are joined
+                let captions = std::iter::once((&p, &img))
+                    .chain(others.iter().map(|(p, img)| (p, img)))
+                    .filter_map(|(p, img)| {
+                        // not retried, as the image was just read
+                        status_line_formatter
+                            .format(p, img)
+                            .map_err(|e| warn!("Failed to read the metadata of {:?}: {}", p, e))
+                            .ok()
+                    })
+                    .collect::<Vec<_>>();
+                if captions.is_empty() {
+                    None
+                } else {
+                    Some(captions.join(&caption_separator))
+                }
+            })
+            .inspect(|loc| info!("Status line: {}", loc));
         if show_time {
             status_line_stream = status_line_stream.unary_notify(
                 Pipeline,
@@ -311,8 +1607,14 @@ fn main() -> RahmenResult<()> {
         let status_line_stream =
             status_line_stream.map(|s| s.split('\n').map(Into::into).collect());
 
-        let text_img_stream =
-            status_line_stream.format_text(&configuration_stream, font_renderer, 2);
+        let text_img_stream = status_line_stream.format_text(
+            &configuration_stream,
+            font_renderer,
+            status_layout,
+            2,
+            marquee,
+            min_font_size,
+        );
 
         let adjusted_configuration_stream = {
             let mut stash: HashMap<_, Vec<_>> = HashMap::new();
@@ -348,15 +1650,16 @@ fn main() -> RahmenResult<()> {
                                                 Some(font_canvas_vstretch);
                                             Configuration::FontCanvasVStretch(font_canvas_vstretch)
                                         }
+                                        // a status line higher than the screen leaves no room
                                         Configuration::ScreenDimensions(width, height) => {
                                             Configuration::ScreenDimensions(
                                                 width,
-                                                height
-                                                    - (current_font_size.unwrap_or(0.)
-                                                        * current_font_canvas_vstretch
-                                                            .unwrap_or(1.0))
-                                                    .ceil()
-                                                        as u32,
+                                                height.saturating_sub(
+                                                    status_layout.reserved_height(
+                                                        current_font_size.unwrap_or(0.),
+                                                        current_font_canvas_vstretch.unwrap_or(1.0),
+                                                    ),
+                                                ),
                                             )
                                         }
                                         configuration => configuration,
@@ -371,13 +1674,53 @@ fn main() -> RahmenResult<()> {
 
         let img_stream = img_path_stream
             .ok()
-            .map(|(_, img)| img)
-            .resize_image(&adjusted_configuration_stream, 1);
+            .map(|(_, _, _, img, _)| img)
+            .resize_image(&adjusted_configuration_stream, 1, letterbox)
+            // make room for the status line above the image
+            .map(move |(key, (x_offset, y_offset), img)| {
+                (key, (x_offset, y_offset + image_y_shift), img)
+            });
 
         let mut size_stash: HashMap<usize, _> = HashMap::new();
         let mut input_buffer: HashMap<_, Vec<(_, _, _)>> = HashMap::new();
 
-        let composed_img_stream = img_stream.concat(&text_img_stream).unary_notify(
+        // overlays stay layers of their own, for the compositor to blend over the photo
+        let img_stream = img_stream.concat(&text_img_stream);
+        let img_stream = match weather_renderer {
+            Some((renderer, layout)) => {
+                img_stream.concat(&input_weather.to_stream(scope).format_text(
+                    &configuration_stream,
+                    renderer,
+                    layout,
+                    3,
+                    None,
+                    None,
+                ))
+            }
+            None => img_stream,
+        };
+        let adjusted_img_stream = match qr_code {
+            Some((url, strip_prefix, corner, margin, module_size)) => {
+                let qr_stream = img_path_stream
+                    .ok()
+                    .flat_map(move |(path, _, _, _, _)| {
+                        let link = rahmen::qr::link(&url, &path, strip_prefix.as_deref());
+                        match rahmen::qr::render(&link, module_size) {
+                            Ok(img) => Some(Arc::new(DynamicImage::ImageRgba8(img))),
+                            Err(err) => {
+                                warn!("{}", err);
+                                None
+                            }
+                        }
+                    })
+                    .place_image(&configuration_stream, corner, margin, 4);
+                img_stream.concat(&qr_stream)
+            }
+            None => img_stream,
+        }
+        .adjust_image(&configuration_stream, filter);
+
+        let composed_img_stream = adjusted_img_stream.unary_notify(
             Pipeline,
             "Infer blanking",
             None,
@@ -392,7 +1735,9 @@ fn main() -> RahmenResult<()> {
                     not.notify_at(time.retain());
                 });
                 not.for_each(|time, _count, _not| {
-                    if let Some(updates) = input_buffer.remove(&time.time()) {
+                    if let Some(mut updates) = input_buffer.remove(&time.time()) {
+                        // draw the status line last, as it may be on top of the image
+                        updates.sort_by_key(|(key, _, _)| *key);
                         output
                             .session(&time)
                             .give_iterator(updates.into_iter().flat_map(
@@ -414,9 +1759,23 @@ fn main() -> RahmenResult<()> {
             },
         );
 
+        let slide_stream = img_path_stream
+            .ok()
+            .map(|(path, duration, total, _, others)| {
+                let paths = std::iter::once(path)
+                    .chain(others.into_iter().map(|(other, _)| other))
+                    .collect();
+                Render::Slide(paths, duration, total)
+            });
+
+        let status_text_stream =
+            status_line_stream.map(|lines| Render::Text(STATUS_LINE_KEY, lines));
+
         err_stream
             .map(Err)
             .concat(&composed_img_stream.map(Ok))
+            .concat(&slide_stream.map(Ok))
+            .concat(&status_text_stream.map(Ok))
             .probe_with(&mut probe)
             .capture()
     });
@@ -425,58 +1784,516 @@ fn main() -> RahmenResult<()> {
     let mut dimensions = None;
 
     input_configuration.send(Configuration::FontSize(font_size_f));
-    // enlarge font canvas vertically by this factor (default given here: 1.4)
-    input_configuration.send(Configuration::FontCanvasVStretch(1.4));
+    // enlarge font canvas vertically by this factor (default given here: 1.4, plus further lines)
+    input_configuration.send(Configuration::FontCanvasVStretch(font_canvas_vstretch));
+    let proof = settings.proof;
+    input_configuration.send(Configuration::Proof(proof));
 
-    let mut next_image_at = start_time.elapsed();
+    // inputs send commands to the main loop
+    let (command_sender, commands) = mpsc::channel();
+    // what is shown, for control interfaces
+    let control_status: SharedStatus = Arc::new(Mutex::new(Status::new(delay)));
+    let health = settings.health.clone().unwrap_or_default();
+    let max_age = Duration::from_secs(health.max_age.unwrap_or(60));
+    let heartbeat = health.heartbeat.map(PathBuf::from);
+    let control = settings.control.clone().unwrap_or_default();
+    if let Some(address) = &control.http {
+        rahmen::control_http::serve(
+            address,
+            command_sender.clone(),
+            Arc::clone(&control_status),
+            max_age,
+        )?;
+    }
+    if control.socket.unwrap_or(false) || control.socket_path.is_some() {
+        let path = control
+            .socket_path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(rahmen::control_socket::default_path);
+        rahmen::control_socket::serve(&path, command_sender.clone(), Arc::clone(&control_status))?;
+    }
+    // displays drawing to the console get no key events, so the keyboards can be read directly
+    if control
+        .keyboard
+        .unwrap_or_else(|| control.keyboard_devices.is_some())
+    {
+        let devices = match &control.keyboard_devices {
+            Some(devices) => devices.iter().map(PathBuf::from).collect(),
+            None => rahmen::input_evdev::keyboards(),
+        };
+        rahmen::input_evdev::start(
+            devices,
+            rahmen::input_evdev::keyboard_keymap(),
+            command_sender.clone(),
+        );
+    }
+    let mut dbus = match control.dbus {
+        Some(bus) => Some(rahmen::control_dbus::serve(
+            bus,
+            command_sender.clone(),
+            Arc::clone(&control_status),
+        )?),
+        None => None,
+    };
+    let mut mqtt = match settings.mqtt.clone() {
+        Some(mqtt) => Some(Mqtt::connect(
+            MqttConfig::new(mqtt)?,
+            command_sender.clone(),
+        )?),
+        None => None,
+    };
+    let favorite = settings.favorite.clone().unwrap_or_default();
+    let favorites = Favorites {
+        rating: favorite.rating.unwrap_or(5).max(1).min(5),
+        label: favorite.label,
+        sidecar: favorite.sidecar.unwrap_or(true),
+    };
+    let rotations = Rotations {
+        sidecar: settings
+            .rotate
+            .as_ref()
+            .and_then(|rotate| rotate.sidecar)
+            .unwrap_or(true),
+    };
+    if let Some(remote) = settings.remote.clone() {
+        let mut keymap = rahmen::input_ir::default_keymap();
+        for binding in remote.keys.unwrap_or_default() {
+            keymap.insert(rahmen::input_evdev::key_code(&binding.key)?, binding.action);
+        }
+        match remote.lircd {
+            Some(socket) => {
+                rahmen::input_ir::start_lircd(Path::new(&socket), keymap, command_sender.clone())?
+            }
+            None => rahmen::input_evdev::start(
+                match remote.devices {
+                    Some(devices) => devices.iter().map(PathBuf::from).collect(),
+                    None => rahmen::input_evdev::remotes(),
+                },
+                keymap,
+                command_sender.clone(),
+            ),
+        }
+    }
+    if let Some(buttons) = settings.gpio.clone() {
+        rahmen::input_gpio::start(
+            buttons
+                .into_iter()
+                .map(|button| Button {
+                    pin: button.pin,
+                    action: button.action,
+                    long_action: button.long_action,
+                    active_low: button.active_low.unwrap_or(true),
+                    debounce: Duration::from_millis(button.debounce.unwrap_or(50)),
+                    long_press: Duration::from_millis(button.long_press.unwrap_or(1000)),
+                })
+                .collect(),
+            command_sender.clone(),
+        )?;
+    }
+    // blank the screen when no one is around, if configured
+    let motion_timeout = match settings.motion.clone() {
+        Some(motion) => {
+            rahmen::input_motion::start(
+                MotionSensor {
+                    pin: motion.pin,
+                    active_low: motion.active_low.unwrap_or(false),
+                },
+                command_sender.clone(),
+            )?;
+            Some(Duration::from_secs_f64(
+                motion.timeout.unwrap_or(10.).max(0.) * 60.,
+            ))
+        }
+        None => None,
+    };
+    // pause or blank the screen while nobody is home, if configured
+    let away = match settings.presence.clone() {
+        Some(presence) => {
+            rahmen::presence::start(
+                rahmen::presence::devices(&presence)?,
+                Duration::from_secs(presence.interval.unwrap_or(60)),
+                Duration::from_secs_f64(presence.grace.unwrap_or(10.).max(0.) * 60.),
+                command_sender.clone(),
+            );
+            Some(presence.away.unwrap_or(Away::Blank))
+        }
+        None => None,
+    };
+    // switch the screen off while it is blank, if configured
+    let power = match settings.power.clone() {
+        Some(power) => Some(PowerSwitch::new(match power.method {
+            PowerMethod::Display => Power::Display,
+            PowerMethod::Vcgencmd => Power::Vcgencmd,
+            PowerMethod::Cec => Power::Cec(power.cec_address.unwrap_or(0)),
+            PowerMethod::Command => match (power.on_command, power.off_command) {
+                (Some(on), Some(off)) => Power::Command(on, off),
+                _ => {
+                    return Err(RahmenError::ConfigError(Arc::new(
+                        config::ConfigError::Message(
+                            "the command power method needs an on_command and an off_command"
+                                .into(),
+                        ),
+                    )))
+                }
+            },
+        })),
+        None => None,
+    };
+    // whether the screen was switched off
+    let mut powered_off = false;
+    rahmen::signals::start(command_sender.clone())?;
 
-    let display_fn = |display: &mut dyn Display| {
+    let mut player = Player::new(
+        active_block
+            .as_ref()
+            .and_then(|block| block.interval)
+            .unwrap_or(delay),
+        settings.history.unwrap_or(50),
+        start_time.elapsed(),
+        Arc::clone(&control_status),
+    );
+    // go back to the slides of the previous run, too
+    player.restore_history(std::mem::take(&mut resumed.history));
+    let mut controls = Controls {
+        proof,
+        clean_toggled: false,
+        brightness_override: None,
+        screen_off: false,
+        away,
+        someone_home: true,
+        motion_timeout,
+        motion_seen_at: Duration::default(),
+        reload_due: false,
+        favorites,
+        rotations,
+        blacklist,
+        requeue: requeue.clone(),
+        status: Arc::clone(&control_status),
+    };
+    let mut brightness = None;
+    // clean mode can be toggled by command, and is forced during quiet hours
+    let mut clean = false;
+    let mut status_text: Option<(usize, Vec<String>)> = None;
+    let mut assets_checked_at = start_time.elapsed();
+    // the current image if it is still being written, with how much of it was shown
+    let mut growing: Option<(PathBuf, u64)> = None;
+    let mut last_time_of_day = chrono::Local::now().time();
+    let mut maintenance_due = false;
+    // the crossfade to the current photo, with its start
+    let mut fade: Option<(Duration, Crossfade)> = None;
+    // the photo, the status line and widgets, by key
+    let mut compositor = Compositor::default();
+    for (key, opacity) in layer_opacity {
+        compositor.set_opacity(key, opacity);
+    }
+    // move the frame against burn-in, if configured
+    let pixel_shift = settings.pixel_shift.clone().map(|shift| PixelShift {
+        radius: shift.radius.unwrap_or(4),
+        interval: Duration::from_secs(shift.interval.unwrap_or(60)),
+    });
+    // the position of the current slide in the slideshow
+    let mut slide_position: (usize, Option<usize>) = (0, None);
+    let mut counter_stale = false;
+
+    let shown_metrics = metrics.clone();
+    events.subscribe(move |event| {
+        if let Event::SlideShown(..) = event {
+            shown_metrics.image_shown();
+        }
+    });
+    let mut metrics_written_at = start_time.elapsed();
+    let mut notifier = Notifier::from_env();
+    let error_status = Arc::clone(&control_status);
+    events.subscribe(move |event| {
+        if let Event::ProviderError(message) = event {
+            error_status.lock().unwrap().error(message.clone());
+        }
+    });
+    let mut heartbeat_written_at = start_time.elapsed();
+
+    // remember how long each image was shown
+    if count_impressions {
+        let (store, shown) = (&store, &mut shown);
+        let requeue = requeue.clone();
+        events.subscribe(move |event| {
+            if let Event::SlideShown(paths, _) = event {
+                let now = start_time.elapsed();
+                if let Some((previous, since)) = shown.take() {
+                    for previous in &previous {
+                        if let Err(err) = rahmen::impressions::record(
+                            &mut **store.borrow_mut(),
+                            previous,
+                            now - since,
+                        ) {
+                            warn!("Saving impressions failed: {}", err);
+                        }
+                    }
+                }
+                // images shown again, such as going back, were counted the first time
+                *shown = if requeue.take_requeued(paths) {
+                    None
+                } else {
+                    Some((paths.clone(), now))
+                };
+            }
+        });
+    }
+
+    let frame_events = events.publisher();
+    let mut frame = |display: &mut dyn Display| {
         let now = start_time.elapsed();
+        notifier.watchdog(now);
+        control_status.lock().unwrap().alive_at = Instant::now();
+        if let Some(path) = &heartbeat {
+            if now >= heartbeat_written_at + HEARTBEAT_INTERVAL {
+                heartbeat_written_at = now;
+                let health = control_status.lock().unwrap().health_json(max_age);
+                if let Err(err) = write_heartbeat(path, &health) {
+                    warn!("Writing the heartbeat failed: {}", err);
+                }
+            }
+        }
+
+        while let Ok(command) = commands.try_recv() {
+            frame_events.publish(Event::InputAction(command.clone()));
+            for update in controls.handle(command, now, &mut player)? {
+                input_configuration.send(update);
+            }
+        }
+
+        let time_of_day = chrono::Local::now().time();
+        if let Some(maintenance_time) = &maintenance_time {
+            if maintenance_time.passed(last_time_of_day, time_of_day) {
+                info!("Stopping for maintenance");
+                maintenance_due = true;
+                return Err(RahmenError::Terminate);
+            }
+        }
+        last_time_of_day = time_of_day;
+
+        // follow the schedule as its blocks start and end
+        let block = schedule.active(chrono::Local::now().naive_local());
+        if block != active_block.as_ref() {
+            let playlist = block.and_then(|block| block.playlist.clone());
+            if playlist
+                != active_block
+                    .as_ref()
+                    .and_then(|block| block.playlist.clone())
+            {
+                playlist_selection.select(playlist);
+                // show the playlist right away
+                for update in controls.apply(player.handle(Command::Next, now)) {
+                    input_configuration.send(update);
+                }
+            }
+            let interval = block.and_then(|block| block.interval);
+            if interval != active_block.as_ref().and_then(|block| block.interval) {
+                player.handle(Command::SetInterval(interval.unwrap_or(delay)), now);
+            }
+            // the screen toggled by command follows the schedule again
+            controls.screen_off = false;
+            active_block = block.cloned();
+        }
+        // a command toggles the screen against the schedule, and without motion or while nobody
+        // is home it is blank
+        let blank = controls.screen_off
+            != active_block
+                .as_ref()
+                .map_or(false, |block| block.screen_off)
+            || controls.absent(now);
+        // a blank screen shows no images, so none are decoded or counted as seen until it is on
+        // again, when the next one follows right away if its time has come
+        if !blank && !controls.held() {
+            for update in controls.apply(player.poll(now)) {
+                input_configuration.send(update);
+            }
+        }
+
+        let quiet = quiet_hours.iter().any(|range| range.contains(time_of_day));
+        if clean != (controls.clean_toggled || quiet || blank) {
+            clean = controls.clean_toggled || quiet || blank;
+            info!("Clean mode: {}", clean);
+            input_configuration.send(Configuration::Clean(clean));
+            counter_stale = true;
+            if let Some((key, lines)) = &status_text {
+                let lines: &[String] = if clean { &[] } else { lines };
+                if let Err(err) = display.text(*key, lines) {
+                    warn!("Text failed: {}", err);
+                }
+            }
+        }
+        if let Some(power) = &power {
+            if powered_off != blank {
+                powered_off = blank;
+                if let Err(err) = power.set(!blank, display) {
+                    warn!("Switching the screen failed: {}", err);
+                }
+            }
+        }
+
+        if let Some(path) = &metrics_textfile {
+            if now >= metrics_written_at + METRICS_INTERVAL {
+                metrics_written_at = now;
+                if let Err(err) = metrics.write_textfile(path) {
+                    warn!("Writing metrics failed: {}", err);
+                }
+            }
+        }
+
+        if now >= assets_checked_at + Duration::from_secs(1) {
+            assets_checked_at = now;
+            if !asset_watcher.changed().is_empty() {
+                input_configuration.send(Configuration::ReloadAssets);
+                // the placeholder is loaded outside of the dataflow, which only reloads the rest
+                if let Some(placeholder) = placeholder.borrow_mut().as_mut() {
+                    placeholder.reload();
+                }
+            }
+            // progressive JPEGs are shown again scan by scan, others as they grow
+            if let Some((path, shown)) = &growing {
+                if arrived(path).map_or(false, |arrived| arrived != *shown) {
+                    info!("Reloading {:?}", path);
+                    requeue.push(path.clone());
+                    input_configuration.send(Configuration::Tick);
+                    growing = None;
+                }
+            }
+        }
 
-        if next_image_at < now {
-            input_configuration.send(Configuration::Tick);
-            next_image_at = now + delay;
+        while let Ok(lux) = light_updates.try_recv() {
+            if let Some(ambient) = &mut ambient {
+                ambient.update(lux);
+            }
+        }
+        // once adjusted, the brightness is kept up to date, so it is restored when the screen is on
+        if brightness.is_some()
+            || brightness_schedule.is_some()
+            || controls.brightness_override.is_some()
+            || ambient.is_some()
+            || blank
+        {
+            let mut level = brightness_schedule
+                .as_ref()
+                .map_or_else(Level::default, |schedule| schedule.level_at(time_of_day));
+            if let Some(ambient) = &ambient {
+                level.brightness *= ambient.brightness();
+            }
+            if let Some(percent) = controls.brightness_override {
+                level.brightness = f32::from(percent) / 100.;
+            }
+            if blank {
+                level.brightness = 0.;
+            }
+            if brightness != Some(level) {
+                brightness = Some(level);
+                match &backlight {
+                    Some(backlight) => {
+                        if let Err(err) = backlight.set(level.brightness) {
+                            warn!("Setting the backlight failed: {}", err);
+                        }
+                        // a blank screen stays black, even where the backlight doesn't go dark
+                        input_configuration.send(Configuration::Brightness(Level {
+                            brightness: if blank { 0. } else { 1. },
+                            ..level
+                        }));
+                    }
+                    None => input_configuration.send(Configuration::Brightness(level)),
+                }
+            }
         }
 
+        // the current image comes again, scaled to the new size, and replaces the old one at once
+        let mut rescaled = false;
         if Some(display.dimensions()) != dimensions {
+            rescaled = dimensions.is_some();
+            if rescaled && fade.take().is_some() {
+                player.transition_finished();
+                for (key, opacity) in &slide_layer_opacity {
+                    compositor.set_opacity(*key, *opacity);
+                }
+            }
             dimensions = Some(display.dimensions());
+            compositor.resize(display.dimensions());
+            counter_stale = true;
+            screen_size.set(display.dimensions().0, display.dimensions().1);
             input_configuration.send(Configuration::ScreenDimensions(
                 display.dimensions().0,
                 display.dimensions().1,
             ));
         }
+        while let Ok(lines) = weather_updates.try_recv() {
+            input_weather.send(lines);
+        }
         input_configuration.advance_to(now);
+        input_weather.advance_to(now);
         while probe.less_than(&now) {
             worker.step();
         }
         let mut has_update = false;
+        // the new photo, if it is to fade in, and the transition given for it in a file list
+        let mut incoming = None;
+        let mut slide_transition = None;
+        // whether the image is a new one, rather than the same one adjusted again
+        let mut new_slide = false;
         let result = match output.try_iter().all(|result| match result {
             // Continue processing on progress messages
-            Event::Progress(_) => true,
+            capture::Event::Progress(_) => true,
             // Handle data messages by rending an image and determining whether to terminate
-            Event::Messages(_, r) => {
+            capture::Event::Messages(_, r) => {
                 let mut terminate = false;
                 for result in r {
                     match result {
-                        Ok(Render::Image(key, (x_offset, y_offset), ref img)) => {
-                            has_update = true;
-                            display
-                                .render(key, x_offset, y_offset, img.as_ref())
-                                .err()
-                                .map(|err| {
-                                    println!("Render failed: {}", err);
-                                    terminate = true;
-                                });
+                        // the slide may come after its image, so the transition is decided later
+                        Ok(Render::Image(1, (x_offset, y_offset), img))
+                            if (transition.is_some() || !list_transitions.is_empty())
+                                && !rescaled =>
+                        {
+                            incoming = Some((x_offset, y_offset, img));
                         }
-                        Ok(Render::Blank(key, x_offset, y_offset, x_size, y_size)) => {
+                        Ok(Render::Image(key, (x_offset, y_offset), img)) => {
+                            compositor.set(key, x_offset, y_offset, img);
+                        }
+                        // replacing the image of a layer uncovers what was below the old one
+                        Ok(Render::Blank(..)) => {}
+                        Ok(Render::Text(key, lines)) => {
                             has_update = true;
-                            display
-                                .blank(key, x_offset, y_offset, x_size, y_size)
-                                .err()
-                                .map(|err| {
-                                    println!("Blank failed: {}", err);
-                                    terminate = true;
-                                });
+                            let shown: &[String] = if clean { &[] } else { &lines };
+                            display.text(key, shown).err().map(|err| {
+                                warn!("Text failed: {}", err);
+                                terminate = true;
+                            });
+                            if key == STATUS_LINE_KEY {
+                                control_status.lock().unwrap().status_line = lines.clone();
+                            }
+                            status_text = Some((key, lines));
+                        }
+                        Ok(Render::Slide(paths, duration, total)) => {
+                            // a slide reloaded while it grew keeps its position
+                            if player.slide_shown(&paths, duration, now) {
+                                slide_position.0 += 1;
+                                // the slide is formed again around its first image
+                                if let (true, Some(position)) = (resume, positions.of(&paths[0])) {
+                                    resumed.position = position;
+                                    resumed.image = Some(paths[0].clone());
+                                    resumed.history = player.history().map(<[_]>::to_vec).collect();
+                                    if let Err(err) = resumed.save(&mut **store.borrow_mut()) {
+                                        warn!("Saving state failed: {}", err);
+                                    }
+                                }
+                            }
+                            slide_position.1 = total;
+                            counter_stale = true;
+                            new_slide = true;
+                            slide_transition = list_transitions.of(&paths[0]);
+                            let path = paths[0].clone();
+                            notifier.slide_shown(&path);
+                            frame_events.publish(Event::SlideShown(paths, duration));
+                            growing = if is_truncated(&path) {
+                                arrived(&path).map(|arrived| (path, arrived))
+                            } else {
+                                None
+                            };
                         }
                         Err(RunControl::Terminate) => terminate = true,
                         _ => {}
@@ -488,37 +2305,355 @@ fn main() -> RahmenResult<()> {
             true => Ok(()),
             false => Err(RahmenError::Terminate),
         };
+        if let Some(mqtt) = &mut mqtt {
+            mqtt.publish_state(&control_status.lock().unwrap());
+        }
+        if let Some(dbus) = &mut dbus {
+            dbus.publish_state(&control_status.lock().unwrap());
+        }
+        if let (Some(progress), Some(dimensions)) = (&mut progress, dimensions) {
+            progress.show_bar(&mut compositor, dimensions, player.progress(now), clean);
+            if counter_stale {
+                counter_stale = false;
+                let (index, total) = slide_position;
+                if let Err(err) =
+                    progress.show_counter(&mut compositor, dimensions, index, total, clean)
+                {
+                    warn!("Counter failed: {}", err);
+                }
+            }
+        }
+        // a transition given for the image in a file list overrides the configured one
+        if let (Some((x_offset, y_offset, img)), false) = (&incoming, new_slide) {
+            // the same image at another brightness or proof takes over as it is
+            match &mut fade {
+                Some((_, crossfade)) => crossfade.retarget((*x_offset, *y_offset, img.as_ref())),
+                None => compositor.set(1, *x_offset, *y_offset, Arc::clone(img)),
+            }
+        } else if let Some((x_offset, y_offset, img)) = incoming {
+            let crossfade = match slide_transition {
+                Some(given) => given.crossfade(transition.as_ref()),
+                None => transition.clone(),
+            };
+            match crossfade {
+                Some((duration, easing)) => {
+                    player.transition_started();
+                    fade = Some((
+                        now,
+                        Crossfade::new(
+                            compositor.layer(1).map(|(x, y, img)| (x, y, img.as_ref())),
+                            (x_offset, y_offset, img.as_ref()),
+                            duration,
+                            easing,
+                        ),
+                    ));
+                }
+                None => {
+                    if fade.take().is_some() {
+                        player.transition_finished();
+                        for (key, opacity) in &slide_layer_opacity {
+                            compositor.set_opacity(*key, *opacity);
+                        }
+                    }
+                    compositor.set(1, x_offset, y_offset, img);
+                }
+            }
+        }
+        if let Some((start, crossfade)) = &fade {
+            let (x_offset, y_offset, frame) = crossfade.frame(now - *start);
+            compositor.set(1, x_offset, y_offset, Arc::new(frame));
+            let progress = crossfade.progress(now - *start);
+            for (key, opacity) in &slide_layer_opacity {
+                compositor.set_opacity(*key, opacity * progress);
+            }
+            if crossfade.is_finished(now - *start) {
+                fade = None;
+                player.transition_finished();
+            }
+        }
+        if let Some(shift) = &pixel_shift {
+            compositor.set_shift(shift.offset(now));
+        }
+        if let Some((x_offset, y_offset, frame)) = compositor.compose() {
+            has_update = true;
+            if let Err(err) = display.render(1, x_offset, y_offset, &DynamicImage::ImageRgb8(frame))
+            {
+                warn!("Render failed: {}", err);
+                control_status
+                    .lock()
+                    .unwrap()
+                    .display_error(err.to_string());
+            }
+        }
         if result.is_ok() && has_update {
-            display.update()
+            let updated = display.update();
+            match &updated {
+                Ok(()) => control_status.lock().unwrap().displayed(),
+                Err(err) => control_status
+                    .lock()
+                    .unwrap()
+                    .display_error(err.to_string()),
+            }
+            updated
         } else {
             result
         }
     };
+    // however a frame ends, its events are dispatched, so subscribers see those of failed frames
+    let display_fn = |display: &mut dyn Display| {
+        let result = frame(display);
+        events.dispatch();
+        result
+    };
 
     match matches.value_of("display").expect("Display missing") {
+        #[cfg(feature = "framebuffer")]
         "framebuffer" => {
             let path_to_device = matches
                 .value_of("output")
                 .expect("Framebuffer output missing");
-            let framebuffer = framebuffer::Framebuffer::new(path_to_device).unwrap();
-            let _ = framebuffer::Framebuffer::set_kd_mode(framebuffer::KdMode::Graphics)
-                .map_err(|_e| println!("Failed to set graphics mode."));
-            ctrlc::set_handler(|| {
-                let _ = framebuffer::Framebuffer::set_kd_mode(framebuffer::KdMode::Text)
-                    .map_err(|_e| println!("Failed to set graphics mode."));
-                std::process::exit(0);
-            })
-            .unwrap();
-            FramebufferDisplay::new(framebuffer).main_loop(display_fn);
-            let _ = framebuffer::Framebuffer::set_kd_mode(framebuffer::KdMode::Text)
-                .map_err(|_e| println!("Failed to set graphics mode."));
+            let framebuffer = settings.framebuffer.unwrap_or_default();
+            let rotation = Rotation::from_degrees(framebuffer.rotate.unwrap_or(0))?;
+            // the console is restored when the display is dropped
+            FramebufferDisplay::open(
+                path_to_device,
+                rotation,
+                framebuffer.restore.unwrap_or(true),
+            )?
+            .main_loop(display_fn);
+        }
+        #[cfg(feature = "drm")]
+        "drm" => {
+            let path_to_device = matches.value_of("output").unwrap_or("/dev/dri/card0");
+            let mode = settings.drm.and_then(|drm| drm.mode);
+            DrmDisplay::new(path_to_device, mode.as_deref())?.main_loop(display_fn);
+        }
+        #[cfg(feature = "eink")]
+        "eink" => {
+            let path_to_device = matches
+                .value_of("output")
+                .expect("Framebuffer output missing");
+            let eink = settings.eink.unwrap_or_default();
+            EinkDisplay::open(
+                path_to_device,
+                eink.gray_levels.unwrap_or(16),
+                eink.full_refresh_every.unwrap_or(10),
+            )?
+            .main_loop(display_fn);
         }
         #[cfg(feature = "fltk")]
-        "fltk" => FltkDisplay::new().main_loop(display_fn),
+        "fltk" => {
+            let fltk = settings.fltk.unwrap_or_default();
+            FltkDisplay::new(
+                command_sender,
+                WindowOptions {
+                    fullscreen: fltk.fullscreen.unwrap_or(false),
+                    borderless: fltk.borderless.unwrap_or(false),
+                    always_on_top: fltk.always_on_top.unwrap_or(false),
+                    monitor: fltk.monitor,
+                    hide_cursor: fltk.hide_cursor.unwrap_or(false),
+                },
+            )
+            .main_loop(display_fn)
+        }
+        #[cfg(feature = "http")]
+        "http" => {
+            let http = settings.http.unwrap_or_default();
+            let resolution = parse_resolution(http.resolution.as_deref().unwrap_or("1920x1080"))?;
+            HttpDisplay::new(
+                http.listen.as_deref().unwrap_or("0.0.0.0:8080"),
+                resolution,
+                http.quality.unwrap_or(85),
+                http.refresh.unwrap_or(10),
+                http.max_clients.unwrap_or(8),
+            )?
+            .main_loop(display_fn);
+        }
+        "multi" => {
+            let multi = settings.multi.unwrap_or_default();
+            #[cfg(feature = "eink")]
+            let eink = settings.eink.unwrap_or_default();
+            #[cfg(feature = "http")]
+            let http = settings.http.unwrap_or_default();
+            #[cfg(feature = "framebuffer")]
+            let framebuffer = settings.framebuffer.unwrap_or_default();
+            #[cfg(feature = "framebuffer")]
+            let rotation = Rotation::from_degrees(framebuffer.rotate.unwrap_or(0))?;
+            #[cfg(feature = "drm")]
+            let drm_mode = settings.drm.and_then(|drm| drm.mode);
+            #[cfg(feature = "terminal")]
+            let protocol = settings
+                .terminal
+                .and_then(|terminal| terminal.protocol)
+                .unwrap_or_else(GraphicsProtocol::detect);
+            #[cfg(feature = "framebuffer")]
+            let mut uses_framebuffer = false;
+            // without any display that can be combined, every output is rejected
+            #[cfg_attr(
+                not(any(
+                    feature = "framebuffer",
+                    feature = "drm",
+                    feature = "http",
+                    feature = "terminal"
+                )),
+                allow(unused_mut)
+            )]
+            let mut outputs: Vec<Box<dyn Display>> = vec![];
+            #[cfg_attr(
+                not(any(
+                    feature = "framebuffer",
+                    feature = "drm",
+                    feature = "http",
+                    feature = "terminal"
+                )),
+                allow(unreachable_code, unused_variables)
+            )]
+            for output in multi.output {
+                let device = output.device.as_deref();
+                let display: Box<dyn Display> = match output.display.as_str() {
+                    #[cfg(feature = "framebuffer")]
+                    "framebuffer" => {
+                        uses_framebuffer = true;
+                        Box::new(FramebufferDisplay::open(
+                            device.unwrap_or("/dev/fb0"),
+                            rotation,
+                            framebuffer.restore.unwrap_or(true),
+                        )?)
+                    }
+                    #[cfg(feature = "eink")]
+                    "eink" => {
+                        uses_framebuffer = true;
+                        Box::new(EinkDisplay::open(
+                            device.unwrap_or("/dev/fb0"),
+                            eink.gray_levels.unwrap_or(16),
+                            eink.full_refresh_every.unwrap_or(10),
+                        )?)
+                    }
+                    #[cfg(feature = "drm")]
+                    "drm" => Box::new(DrmDisplay::new(
+                        device.unwrap_or("/dev/dri/card0"),
+                        drm_mode.as_deref(),
+                    )?),
+                    #[cfg(feature = "http")]
+                    "http" => Box::new(HttpDisplay::new(
+                        device.or(http.listen.as_deref()).unwrap_or("0.0.0.0:8080"),
+                        parse_resolution(http.resolution.as_deref().unwrap_or("1920x1080"))?,
+                        http.quality.unwrap_or(85),
+                        http.refresh.unwrap_or(10),
+                        http.max_clients.unwrap_or(8),
+                    )?),
+                    #[cfg(feature = "terminal")]
+                    "terminal" => Box::new(TerminalDisplay::new(protocol)),
+                    other => {
+                        return Err(RahmenError::DisplayError(format!(
+                            "{} can't be combined with other displays",
+                            other
+                        )))
+                    }
+                };
+                outputs.push(display);
+            }
+            #[cfg(feature = "framebuffer")]
+            if uses_framebuffer {
+                let _ = framebuffer::Framebuffer::set_kd_mode(framebuffer::KdMode::Graphics)
+                    .map_err(|_e| warn!("Failed to set graphics mode."));
+            }
+            MultiDisplay::new(outputs, multi.mode.unwrap_or(MultiMode::Mirror), 1)?
+                .main_loop(display_fn);
+            #[cfg(feature = "framebuffer")]
+            if uses_framebuffer {
+                let _ = framebuffer::Framebuffer::set_kd_mode(framebuffer::KdMode::Text)
+                    .map_err(|_e| warn!("Failed to restore text mode."));
+            }
+        }
+        display @ "null" | display @ "png" => {
+            let png = settings.png.unwrap_or_default();
+            let resolution = parse_resolution(png.resolution.as_deref().unwrap_or("1920x1080"))?;
+            // the null display keeps the frames in memory only
+            let dir = match display {
+                "png" => matches
+                    .value_of("output")
+                    .or(png.dir.as_deref())
+                    .map(PathBuf::from),
+                _ => None,
+            };
+            PngDisplay::new(resolution, dir, png.frames)?.main_loop(display_fn);
+        }
+        #[cfg(feature = "sdl2")]
+        "sdl2" => {
+            let resolution = settings.sdl2.and_then(|sdl2| sdl2.resolution);
+            Sdl2Display::new(command_sender, resolution.as_deref())?.main_loop(display_fn);
+        }
+        #[cfg(feature = "terminal")]
+        "terminal" => {
+            let protocol = settings
+                .terminal
+                .and_then(|terminal| terminal.protocol)
+                .unwrap_or_else(GraphicsProtocol::detect);
+            TerminalDisplay::new(protocol).main_loop(display_fn);
+        }
+        #[cfg(feature = "x11")]
+        "x11" => {
+            let override_redirect = settings
+                .x11
+                .and_then(|x11| x11.override_redirect)
+                .unwrap_or(false);
+            X11Display::new(override_redirect)?.main_loop(display_fn);
+        }
         _ => panic!("Unknown display"),
     };
 
     input_configuration.close();
     while worker.step() {}
-    Ok(())
+    // release the subscribers
+    drop(events);
+
+    // the last counts, such as the input running out, outlive the process
+    if let Some(path) = &metrics_textfile {
+        metrics.write_textfile(path)?;
+    }
+
+    let mut store = store.into_inner();
+    if let Some((paths, since)) = shown {
+        for path in &paths {
+            rahmen::impressions::record(&mut *store, path, start_time.elapsed() - since)?;
+        }
+    }
+    // restarting skips dropping the store
+    store.flush()?;
+
+    // a failure of the maintenance ends Rahmen with an error, unless it restarts in place
+    let mut maintenance_result = Ok(());
+    if maintenance_due {
+        if let Some((dir, max_bytes)) = transcode_cache_limit {
+            info!("Compacting {:?}", dir);
+            if let Err(err) = rahmen::transcode::compact(&dir, max_bytes) {
+                error!("Compacting failed: {}", err);
+                maintenance_result = Err(err);
+            }
+        }
+        if let Err(err) = rahmen::logging::rotate(logs_kept) {
+            error!("Rotating the log failed: {}", err);
+            maintenance_result = Err(err.into());
+        }
+    }
+    if controls.reload_due || (maintenance_due && !exit_for_maintenance) {
+        // start over, which also reads the configuration and scans the inputs again
+        info!("Restarting");
+        // restore what is restored on exit, as the process is replaced
+        drop(backlight);
+        let exe = std::env::current_exe()?;
+        let mut restart = std::process::Command::new(exe);
+        restart.args(std::env::args_os().skip(1));
+        #[cfg(unix)]
+        return Err(restart.exec().into());
+        // without exec, start the new process before this one exits
+        #[cfg(not(unix))]
+        {
+            restart.spawn()?;
+            return Ok(());
+        }
+    }
+    notifier.stopping();
+    maintenance_result
 }
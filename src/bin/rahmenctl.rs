@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+use clap::{App, Arg};
+
+use rahmen::errors::{RahmenError, RahmenResult};
+
+fn main() -> RahmenResult<()> {
+    // the runtime directory is set for users logged in, but usually not for system services run
+    // as root, so the two find the socket in different places
+    let socket_help = format!(
+        "The control socket, by default rahmen.sock in $XDG_RUNTIME_DIR, or /tmp/rahmen.sock if that is not set, as for system services [currently {}]",
+        rahmen::control_socket::default_path().display()
+    );
+    let matches = App::new("Rahmen control")
+        .about("Control a running Rahmen through its control socket")
+        .arg(
+            Arg::new("socket")
+                .long("socket")
+                .short('s')
+                .about(&*socket_help)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("command")
+                .about("next, previous, pause, resume, toggle, favorite, blacklist, rotate [left], motion, show <path>, interval <seconds>, brightness <percent> or status")
+                .required(true)
+                .multiple(true),
+        )
+        .get_matches();
+
+    let socket = matches
+        .value_of("socket")
+        .map(PathBuf::from)
+        .unwrap_or_else(rahmen::control_socket::default_path);
+    let mut words: Vec<String> = matches
+        .values_of("command")
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+    // Rahmen may run in another directory, so relative paths are resolved here
+    if words.len() > 1 && words[0] == "show" {
+        let path = words.split_off(1).join(" ");
+        words.push(match std::fs::canonicalize(&path) {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => path,
+        });
+    }
+    let line = words.join(" ");
+    // fail early instead of waiting for the response to a request that cannot work
+    if let Err(message) = rahmen::control_socket::parse(&line) {
+        eprintln!("{}", message);
+        std::process::exit(2);
+    }
+    let response = rahmen::control_socket::request(&socket, &line).map_err(|e| {
+        eprintln!("Cannot reach Rahmen at {:?}", socket);
+        e
+    })?;
+    match response.strip_prefix("error: ") {
+        Some(message) => Err(RahmenError::IoError(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            message,
+        ))),
+        None => {
+            if response != "ok" {
+                println!("{}", response);
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! Check the configuration without starting the slideshow
+//!
+//! Everything that would otherwise fail once Rahmen runs, or only when the first image with a
+//! certain tag is shown, is tried out: regular expressions are compiled, cases, locales and date
+//! formats resolved, the Python and Lua postprocessing loaded, inputs, fonts and devices looked
+//! up, listen addresses resolved, remote keys, GPIO pins and the MQTT settings checked, and the
+//! D-Bus connected to. All problems are reported, each with the setting it was found in, such as
+//! `status_line[2].replace[0].regex`. The parsers are the ones used when Rahmen starts.
+
+use std::fmt;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::adjust::BrightnessSchedule;
+use crate::config::Settings;
+use crate::control_dbus;
+use crate::dataflow::{font_canvas_vstretch, StatusLayout, StatusPosition, DEFAULT_FONT_SIZE};
+use crate::date;
+use crate::display::parse_resolution;
+use crate::font::FontRenderer;
+use crate::http_server;
+use crate::input_evdev::key_code;
+use crate::input_gpio;
+use crate::lua::LuaPostprocess;
+use crate::mqtt::MqttConfig;
+use crate::presence;
+use crate::provider::str_to_case;
+use crate::python::{self, PyPostprocess};
+use crate::schedule::{Block, DailyTime, TimeRange};
+use crate::transition;
+
+/// A problem with a setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// where the setting is, such as `source[1].input`
+    pub location: String,
+    /// what is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// The problems found so far
+#[derive(Debug, Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    /// Record a problem at `location`
+    fn report<L: Into<String>>(&mut self, location: L, message: String) {
+        self.0.push(Problem {
+            location: location.into(),
+            message,
+        });
+    }
+
+    /// Record the error of `result`, if any, as a problem at `location`
+    fn check<T, E: fmt::Display, L: Into<String>>(&mut self, location: L, result: Result<T, E>) {
+        if let Err(e) = result {
+            self.report(location, e.to_string());
+        }
+    }
+}
+
+/// Check that an input, a list file or a glob pattern, names images
+fn check_input(input: &str) -> Result<(), String> {
+    if input == "-" || Path::new(input).is_file() {
+        return Ok(());
+    }
+    let mut paths = glob::glob(input).map_err(|e| format!("invalid pattern: {}", e))?;
+    if paths.any(|path| path.is_ok()) {
+        Ok(())
+    } else {
+        Err(format!("no file matches {:?}", input))
+    }
+}
+
+/// Check that a file exists
+fn check_file(path: &str) -> Result<(), String> {
+    if Path::new(path).is_file() {
+        Ok(())
+    } else {
+        Err(format!("{:?} does not exist", path))
+    }
+}
+
+/// Check that a device or socket exists
+fn check_exists(path: &str) -> Result<(), String> {
+    if Path::new(path).exists() {
+        Ok(())
+    } else {
+        Err(format!("{:?} does not exist", path))
+    }
+}
+
+/// Check `settings`, with the `inputs` and the font given on the command line, and return all
+/// problems found
+pub fn check(settings: &Settings, inputs: &[&str], font: &Path) -> Vec<Problem> {
+    let mut problems = Problems::default();
+
+    for (index, input) in inputs.iter().enumerate() {
+        problems.check(format!("input {}", index + 1), check_input(input));
+    }
+    for (index, source) in settings.source.iter().flatten().enumerate() {
+        problems.check(
+            format!("source[{}].input", index),
+            check_input(&source.input),
+        );
+    }
+    for (name, input) in settings.playlists.iter().flatten() {
+        problems.check(format!("playlists.{}", name), check_input(input));
+    }
+
+    for (index, element) in settings.status_line.iter().enumerate() {
+        let location = format!("status_line[{}]", index);
+        if let Some(format) = &element.date_format {
+            problems.check(
+                format!("{}.date_format", location),
+                date::parse_format(format),
+            );
+        }
+        if let Some(locale) = &element.locale {
+            problems.check(format!("{}.locale", location), date::parse_locale(locale));
+        }
+        if let Some(conversion) = &element.case_conversion {
+            problems.check(
+                format!("{}.case_conversion.from", location),
+                str_to_case(conversion.from.clone()),
+            );
+            problems.check(
+                format!("{}.case_conversion.to", location),
+                str_to_case(conversion.to.clone()),
+            );
+        }
+        for (index, replace) in element.replace.iter().flatten().enumerate() {
+            problems.check(
+                format!("{}.replace[{}].regex", location, index),
+                Regex::new(&replace.regex),
+            );
+        }
+        for (index, condition) in element.when.iter().flatten().enumerate() {
+            if let Some(regex) = &condition.matches {
+                problems.check(
+                    format!("{}.when[{}].matches", location, index),
+                    Regex::new(regex),
+                );
+            }
+        }
+    }
+    if let Some(paths) = &settings.py_path {
+        problems.check("py_path", python::prepend_path(paths));
+    }
+    if let Some(module) = &settings.py_postprocess {
+        problems.check("py_postprocess", PyPostprocess::load(module));
+    }
+    if let Some(module) = &settings.lua_postprocess {
+        let paths = settings.lua_path.as_deref().unwrap_or_default();
+        problems.check("lua_postprocess", LuaPostprocess::load(module, paths));
+    }
+    if let Some(timezone) = &settings.camera_timezone {
+        problems.check("camera_timezone", date::parse_timezone(timezone));
+    }
+    if let Some(geocode) = &settings.geocode {
+        problems.check("geocode.cities", check_file(&geocode.cities));
+        if let Some(admin1) = &geocode.admin1 {
+            problems.check("geocode.admin1", check_file(admin1));
+        }
+        if let Some(countries) = &geocode.countries {
+            problems.check("geocode.countries", check_file(countries));
+        }
+        match (&geocode.language, &geocode.alternate_names) {
+            (_, Some(alternate_names)) => {
+                problems.check("geocode.alternate_names", check_file(alternate_names))
+            }
+            (Some(_), None) => problems.report(
+                "geocode.language",
+                "needs the names in other languages, from `alternate_names`".to_string(),
+            ),
+            (None, None) => {}
+        }
+    }
+    if let Some(settings) = &settings.presence {
+        problems.check("presence", presence::devices(settings));
+    }
+
+    if let Some(control) = &settings.control {
+        if let Some(address) = &control.http {
+            problems.check("control.http", http_server::resolve(address));
+        }
+        if let Some(bus) = control.dbus {
+            problems.check("control.dbus", control_dbus::check(bus));
+        }
+        for (index, device) in control.keyboard_devices.iter().flatten().enumerate() {
+            problems.check(
+                format!("control.keyboard_devices[{}]", index),
+                check_exists(device),
+            );
+        }
+    }
+    if let Some(address) = settings.metrics.as_ref().and_then(|m| m.address.as_ref()) {
+        problems.check("metrics.address", http_server::resolve(address));
+    }
+    if let Some(address) = settings.http.as_ref().and_then(|h| h.listen.as_ref()) {
+        problems.check("http.listen", http_server::resolve(address));
+    }
+    for (index, output) in settings.multi.iter().flat_map(|m| &m.output).enumerate() {
+        if let (Some(address), "http") = (&output.device, output.display.as_str()) {
+            problems.check(
+                format!("multi.output[{}].device", index),
+                http_server::resolve(address),
+            );
+        }
+    }
+    if let Some(mqtt) = &settings.mqtt {
+        problems.check("mqtt", MqttConfig::new(mqtt.clone()));
+    }
+    if let Some(remote) = &settings.remote {
+        for (index, binding) in remote.keys.iter().flatten().enumerate() {
+            problems.check(
+                format!("remote.keys[{}].key", index),
+                key_code(&binding.key),
+            );
+        }
+        if let Some(socket) = &remote.lircd {
+            problems.check("remote.lircd", check_exists(socket));
+        }
+        for (index, device) in remote.devices.iter().flatten().enumerate() {
+            problems.check(format!("remote.devices[{}]", index), check_exists(device));
+        }
+    }
+    if let Some(buttons) = &settings.gpio {
+        problems.check(
+            "gpio",
+            input_gpio::check_pins(buttons.iter().map(|button| button.pin)),
+        );
+    }
+
+    problems.check(
+        "font",
+        FontRenderer::from_path(font).map_err(|e| format!("{:?}: {}", font, e)),
+    );
+    // the status line must leave room for the image at the resolutions configured
+    let status = settings.status.clone().unwrap_or_default();
+    let status_layout = StatusLayout {
+        position: status.position.unwrap_or(StatusPosition::Bottom),
+        margin: status.margin.unwrap_or(0),
+        overlay: status.overlay.unwrap_or(false),
+    };
+    let reserved = status_layout.reserved_height(
+        settings.font_size.unwrap_or(DEFAULT_FONT_SIZE),
+        font_canvas_vstretch(status.max_lines.unwrap_or(1)),
+    );
+    let resolutions = [
+        (
+            "png.resolution",
+            settings
+                .png
+                .as_ref()
+                .and_then(|png| png.resolution.as_ref()),
+        ),
+        (
+            "http.resolution",
+            settings
+                .http
+                .as_ref()
+                .and_then(|http| http.resolution.as_ref()),
+        ),
+        (
+            "sdl2.resolution",
+            settings
+                .sdl2
+                .as_ref()
+                .and_then(|sdl2| sdl2.resolution.as_ref()),
+        ),
+    ];
+    for (location, resolution) in resolutions.iter() {
+        if let Some(resolution) = resolution {
+            match parse_resolution(resolution) {
+                Ok((_, height)) if height <= reserved => problems.report(
+                    *location,
+                    format!(
+                        "the status line takes {} pixels of the height of {}, leaving none for \
+                         the image",
+                        reserved, height
+                    ),
+                ),
+                result => problems.check(*location, result),
+            }
+        }
+    }
+
+    if let Some(module) = &settings.py_easings {
+        problems.check("py_easings", python::register_easings(module));
+    }
+    if let Some(transition) = &settings.transition {
+        if let Some(seconds) = transition.duration {
+            problems.check("transition.duration", transition::duration(seconds));
+        }
+        if let Some(name) = &transition.easing {
+            problems.check("transition.easing", transition::easing(name));
+        }
+    }
+    for (index, period) in settings.quiet_hours.iter().flatten().enumerate() {
+        problems.check(format!("quiet_hours[{}]", index), TimeRange::new(period));
+    }
+    for (index, entry) in settings.brightness.iter().flatten().enumerate() {
+        problems.check(
+            format!("brightness[{}]", index),
+            BrightnessSchedule::new(std::iter::once(entry.clone())),
+        );
+    }
+    if let Some(maintenance) = &settings.maintenance {
+        problems.check("maintenance.at", DailyTime::new(&maintenance.at));
+    }
+    for (index, block) in settings.schedule.iter().flatten().enumerate() {
+        let location = format!("schedule[{}]", index);
+        problems.check(location.clone(), Block::new(block));
+        if let Some(name) = &block.playlist {
+            let known = settings
+                .playlists
+                .as_ref()
+                .map_or(false, |playlists| playlists.contains_key(name));
+            if !known {
+                problems.report(
+                    format!("{}.playlist", location),
+                    format!("unknown playlist {:?}", name),
+                );
+            }
+        }
+    }
+
+    problems.0
+}
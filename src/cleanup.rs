@@ -0,0 +1,223 @@
+//! Remove dust and scratches from scanned slides and negatives
+//!
+//! Dust on a scanned slide shows up as small specks that are much brighter or darker than their
+//! surroundings. Pixels deviating strongly from the median of their neighborhood are taken to be
+//! specks, and replaced by the median of the pixels around them that are not. Finer detail, which
+//! differs less from its surroundings, is kept.
+//!
+//! Optionally, red eyes from the flash are removed as well. They are small, roundish patches of
+//! pixels that are far redder than they are green or blue, which are darkened to the brightness of
+//! their green and blue. Larger red areas, such as clothes or flowers, are left alone.
+//!
+//! As this is slow for large images, cleaned copies are cached like transcoded images, separately
+//! for each choice of settings, so changing them cleans the images anew.
+
+use std::path::{Path, PathBuf};
+
+use glob::Pattern;
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+use tracing::debug_span;
+
+use crate::errors::RahmenResult;
+use crate::pixel::luma;
+use crate::provider::load_image_from_path;
+use crate::transcode::{cached_path, write_jpeg};
+
+/// The median of some values, which must not be empty
+fn median(values: &mut [u8]) -> u8 {
+    let middle = values.len() / 2;
+    *values.select_nth_unstable(middle).1
+}
+
+/// Remove specks of up to `max_speck` pixels across that differ from their surroundings by more
+/// than `threshold` in brightness
+pub fn remove_specks(img: &DynamicImage, max_speck: u32, threshold: u8) -> DynamicImage {
+    let mut rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let gray = GrayImage::from_fn(width, height, |x, y| Luma([luma(rgb.get_pixel(x, y).0)]));
+    // the neighborhood must be more than twice the size of a speck, so the median ignores it
+    let radius = max_speck.max(1);
+    let window = |x: u32, y: u32| {
+        let (x0, x1) = (x.saturating_sub(radius), (x + radius).min(width - 1));
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius).min(height - 1));
+        (y0..=y1).flat_map(move |y| (x0..=x1).map(move |x| (x, y)))
+    };
+
+    // detect the specks
+    let mut values = vec![];
+    let mut speck = GrayImage::new(width, height);
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        values.clear();
+        values.extend(window(x, y).map(|(x, y)| gray.get_pixel(x, y)[0]));
+        if (i16::from(pixel[0]) - i16::from(median(&mut values))).abs() > i16::from(threshold) {
+            speck.put_pixel(x, y, Luma([255]));
+        }
+    }
+
+    // fill them in from their surroundings
+    let source: RgbImage = rgb.clone();
+    let mut channels: [Vec<u8>; 3] = Default::default();
+    for (x, y, _) in speck.enumerate_pixels().filter(|(_, _, s)| s[0] > 0) {
+        for channel in &mut channels {
+            channel.clear();
+        }
+        for (nx, ny) in window(x, y).filter(|(nx, ny)| speck.get_pixel(*nx, *ny)[0] == 0) {
+            for (channel, values) in channels.iter_mut().enumerate() {
+                values.push(source.get_pixel(nx, ny)[channel]);
+            }
+        }
+        if channels[0].is_empty() {
+            continue;
+        }
+        let [r, g, b] = &mut channels;
+        rgb.put_pixel(x, y, Rgb([median(r), median(g), median(b)]));
+    }
+    DynamicImage::ImageRgb8(rgb)
+}
+
+/// Whether a pixel may be part of a red eye
+fn is_red(pixel: &Rgb<u8>) -> bool {
+    let [r, g, b] = pixel.0;
+    r >= 80 && f32::from(r) > 1.8 * f32::from(g.max(b))
+}
+
+/// Remove red eyes, patches of red pixels no larger across than a fortieth of the shorter side of
+/// the image, which fill at least half of their bounding box
+pub fn remove_red_eye(img: &DynamicImage) -> DynamicImage {
+    let mut rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let max_eye = (width.min(height) / 40).max(2);
+    let mut visited = vec![false; width as usize * height as usize];
+    let index = |x: u32, y: u32| y as usize * width as usize + x as usize;
+    let mut stack = vec![];
+    let mut patch = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            if visited[index(x, y)] || !is_red(rgb.get_pixel(x, y)) {
+                continue;
+            }
+            // collect the patch of red pixels connected to this one
+            visited[index(x, y)] = true;
+            stack.push((x, y));
+            patch.clear();
+            while let Some((x, y)) = stack.pop() {
+                patch.push((x, y));
+                let neighbors = [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ];
+                for &(nx, ny) in &neighbors {
+                    if nx < width
+                        && ny < height
+                        && !visited[index(nx, ny)]
+                        && is_red(rgb.get_pixel(nx, ny))
+                    {
+                        visited[index(nx, ny)] = true;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+            let (x0, x1) = patch.iter().fold((u32::MAX, 0), |(min, max), (x, _)| {
+                (min.min(*x), max.max(*x))
+            });
+            let (y0, y1) = patch.iter().fold((u32::MAX, 0), |(min, max), (_, y)| {
+                (min.min(*y), max.max(*y))
+            });
+            let (patch_width, patch_height) = (x1 - x0 + 1, y1 - y0 + 1);
+            let round = patch_width <= 2 * patch_height
+                && patch_height <= 2 * patch_width
+                && 2 * patch.len() as u32 >= patch_width * patch_height;
+            if patch_width.max(patch_height) <= max_eye && round {
+                for &(x, y) in &patch {
+                    let pixel = rgb.get_pixel_mut(x, y);
+                    let [_, g, b] = pixel.0;
+                    pixel[0] = ((u16::from(g) + u16::from(b)) / 2) as u8;
+                }
+            }
+        }
+    }
+    DynamicImage::ImageRgb8(rgb)
+}
+
+/// A cache of cleaned images
+#[derive(Debug)]
+pub struct CleanupCache {
+    dir: PathBuf,
+    patterns: Vec<Pattern>,
+    max_speck: u32,
+    threshold: u8,
+    red_eye: bool,
+}
+
+impl CleanupCache {
+    /// Create a cache storing cleaned images in `dir`. Only images whose paths match one of the
+    /// `patterns` are cleaned, or all images if there are none. Red eyes are removed as well if
+    /// `red_eye` is set.
+    pub fn new(
+        dir: PathBuf,
+        patterns: &[String],
+        max_speck: u32,
+        threshold: u8,
+        red_eye: bool,
+    ) -> RahmenResult<Self> {
+        // images cleaned with other settings are kept apart
+        let dir = dir.join(format!(
+            "speck-{}-threshold-{}{}",
+            max_speck,
+            threshold,
+            if red_eye { "-red-eye" } else { "" }
+        ));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            patterns: patterns
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<_, _>>()?,
+            max_speck,
+            threshold,
+            red_eye,
+        })
+    }
+
+    /// Whether the image at `path` is to be cleaned
+    pub fn applies_to(&self, path: &Path) -> bool {
+        self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.matches_path(path))
+    }
+
+    /// Load the cleaned copy of the image at `path`, which is loaded from `source`, cleaning it if
+    /// there is no up-to-date copy yet
+    pub fn load(
+        &self,
+        path: &Path,
+        source: &Path,
+        max_size: Option<usize>,
+    ) -> RahmenResult<DynamicImage> {
+        let cached = cached_path(&self.dir, path);
+        let source_modified = std::fs::metadata(source)?.modified()?;
+        match std::fs::metadata(&cached).and_then(|metadata| metadata.modified()) {
+            Ok(cached_modified) if cached_modified >= source_modified => {
+                load_image_from_path(&cached, max_size)
+            }
+            _ => {
+                let _span = debug_span!("clean").entered();
+                let mut img = remove_specks(
+                    &load_image_from_path(source, max_size)?,
+                    self.max_speck,
+                    self.threshold,
+                );
+                if self.red_eye {
+                    img = remove_red_eye(&img);
+                }
+                write_jpeg(&img, &cached)?;
+                Ok(img)
+            }
+        }
+    }
+}
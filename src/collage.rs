@@ -0,0 +1,169 @@
+//! Show several images at once, in a collage
+//!
+//! A collage fills the screen with 2 to 6 images. Two and four images, and six, form a grid; with
+//! three or five, the first image takes a larger cell next to the others, like a mosaic. The
+//! layouts are for landscape screens, and mirrored along the diagonal for portrait screens. Each
+//! image is either fit into its cell whole, or cropped to fill it, which can differ for portraits,
+//! as cropping their tops and bottoms often cuts off heads.
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba, RgbaImage};
+
+/// The most images in a collage
+pub const MAX_IMAGES: usize = 6;
+
+/// How an image fills its cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fit {
+    /// Show the whole image, with bars where its aspect ratio differs from the cell's
+    Contain,
+    /// Fill the cell, cropping the image
+    Cover,
+    /// Fill the cell if that crops at most a quarter of the image, otherwise show it whole
+    Auto,
+}
+
+/// A cell of a collage, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    /// the left edge
+    pub x: u32,
+    /// the top edge
+    pub y: u32,
+    /// the width
+    pub width: u32,
+    /// the height
+    pub height: u32,
+}
+
+/// The cells for `count` images on a landscape screen, as fractions of the screen: left, top,
+/// width and height
+fn layout(count: usize) -> &'static [(f64, f64, f64, f64)] {
+    const HALF: f64 = 1. / 2.;
+    const THIRD: f64 = 1. / 3.;
+    const QUARTER: f64 = 1. / 4.;
+    match count {
+        0 | 1 => &[(0., 0., 1., 1.)],
+        2 => &[(0., 0., HALF, 1.), (HALF, 0., HALF, 1.)],
+        3 => &[
+            (0., 0., HALF, 1.),
+            (HALF, 0., HALF, HALF),
+            (HALF, HALF, HALF, HALF),
+        ],
+        4 => &[
+            (0., 0., HALF, HALF),
+            (HALF, 0., HALF, HALF),
+            (0., HALF, HALF, HALF),
+            (HALF, HALF, HALF, HALF),
+        ],
+        5 => &[
+            (0., 0., HALF, 1.),
+            (HALF, 0., QUARTER, HALF),
+            (HALF + QUARTER, 0., QUARTER, HALF),
+            (HALF, HALF, QUARTER, HALF),
+            (HALF + QUARTER, HALF, QUARTER, HALF),
+        ],
+        _ => &[
+            (0., 0., THIRD, HALF),
+            (THIRD, 0., THIRD, HALF),
+            (2. * THIRD, 0., THIRD, HALF),
+            (0., HALF, THIRD, HALF),
+            (THIRD, HALF, THIRD, HALF),
+            (2. * THIRD, HALF, THIRD, HALF),
+        ],
+    }
+}
+
+/// How to lay out a collage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collage {
+    /// the number of images, from 2 to `MAX_IMAGES`
+    pub count: usize,
+    /// the gap between cells, in pixels
+    pub gap: u32,
+    /// the color of the gaps and bars
+    pub background: [u8; 3],
+    /// how images fill their cells
+    pub fit: Fit,
+    /// how portrait images fill their cells
+    pub portrait_fit: Fit,
+}
+
+impl Collage {
+    /// The cells for `count` images on a screen `width` wide and `height` high
+    pub fn cells(&self, count: usize, width: u32, height: u32) -> Vec<Cell> {
+        let portrait = height > width;
+        // lay out on a canvas larger by one gap, and drop the gap after each cell
+        let (across, down) = if portrait {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let (across, down) = (f64::from(across + self.gap), f64::from(down + self.gap));
+        layout(count)
+            .iter()
+            .map(|(x, y, w, h)| {
+                let x0 = (x * across).round() as u32;
+                let y0 = (y * down).round() as u32;
+                let x1 = ((x + w) * across).round() as u32;
+                let y1 = ((y + h) * down).round() as u32;
+                let (width, height) = (
+                    x1.saturating_sub(x0 + self.gap).max(1),
+                    y1.saturating_sub(y0 + self.gap).max(1),
+                );
+                if portrait {
+                    Cell {
+                        x: y0,
+                        y: x0,
+                        width: height,
+                        height: width,
+                    }
+                } else {
+                    Cell {
+                        x: x0,
+                        y: y0,
+                        width,
+                        height,
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Compose `images` into a collage filling a screen `width` wide and `height` high
+    pub fn compose(&self, images: &[&DynamicImage], width: u32, height: u32) -> DynamicImage {
+        let [r, g, b] = self.background;
+        let mut canvas = RgbaImage::from_pixel(width, height, Rgba([r, g, b, 255]));
+        let cells = self.cells(images.len(), width, height);
+        for (img, cell) in images.iter().zip(cells) {
+            let fit = if img.height() > img.width() {
+                self.portrait_fit
+            } else {
+                self.fit
+            };
+            let cover = match fit {
+                Fit::Contain => false,
+                Fit::Cover => true,
+                Fit::Auto => {
+                    let image_aspect = f64::from(img.width()) / f64::from(img.height().max(1));
+                    let cell_aspect = f64::from(cell.width) / f64::from(cell.height);
+                    let kept = image_aspect.min(cell_aspect) / image_aspect.max(cell_aspect);
+                    kept >= 0.75
+                }
+            };
+            let fitted = if cover {
+                img.resize_to_fill(cell.width, cell.height, FilterType::Triangle)
+            } else {
+                img.resize(cell.width, cell.height, FilterType::Triangle)
+            };
+            // center the image in its cell, which it fits by construction
+            let _ = canvas.copy_from(
+                &fitted.to_rgba8(),
+                cell.x + (cell.width - fitted.width()) / 2,
+                cell.y + (cell.height - fitted.height()) / 2,
+            );
+        }
+        DynamicImage::ImageRgba8(canvas)
+    }
+}
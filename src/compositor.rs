@@ -0,0 +1,188 @@
+//! Compose the frame shown from layers, such as the photo, the status line and widgets
+//!
+//! Each layer is an image at an offset, stacked by its key: the photo is at the bottom, the
+//! status line and widgets above it. Layers may be partly transparent, both per pixel and as a
+//! whole. Only the region covered by layers that changed is composed again, so a widget can be
+//! refreshed without redrawing the photo. All layers can be shifted by a few pixels together, to
+//! protect displays from burn-in.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+/// A rectangle as `(x0, y0, x1, y1)`, from the top left corner inclusive to the bottom right
+/// corner exclusive
+type Region = (u32, u32, u32, u32);
+
+/// An image placed on the screen
+#[derive(Debug, Clone)]
+struct Layer {
+    x: u32,
+    y: u32,
+    image: Arc<DynamicImage>,
+}
+
+impl Layer {
+    /// The top left corner of the layer when shifted by `shift`, which may be off the screen
+    fn origin(&self, shift: (i32, i32)) -> (i64, i64) {
+        (
+            i64::from(self.x) + i64::from(shift.0),
+            i64::from(self.y) + i64::from(shift.1),
+        )
+    }
+
+    /// The region of the screen covered by the layer when shifted by `shift`
+    fn region(&self, shift: (i32, i32)) -> Region {
+        let (x, y) = self.origin(shift);
+        let clamp = |value: i64| value.max(0) as u32;
+        (
+            clamp(x),
+            clamp(y),
+            clamp(x + i64::from(self.image.width())),
+            clamp(y + i64::from(self.image.height())),
+        )
+    }
+}
+
+fn union(a: Region, b: Option<Region>) -> Region {
+    match b {
+        Some(b) => (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)),
+        None => a,
+    }
+}
+
+/// Blend `source` over `target` with `alpha`, from 0 to 255
+fn blend(target: &mut Rgb<u8>, source: [u8; 3], alpha: u32) {
+    for (channel, value) in target.0.iter_mut().zip(source.iter()) {
+        *channel = ((u32::from(*value) * alpha + u32::from(*channel) * (255 - alpha)) / 255) as u8;
+    }
+}
+
+/// Composes layers into the frame shown
+#[derive(Debug, Default)]
+pub struct Compositor {
+    dimensions: (u32, u32),
+    layers: BTreeMap<usize, Layer>,
+    opacity: HashMap<usize, u8>,
+    /// the offset of all layers
+    shift: (i32, i32),
+    dirty: Option<Region>,
+}
+
+impl Compositor {
+    /// Create a compositor for a screen of `(width, height)` pixels
+    pub fn new(dimensions: (u32, u32)) -> Self {
+        Self {
+            dimensions,
+            ..Default::default()
+        }
+    }
+
+    /// Change the size of the screen, which requires composing everything again
+    pub fn resize(&mut self, dimensions: (u32, u32)) {
+        self.dimensions = dimensions;
+        self.invalidate();
+    }
+
+    /// Compose the whole screen again on the next call to `compose`
+    pub fn invalidate(&mut self) {
+        self.dirty = Some((0, 0, self.dimensions.0, self.dimensions.1));
+    }
+
+    /// Shift all layers by `(dx, dy)` pixels from where they were set
+    pub fn set_shift(&mut self, shift: (i32, i32)) {
+        if self.shift != shift {
+            self.shift = shift;
+            self.invalidate();
+        }
+    }
+
+    /// Set the opacity of the layer `key` as a whole, from 0 (invisible) to 1 (opaque)
+    pub fn set_opacity(&mut self, key: usize, opacity: f32) {
+        let opacity = (opacity.max(0.).min(1.) * 255.).round() as u8;
+        if self.opacity.insert(key, opacity) != Some(opacity) {
+            if let Some(layer) = self.layers.get(&key) {
+                self.dirty = Some(union(layer.region(self.shift), self.dirty));
+            }
+        }
+    }
+
+    /// Show `image` at `(x, y)` as the layer `key`, replacing the previous image of that layer
+    pub fn set(&mut self, key: usize, x: u32, y: u32, image: Arc<DynamicImage>) {
+        let layer = Layer { x, y, image };
+        self.dirty = Some(union(layer.region(self.shift), self.dirty));
+        if let Some(previous) = self.layers.insert(key, layer) {
+            self.dirty = Some(union(previous.region(self.shift), self.dirty));
+        }
+    }
+
+    /// Remove the layer `key`
+    pub fn clear(&mut self, key: usize) {
+        if let Some(previous) = self.layers.remove(&key) {
+            self.dirty = Some(union(previous.region(self.shift), self.dirty));
+        }
+    }
+
+    /// The image and offset of the layer `key`, if it is shown, before shifting
+    pub fn layer(&self, key: usize) -> Option<(u32, u32, &Arc<DynamicImage>)> {
+        self.layers
+            .get(&key)
+            .map(|layer| (layer.x, layer.y, &layer.image))
+    }
+
+    /// Compose the region that changed since the last call, returning its offset and image.
+    /// Where no layer covers the screen, it is black.
+    pub fn compose(&mut self) -> Option<(u32, u32, RgbImage)> {
+        let (x0, y0, x1, y1) = self.dirty.take()?;
+        let (x1, y1) = (x1.min(self.dimensions.0), y1.min(self.dimensions.1));
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        let mut canvas = RgbImage::new(x1 - x0, y1 - y0);
+        for (key, layer) in &self.layers {
+            let opacity = u32::from(*self.opacity.get(key).unwrap_or(&255));
+            if opacity == 0 {
+                continue;
+            }
+            let (lx0, ly0, lx1, ly1) = layer.region(self.shift);
+            let (cx0, cy0) = (lx0.max(x0), ly0.max(y0));
+            let (cx1, cy1) = (lx1.min(x1), ly1.min(y1));
+            if cx0 >= cx1 || cy0 >= cy1 {
+                continue;
+            }
+            // offsets into the layer, and into the canvas
+            let (ox, oy) = layer.origin(self.shift);
+            let rows = (cy0..cy1).map(|y| ((i64::from(y) - oy) as u32, y - y0));
+            let columns = || (cx0..cx1).map(|x| ((i64::from(x) - ox) as u32, x - x0));
+            let converted;
+            let translucent = match layer.image.as_ref() {
+                DynamicImage::ImageRgb8(buffer) => {
+                    for (ly, cy) in rows {
+                        for (lx, cx) in columns() {
+                            let source = buffer.get_pixel(lx, ly).0;
+                            blend(canvas.get_pixel_mut(cx, cy), source, opacity);
+                        }
+                    }
+                    continue;
+                }
+                DynamicImage::ImageRgba8(buffer) => buffer,
+                img => {
+                    converted = img.to_rgba8();
+                    &converted
+                }
+            };
+            for (ly, cy) in rows {
+                for (lx, cx) in columns() {
+                    let [r, g, b, a] = translucent.get_pixel(lx, ly).0;
+                    blend(
+                        canvas.get_pixel_mut(cx, cy),
+                        [r, g, b],
+                        u32::from(a) * opacity / 255,
+                    );
+                }
+            }
+        }
+        Some((x0, y0, canvas))
+    }
+}
@@ -1,5 +1,26 @@
 //! Configuration data for Rahmen
 
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::adjust::Proof;
+use crate::collage::Fit;
+use crate::control::Action;
+use crate::control_dbus::Bus;
+use crate::dataflow::StatusPosition;
+use crate::display::GraphicsProtocol;
+use crate::display_multi::MultiMode;
+use crate::errors::{OnError, RahmenError, RahmenResult};
+use crate::font::Alignment;
+use crate::geocode::Detail;
+use crate::light_sensor::SensorKind;
+use crate::power::PowerMethod;
+use crate::presence::Away;
+use crate::provider_orientation::OrientationMode;
+use crate::store::StoreBackend;
+use crate::weather::{Units, WeatherService};
+
 /// An element of the status line
 #[derive(Debug, Deserialize, Clone)]
 pub struct Element {
@@ -11,6 +32,27 @@ pub struct Element {
     pub replace: Option<Vec<Replacement>>,
     /// Optional case conversion instruction
     pub case_conversion: Option<CaseConversion>,
+    /// Optional strftime format to show a date in, such as `%e %B %Y`
+    pub date_format: Option<String>,
+    /// Optional locale of the names of months and days, such as `de_CH` (default `en_US`)
+    pub locale: Option<String>,
+    /// Optional conditions, which all have to hold for the element to be shown
+    pub when: Option<Vec<Condition>>,
+}
+
+/// condition on a tag of the image
+#[derive(Debug, Deserialize, Clone)]
+pub struct Condition {
+    /// the tag to test
+    pub tag: String,
+    /// whether the tag has to be empty or missing, or present (optional)
+    pub empty: Option<bool>,
+    /// the value the tag has to have (optional)
+    pub equals: Option<String>,
+    /// a value the tag must not have, which holds if it is missing (optional)
+    pub not_equals: Option<String>,
+    /// a regular expression the value of the tag has to match (optional)
+    pub matches: Option<String>,
 }
 
 /// case conversion
@@ -31,6 +73,809 @@ pub struct Replacement {
     pub replace: String,
 }
 
+/// Settings for naming the place a photo was taken at
+#[derive(Debug, Deserialize, Clone)]
+pub struct GeocodeSettings {
+    /// GeoNames file of cities, such as `cities1000.txt`
+    pub cities: String,
+    /// GeoNames file of the names of first-level divisions, `admin1CodesASCII.txt` (optional)
+    pub admin1: Option<String>,
+    /// GeoNames file of the names of countries, `countryInfo.txt` (optional, default: ISO codes)
+    pub countries: Option<String>,
+    /// ignore cities with fewer inhabitants (optional, default 0)
+    pub min_population: Option<u64>,
+    /// give names in ASCII rather than their local spelling (optional, default false)
+    pub ascii: Option<bool>,
+    /// give names in the language of this locale, such as `de` or `fr_CH`, where `alternate_names`
+    /// has them (optional, default the local names)
+    pub language: Option<String>,
+    /// GeoNames file of names in other languages, `alternateNamesV2.txt` or one of
+    /// `alternatenames/` (optional, needed for `language`)
+    pub alternate_names: Option<String>,
+    /// the levels shown by `Rahmen.Geo.Place` (optional, default `["city", "country"]`)
+    pub detail: Option<Vec<Detail>>,
+    /// the separator between the levels (optional, default `", "`)
+    pub separator: Option<String>,
+    /// cache places on disk (optional, default true)
+    pub cache: Option<bool>,
+    /// decimal places positions are rounded to for the cache (optional, default 2, about 1 km)
+    pub cache_decimals: Option<usize>,
+    /// path of the cache, in the format of the state backend (optional, default in the XDG data
+    /// directory)
+    pub cache_path: Option<String>,
+}
+
+/// Settings for showing how far from home photos were taken
+#[derive(Debug, Deserialize, Clone)]
+pub struct HomeSettings {
+    /// latitude of home
+    pub latitude: f64,
+    /// longitude of home
+    pub longitude: f64,
+    /// `metric` for kilometers, or `imperial` for miles (optional, default `metric`)
+    pub units: Option<Units>,
+    /// decimal places of distances (optional, default 1)
+    pub decimals: Option<usize>,
+}
+
+/// A brightness schedule entry, taking effect at a time of day
+#[derive(Debug, Deserialize, Clone)]
+pub struct BrightnessEntry {
+    /// time of day (`HH:MM`) from which on this entry applies
+    pub from: String,
+    /// brightness factor (optional, default 1.0)
+    pub brightness: Option<f32>,
+    /// gamma correction (optional, default 1.0)
+    pub gamma: Option<f32>,
+}
+
+/// Settings for dimming the images with the light in the room
+#[derive(Debug, Deserialize, Clone)]
+pub struct LightSensorSettings {
+    /// the sensor: `iio`, `tsl2561` or `veml7700`
+    pub sensor: SensorKind,
+    /// the directory of the IIO device (optional, default `/sys/bus/iio/devices/iio:device0`)
+    pub device: Option<String>,
+    /// the I2C bus (optional, default 1)
+    pub bus: Option<u8>,
+    /// the I2C address (optional, default 0x39 for the TSL2561 and 0x10 for the VEML7700)
+    pub address: Option<u16>,
+    /// seconds between readings (optional, default 2)
+    pub interval: Option<f64>,
+    /// the illuminance at and below which the brightness is least, in lux (optional, default 5)
+    pub dark: Option<f32>,
+    /// the illuminance at and above which the brightness is full, in lux (optional, default 300)
+    pub bright: Option<f32>,
+    /// the least brightness factor (optional, default 0.3)
+    pub min_brightness: Option<f32>,
+}
+
+/// Settings for dimming the backlight of the panel, rather than the images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct BacklightSettings {
+    /// the backlight device in `/sys/class/backlight`, such as `rpi_backlight` (optional,
+    /// default: the first one)
+    pub device: Option<String>,
+}
+
+/// A period of each day
+#[derive(Debug, Deserialize, Clone)]
+pub struct DailyPeriod {
+    /// start time (`HH:MM`)
+    pub from: String,
+    /// end time (`HH:MM`), can be before the start time to span midnight
+    pub to: String,
+}
+
+/// A block of the schedule, changing the slideshow during a period on some days
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScheduleBlock {
+    /// start time (`HH:MM`)
+    pub from: String,
+    /// end time (`HH:MM`), can be before the start time to span midnight
+    pub to: String,
+    /// the days of the week the block starts on, such as `mon` or `sat` (optional, default: every
+    /// day)
+    pub days: Option<Vec<String>>,
+    /// turn the screen off (optional, default false)
+    pub screen_off: Option<bool>,
+    /// the name of the playlist to show (optional, default: the input)
+    pub playlist: Option<String>,
+    /// seconds to show each image (optional, default: the configured delay)
+    pub interval: Option<f64>,
+}
+
+/// An external command transcoding images Rahmen can't decode to JPEG
+#[derive(Debug, Deserialize, Clone)]
+pub struct Converter {
+    /// file extensions this converter handles, case-insensitive
+    pub extensions: Vec<String>,
+    /// program and arguments, `{input}` and `{output}` are replaced by the paths
+    pub command: Vec<String>,
+}
+
+/// Settings for reading the durations of images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct DurationSettings {
+    /// read the seconds to show an image from a file named like it with `.duration` appended
+    /// (optional, default true)
+    pub sidecar: Option<bool>,
+    /// the EXIF, IPTC or XMP tag holding the seconds to show an image (optional, default: none)
+    pub tag: Option<String>,
+}
+
+/// Settings for skipping near-duplicates of recent images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct DuplicateSettings {
+    /// the number of recent images to compare with (optional, default 10)
+    pub window: Option<usize>,
+    /// the number of bits, of 64, in which the hashes of near-duplicates may differ (optional,
+    /// default 6)
+    pub threshold: Option<u32>,
+    /// the file to cache the hashes in (optional, default: in the XDG cache directory)
+    pub cache: Option<String>,
+}
+
+/// Settings for skipping images too small for the screen
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ResolutionSettings {
+    /// the least number of pixels, such as 2000000 for 2 megapixels (optional)
+    pub min_pixels: Option<u64>,
+    /// the most an image may be enlarged to fit the screen, such as 1.5 (optional)
+    pub max_upscale: Option<f64>,
+}
+
+/// Settings for selecting images of the same orientation as the screen
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct OrientationSettings {
+    /// `prefer` or `require` images of the orientation of the screen (optional, default `prefer`)
+    pub mode: Option<OrientationMode>,
+    /// how much longer than the shorter side the longer one may be for an image to count as square,
+    /// and match either orientation (optional, default 0.1)
+    pub tolerance: Option<f64>,
+}
+
+/// Settings for skipping blurry and nearly black or white images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct QualitySettings {
+    /// the least variance of the Laplacian, at a size of at most 512 by 512 pixels (optional,
+    /// default 50)
+    pub min_sharpness: Option<f64>,
+    /// the least mean gray value, from 0 to 255 (optional, default 16)
+    pub min_brightness: Option<f64>,
+    /// the most mean gray value, from 0 to 255 (optional, default 240)
+    pub max_brightness: Option<f64>,
+}
+
+/// An additional source of images
+#[derive(Debug, Deserialize, Clone)]
+pub struct SourceSettings {
+    /// a list file or a glob pattern, like the input argument
+    pub input: String,
+    /// how often to pick images from this source relative to the others (optional, default 1)
+    pub weight: Option<u32>,
+}
+
+/// Settings for the status line font
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct FontSettings {
+    /// path to a TrueType or OpenType font file (optional, overridden by `--font`)
+    pub path: Option<String>,
+    /// text color as `[r, g, b]` (optional, default white)
+    pub color: Option<[u8; 3]>,
+    /// width of an outline around the glyphs in pixels (optional)
+    pub outline: Option<u32>,
+    /// outline color as `[r, g, b]` (optional, default black)
+    pub outline_color: Option<[u8; 3]>,
+    /// offset of a drop shadow in pixels (optional)
+    pub shadow: Option<u32>,
+    /// shadow color as `[r, g, b]` (optional, default black)
+    pub shadow_color: Option<[u8; 3]>,
+}
+
+/// Background behind the status line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusBackground {
+    /// No background
+    None,
+    /// A box behind the whole status line
+    Box,
+    /// A gradient, fading out towards the middle of the screen
+    Gradient,
+}
+
+/// Settings for the placement and background of the status line
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct StatusSettings {
+    /// `top` or `bottom` (optional, default `bottom`)
+    pub position: Option<StatusPosition>,
+    /// draw the status line over the image instead of next to it (optional, default false)
+    pub overlay: Option<bool>,
+    /// distance from the edges of the screen in pixels (optional, default 0)
+    pub margin: Option<u32>,
+    /// `left`, `center` or `right` (optional, default `left`)
+    pub align: Option<Alignment>,
+    /// wrap long text onto up to this many lines, and shorten it with an ellipsis beyond
+    /// (optional, by default long lines are cut off)
+    pub max_lines: Option<usize>,
+    /// `none`, `box` or `gradient` (optional, default `none`)
+    pub background: Option<StatusBackground>,
+    /// background color as `[r, g, b]` (optional, default black)
+    pub background_color: Option<[u8; 3]>,
+    /// opacity of the background from 0 to 1 (optional, default 0.5)
+    pub background_opacity: Option<f32>,
+    /// draw text too long for the screen smaller, down to this font size, to fit it on one line
+    /// (optional, by default the font size is kept)
+    pub min_font_size: Option<f32>,
+    /// scroll text too long for the screen, rather than cutting it off or wrapping it (optional,
+    /// default false)
+    pub marquee: Option<bool>,
+    /// pixels per second to scroll long text (optional, default 60)
+    pub marquee_speed: Option<f32>,
+    /// seconds to show the start and the end of long text before scrolling on (optional, default
+    /// 2)
+    pub marquee_pause: Option<f64>,
+}
+
+/// Settings for transitions between images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct TransitionSettings {
+    /// duration of the crossfade in seconds (optional, default 1)
+    pub duration: Option<f64>,
+    /// easing curve, `linear`, `ease-in`, `ease-out`, `ease-in-out`, `cubic` or one registered by
+    /// the `py_easings` module (optional, default `ease-in-out`)
+    pub easing: Option<String>,
+}
+
+/// A corner of the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    /// The top left corner
+    TopLeft,
+    /// The top right corner
+    TopRight,
+    /// The bottom left corner
+    BottomLeft,
+    /// The bottom right corner
+    BottomRight,
+}
+
+/// Settings for showing the weather
+#[derive(Debug, Deserialize, Clone)]
+pub struct WeatherSettings {
+    /// the service to query, `open-meteo` or `openweathermap` (optional, default `open-meteo`)
+    pub service: Option<WeatherService>,
+    /// latitude of the place to show the weather of
+    pub latitude: f64,
+    /// longitude of the place to show the weather of
+    pub longitude: f64,
+    /// API key, needed for OpenWeatherMap (optional)
+    pub api_key: Option<String>,
+    /// units, `metric` or `imperial` (optional, default `metric`)
+    pub units: Option<Units>,
+    /// how often to fetch the weather, in minutes (optional, default 15)
+    pub interval: Option<u64>,
+    /// the corner to show the weather in (optional, default `top-right`)
+    pub corner: Option<Corner>,
+    /// distance from the edges of the screen in pixels (optional, default 10)
+    pub margin: Option<u32>,
+}
+
+/// Opacity of the layers drawn over the image, from 0 (invisible) to 1 (opaque)
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct LayerSettings {
+    /// opacity of the status line (optional, default 1)
+    pub status: Option<f32>,
+    /// opacity of the weather (optional, default 1)
+    pub weather: Option<f32>,
+    /// opacity of the QR code (optional, default 1)
+    pub qr: Option<f32>,
+    /// opacity of the progress bar and counter (optional, default 0.5)
+    pub progress: Option<f32>,
+}
+
+/// Settings for moving the frame by a few pixels over time, against burn-in
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct PixelShiftSettings {
+    /// the most pixels to move the frame in each direction (optional, default 4)
+    pub radius: Option<u32>,
+    /// seconds between steps of one pixel (optional, default 60)
+    pub interval: Option<u64>,
+}
+
+/// Settings for showing the progress of the slideshow
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ProgressSettings {
+    /// show a bar filling up while an image is shown (optional, default true)
+    pub bar: Option<bool>,
+    /// height of the bar in pixels (optional, default 4)
+    pub bar_height: Option<u32>,
+    /// the edge of the screen to show the bar at (optional, default `bottom`)
+    pub bar_position: Option<StatusPosition>,
+    /// color of the bar and the counter (optional, default white)
+    pub color: Option<[u8; 3]>,
+    /// show the position of the image in the slideshow, such as "3 / 120" (optional, default
+    /// false)
+    pub counter: Option<bool>,
+    /// the corner to show the counter in (optional, default `top-left`)
+    pub counter_corner: Option<Corner>,
+    /// distance of the counter from the edges of the screen in pixels (optional, default 10)
+    pub margin: Option<u32>,
+}
+
+/// Settings for a QR code linking to the image shown
+#[derive(Debug, Deserialize, Clone)]
+pub struct QrSettings {
+    /// template of the link, with the placeholders `{path}`, `{name}`, `{stem}` and `{dir}`
+    pub url: String,
+    /// take the path relative to this directory (optional)
+    pub strip_prefix: Option<String>,
+    /// the corner to show the code in (optional, default `bottom-right`)
+    pub corner: Option<Corner>,
+    /// distance from the edges of the screen in pixels (optional, default 10)
+    pub margin: Option<u32>,
+    /// size of a module, the square dots of the code, in pixels (optional, default 4)
+    pub module_size: Option<u32>,
+}
+
+/// Settings for removing dust from scanned images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct CleanupSettings {
+    /// glob patterns of the images to clean (optional, default: all images)
+    pub patterns: Option<Vec<String>>,
+    /// the size of the largest specks to remove, in pixels (optional, default 2)
+    pub max_speck: Option<u32>,
+    /// how much brighter or darker than their surroundings specks are, from 0 to 255 (optional,
+    /// default 40)
+    pub threshold: Option<u8>,
+    /// whether to remove red eyes as well (optional, default false)
+    pub red_eye: Option<bool>,
+    /// directory to store cleaned images (optional, default: the XDG cache directory)
+    pub cache_dir: Option<String>,
+}
+
+/// Settings for straightening tilted images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct StraightenSettings {
+    /// the largest tilt to correct, in degrees (optional, default 3)
+    pub max_angle: Option<f32>,
+}
+
+/// What to fill the bars around images with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LetterboxFill {
+    /// Black bars
+    Black,
+    /// A configured color
+    Color,
+    /// The dominant color of the image, darkened
+    Palette,
+    /// The average color of the image, darkened
+    Average,
+    /// A configured image, repeated
+    Texture,
+}
+
+/// Settings for the bars around images whose aspect ratio differs from the screen's
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct LetterboxSettings {
+    /// what to fill the bars with, `black`, `color`, `palette`, `average` or `texture` (optional,
+    /// default `black`)
+    pub fill: Option<LetterboxFill>,
+    /// brightness of the palette or average color, from 0 (black) to 1 (unchanged) (optional,
+    /// default 0.4)
+    pub brightness: Option<f32>,
+    /// the color of the bars with the `color` fill (optional, default black)
+    pub color: Option<[u8; 3]>,
+    /// the image to fill the bars with for the `texture` fill
+    pub texture: Option<String>,
+}
+
+/// A look given to all images shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    /// Grayscale
+    Grayscale,
+    /// Brown tones, like old photographs
+    Sepia,
+    /// Two colors, one for dark and one for light areas
+    Duotone,
+}
+
+/// Settings for the filter giving all images the same look
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct FilterSettings {
+    /// `grayscale`, `sepia` or `duotone` (optional, default: no filter)
+    pub kind: Option<FilterKind>,
+    /// the color of dark areas with the `duotone` filter (optional, default dark blue)
+    pub shadows: Option<[u8; 3]>,
+    /// the color of light areas with the `duotone` filter (optional, default cream)
+    pub highlights: Option<[u8; 3]>,
+}
+
+/// Settings for showing two portrait images side by side on a landscape screen
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct PairSettings {
+    /// the gap between the images, in pixels (optional, default 20)
+    pub gap: Option<u32>,
+    /// the color of the gap (optional, default black)
+    pub divider: Option<[u8; 3]>,
+}
+
+/// Settings for showing several images at once, in a collage
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct CollageSettings {
+    /// the number of images, from 2 to 6 (optional, default 4)
+    pub count: Option<usize>,
+    /// the gap between images, in pixels (optional, default 10)
+    pub gap: Option<u32>,
+    /// the color of the gaps, and of the bars around images not filling their cells (optional,
+    /// default black)
+    pub background: Option<[u8; 3]>,
+    /// how images fill their cells: `contain`, `cover`, or `auto` to crop only a little (optional,
+    /// default `auto`)
+    pub fit: Option<Fit>,
+    /// how portrait images fill their cells, like `fit` (optional, default `fit`)
+    pub portrait_fit: Option<Fit>,
+    /// the separator between the captions of the images in the status line (optional, default
+    /// ` | `)
+    pub separator: Option<String>,
+}
+
+/// Settings for persistent state
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct StateSettings {
+    /// the persistence backend, `json` or `sqlite` (optional, default `json`)
+    pub backend: Option<StoreBackend>,
+    /// the file to keep the state in (optional, default: in the XDG data directory)
+    pub path: Option<String>,
+    /// continue after the last shown image when restarted (optional, default false)
+    pub resume: Option<bool>,
+    /// count how often and how long each image is shown (optional, default false)
+    pub impressions: Option<bool>,
+    /// the file listing images never to show again (optional, default: in the XDG data
+    /// directory)
+    pub blacklist: Option<String>,
+}
+
+/// Settings for controlling the slideshow remotely
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ControlSettings {
+    /// address to serve the HTTP control API on, such as `127.0.0.1:8081` (optional, default:
+    /// no HTTP control API)
+    pub http: Option<String>,
+    /// the bus to serve the D-Bus interface on, `session` or `system` (optional, default: no
+    /// D-Bus interface)
+    pub dbus: Option<Bus>,
+    /// serve the control socket for `rahmenctl` (optional, default false)
+    pub socket: Option<bool>,
+    /// the path of the control socket (optional, default: `rahmen.sock` in the runtime
+    /// directory)
+    pub socket_path: Option<String>,
+    /// read the keys of attached keyboards (optional, default: true if `keyboard_devices` is set)
+    pub keyboard: Option<bool>,
+    /// the event devices to read keys from (optional, default: all keyboards)
+    pub keyboard_devices: Option<Vec<String>>,
+}
+
+/// Settings for exporting metrics to Prometheus
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct MetricsSettings {
+    /// address to serve `/metrics` on, such as `0.0.0.0:9185` (optional, default: not served)
+    pub address: Option<String>,
+    /// file to write the metrics to for the node exporter's textfile collector (optional,
+    /// default: not written)
+    pub textfile: Option<String>,
+}
+
+/// Settings for handling errors loading images and their metadata
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ErrorSettings {
+    /// `skip` the image, `retry` transient errors before skipping it, or `abort` the slideshow
+    /// (optional, default `retry`)
+    pub on_error: Option<OnError>,
+    /// how often to retry transient errors (optional, default 3)
+    pub retries: Option<u32>,
+    /// seconds to wait before the first retry, doubled for each further one (optional, default 1)
+    pub backoff: Option<f64>,
+    /// show the name of the file and the error instead of skipping the image (optional, default
+    /// false)
+    pub placeholder: Option<bool>,
+    /// an image to show instead of skipping the image, rather than the error (optional)
+    pub placeholder_image: Option<String>,
+    /// how many images in a row must fail to load before the placeholder is shown instead of
+    /// skipping them (optional, default 3)
+    pub placeholder_after: Option<u32>,
+}
+
+/// Settings for reporting the health of the slideshow
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct HealthSettings {
+    /// file to write the health to every few seconds, such as `/run/rahmen/health.json`
+    /// (optional, default: not written)
+    pub heartbeat: Option<String>,
+    /// seconds after which the slideshow counts as stuck if the main loop did not run (optional,
+    /// default 60)
+    pub max_age: Option<u64>,
+}
+
+/// Settings for blanking the screen when no motion is seen for a while
+#[derive(Debug, Deserialize, Clone)]
+pub struct MotionSettings {
+    /// the BCM number of the pin the PIR sensor is connected to
+    pub pin: u8,
+    /// minutes without motion after which to blank the screen (optional, default 10)
+    pub timeout: Option<f64>,
+    /// whether the sensor pulls the pin low on motion (optional, default false)
+    pub active_low: Option<bool>,
+}
+
+/// Settings for noticing whether anyone is home, by their phones
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct PresenceSettings {
+    /// hosts on the network to ping, by name or address (optional, but at least one host or
+    /// Bluetooth address is needed)
+    pub hosts: Option<Vec<String>>,
+    /// addresses of paired classic Bluetooth devices to ping with `l2ping` (optional)
+    pub bluetooth: Option<Vec<String>>,
+    /// seconds between looking for the devices (optional, default 60)
+    pub interval: Option<u64>,
+    /// minutes without seeing any device after which nobody counts as home (optional, default
+    /// 10)
+    pub grace: Option<f64>,
+    /// `pause` the slideshow or `blank` the screen while nobody is home (optional, default
+    /// `blank`)
+    pub away: Option<Away>,
+}
+
+/// Settings for switching the screen off while it is blank
+#[derive(Debug, Deserialize, Clone)]
+pub struct PowerSettings {
+    /// `display` to ask the display, `vcgencmd` or `cec` for HDMI screens, or `command` to run
+    /// shell commands
+    pub method: PowerMethod,
+    /// the logical CEC address of the TV (optional, default 0)
+    pub cec_address: Option<u8>,
+    /// the shell command switching the screen on, for the `command` method
+    pub on_command: Option<String>,
+    /// the shell command switching the screen off, for the `command` method
+    pub off_command: Option<String>,
+}
+
+/// A button connected to a GPIO pin
+#[derive(Debug, Deserialize, Clone)]
+pub struct GpioButton {
+    /// the BCM number of the pin
+    pub pin: u8,
+    /// the action when pressed: `next`, `previous`, `pause`, `screen-off`, `toggle-clean`,
+    /// `cycle-proof`, `favorite`, `blacklist`, `rotate-right` or `rotate-left`
+    pub action: Action,
+    /// the action when held (optional, default: none)
+    pub long_action: Option<Action>,
+    /// whether the button connects the pin to ground rather than to 3.3 V (optional, default
+    /// true)
+    pub active_low: Option<bool>,
+    /// how long the level must be stable to count, in milliseconds (optional, default 50)
+    pub debounce: Option<u64>,
+    /// how long the button must be held for the long action, in milliseconds (optional,
+    /// default 1000)
+    pub long_press: Option<u64>,
+}
+
+/// Settings for marking favorites
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct FavoriteSettings {
+    /// the XMP rating to give, from 1 to 5 (optional, default 5)
+    pub rating: Option<u8>,
+    /// the XMP label to give, such as `Favorite` (optional, default: none)
+    pub label: Option<String>,
+    /// write XMP sidecar files, leaving images untouched, rather than the images (optional,
+    /// default true)
+    pub sidecar: Option<bool>,
+}
+
+/// Settings for showing HDR images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct HdrSettings {
+    /// take HDR images down to SDR (optional, default true)
+    pub tone_map: Option<bool>,
+    /// the luminance shown as white, in nits, for HDR HEIC and AVIF images (optional, default
+    /// 1000)
+    pub peak: Option<f32>,
+}
+
+/// Settings for turning images
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct RotateSettings {
+    /// write XMP sidecar files, leaving images untouched, rather than the images (optional,
+    /// default true)
+    pub sidecar: Option<bool>,
+}
+
+/// A key bound to an action
+#[derive(Debug, Deserialize, Clone)]
+pub struct KeyBinding {
+    /// the name of the key, such as `KEY_RED`, or its code
+    pub key: String,
+    /// the action: `next`, `previous`, `pause`, `screen-off`, `toggle-clean`, `cycle-proof`,
+    /// `favorite`, `blacklist`, `rotate-right` or `rotate-left`
+    pub action: Action,
+}
+
+/// Settings for infrared remote controls
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct RemoteSettings {
+    /// the event devices of the receivers (optional, default: all receivers handled by rc-core)
+    pub devices: Option<Vec<String>>,
+    /// the socket of `lircd`, to read from LIRC instead (optional)
+    pub lircd: Option<String>,
+    /// keys bound to actions, in addition to the default ones (optional)
+    pub keys: Option<Vec<KeyBinding>>,
+}
+
+/// Settings for connecting to an MQTT broker
+#[derive(Debug, Deserialize, Clone)]
+pub struct MqttSettings {
+    /// host name of the broker
+    pub host: String,
+    /// port of the broker (optional, default 1883)
+    pub port: Option<u16>,
+    /// user name, if the broker needs one (optional)
+    pub username: Option<String>,
+    /// password, if the broker needs one (optional)
+    pub password: Option<String>,
+    /// the client id, which also identifies the frame in Home Assistant (optional, default
+    /// `rahmen`)
+    pub client_id: Option<String>,
+    /// the topic below which to publish the state and receive commands (optional, default
+    /// `rahmen`)
+    pub topic: Option<String>,
+    /// announce the frame to Home Assistant (optional, default true)
+    pub discovery: Option<bool>,
+    /// the prefix of Home Assistant's discovery topics (optional, default `homeassistant`)
+    pub discovery_prefix: Option<String>,
+    /// the name of the frame in Home Assistant (optional, default `Rahmen`)
+    pub name: Option<String>,
+}
+
+/// Settings for the transcoding cache
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct TranscodeSettings {
+    /// directory to store transcoded images (optional, default: the XDG cache directory)
+    pub cache_dir: Option<String>,
+    /// the converters to use
+    #[serde(default)]
+    pub converter: Vec<Converter>,
+    /// maximum size of the cache in megabytes, enforced during maintenance (optional)
+    pub max_cache_size: Option<u64>,
+}
+
+/// Settings for the daily maintenance
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaintenanceSettings {
+    /// time of day (`HH:MM`) to perform maintenance
+    pub at: String,
+    /// exit afterwards, for a service manager to restart Rahmen, instead of restarting in place
+    /// (optional, default false)
+    pub exit: Option<bool>,
+    /// how many older log files to keep when logging to a file (optional, default 7)
+    pub logs_kept: Option<usize>,
+}
+
+/// Settings for the DRM/KMS display
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct DrmSettings {
+    /// display mode as `WxH` or `WxH@R` (optional, default: the display's preferred mode)
+    pub mode: Option<String>,
+}
+
+/// Settings for the framebuffer display
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct FramebufferSettings {
+    /// rotate the content clockwise by 0, 90, 180 or 270 degrees (optional, default 0)
+    pub rotate: Option<u16>,
+    /// show what the console showed before when stopping, instead of a black screen (optional,
+    /// default true)
+    pub restore: Option<bool>,
+}
+
+/// Settings for e-paper displays
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct EinkSettings {
+    /// number of gray levels the panel can show (optional, default 16)
+    pub gray_levels: Option<u8>,
+    /// do a full refresh after this many updates to clear ghosting (optional, default 10)
+    pub full_refresh_every: Option<usize>,
+}
+
+/// Settings for serving the frame over HTTP
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct HttpSettings {
+    /// address to listen on (optional, default `0.0.0.0:8080`)
+    pub listen: Option<String>,
+    /// frame resolution as `WxH` (optional, default `1920x1080`)
+    pub resolution: Option<String>,
+    /// JPEG quality from 1 to 100 (optional, default 85)
+    pub quality: Option<u8>,
+    /// reload interval of the `/refresh` page in seconds (optional, default 10)
+    pub refresh: Option<u32>,
+    /// how many clients are served at the same time, each MJPEG stream holding one connection
+    /// (optional, default 8)
+    pub max_clients: Option<usize>,
+}
+
+/// One of several displays driven at once
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutputSettings {
+    /// the display provider: `framebuffer`, `eink`, `drm`, `http` or `terminal`
+    pub display: String,
+    /// the device, or the address to listen on for `http` (optional, default as for `--output`)
+    pub device: Option<String>,
+}
+
+/// Settings for driving several displays at once
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct MultiSettings {
+    /// `mirror` or `alternate` (optional, default `mirror`)
+    pub mode: Option<MultiMode>,
+    /// the displays, images are composed at the size of the first one
+    #[serde(default)]
+    pub output: Vec<OutputSettings>,
+}
+
+/// Settings for the FLTK display
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct FltkSettings {
+    /// start in fullscreen mode (optional, default false)
+    pub fullscreen: Option<bool>,
+    /// leave out the title bar and borders (optional, default false)
+    pub borderless: Option<bool>,
+    /// ask the window manager to keep the window above all others, which needs the `x11` feature
+    /// (optional, default false)
+    pub always_on_top: Option<bool>,
+    /// the monitor to show the window on, counting from 0 (optional, default: the primary one)
+    pub monitor: Option<usize>,
+    /// hide the mouse cursor over the window (optional, default false)
+    pub hide_cursor: Option<bool>,
+}
+
+/// Settings for the PNG display
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct PngSettings {
+    /// frame resolution as `WxH` (optional, default `1920x1080`)
+    pub resolution: Option<String>,
+    /// the directory to write the frames to as numbered PNG files (optional, default: keep them in
+    /// memory only)
+    pub dir: Option<String>,
+    /// stop after this many frames (optional, default: never)
+    pub frames: Option<u64>,
+}
+
+/// Settings for the SDL2 display
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct Sdl2Settings {
+    /// render at this resolution (`WxH`) and scale to the window (optional, default: window size)
+    pub resolution: Option<String>,
+}
+
+/// Settings for the terminal display
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct TerminalSettings {
+    /// graphics protocol, `kitty` or `sixel` (optional, default: guessed from the environment)
+    pub protocol: Option<GraphicsProtocol>,
+}
+
+/// Settings for the X11 display
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct X11Settings {
+    /// bypass the window manager: no decorations, always on top (optional, default false)
+    pub override_redirect: Option<bool>,
+}
+
 /// Config file root structure
 #[derive(Debug, Default, Deserialize, Clone)]
 // this is called in rahmen.rs where a new status line formatter is constructed
@@ -39,10 +884,27 @@ pub struct Settings {
     pub delay: Option<f64>,
     /// Font size of the status line (optional)
     pub font_size: Option<f32>,
+    /// Font and effects for the status line
+    pub font: Option<FontSettings>,
+    /// Placement and background of the status line
+    pub status: Option<StatusSettings>,
     /// Python module paths
     pub py_path: Option<Vec<String>>,
     /// python code to postprocess the status line
     pub py_postprocess: Option<String>,
+    /// Python module registering easing curves for transitions (optional)
+    pub py_easings: Option<String>,
+    /// Lua module to postprocess the status line
+    pub lua_postprocess: Option<String>,
+    /// Lua module paths, searched before the default ones
+    pub lua_path: Option<Vec<String>>,
+    /// Name the place a photo was taken at
+    pub geocode: Option<GeocodeSettings>,
+    /// Show how far from home a photo was taken
+    pub home: Option<HomeSettings>,
+    /// The time zone the camera's clock is set to, such as `Europe/Zurich` (optional, default:
+    /// the local time zone)
+    pub camera_timezone: Option<String>,
     /// the separator that will be inserted between every element (metadata) of the
     /// status line (optional, but default is to insert ", ")
     pub separator: Option<String>,
@@ -52,4 +914,235 @@ pub struct Settings {
     pub display_time: Option<bool>,
     /// Time format string
     pub time_format: Option<String>,
+    /// Periods during which only the image is shown, without status line
+    pub quiet_hours: Option<Vec<DailyPeriod>>,
+    /// Brightness and gamma to apply depending on the time of day
+    pub brightness: Option<Vec<BrightnessEntry>>,
+    /// Dim the images with the light in the room
+    pub light_sensor: Option<LightSensorSettings>,
+    /// Dim the backlight of the panel, rather than the images
+    pub backlight: Option<BacklightSettings>,
+    /// Blocks of time changing the playlist, the interval or turning the screen off, the first
+    /// matching block applies
+    pub schedule: Option<Vec<ScheduleBlock>>,
+    /// Playlists to switch to by schedule, by their names, each a list file or a pattern
+    pub playlists: Option<BTreeMap<String, String>>,
+    /// Simulate another kind of display (optional, `grayscale` or `eink`)
+    pub proof: Option<Proof>,
+    /// Additional sources of images, interleaved with the input
+    pub source: Option<Vec<SourceSettings>>,
+    /// Show an image of every source at least after this many images of other sources
+    pub max_gap: Option<usize>,
+    /// Show the images in random order
+    pub shuffle: Option<bool>,
+    /// Seed for a reproducible random order, implies `shuffle`
+    pub seed: Option<u64>,
+    /// Number of upcoming images to load in the background (optional, default 1)
+    pub prefetch: Option<usize>,
+    /// Number of images shown to remember, to go back to (optional, default 50)
+    pub history: Option<usize>,
+    /// Where to read the durations of single images from
+    pub duration: Option<DurationSettings>,
+    /// Skip near-duplicates of recent images
+    pub duplicates: Option<DuplicateSettings>,
+    /// Skip blurry and nearly black or white images
+    pub quality: Option<QualitySettings>,
+    /// Skip images too small for the screen
+    pub resolution: Option<ResolutionSettings>,
+    /// Select images of the same orientation as the screen
+    pub orientation: Option<OrientationSettings>,
+    /// Restart daily, after cleaning up
+    pub maintenance: Option<MaintenanceSettings>,
+    /// Show the weather in a corner
+    pub weather: Option<WeatherSettings>,
+    /// Opacity of the layers drawn over the image
+    pub layers: Option<LayerSettings>,
+    /// Move the frame by a few pixels over time, against burn-in
+    pub pixel_shift: Option<PixelShiftSettings>,
+    /// Show a QR code linking to the image shown
+    pub qr: Option<QrSettings>,
+    /// Show the progress of the slideshow
+    pub progress: Option<ProgressSettings>,
+    /// Remove dust and scratches from scanned images
+    pub cleanup: Option<CleanupSettings>,
+    /// Straighten slightly tilted images
+    pub straighten: Option<StraightenSettings>,
+    /// Bars around images whose aspect ratio differs from the screen's
+    pub letterbox: Option<LetterboxSettings>,
+    /// Give all images the same look
+    pub filter: Option<FilterSettings>,
+    /// Show two portrait images side by side on a landscape screen
+    pub pairs: Option<PairSettings>,
+    /// Show several images at once, in a collage
+    pub collage: Option<CollageSettings>,
+    /// Fade between images
+    pub transition: Option<TransitionSettings>,
+    /// Persistent state
+    pub state: Option<StateSettings>,
+    /// Control the slideshow remotely
+    pub control: Option<ControlSettings>,
+    /// Publish the state and receive commands over MQTT
+    pub mqtt: Option<MqttSettings>,
+    /// Export metrics to Prometheus
+    pub metrics: Option<MetricsSettings>,
+    /// Report the health of the slideshow
+    pub health: Option<HealthSettings>,
+    /// What to do when loading an image or its metadata fails
+    pub errors: Option<ErrorSettings>,
+    /// Buttons connected to GPIO pins
+    pub gpio: Option<Vec<GpioButton>>,
+    /// Infrared remote controls
+    pub remote: Option<RemoteSettings>,
+    /// Blank the screen when no motion is seen for a while
+    pub motion: Option<MotionSettings>,
+    /// Pause or blank the screen while nobody is home
+    pub presence: Option<PresenceSettings>,
+    /// Switch the screen off while it is blank
+    pub power: Option<PowerSettings>,
+    /// How to mark favorites
+    pub favorite: Option<FavoriteSettings>,
+    /// How to remember turning images
+    pub rotate: Option<RotateSettings>,
+    /// Transcode images in other formats, and cache the results
+    pub transcode: Option<TranscodeSettings>,
+    /// How to show HDR images
+    pub hdr: Option<HdrSettings>,
+    /// Settings for the DRM/KMS display
+    pub drm: Option<DrmSettings>,
+    /// Settings for the framebuffer display
+    pub framebuffer: Option<FramebufferSettings>,
+    /// Settings for the e-paper display
+    pub eink: Option<EinkSettings>,
+    /// Settings for the FLTK display
+    pub fltk: Option<FltkSettings>,
+    /// Settings for the PNG display
+    pub png: Option<PngSettings>,
+    /// Settings for serving the frame over HTTP
+    pub http: Option<HttpSettings>,
+    /// Settings for driving several displays at once
+    pub multi: Option<MultiSettings>,
+    /// Settings for the SDL2 display
+    pub sdl2: Option<Sdl2Settings>,
+    /// Settings for the terminal display
+    pub terminal: Option<TerminalSettings>,
+    /// Settings for the X11 display
+    pub x11: Option<X11Settings>,
+}
+
+/// Replace the references `${VAR}` in a string value of the configuration by the value of the
+/// environment variable `VAR`, or by `default` for `${VAR:-default}` if it is not set. `$${`
+/// stands for a literal `${`. An unset variable without a default is an error.
+pub fn expand_env(text: &str) -> RahmenResult<String> {
+    let error = |message: String| {
+        RahmenError::ConfigError(Arc::new(config::ConfigError::Message(format!(
+            "{} in {:?}",
+            message, text
+        ))))
+    };
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            expanded.push_str(&rest[..start - 1]);
+            expanded.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| error("unclosed ${".into()))?;
+        let reference = &rest[start + 2..start + end];
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        match (std::env::var(name), default) {
+            (Ok(value), _) => expanded.push_str(&value),
+            (Err(_), Some(default)) => expanded.push_str(default),
+            (Err(_), None) => {
+                return Err(error(format!("environment variable {} is not set", name)))
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// The names of the configuration files looked for, in order
+pub const CONFIG_FILE_NAMES: &[&str] = &["rahmen.toml", "rahmen.yaml", "rahmen.yml", "rahmen.json"];
+
+/// The format of a configuration file, by its extension: YAML for `.yaml` and `.yml`, JSON for
+/// `.json`, and TOML otherwise
+pub fn file_format(path: &Path) -> config::FileFormat {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml") | Some("yml") => config::FileFormat::Yaml,
+        Some("json") => config::FileFormat::Json,
+        _ => config::FileFormat::Toml,
+    }
+}
+
+/// Expand the references to environment variables in the strings of `value`, and of the tables and
+/// lists it contains. The kinds of values are private to the config crate, so they are told apart
+/// through a JSON value.
+fn expand_value(value: config::Value) -> RahmenResult<config::Value> {
+    expand_json(<serde_json::Value as serde::Deserialize>::deserialize(
+        value,
+    )?)
+}
+
+fn expand_json(value: serde_json::Value) -> RahmenResult<config::Value> {
+    Ok(match value {
+        serde_json::Value::String(text) => config::Value::new(None, expand_env(&text)?),
+        serde_json::Value::Object(table) => config::Value::new(
+            None,
+            table
+                .into_iter()
+                .map(|(key, value)| Ok((key, expand_json(value)?)))
+                .collect::<RahmenResult<HashMap<_, _>>>()?,
+        ),
+        serde_json::Value::Array(array) => config::Value::new(
+            None,
+            array
+                .into_iter()
+                .map(expand_json)
+                .collect::<RahmenResult<Vec<_>>>()?,
+        ),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(number) => config::Value::new(None, number),
+            None => config::Value::new(None, number.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::Bool(flag) => config::Value::new(None, flag),
+        serde_json::Value::Null => config::Value::new(None, None::<String>),
+    })
+}
+
+/// The settings of a configuration file, with the references to environment variables expanded
+#[derive(Debug, Clone)]
+struct ExpandedFile(HashMap<String, config::Value>);
+
+impl config::Source for ExpandedFile {
+    fn clone_into_box(&self) -> Box<dyn config::Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, config::Value>, config::ConfigError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Read the configuration file at `path` into `c`, in the format of its extension, with the
+/// references to environment variables in string values expanded. They are expanded after parsing,
+/// so comments are left alone and the values of variables can't change the structure of the file.
+pub fn merge_file(c: &mut config::Config, path: &Path) -> RahmenResult<()> {
+    use config::Source;
+    let text = std::fs::read_to_string(path)?;
+    let settings = config::File::from_str(&text, file_format(path))
+        .collect()?
+        .into_iter()
+        .map(|(key, value)| Ok((key, expand_value(value)?)))
+        .collect::<RahmenResult<_>>()?;
+    c.merge(ExpandedFile(settings))?;
+    Ok(())
 }
@@ -0,0 +1,256 @@
+//! Commands to control a running slideshow
+//!
+//! Inputs, such as key presses, send commands through a channel, which the main loop drains. The
+//! main loop reports what it shows in a shared `Status`, which control interfaces can read, and
+//! how it is doing, so monitoring can tell a frame that is stuck.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::metadata::Metadata;
+use crate::xmp;
+
+/// A command issued by an input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Switch to the next soft proofing mode
+    CycleProof,
+    /// Hide or show the status line
+    ToggleClean,
+    /// Show the next image now
+    Next,
+    /// Show the image shown before the current one
+    Previous,
+    /// Keep showing the current image
+    Pause,
+    /// Continue the slideshow after a pause
+    Resume,
+    /// Pause, or resume if paused
+    TogglePause,
+    /// Show the given image now, and continue the slideshow after it
+    Show(PathBuf),
+    /// Show images for the given time from now on
+    SetInterval(Duration),
+    /// Set the brightness, in percent, overriding the brightness schedule
+    SetBrightness(u8),
+    /// Blank the screen, or show the slideshow again if blanked
+    ToggleScreen,
+    /// Start over with the configuration read again
+    Reload,
+    /// Mark the image shown as a favorite
+    Favorite,
+    /// Never show the image shown again, and go to the next one
+    Blacklist,
+    /// Turn the image shown by a quarter turn, clockwise if true, and remember it
+    Rotate(bool),
+    /// Stop the slideshow, saving its state
+    Quit,
+    /// Someone is in front of the frame, so show the slideshow if blanked for lack of motion
+    Motion,
+    /// Someone came home, or everyone left
+    Presence(bool),
+}
+
+impl Command {
+    /// The command setting the brightness to `percent`, rounded, which every control checks the
+    /// same way: it must be from 0 to 100
+    pub fn set_brightness(percent: f64) -> Result<Self, String> {
+        if (0. ..=100.).contains(&percent) {
+            Ok(Command::SetBrightness(percent.round() as u8))
+        } else {
+            Err(format!("invalid brightness {}, expected 0 to 100", percent))
+        }
+    }
+}
+
+/// An action bound to a button or key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// Show the next image now
+    Next,
+    /// Show the image shown before the current one
+    Previous,
+    /// Pause, or resume if paused
+    Pause,
+    /// Blank the screen, or show the slideshow again if blanked
+    ScreenOff,
+    /// Hide or show the status line
+    ToggleClean,
+    /// Switch to the next soft proofing mode
+    CycleProof,
+    /// Mark the image shown as a favorite
+    Favorite,
+    /// Never show the image shown again, and go to the next one
+    Blacklist,
+    /// Turn the image shown clockwise
+    RotateRight,
+    /// Turn the image shown counterclockwise
+    RotateLeft,
+}
+
+impl Action {
+    /// The command to issue for this action
+    pub fn command(self) -> Command {
+        match self {
+            Action::Next => Command::Next,
+            Action::Previous => Command::Previous,
+            Action::Pause => Command::TogglePause,
+            Action::ScreenOff => Command::ToggleScreen,
+            Action::ToggleClean => Command::ToggleClean,
+            Action::CycleProof => Command::CycleProof,
+            Action::Favorite => Command::Favorite,
+            Action::Blacklist => Command::Blacklist,
+            Action::RotateRight => Command::Rotate(true),
+            Action::RotateLeft => Command::Rotate(false),
+        }
+    }
+}
+
+/// What the slideshow shows
+#[derive(Debug, Clone)]
+pub struct Status {
+    /// the image shown, if any
+    pub path: Option<PathBuf>,
+    /// the lines of the status line of the image shown
+    pub status_line: Vec<String>,
+    /// whether the slideshow is paused
+    pub paused: bool,
+    /// how long images are shown
+    pub interval: Duration,
+    /// the brightness set by a command, in percent, if any
+    pub brightness: Option<u8>,
+    /// when the slideshow started
+    pub started: Instant,
+    /// when the main loop last ran
+    pub alive_at: Instant,
+    /// when the display was last updated successfully, if ever
+    pub displayed_at: Option<SystemTime>,
+    /// whether the last update of the display failed
+    pub display_failed: bool,
+    /// the last error, and when it happened
+    pub last_error: Option<(SystemTime, String)>,
+}
+
+/// A status shared between the main loop and control interfaces
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+impl Status {
+    /// Create the status of a slideshow starting now, showing images for `interval`
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            path: None,
+            status_line: vec![],
+            paused: false,
+            interval,
+            brightness: None,
+            started: Instant::now(),
+            alive_at: Instant::now(),
+            displayed_at: None,
+            display_failed: false,
+            last_error: None,
+        }
+    }
+
+    /// Record that the display was updated successfully
+    pub fn displayed(&mut self) {
+        self.displayed_at = Some(SystemTime::now());
+        self.display_failed = false;
+    }
+
+    /// Record an error
+    pub fn error(&mut self, message: String) {
+        self.last_error = Some((SystemTime::now(), message));
+    }
+
+    /// Record an error updating the display
+    pub fn display_error(&mut self, message: String) {
+        self.error(message);
+        self.display_failed = true;
+    }
+
+    /// Whether the main loop ran within `max_age`, and the display did not fail since
+    pub fn is_healthy(&self, max_age: Duration) -> bool {
+        self.alive_at.elapsed() <= max_age && !self.display_failed
+    }
+
+    /// The health as a JSON object, with times as seconds since the epoch
+    pub fn health_json(&self, max_age: Duration) -> serde_json::Value {
+        let epoch = |time: SystemTime| {
+            time.duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        };
+        serde_json::json!({
+            "healthy": self.is_healthy(max_age),
+            "loop_age": self.alive_at.elapsed().as_secs_f64(),
+            "displayed_at": self.displayed_at.map(epoch),
+            "last_error": self.last_error.as_ref().map(|(at, message)| serde_json::json!({
+                "at": epoch(*at),
+                "message": message,
+            })),
+        })
+    }
+
+    /// The status as a JSON object
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "path": self.path,
+            "status_line": self.status_line,
+            "paused": self.paused,
+            "interval": self.interval.as_secs_f64(),
+            "brightness": self.brightness,
+            "uptime": self.started.elapsed().as_secs(),
+        })
+    }
+}
+
+/// The status as a JSON object, with the metadata of the image shown under `metadata`. The
+/// metadata is read without holding the lock, so the main loop doesn't wait for it.
+pub fn status_json(status: &SharedStatus) -> serde_json::Value {
+    let status = status.lock().unwrap().clone();
+    let mut json = status.to_json();
+    json["metadata"] = status
+        .path
+        .as_deref()
+        .map_or(serde_json::Value::Null, image_metadata);
+    json
+}
+
+/// The metadata of the image at `path` reported with the status, as far as it is known: its
+/// dimensions, when, with which camera and where it was taken, its caption, rating, keywords and
+/// the people shown
+pub fn image_metadata(path: &Path) -> serde_json::Value {
+    let metadata = match Metadata::new_from_path(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return serde_json::Value::Null,
+    };
+    let tag = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            let value = if xmp::handles(name) {
+                xmp::tag(&metadata, name)
+            } else {
+                metadata.get_tag_interpreted_string(name).ok()
+            };
+            value.filter(|value| !value.trim().is_empty())
+        })
+    };
+    serde_json::json!({
+        "width": metadata.get_pixel_width(),
+        "height": metadata.get_pixel_height(),
+        "taken": tag(&["Exif.Photo.DateTimeOriginal", "Exif.Image.DateTime"]),
+        "camera": tag(&["Exif.Image.Model"]),
+        "location": metadata.get_gps_info().map(|gps| serde_json::json!({
+            "latitude": gps.latitude,
+            "longitude": gps.longitude,
+        })),
+        "caption": tag(&["Xmp.dc.description", "Iptc.Application2.Caption"]),
+        "rating": if metadata.has_tag("Xmp.xmp.Rating") {
+            Some(metadata.get_tag_numeric("Xmp.xmp.Rating"))
+        } else {
+            None
+        },
+        "keywords": tag(&["Rahmen.Xmp.Keywords", "Xmp.dc.subject", "Iptc.Application2.Keywords"]),
+        "persons": tag(&["Rahmen.Xmp.Persons"]),
+    })
+}
@@ -0,0 +1,348 @@
+//! Control the slideshow over D-Bus
+//!
+//! The interface `org.rahmen.Frame1` is served at `/org/rahmen/Frame` under the bus name
+//! `org.rahmen.Frame`, on the session or the system bus. Its methods control the slideshow, and
+//! its properties report what is shown, so desktop tools and systemd units can script the frame
+//! with `busctl` or `gdbus`. Changes of the properties are announced with `PropertiesChanged`, so
+//! clients can follow the slideshow without polling. D-Bus support is behind the `dbus` feature.
+
+use std::sync::mpsc::Sender;
+
+#[cfg(feature = "dbus")]
+use std::collections::HashMap;
+#[cfg(feature = "dbus")]
+use std::path::PathBuf;
+#[cfg(feature = "dbus")]
+use std::sync::Mutex;
+#[cfg(feature = "dbus")]
+use std::time::Duration;
+
+#[cfg(feature = "dbus")]
+use tracing::{debug, info, warn};
+
+use crate::control::{Command, SharedStatus, Status};
+use crate::errors::{RahmenError, RahmenResult};
+
+/// The well-known name requested on the bus
+pub const BUS_NAME: &str = "org.rahmen.Frame";
+
+/// The path of the object implementing the interface
+pub const OBJECT_PATH: &str = "/org/rahmen/Frame";
+
+/// The name of the interface
+pub const INTERFACE: &str = "org.rahmen.Frame1";
+
+/// How long to wait after the first of consecutive failures to handle requests, doubled for each
+/// further one
+#[cfg(feature = "dbus")]
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+/// The longest wait after failures to handle requests
+#[cfg(feature = "dbus")]
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The bus to serve the interface on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    /// The bus of the user's session
+    Session,
+    /// The system-wide bus, which needs a policy allowing Rahmen to own the name
+    System,
+}
+
+/// The object implementing `org.rahmen.Frame1`
+#[cfg(feature = "dbus")]
+struct Frame {
+    commands: Mutex<Sender<Command>>,
+    status: SharedStatus,
+}
+
+#[cfg(feature = "dbus")]
+impl Frame {
+    fn send(&self, command: Command) -> zbus::fdo::Result<()> {
+        self.commands
+            .lock()
+            .unwrap()
+            .send(command)
+            .map_err(|_| zbus::fdo::Error::Failed("slideshow stopped".into()))
+    }
+}
+
+#[cfg(feature = "dbus")]
+#[zbus::dbus_interface(name = "org.rahmen.Frame1")]
+impl Frame {
+    /// Show the next image now
+    fn next(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::Next)
+    }
+
+    /// Show the image shown before the current one
+    fn previous(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::Previous)
+    }
+
+    /// Keep showing the current image
+    fn pause(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::Pause)
+    }
+
+    /// Continue the slideshow
+    fn resume(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::Resume)
+    }
+
+    /// Pause, or resume if paused
+    fn toggle_pause(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::TogglePause)
+    }
+
+    /// Mark the image shown as a favorite
+    fn favorite(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::Favorite)
+    }
+
+    /// Never show the image shown again, and go to the next one
+    fn blacklist(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::Blacklist)
+    }
+
+    /// Turn the image shown by a quarter turn, clockwise or counterclockwise, and remember it
+    fn rotate(&self, clockwise: bool) -> zbus::fdo::Result<()> {
+        self.send(Command::Rotate(clockwise))
+    }
+
+    /// Show the image at `path` now
+    fn show(&self, path: &str) -> zbus::fdo::Result<()> {
+        self.send(Command::Show(PathBuf::from(path)))
+    }
+
+    /// Show images for `seconds` from now on
+    fn set_interval(&self, seconds: f64) -> zbus::fdo::Result<()> {
+        if seconds <= 0. || !seconds.is_finite() {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "invalid interval {}",
+                seconds
+            )));
+        }
+        self.send(Command::SetInterval(Duration::from_secs_f64(seconds)))
+    }
+
+    /// Set the brightness to `percent`, overriding the brightness schedule
+    fn set_brightness(&self, percent: f64) -> zbus::fdo::Result<()> {
+        let command = Command::set_brightness(percent).map_err(zbus::fdo::Error::InvalidArgs)?;
+        self.send(command)
+    }
+
+    /// The path of the image shown, empty before the first image
+    #[dbus_interface(property)]
+    fn current_image(&self) -> String {
+        let status = self.status.lock().unwrap();
+        status
+            .path
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// The lines of the status line of the image shown
+    #[dbus_interface(property)]
+    fn status_line(&self) -> Vec<String> {
+        self.status.lock().unwrap().status_line.clone()
+    }
+
+    /// Whether the slideshow is paused
+    #[dbus_interface(property)]
+    fn paused(&self) -> bool {
+        self.status.lock().unwrap().paused
+    }
+
+    /// How long images are shown, in seconds
+    #[dbus_interface(property)]
+    fn interval(&self) -> f64 {
+        self.status.lock().unwrap().interval.as_secs_f64()
+    }
+
+    /// How long the slideshow is running, in seconds
+    #[dbus_interface(property)]
+    fn uptime(&self) -> u64 {
+        self.status.lock().unwrap().started.elapsed().as_secs()
+    }
+}
+
+/// The properties that are announced when they change
+#[cfg(feature = "dbus")]
+#[derive(Debug, Clone, PartialEq)]
+struct Properties {
+    current_image: String,
+    status_line: Vec<String>,
+    paused: bool,
+    interval: f64,
+}
+
+#[cfg(feature = "dbus")]
+impl Properties {
+    fn of(status: &Status) -> Self {
+        Self {
+            current_image: status
+                .path
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            status_line: status.status_line.clone(),
+            paused: status.paused,
+            interval: status.interval.as_secs_f64(),
+        }
+    }
+
+    /// The properties that differ from `previous`, by their D-Bus names
+    fn changed(&self, previous: Option<&Self>) -> HashMap<&'static str, zvariant::Value<'_>> {
+        let mut changed = HashMap::new();
+        if previous.map(|p| &p.current_image) != Some(&self.current_image) {
+            changed.insert("CurrentImage", self.current_image.as_str().into());
+        }
+        if previous.map(|p| &p.status_line) != Some(&self.status_line) {
+            changed.insert("StatusLine", self.status_line.clone().into());
+        }
+        if previous.map(|p| p.paused) != Some(self.paused) {
+            changed.insert("Paused", self.paused.into());
+        }
+        if previous.map(|p| p.interval) != Some(self.interval) {
+            changed.insert("Interval", self.interval.into());
+        }
+        changed
+    }
+}
+
+/// The interface served on a bus, to announce changes of its properties
+pub struct Dbus {
+    #[cfg(feature = "dbus")]
+    connection: zbus::Connection,
+    /// the properties announced last, to announce changes only
+    #[cfg(feature = "dbus")]
+    published: Option<Properties>,
+}
+
+impl std::fmt::Debug for Dbus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dbus").finish()
+    }
+}
+
+impl Dbus {
+    /// Announce the properties that changed with `PropertiesChanged`
+    #[cfg(feature = "dbus")]
+    pub fn publish_state(&mut self, status: &Status) {
+        let properties = Properties::of(status);
+        let changed = properties.changed(self.published.as_ref());
+        if changed.is_empty() {
+            return;
+        }
+        match self.connection.emit_signal(
+            None,
+            OBJECT_PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(INTERFACE, changed, Vec::<&str>::new()),
+        ) {
+            Ok(()) => self.published = Some(properties),
+            // announced again with the next change
+            Err(e) => debug!("Announcing D-Bus properties failed: {}", e),
+        }
+    }
+
+    /// Announce the properties that changed, which needs D-Bus support
+    #[cfg(not(feature = "dbus"))]
+    pub fn publish_state(&mut self, _status: &Status) {}
+}
+
+#[cfg(feature = "dbus")]
+fn dbus_err(e: zbus::Error) -> RahmenError {
+    RahmenError::DbusError(e.to_string())
+}
+
+/// Connect to `bus`
+#[cfg(feature = "dbus")]
+fn connect(bus: Bus) -> RahmenResult<zbus::Connection> {
+    match bus {
+        Bus::Session => zbus::Connection::new_session(),
+        Bus::System => zbus::Connection::new_system(),
+    }
+    .map_err(|e| RahmenError::DbusError(format!("cannot connect to the {:?} bus: {}", bus, e)))
+}
+
+/// Check that the interface can be served on `bus`, which can be connected to
+#[cfg(feature = "dbus")]
+pub fn check(bus: Bus) -> RahmenResult<()> {
+    connect(bus).map(|_| ())
+}
+
+/// Check that the interface can be served on `bus`, which needs D-Bus support
+#[cfg(not(feature = "dbus"))]
+pub fn check(bus: Bus) -> RahmenResult<()> {
+    Err(RahmenError::Unsupported(format!(
+        "compiled without D-Bus support, cannot serve on the {:?} bus",
+        bus
+    )))
+}
+
+/// Serve the interface on `bus` in a background thread, sending commands to `commands` and
+/// reporting `status`. Requests are no longer handled once the connection to the bus is lost.
+#[cfg(feature = "dbus")]
+pub fn serve(bus: Bus, commands: Sender<Command>, status: SharedStatus) -> RahmenResult<Dbus> {
+    let connection = connect(bus)?;
+    let reply = zbus::fdo::DBusProxy::new(&connection)
+        .map_err(dbus_err)?
+        .request_name(BUS_NAME, zbus::fdo::RequestNameFlags::DoNotQueue.into())
+        .map_err(|e| RahmenError::DbusError(e.to_string()))?;
+    if reply != zbus::fdo::RequestNameReply::PrimaryOwner {
+        return Err(RahmenError::DbusError(format!(
+            "{} is owned by another process",
+            BUS_NAME
+        )));
+    }
+    info!("D-Bus interface {} on the {:?} bus", BUS_NAME, bus);
+    let dbus = Dbus {
+        connection: connection.clone(),
+        published: None,
+    };
+    // the object server is bound to the thread it handles requests on
+    std::thread::spawn(move || {
+        let mut server = zbus::ObjectServer::new(&connection);
+        let frame = Frame {
+            commands: Mutex::new(commands),
+            status,
+        };
+        if let Err(e) = server.at(
+            &zvariant::ObjectPath::from_str_unchecked(OBJECT_PATH),
+            frame,
+        ) {
+            warn!("D-Bus interface failed: {}", e);
+            return;
+        }
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            match server.try_handle_next() {
+                Ok(_) => backoff = MIN_BACKOFF,
+                Err(zbus::Error::Io(e)) => {
+                    warn!("D-Bus connection lost: {}", e);
+                    return;
+                }
+                Err(e) => {
+                    warn!("D-Bus request failed: {}", e);
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    });
+    Ok(dbus)
+}
+
+/// Serve the interface on `bus`, which needs D-Bus support
+#[cfg(not(feature = "dbus"))]
+pub fn serve(bus: Bus, _commands: Sender<Command>, _status: SharedStatus) -> RahmenResult<Dbus> {
+    Err(RahmenError::Unsupported(format!(
+        "compiled without D-Bus support, cannot serve on the {:?} bus",
+        bus
+    )))
+}
@@ -0,0 +1,93 @@
+//! Control the slideshow over HTTP
+//!
+//! A small REST API: `GET /status` reports what is shown, and `POST` requests to `/next`,
+//! `/previous`, `/pause`, `/resume`, `/favorite`, `/blacklist`, `/rotate` (with `left` as body to
+//! turn counterclockwise), `/show` (with the path of an image as body), `/interval` (with the
+//! seconds to show each image as body) and `/brightness` (with the percent as body) control the
+//! slideshow. All requests answer with the status as JSON, including the metadata of the image
+//! shown. `GET /health` reports the health instead, and answers with `503 Service Unavailable` if
+//! the slideshow is stuck.
+
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::control::{status_json, Command, SharedStatus};
+use crate::errors::RahmenResult;
+use crate::http_server::{self, Limits, Request, Response};
+
+/// Serve the API on `address`, such as `127.0.0.1:8081`, in a background thread, sending
+/// commands to `commands` and reporting `status`, which is unhealthy if the main loop did not run
+/// for `max_age`
+pub fn serve(
+    address: &str,
+    commands: Sender<Command>,
+    status: SharedStatus,
+    max_age: Duration,
+) -> RahmenResult<()> {
+    let commands = Mutex::new(commands);
+    let address = http_server::serve(address, Limits::default(), move |request| {
+        handle(request, &commands, &status, max_age)
+    })?;
+    info!("Control API on http://{}/", address);
+    Ok(())
+}
+
+fn error(status: &'static str, message: &str) -> Response {
+    Response::json(status, &serde_json::json!({ "error": message }))
+}
+
+fn handle(
+    request: Request,
+    commands: &Mutex<Sender<Command>>,
+    status: &SharedStatus,
+    max_age: Duration,
+) -> Response {
+    let body = String::from_utf8_lossy(&request.body);
+    let command = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => None,
+        ("GET", "/health") => {
+            let status = status.lock().unwrap();
+            let body = status.health_json(max_age);
+            return if status.is_healthy(max_age) {
+                Response::json("200 OK", &body)
+            } else {
+                Response::json("503 Service Unavailable", &body)
+            };
+        }
+        ("POST", "/next") => Some(Command::Next),
+        ("POST", "/previous") => Some(Command::Previous),
+        ("POST", "/pause") => Some(Command::Pause),
+        ("POST", "/resume") => Some(Command::Resume),
+        ("POST", "/favorite") => Some(Command::Favorite),
+        ("POST", "/blacklist") => Some(Command::Blacklist),
+        ("POST", "/rotate") => Some(Command::Rotate(body.trim() != "left")),
+        ("POST", "/show") if !body.trim().is_empty() => {
+            Some(Command::Show(PathBuf::from(body.trim())))
+        }
+        ("POST", "/interval") => match body.trim().parse::<f64>() {
+            Ok(seconds) if seconds > 0. && seconds.is_finite() => {
+                Some(Command::SetInterval(Duration::from_secs_f64(seconds)))
+            }
+            _ => return error("400 Bad Request", "expected seconds"),
+        },
+        ("POST", "/brightness") => match body.trim().parse::<f64>() {
+            Ok(percent) => match Command::set_brightness(percent) {
+                Ok(command) => Some(command),
+                Err(message) => return error("400 Bad Request", &message),
+            },
+            _ => return error("400 Bad Request", "expected percent"),
+        },
+        ("POST", "/show") => return error("400 Bad Request", "expected a path"),
+        _ => return error("404 Not Found", "unknown request"),
+    };
+    if let Some(command) = command {
+        if commands.lock().unwrap().send(command).is_err() {
+            return error("503 Service Unavailable", "slideshow stopped");
+        }
+    }
+    Response::json("200 OK", &status_json(status))
+}
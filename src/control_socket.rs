@@ -0,0 +1,161 @@
+//! Control the slideshow through a local socket
+//!
+//! The socket speaks a line protocol: each request is a line with a command and its argument, and
+//! is answered with a line, `ok`, `error: ` followed by a message, or the status as JSON for
+//! `status`. The commands are `next`, `previous`, `pause`, `resume`, `toggle`, `favorite`,
+//! `blacklist`, `rotate` (or `rotate left`), `motion`, `show <path>`, `interval <seconds>`,
+//! `brightness <percent>` and `status`. The `rahmenctl` binary sends them from the command line.
+
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+#[cfg(unix)]
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(unix)]
+use tracing::{info, warn};
+
+#[cfg(unix)]
+use crate::control::status_json;
+use crate::control::{Command, SharedStatus};
+use crate::dirs::BaseDirectories;
+use crate::errors::{RahmenError, RahmenResult};
+
+/// The socket in the user's runtime directory, or in `/tmp` if there is none
+pub fn default_path() -> PathBuf {
+    BaseDirectories::new()
+        .ok()
+        .and_then(|dirs| dirs.place_runtime_file("rahmen.sock").ok())
+        .unwrap_or_else(|| "/tmp/rahmen.sock".into())
+}
+
+/// Parse a request line into a command, or `None` for `status`
+pub fn parse(line: &str) -> Result<Option<Command>, String> {
+    let line = line.trim();
+    let (name, argument) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, ""),
+    };
+    let command = match (name, argument) {
+        ("status", "") => return Ok(None),
+        ("next", "") => Command::Next,
+        ("previous", "") => Command::Previous,
+        ("pause", "") => Command::Pause,
+        ("resume", "") => Command::Resume,
+        ("toggle", "") => Command::TogglePause,
+        ("favorite", "") => Command::Favorite,
+        ("blacklist", "") => Command::Blacklist,
+        ("rotate", "") | ("rotate", "right") => Command::Rotate(true),
+        ("rotate", "left") => Command::Rotate(false),
+        ("motion", "") => Command::Motion,
+        ("show", path) if !path.is_empty() => Command::Show(PathBuf::from(path)),
+        ("interval", seconds) => match seconds.parse::<f64>() {
+            Ok(seconds) if seconds > 0. && seconds.is_finite() => {
+                Command::SetInterval(Duration::from_secs_f64(seconds))
+            }
+            _ => return Err(format!("invalid interval {:?}", seconds)),
+        },
+        ("brightness", percent) => match percent.parse::<f64>() {
+            Ok(percent) => Command::set_brightness(percent)?,
+            _ => return Err(format!("invalid brightness {:?}", percent)),
+        },
+        _ => return Err(format!("unknown request {:?}", line)),
+    };
+    Ok(Some(command))
+}
+
+/// Serve requests on a socket at `path` in a background thread, sending commands to `commands`
+/// and reporting `status`. A stale socket left behind by an earlier run is replaced.
+#[cfg(unix)]
+pub fn serve(path: &Path, commands: Sender<Command>, status: SharedStatus) -> RahmenResult<()> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(RahmenError::IoError(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            format!("{:?} is in use by another instance", path),
+        )));
+    }
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    info!("Control socket at {:?}", path);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                // clients may keep the connection open, so each gets a thread
+                Ok(stream) => {
+                    let (commands, status) = (commands.clone(), Arc::clone(&status));
+                    std::thread::spawn(move || {
+                        if let Err(e) = handle(stream, &commands, &status) {
+                            warn!("Control request failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Control connection failed: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Serve requests on a socket at `path`, which needs Unix domain sockets
+#[cfg(not(unix))]
+pub fn serve(path: &Path, _commands: Sender<Command>, _status: SharedStatus) -> RahmenResult<()> {
+    Err(unsupported(path))
+}
+
+#[cfg(unix)]
+fn handle(
+    stream: UnixStream,
+    commands: &Sender<Command>,
+    status: &SharedStatus,
+) -> std::io::Result<()> {
+    let mut writer = &stream;
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match parse(&line) {
+            Ok(None) => status_json(status).to_string(),
+            Ok(Some(command)) => match commands.send(command) {
+                Ok(()) => "ok".to_string(),
+                Err(_) => "error: slideshow stopped".to_string(),
+            },
+            Err(message) => format!("error: {}", message),
+        };
+        writeln!(writer, "{}", response)?;
+    }
+    Ok(())
+}
+
+/// Send a request line to the socket at `path`, and return the response line
+#[cfg(unix)]
+pub fn request(path: &Path, line: &str) -> RahmenResult<String> {
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "{}", line)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response)?;
+    Ok(response.trim_end().to_string())
+}
+
+/// Send a request line to the socket at `path`, which needs Unix domain sockets
+#[cfg(not(unix))]
+pub fn request(path: &Path, _line: &str) -> RahmenResult<String> {
+    Err(unsupported(path))
+}
+
+/// The error for the socket at `path` on a system without Unix domain sockets
+#[cfg(not(unix))]
+fn unsupported(path: &Path) -> RahmenError {
+    RahmenError::IoError(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!(
+            "cannot use the control socket {:?} without Unix domain sockets",
+            path
+        ),
+    ))
+}
@@ -1,11 +1,19 @@
 //! Dataflow operators to handle images are required for Rahmen
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
+use tracing::{debug_span, info, warn};
+
+use crate::adjust::{apply_lut, Filter, Level, Proof};
+use crate::config::Corner;
+use crate::errors::RahmenResult;
 use crate::font::FontRenderer;
-use crate::Timer;
-use image::{DynamicImage, GenericImageView};
+use crate::provider::load_image_from_path;
+use crate::stats::{average_color, dominant_colors};
+use image::{DynamicImage, GenericImageView, Pixel, Rgb, RgbImage};
 use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::Operator;
 use timely::dataflow::{Scope, Stream};
@@ -28,34 +36,246 @@ pub enum Configuration {
     FontCanvasVStretch(f32),
     /// Update the screen dimensions
     ScreenDimensions(u32, u32),
+    /// Update the brightness and gamma of everything shown
+    Brightness(Level),
+    /// Hide the status line (`true`) or show it again
+    Clean(bool),
+    /// Simulate another kind of display, or stop doing so
+    Proof(Option<Proof>),
+    /// Asset files changed on disk and should be reloaded
+    ReloadAssets,
     /// Show a new image
     Tick,
 }
 
+/// The edge of the screen to show the status line at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusPosition {
+    /// At the top of the screen
+    Top,
+    /// At the bottom of the screen
+    Bottom,
+}
+
+/// The font size of the status line in pixels, unless set otherwise
+pub const DEFAULT_FONT_SIZE: f32 = 30.;
+
+/// Factor by which the status line is higher than the font size
+pub const FONT_CANVAS_VSTRETCH: f32 = 1.4;
+
+/// Factor by which the distance between lines of the status line is larger than the font size
+pub const LINE_SPACING: f32 = 1.05;
+
+/// Factor by which a status line of up to `max_lines` lines is higher than the font size
+pub fn font_canvas_vstretch(max_lines: usize) -> f32 {
+    FONT_CANVAS_VSTRETCH + LINE_SPACING * (max_lines.max(1) - 1) as f32
+}
+
+/// Placement of the status line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusLayout {
+    /// the edge of the screen to show the status line at
+    pub position: StatusPosition,
+    /// distance from that edge in pixels
+    pub margin: u32,
+    /// draw the status line over the image, instead of reserving space for it
+    pub overlay: bool,
+}
+
+impl Default for StatusLayout {
+    fn default() -> Self {
+        Self {
+            position: StatusPosition::Bottom,
+            margin: 0,
+            overlay: false,
+        }
+    }
+}
+
+impl StatusLayout {
+    /// The height the status line takes from the image, at `font_size` and with the height
+    /// stretched by `font_canvas_vstretch`. Over the image, it needs no space.
+    pub fn reserved_height(&self, font_size: f32, font_canvas_vstretch: f32) -> u32 {
+        if self.overlay {
+            0
+        } else {
+            (font_size * font_canvas_vstretch).ceil() as u32 + self.margin
+        }
+    }
+}
+
+/// What to fill the screen around an image with, where the aspect ratios differ
+#[derive(Debug, Clone, PartialEq)]
+pub enum Letterbox {
+    /// Leave the bars black
+    Black,
+    /// Fill the bars with the given color
+    Color([u8; 3]),
+    /// Fill the bars with the dominant color of the image, scaled by the given brightness from 0
+    /// (black) to 1 (unchanged)
+    Palette(f32),
+    /// Fill the bars with the average color of the image, scaled by the given brightness
+    Average(f32),
+    /// Fill the bars with the given image, repeated
+    Texture(Texture),
+}
+
+/// An image to fill the bars with, which is loaded again when its file changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Texture {
+    path: PathBuf,
+    max_size: Option<usize>,
+    image: Arc<RgbImage>,
+}
+
+impl Texture {
+    /// Load the image at `path`, limited to `max_size` like the images of the slideshow
+    pub fn load<P: Into<PathBuf>>(path: P, max_size: Option<usize>) -> RahmenResult<Self> {
+        let path = path.into();
+        let image = Arc::new(load_image_from_path(&path, max_size)?.to_rgb8());
+        Ok(Self {
+            path,
+            max_size,
+            image,
+        })
+    }
+
+    /// Load the image again from its file. On failure, the current image is kept.
+    fn reload(&mut self) {
+        match load_image_from_path(&self.path, self.max_size) {
+            Ok(image) => {
+                info!("Reloaded texture {:?}", self.path);
+                self.image = Arc::new(image.to_rgb8());
+            }
+            Err(e) => warn!("Failed to reload texture {:?}: {}", self.path, e),
+        }
+    }
+}
+
+impl Letterbox {
+    /// The background of a screen `width` wide and `height` high, to show `img` on, unless it is
+    /// black
+    fn fill(&self, img: &DynamicImage, width: u32, height: u32) -> Option<RgbImage> {
+        let scaled = |color: [u8; 3], brightness: f32| {
+            Rgb(color).map(|c| (f32::from(c) * brightness).round() as u8)
+        };
+        let color = match self {
+            Letterbox::Black => return None,
+            Letterbox::Color(color) => Rgb(*color),
+            Letterbox::Palette(brightness) => scaled(
+                dominant_colors(&img.thumbnail(64, 64), 1)
+                    .first()
+                    .copied()
+                    .unwrap_or_default(),
+                *brightness,
+            ),
+            Letterbox::Average(brightness) => {
+                scaled(average_color(&img.thumbnail(64, 64)), *brightness)
+            }
+            Letterbox::Texture(Texture { image: texture, .. })
+                if texture.width() > 0 && texture.height() > 0 =>
+            {
+                return Some(RgbImage::from_fn(width, height, |x, y| {
+                    *texture.get_pixel(x % texture.width(), y % texture.height())
+                }))
+            }
+            Letterbox::Texture(_) => return None,
+        };
+        Some(RgbImage::from_pixel(width, height, color))
+    }
+}
+
+/// Scrolling of text too long for the screen, like a marquee
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Marquee {
+    /// pixels to scroll per second
+    pub speed: f32,
+    /// how long to show the start and the end of the text before scrolling on
+    pub pause: Duration,
+}
+
+/// How often scrolling text moves
+const MARQUEE_STEP: Duration = Duration::from_millis(50);
+
+impl Marquee {
+    /// The offset of the part of a text `overflow` pixels too wide to show, `elapsed` after the
+    /// new text was first shown
+    fn offset(&self, overflow: u32, elapsed: Duration) -> u32 {
+        let scroll = Duration::from_secs_f32(overflow as f32 / self.speed.max(1.));
+        let cycle = (2 * self.pause + scroll).as_millis().max(1);
+        // pause at the start, scroll to the end, pause there and start over
+        let position = Duration::from_millis((elapsed.as_millis() % cycle) as u64);
+        if position <= self.pause {
+            0
+        } else {
+            ((position - self.pause).as_secs_f32() * self.speed).min(overflow as f32) as u32
+        }
+    }
+}
+
+/// The largest font size from `min_size` to `size`, in whole pixels below `size`, at which
+/// `renderer` fits `lines` into `width` pixels, or `min_size` if none does
+fn fitting_font_size<'a, I: Iterator<Item = &'a str> + Clone>(
+    renderer: &mut FontRenderer,
+    lines: I,
+    size: f32,
+    min_size: f32,
+    width: u32,
+) -> f32 {
+    let mut fitted = size;
+    // the width of text scales about linearly with the size, apart from hinting and margins
+    for _ in 0..4 {
+        let text_width = renderer.text_width(lines.clone(), fitted);
+        if text_width <= width as f32 || fitted <= min_size {
+            break;
+        }
+        fitted = (fitted * width as f32 / text_width)
+            .floor()
+            .min(fitted - 1.)
+            .max(min_size);
+    }
+    fitted
+}
+
 /// Format text for the status line trait
 pub trait FormatText<S: Scope> {
-    /// Format text stream operation
+    /// Format text stream operation. With a `marquee`, text too long for the screen scrolls
+    /// instead of being cut off or wrapped, starting over with each new image. With a
+    /// `min_font_size`, long text is first drawn smaller, down to that size, to fit the screen.
     fn format_text(
         &self,
         configuration_stream: &ConfigurationStream<S>,
         font_renderer: FontRenderer,
+        layout: StatusLayout,
         key: usize,
+        marquee: Option<Marquee>,
+        min_font_size: Option<f32>,
     ) -> ImagePosStream<S>;
 }
 
-impl<S: Scope> FormatText<S> for Stream<S, Vec<String>> {
+impl<S: Scope<Timestamp = Duration>> FormatText<S> for Stream<S, Vec<String>> {
     fn format_text(
         &self,
         configuration_stream: &ConfigurationStream<S>,
         mut font_renderer: FontRenderer,
+        layout: StatusLayout,
         key: usize,
+        marquee: Option<Marquee>,
+        min_font_size: Option<f32>,
     ) -> ImagePosStream<S> {
         let mut configuration_stash = HashMap::new();
         let mut text_stash = HashMap::new();
         let mut current_screen_dimension = None;
         let mut current_font_size = None;
         let mut current_font_canvas_vstretch = None;
+        let mut current_clean = false;
         let mut current_text = None;
+        // the rendered text with its vertical offset, and when it started scrolling, if too long
+        let mut current_img: Option<(Arc<DynamicImage>, u32)> = None;
+        let mut scrolling_since: Option<Duration> = None;
+        // the next step of scrolling text, once requested
+        let mut next_step: Option<Duration> = None;
         let mut in_buffer1 = vec![];
         let mut in_buffer2 = vec![];
         self.binary_notify(
@@ -65,7 +285,6 @@ impl<S: Scope> FormatText<S> for Stream<S, Vec<String>> {
             "Format text",
             None,
             move |in1, in2, out, not| {
-                let _t = Timer::new(|e| println!("Render font op {}ms", e.as_millis()));
                 in1.for_each(|time, data| {
                     data.swap(&mut in_buffer1);
                     for text in in_buffer1.drain(..) {
@@ -83,10 +302,18 @@ impl<S: Scope> FormatText<S> for Stream<S, Vec<String>> {
                     }
                     not.notify_at(time.retain());
                 });
-                not.for_each(|time, _cnt, _not| {
+                not.for_each(|time, _cnt, not| {
+                    let now = *time.time();
+                    if next_step.map_or(false, |step| step <= now) {
+                        next_step = None;
+                    }
+                    let mut changed = false;
+                    let mut new_image = false;
                     if let Some(configurations) = configuration_stash.remove(time.time()) {
+                        changed = true;
                         for configuration in configurations {
                             match configuration {
+                                Configuration::Tick => new_image = true,
                                 Configuration::FontSize(font_size) => {
                                     current_font_size = Some(font_size)
                                 }
@@ -96,38 +323,206 @@ impl<S: Scope> FormatText<S> for Stream<S, Vec<String>> {
                                 Configuration::ScreenDimensions(width, height) => {
                                     current_screen_dimension = Some((width, height))
                                 }
+                                Configuration::Clean(clean) => current_clean = clean,
+                                Configuration::ReloadAssets => font_renderer.reload(),
                                 _ => {}
                             }
                         }
                     }
                     if let Some(text) = text_stash.remove(time.time()) {
+                        changed = true;
                         current_text = Some(text);
+                        // the text of a new image scrolls from its start again
+                        if new_image {
+                            scrolling_since = None;
+                        }
                     }
+                    // only render the text again when something changed
                     if let (
                         Some(text),
                         Some(dimension),
                         Some(font_size),
                         Some(font_canvas_vstretch),
                     ) = (
-                        current_text.as_ref(),
+                        current_text.as_ref().filter(|_| changed),
                         current_screen_dimension.as_ref(),
                         current_font_size,
                         current_font_canvas_vstretch,
                     ) {
+                        let _span = debug_span!("render_text").entered();
+                        // in clean mode, render an empty status line
+                        let clean = current_clean;
+                        let lines = text.iter().map(String::as_str).filter(|_| !clean);
+                        let font_size = match min_font_size {
+                            Some(min_font_size) => fitting_font_size(
+                                &mut font_renderer,
+                                lines.clone(),
+                                font_size,
+                                min_font_size.min(font_size),
+                                dimension.0,
+                            ),
+                            None => font_size,
+                        };
                         // font canvas height, factor controls vertical padding
                         let canvas_height = font_size * font_canvas_vstretch;
-                        let img = font_renderer
-                            .render(
-                                text.iter().map(String::as_str),
-                                font_size,
-                                (dimension.0, canvas_height as _),
-                            )
-                            .unwrap();
-                        out.session(&time).give((
-                            key,
-                            (0, dimension.1 - canvas_height as u32),
-                            Arc::new(img),
-                        ));
+                        // text too long for the screen is rendered whole, to scroll through
+                        let text_width = match marquee {
+                            Some(_) => font_renderer.text_width(lines.clone(), font_size).ceil(),
+                            None => 0.,
+                        };
+                        let canvas_dimensions =
+                            ((text_width as u32).max(dimension.0), canvas_height as _);
+                        // over the image, the text is blended later
+                        let img = if layout.overlay {
+                            font_renderer
+                                .render_rgba(lines, font_size, canvas_dimensions)
+                                .map(DynamicImage::ImageRgba8)
+                        } else {
+                            font_renderer.render(lines, font_size, canvas_dimensions)
+                        }
+                        .unwrap();
+                        let y_offset = match layout.position {
+                            StatusPosition::Top => layout.margin,
+                            StatusPosition::Bottom => {
+                                dimension.1 - canvas_height as u32 - layout.margin
+                            }
+                        };
+                        current_img = Some((Arc::new(img), y_offset));
+                    }
+                    let (img, y_offset) = match &current_img {
+                        Some((img, y_offset)) => (img, *y_offset),
+                        None => return,
+                    };
+                    let (marquee, width) = match (marquee, current_screen_dimension) {
+                        (Some(marquee), Some((width, _))) if img.width() > width => {
+                            (marquee, width)
+                        }
+                        _ => {
+                            scrolling_since = None;
+                            if changed {
+                                out.session(&time)
+                                    .give((key, (0, y_offset), Arc::clone(img)));
+                            }
+                            return;
+                        }
+                    };
+                    // show the part of the text scrolled to, and move on in a moment
+                    let since = *scrolling_since.get_or_insert(now);
+                    let x = marquee.offset(img.width() - width, now - since);
+                    let part = img.crop_imm(x, 0, width, img.height());
+                    out.session(&time)
+                        .give((key, (0, y_offset), Arc::new(part)));
+                    if next_step.is_none() && !not.frontier(0).is_empty() {
+                        let mut step = now + MARQUEE_STEP;
+                        while !not.frontier(0).less_equal(&step) {
+                            step += MARQUEE_STEP;
+                        }
+                        not.notify_at(time.delayed(&step));
+                        next_step = Some(step);
+                    }
+                });
+            },
+        )
+    }
+}
+
+/// Place images, such as a QR code, in a corner of the screen
+pub trait PlaceImage<S: Scope> {
+    /// Place each image `margin` pixels from the edges of `corner`. In clean mode, the image is
+    /// replaced by a transparent one.
+    fn place_image(
+        &self,
+        configuration_stream: &ConfigurationStream<S>,
+        corner: Corner,
+        margin: u32,
+        key: usize,
+    ) -> ImagePosStream<S>;
+}
+
+impl<S: Scope> PlaceImage<S> for ImageStream<S> {
+    fn place_image(
+        &self,
+        configuration_stream: &ConfigurationStream<S>,
+        corner: Corner,
+        margin: u32,
+        key: usize,
+    ) -> ImagePosStream<S> {
+        let mut buffer1 = vec![];
+        let mut buffer2 = vec![];
+        let mut img_stash = HashMap::new();
+        let mut configuration_stash = HashMap::new();
+        let mut current_screen_size = None;
+        let mut current_clean = false;
+        let mut current_image: Option<Arc<DynamicImage>> = None;
+        self.binary_notify(
+            &configuration_stream,
+            Pipeline,
+            Pipeline,
+            "Place image",
+            None,
+            move |in1, in2, out, not| {
+                in1.for_each(|time, data| {
+                    data.swap(&mut buffer1);
+                    for img in buffer1.drain(..) {
+                        img_stash.insert(time.time().clone(), img);
+                    }
+                    not.notify_at(time.retain());
+                });
+                in2.for_each(|time, data| {
+                    data.swap(&mut buffer2);
+                    for configuration in buffer2.drain(..) {
+                        configuration_stash
+                            .entry(time.time().clone())
+                            .or_insert_with(Vec::new)
+                            .push(configuration);
+                    }
+                    not.notify_at(time.retain());
+                });
+                not.for_each(|time, _cnt, _not| {
+                    let mut changed = false;
+                    for configuration in configuration_stash
+                        .remove(time.time())
+                        .into_iter()
+                        .flatten()
+                    {
+                        match configuration {
+                            Configuration::ScreenDimensions(width, height) => {
+                                current_screen_size = Some((width, height));
+                                changed = true;
+                            }
+                            Configuration::Clean(clean) => {
+                                current_clean = clean;
+                                changed = true;
+                            }
+                            _ => {}
+                        }
+                    }
+                    if let Some(img) = img_stash.remove(time.time()) {
+                        current_image = Some(img);
+                        changed = true;
+                    }
+                    if let (true, Some((width, height)), Some(img)) =
+                        (changed, current_screen_size, current_image.as_ref())
+                    {
+                        let (img_width, img_height) = img.dimensions();
+                        let x_offset = match corner {
+                            Corner::TopLeft | Corner::BottomLeft => margin,
+                            Corner::TopRight | Corner::BottomRight => {
+                                width.saturating_sub(img_width + margin)
+                            }
+                        };
+                        let y_offset = match corner {
+                            Corner::TopLeft | Corner::TopRight => margin,
+                            Corner::BottomLeft | Corner::BottomRight => {
+                                height.saturating_sub(img_height + margin)
+                            }
+                        };
+                        let img = if current_clean {
+                            Arc::new(DynamicImage::new_rgba8(img_width, img_height))
+                        } else {
+                            Arc::clone(img)
+                        };
+                        out.session(&time).give((key, (x_offset, y_offset), img));
                     }
                 });
             },
@@ -142,6 +537,7 @@ pub trait ResizeImage<S: Scope> {
         &self,
         configuration_stream: &ConfigurationStream<S>,
         key: usize,
+        letterbox: Letterbox,
     ) -> ImagePosStream<S>;
 }
 
@@ -150,6 +546,7 @@ impl<S: Scope> ResizeImage<S> for ImageStream<S> {
         &self,
         configuration_stream: &ConfigurationStream<S>,
         key: usize,
+        mut letterbox: Letterbox,
     ) -> ImagePosStream<S> {
         let mut buffer1 = vec![];
         let mut buffer2 = vec![];
@@ -164,7 +561,6 @@ impl<S: Scope> ResizeImage<S> for ImageStream<S> {
             "Resize image",
             None,
             move |in1, in2, out, not| {
-                let _t = Timer::new(|e| println!("Resize image op {}ms", e.as_millis()));
                 in1.for_each(|time, data| {
                     data.swap(&mut buffer1);
                     for img in buffer1.drain(..) {
@@ -183,19 +579,38 @@ impl<S: Scope> ResizeImage<S> for ImageStream<S> {
                     not.notify_at(time.retain());
                 });
                 not.for_each(|time, _cnt, _not| {
+                    // other configuration leaves the resized image as it is
+                    let mut changed = false;
                     if let Some(configurations) = configuration_stash.remove(time.time()) {
                         for configuration in configurations {
-                            if let Configuration::ScreenDimensions(width, height) = configuration {
-                                current_screen_size = Some((width, height))
+                            match configuration {
+                                Configuration::ScreenDimensions(width, height)
+                                    if current_screen_size != Some((width, height)) =>
+                                {
+                                    current_screen_size = Some((width, height));
+                                    changed = true;
+                                }
+                                Configuration::ReloadAssets => {
+                                    if let Letterbox::Texture(texture) = &mut letterbox {
+                                        texture.reload();
+                                        changed = true;
+                                    }
+                                }
+                                _ => {}
                             }
                         }
                     }
                     if let Some(img) = img_stash.remove(time.time()) {
                         current_image = Some(img);
+                        changed = true;
                     }
-                    if let (Some(screen_size), Some(image)) =
-                        (current_screen_size, current_image.as_ref())
-                    {
+                    // a status line as high as the screen leaves no room for the image
+                    if let (true, Some(screen_size), Some(image)) = (
+                        changed,
+                        current_screen_size.filter(|(width, height)| *width > 0 && *height > 0),
+                        current_image.as_ref(),
+                    ) {
+                        let _span = debug_span!("resize").entered();
                         let resized = image.resize(
                             screen_size.0,
                             screen_size.1,
@@ -203,8 +618,148 @@ impl<S: Scope> ResizeImage<S> for ImageStream<S> {
                         );
                         let x_offset = (screen_size.0 - resized.dimensions().0) / 2;
                         let y_offset = (screen_size.1 - resized.dimensions().1) / 2;
-                        out.session(&time)
-                            .give((key, (x_offset, y_offset), Arc::new(resized)));
+                        let fill = if resized.dimensions() == screen_size {
+                            None
+                        } else {
+                            letterbox.fill(&resized, screen_size.0, screen_size.1)
+                        };
+                        match fill {
+                            Some(mut filled) => {
+                                image::imageops::overlay(
+                                    &mut filled,
+                                    &resized.to_rgb8(),
+                                    x_offset,
+                                    y_offset,
+                                );
+                                out.session(&time).give((
+                                    key,
+                                    (0, 0),
+                                    Arc::new(DynamicImage::ImageRgb8(filled)),
+                                ));
+                            }
+                            None => out.session(&time).give((
+                                key,
+                                (x_offset, y_offset),
+                                Arc::new(resized),
+                            )),
+                        }
+                    }
+                })
+            },
+        )
+    }
+}
+
+/// Apply `adjust` to the colors of `img`, keeping the transparency of overlays for the compositor
+fn keep_alpha<F: Fn(&DynamicImage) -> DynamicImage>(img: &DynamicImage, adjust: F) -> DynamicImage {
+    let adjusted = adjust(img);
+    if !img.color().has_alpha() {
+        return adjusted;
+    }
+    let mut rgba = img.to_rgba8();
+    for (pixel, color) in rgba.pixels_mut().zip(adjusted.to_rgb8().pixels()) {
+        pixel.0[..3].copy_from_slice(&color.0);
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Adjust images right before they are shown, e.g. to dim them
+pub trait AdjustImage<S: Scope> {
+    /// Adjust images according to the current configuration, after giving them the look of
+    /// `filter`, if any
+    fn adjust_image(
+        &self,
+        configuration_stream: &ConfigurationStream<S>,
+        filter: Option<Filter>,
+    ) -> ImagePosStream<S>;
+}
+
+impl<S: Scope> AdjustImage<S> for ImagePosStream<S> {
+    fn adjust_image(
+        &self,
+        configuration_stream: &ConfigurationStream<S>,
+        filter: Option<Filter>,
+    ) -> ImagePosStream<S> {
+        let mut buffer1 = vec![];
+        let mut buffer2 = vec![];
+        let mut img_stash = HashMap::new();
+        let mut configuration_stash = HashMap::new();
+        let mut current_level = Level::default();
+        let mut current_lut = current_level.lut();
+        let mut current_proof = None;
+        // the latest image of each layer, before adjusting it
+        let mut latest: HashMap<usize, ((u32, u32), Arc<DynamicImage>)> = HashMap::new();
+        self.binary_notify(
+            &configuration_stream,
+            Pipeline,
+            Pipeline,
+            "Adjust image",
+            None,
+            move |in1, in2, out, not| {
+                in1.for_each(|time, data| {
+                    data.swap(&mut buffer1);
+                    img_stash
+                        .entry(time.time().clone())
+                        .or_insert_with(Vec::new)
+                        .extend(buffer1.drain(..));
+                    not.notify_at(time.retain());
+                });
+                in2.for_each(|time, data| {
+                    data.swap(&mut buffer2);
+                    configuration_stash
+                        .entry(time.time().clone())
+                        .or_insert_with(Vec::new)
+                        .extend(buffer2.drain(..));
+                    not.notify_at(time.retain());
+                });
+                not.for_each(|time, _cnt, _not| {
+                    // a new level or proof applies to the images shown already, too
+                    let mut readjust = false;
+                    if let Some(configurations) = configuration_stash.remove(time.time()) {
+                        for configuration in configurations {
+                            match configuration {
+                                Configuration::Brightness(level) if level != current_level => {
+                                    current_level = level;
+                                    current_lut = level.lut();
+                                    readjust = true;
+                                }
+                                Configuration::Proof(proof) if proof != current_proof => {
+                                    current_proof = proof;
+                                    readjust = true;
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    let imgs = img_stash.remove(time.time()).unwrap_or_default();
+                    let stale: Vec<_> = if readjust {
+                        latest
+                            .iter()
+                            .filter(|(key, _)| imgs.iter().all(|(new, _, _)| new != *key))
+                            .map(|(key, (offset, img))| (*key, *offset, Arc::clone(img)))
+                            .collect()
+                    } else {
+                        vec![]
+                    };
+                    for (key, offset, img) in &imgs {
+                        latest.insert(*key, (*offset, Arc::clone(img)));
+                    }
+                    if !imgs.is_empty() || !stale.is_empty() {
+                        let _span = debug_span!("adjust").entered();
+                        let mut session = out.session(&time);
+                        for (key, offset, mut img) in imgs.into_iter().chain(stale) {
+                            if let Some(filter) = filter {
+                                img = Arc::new(keep_alpha(&img, |img| filter.apply(img)));
+                            }
+                            if !current_level.is_identity() {
+                                img =
+                                    Arc::new(keep_alpha(&img, |img| apply_lut(img, &current_lut)));
+                            }
+                            if let Some(proof) = current_proof {
+                                img = Arc::new(keep_alpha(&img, |img| proof.apply(img)));
+                            }
+                            session.give((key, offset, img));
+                        }
                     }
                 })
             },
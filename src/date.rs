@@ -0,0 +1,191 @@
+//! The time a photo was taken, in the local time of the place it was taken at
+//!
+//! Cameras record the time of their clock, which travellers rarely set to the local time zone.
+//! The time is taken as universal time from the GPS timestamp if there is one, or from the
+//! original date with its offset, or else in the time zone the camera's clock is set to. It is
+//! then shown in the time zone of the place the photo was taken at, as named by the reverse
+//! geocoder, or else looked up from the position in a map of the time zones built in, which works
+//! without geocoding.
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{
+    DateTime, FixedOffset, Local, Locale, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+use crate::errors::{RahmenError, RahmenResult};
+use crate::exposure::rational;
+use crate::metadata::Metadata;
+
+/// Prefix of the pseudo-tags provided by this module
+pub const TAG_PREFIX: &str = "Rahmen.Date.";
+
+/// The format of dates in EXIF
+const EXIF_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// The format of dates in EXIF followed by their offset, as given by `Rahmen.Date.Local`
+const OFFSET_FORMAT: &str = "%Y:%m:%d %H:%M:%S%:z";
+
+/// Parse the name of an IANA time zone, such as `Europe/Zurich`
+pub fn parse_timezone(name: &str) -> RahmenResult<Tz> {
+    name.parse().map_err(|e| {
+        RahmenError::ConfigError(std::sync::Arc::new(config::ConfigError::Message(format!(
+            "time zone {:?}: {}",
+            name, e
+        ))))
+    })
+}
+
+/// Parse an offset from universal time, such as `+02:00`
+fn offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    let sign = match value.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let mut parts = value[1..].split(':');
+    let hours: i32 = parts.next()?.parse().ok()?;
+    let minutes: i32 = parts.next().unwrap_or("0").parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// The universal time of the GPS timestamp
+fn gps_time(metadata: &Metadata) -> Option<DateTime<Utc>> {
+    let date = metadata.get_tag_string("Exif.GPSInfo.GPSDateStamp").ok()?;
+    let date = NaiveDate::parse_from_str(date.trim(), "%Y:%m:%d").ok()?;
+    let time = metadata.get_tag_string("Exif.GPSInfo.GPSTimeStamp").ok()?;
+    let mut parts = time.split_whitespace().map(rational);
+    let (hours, minutes, seconds) = (parts.next()??, parts.next()??, parts.next()??);
+    let time = date.and_hms_opt(hours as u32, minutes as u32, seconds as u32)?;
+    Some(Utc.from_utc_datetime(&time))
+}
+
+/// The original date of the photo, with its offset if recorded
+fn original_time(metadata: &Metadata) -> Option<(NaiveDateTime, Option<FixedOffset>)> {
+    let date = metadata
+        .get_tag_string("Exif.Photo.DateTimeOriginal")
+        .ok()?;
+    let date = NaiveDateTime::parse_from_str(date.trim(), EXIF_FORMAT).ok()?;
+    let offset = metadata
+        .get_tag_string("Exif.Photo.OffsetTimeOriginal")
+        .ok()
+        .and_then(|value| offset(&value));
+    Some((date, offset))
+}
+
+/// The offset of the camera's clock from universal time, if recorded
+pub fn recorded_offset(metadata: &Metadata) -> Option<FixedOffset> {
+    original_time(metadata)?.1
+}
+
+/// The universal time a photo was taken at. Without a GPS timestamp or a recorded offset, the
+/// camera's clock is taken to be set to `camera`, or to the local time zone.
+pub fn capture_time(metadata: &Metadata, camera: Option<Tz>) -> Option<DateTime<Utc>> {
+    if let Some(time) = gps_time(metadata) {
+        return Some(time);
+    }
+    let (date, offset) = original_time(metadata)?;
+    let local = match (offset, camera) {
+        (Some(offset), _) => offset
+            .from_local_datetime(&date)
+            .single()?
+            .with_timezone(&Utc),
+        (None, Some(camera)) => camera
+            .from_local_datetime(&date)
+            .earliest()?
+            .with_timezone(&Utc),
+        (None, None) => Local
+            .from_local_datetime(&date)
+            .earliest()?
+            .with_timezone(&Utc),
+    };
+    Some(local)
+}
+
+/// The name of the time zone at a position, such as `Europe/Zurich`, from a map built in
+pub fn timezone_at(latitude: f64, longitude: f64) -> Option<String> {
+    tz_search::lookup(latitude, longitude)
+}
+
+/// The time a photo was taken at in the time zone of the place, if known, or else as recorded
+pub fn local_time(
+    metadata: &Metadata,
+    camera: Option<Tz>,
+    timezone: Option<&str>,
+) -> Option<DateTime<FixedOffset>> {
+    let timezone: Option<Tz> = timezone.and_then(|timezone| timezone.parse().ok());
+    match timezone {
+        Some(timezone) => {
+            let time = capture_time(metadata, camera)?.with_timezone(&timezone);
+            Some(time.with_timezone(&time.offset().fix()))
+        }
+        None => {
+            // keep the time of the camera's clock, with the offset only if recorded
+            let (date, offset) = original_time(metadata)?;
+            let offset = offset.unwrap_or_else(|| FixedOffset::east(0));
+            offset.from_local_datetime(&date).single()
+        }
+    }
+}
+
+/// Parse the name of a locale, such as `de_CH`
+pub fn parse_locale(name: &str) -> RahmenResult<Locale> {
+    Locale::try_from(name).map_err(|_| {
+        RahmenError::ConfigError(std::sync::Arc::new(config::ConfigError::Message(format!(
+            "unknown locale {:?}",
+            name
+        ))))
+    })
+}
+
+/// Check a strftime `format`, such as `%-d. %B %Y`, for unknown specifiers
+pub fn parse_format(format: &str) -> RahmenResult<()> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(RahmenError::ConfigError(std::sync::Arc::new(
+            config::ConfigError::Message(format!("invalid date format {:?}", format)),
+        )));
+    }
+    Ok(())
+}
+
+/// Format an EXIF date, such as `2021:05:03 14:22:01`, with a strftime `format` in `locale`, in
+/// the time zone of its offset. Dates without one, such as read from the camera's clock, are taken
+/// at `offset`, or at universal time. Other values are returned as they are.
+pub fn format(value: &str, format: &str, locale: Locale, offset: Option<FixedOffset>) -> String {
+    let value = value.trim();
+    let date = match DateTime::parse_from_str(value, OFFSET_FORMAT) {
+        Ok(date) => date,
+        Err(_) => match NaiveDateTime::parse_from_str(value, EXIF_FORMAT) {
+            Ok(date) => match offset
+                .unwrap_or_else(|| FixedOffset::east(0))
+                .from_local_datetime(&date)
+                .single()
+            {
+                Some(date) => date,
+                None => return value.to_string(),
+            },
+            Err(_) => return value.to_string(),
+        },
+    };
+    // formats are checked when loading the configuration, but fail gracefully anyway
+    let mut formatted = String::new();
+    match write!(formatted, "{}", date.format_localized(format, locale)) {
+        Ok(()) => formatted,
+        Err(_) => value.to_string(),
+    }
+}
+
+/// The value of a pseudo-tag:
+///
+/// * `Rahmen.Date.Local`: the time the photo was taken at, in the time zone of the place, in the
+///   format of EXIF dates followed by its offset, such as `2021:05:03 14:22:01+02:00`
+pub fn tag(time: &DateTime<FixedOffset>, name: &str) -> Option<String> {
+    match name.strip_prefix(TAG_PREFIX)? {
+        "Local" => Some(time.format(OFFSET_FORMAT).to_string()),
+        _ => None,
+    }
+}
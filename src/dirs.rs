@@ -0,0 +1,88 @@
+//! Where the configuration is looked up, and state and caches are kept
+//!
+//! On Unix, including macOS, these are the XDG base directories, such as `~/.config/rahmen` and
+//! `~/.local/share/rahmen`. Windows has no such convention, so there, the configuration and state
+//! are kept in the roaming application data of the user, `%APPDATA%`, caches in the local one,
+//! `%LOCALAPPDATA%`, and runtime files in the temporary directory.
+
+#[cfg(unix)]
+pub use xdg::BaseDirectories;
+
+#[cfg(not(unix))]
+pub use self::appdata::BaseDirectories;
+
+#[cfg(not(unix))]
+mod appdata {
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    /// The application data directories of the user, in place of the XDG base directories
+    #[derive(Debug, Clone)]
+    pub struct BaseDirectories {
+        config_home: PathBuf,
+        cache_home: PathBuf,
+        runtime_dir: PathBuf,
+    }
+
+    /// The directory named by the environment variable `name`
+    fn env_dir(name: &str) -> io::Result<PathBuf> {
+        std::env::var_os(name)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not set", name)))
+    }
+
+    /// `dir` joined with `path`, creating the directories leading to it
+    fn place(dir: &Path, path: &Path) -> io::Result<PathBuf> {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+
+    impl BaseDirectories {
+        /// The directories of the user
+        pub fn new() -> io::Result<Self> {
+            Self::with_prefix("")
+        }
+
+        /// The directories of the user, each with `prefix` appended
+        pub fn with_prefix<P: AsRef<Path>>(prefix: P) -> io::Result<Self> {
+            let roaming = env_dir("APPDATA")?;
+            let local = env_dir("LOCALAPPDATA").unwrap_or_else(|_| roaming.clone());
+            Ok(Self {
+                config_home: roaming.join(&prefix),
+                cache_home: local.join(&prefix),
+                runtime_dir: std::env::temp_dir().join(&prefix),
+            })
+        }
+
+        /// The configuration file at `path`, if it exists
+        pub fn find_config_file<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+            Some(self.config_home.join(path)).filter(|path| path.is_file())
+        }
+
+        /// The state file at `path`, creating the directories leading to it
+        pub fn place_data_file<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+            place(&self.config_home, path.as_ref())
+        }
+
+        /// The cache file at `path`, creating the directories leading to it
+        pub fn place_cache_file<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+            place(&self.cache_home, path.as_ref())
+        }
+
+        /// The runtime file at `path`, creating the directories leading to it
+        pub fn place_runtime_file<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+            place(&self.runtime_dir, path.as_ref())
+        }
+
+        /// The cache directory at `path`, which is created
+        pub fn create_cache_directory<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+            let dir = self.cache_home.join(path);
+            std::fs::create_dir_all(&dir)?;
+            Ok(dir)
+        }
+    }
+}
@@ -1,8 +1,10 @@
 //! Functionality to render images on a display
 
+use std::sync::{Arc, Mutex};
+
 use image::DynamicImage;
 
-use crate::errors::RahmenResult;
+use crate::errors::{RahmenError, RahmenResult};
 
 /// Trait describing the interface to display an image
 pub trait Display {
@@ -25,10 +27,122 @@ pub trait Display {
         y_size: u32,
     ) -> RahmenResult<()>;
 
+    /// Show the status line as text. Displays that can show text natively may override this, and
+    /// skip images with the same key.
+    fn text(&mut self, _key: usize, _lines: &[String]) -> RahmenResult<()> {
+        Ok(())
+    }
+
     /// Update the image content. This would be a good opportunity to reveal any render/blank
     /// operations to the user.
     fn update(&mut self) -> RahmenResult<()>;
 
     /// Return the dimensions of the display as `(width, height)`
     fn dimensions(&self) -> (u32, u32);
+
+    /// Switch the screen off or on, for displays which can. While off, the content may still be
+    /// updated, and is shown once switched on again.
+    fn set_power(&mut self, _on: bool) -> RahmenResult<()> {
+        Err(RahmenError::DisplayError(
+            "this display cannot be switched off".into(),
+        ))
+    }
+
+    /// Handle what happened to the display since the last call, such as a switch to another
+    /// console, and return whether it is shown. Called before every frame by the control loop,
+    /// which skips frames while the display isn't shown.
+    fn poll(&mut self) -> bool {
+        true
+    }
+}
+
+/// Handle to tell providers the size of the screen, which is known once the display is open
+#[derive(Debug, Clone, Default)]
+pub struct ScreenSize {
+    size: Arc<Mutex<Option<(u32, u32)>>>,
+}
+
+impl ScreenSize {
+    /// Set the size of the screen, in pixels
+    pub fn set(&self, width: u32, height: u32) {
+        *self.size.lock().unwrap() = Some((width, height));
+    }
+
+    /// The size of the screen, if known
+    pub fn get(&self) -> Option<(u32, u32)> {
+        *self.size.lock().unwrap()
+    }
+}
+
+/// Clockwise rotation of the content shown on a display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// Show the content as it is
+    None,
+    /// Rotate by 90 degrees
+    Rotate90,
+    /// Rotate by 180 degrees
+    Rotate180,
+    /// Rotate by 270 degrees
+    Rotate270,
+}
+
+impl Rotation {
+    /// Construct a rotation from an angle in degrees, which must be a multiple of 90
+    pub fn from_degrees(degrees: u16) -> RahmenResult<Self> {
+        match degrees % 360 {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Rotate90),
+            180 => Ok(Rotation::Rotate180),
+            270 => Ok(Rotation::Rotate270),
+            _ => Err(RahmenError::DisplayError(format!(
+                "invalid rotation {}, expected 0, 90, 180 or 270",
+                degrees
+            ))),
+        }
+    }
+
+    /// Determine the dimensions of content which fits a display of `dimensions` once rotated
+    pub fn content_dimensions(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            Rotation::None | Rotation::Rotate180 => (width, height),
+            Rotation::Rotate90 | Rotation::Rotate270 => (height, width),
+        }
+    }
+}
+
+/// Protocols to draw graphics in a terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphicsProtocol {
+    /// The Kitty graphics protocol, also supported by WezTerm and Konsole
+    Kitty,
+    /// Sixel graphics, supported by xterm, mlterm, foot and others
+    Sixel,
+}
+
+impl GraphicsProtocol {
+    /// Guess the protocol the terminal supports from the environment
+    pub fn detect() -> Self {
+        let term = std::env::var("TERM").unwrap_or_default();
+        let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+        if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term_program == "WezTerm"
+        {
+            GraphicsProtocol::Kitty
+        } else {
+            GraphicsProtocol::Sixel
+        }
+    }
+}
+
+/// Parse a resolution given as `WxH`
+pub fn parse_resolution(resolution: &str) -> RahmenResult<(u32, u32)> {
+    resolution
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .ok_or_else(|| {
+            RahmenError::DisplayError(format!("invalid resolution {}, expected WxH", resolution))
+        })
 }
@@ -0,0 +1,256 @@
+//! Functionality to render images using DRM/KMS
+//!
+//! This replaces the deprecated fbdev interface on newer systems. Images are drawn into one of two
+//! dumb buffers, which is then flipped to the screen on the next vertical blank. When the display
+//! is dropped, the screen shows what it showed before, usually the console.
+//!
+//! The screen is switched off through the DPMS property of the connector. Pages can't be flipped
+//! while it is off, so the latest image is only flipped to the screen once it is on again.
+
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::time::Duration;
+
+use drm::buffer::{Buffer, DrmFourcc};
+use drm::control::dumbbuffer::DumbBuffer;
+use drm::control::{
+    connector, crtc, framebuffer, property, Device as ControlDevice, Event, Mode, PageFlipFlags,
+};
+use image::{Bgra, DynamicImage, GenericImage};
+use tracing::{debug_span, info};
+
+use crate::display::Display;
+use crate::errors::{RahmenError, RahmenResult};
+use crate::pixel::BgraImage;
+
+/// A DRM device node, such as `/dev/dri/card0`
+#[derive(Debug)]
+struct Card(File);
+
+impl AsRawFd for Card {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl drm::Device for Card {}
+
+impl ControlDevice for Card {}
+
+/// A display driver using DRM/KMS
+#[derive(Debug)]
+pub struct DrmDisplay {
+    card: Card,
+    crtc: crtc::Handle,
+    connector: connector::Handle,
+    /// the state of the CRTC before we took it over
+    saved: Option<crtc::Info>,
+    mode: Mode,
+    buffers: Vec<(DumbBuffer, framebuffer::Handle)>,
+    front: usize,
+    image: BgraImage,
+    /// whether the screen was switched off
+    off: bool,
+}
+
+fn drm_err<E: std::fmt::Display>(err: E) -> RahmenError {
+    RahmenError::DisplayError(format!("DRM: {}", err))
+}
+
+impl DrmDisplay {
+    /// Set up the first connected display of a DRM device. The mode can be given as `WxH` or
+    /// `WxH@R`, otherwise the display's first (usually preferred) mode is used.
+    pub fn new<P: AsRef<Path>>(path: P, mode: Option<&str>) -> RahmenResult<Self> {
+        let card = Card(OpenOptions::new().read(true).write(true).open(path)?);
+        let resources = card.resource_handles().map_err(drm_err)?;
+        let connector = resources
+            .connectors()
+            .iter()
+            .flat_map(|handle| card.get_connector(*handle))
+            .find(|info| info.state() == connector::State::Connected)
+            .ok_or_else(|| drm_err("no connected display"))?;
+        let mode = select_mode(connector.modes(), mode)?;
+        let crtc = connector
+            .current_encoder()
+            .and_then(|encoder| card.get_encoder(encoder).ok())
+            .and_then(|encoder| encoder.crtc())
+            .or_else(|| resources.crtcs().first().cloned())
+            .ok_or_else(|| drm_err("no CRTC available"))?;
+
+        let (width, height) = mode.size();
+        let mut buffers = vec![];
+        for _ in 0..2 {
+            let buffer = card
+                .create_dumb_buffer((width.into(), height.into()), DrmFourcc::Xrgb8888, 32)
+                .map_err(drm_err)?;
+            let framebuffer = card.add_framebuffer(&buffer, 24, 32).map_err(drm_err)?;
+            buffers.push((buffer, framebuffer));
+        }
+        let saved = card.get_crtc(crtc).ok();
+        card.set_crtc(
+            crtc,
+            Some(buffers[0].1),
+            (0, 0),
+            &[connector.handle()],
+            Some(mode),
+        )
+        .map_err(drm_err)?;
+        info!("DRM mode: {}x{}@{}", width, height, mode.vrefresh());
+
+        Ok(Self {
+            card,
+            crtc,
+            connector: connector.handle(),
+            saved,
+            mode,
+            buffers,
+            front: 0,
+            image: BgraImage::new(width.into(), height.into()),
+            off: false,
+        })
+    }
+
+    /// The DPMS property of the connector
+    fn dpms_property(&self) -> RahmenResult<property::Handle> {
+        let properties = self.card.get_properties(self.connector).map_err(drm_err)?;
+        let (handles, _) = properties.as_props_and_values();
+        handles
+            .iter()
+            .cloned()
+            .find(|handle| {
+                self.card
+                    .get_property(*handle)
+                    .map_or(false, |info| info.name().to_bytes() == b"DPMS")
+            })
+            .ok_or_else(|| drm_err("the connector has no DPMS property"))
+    }
+
+    /// Enter the control loop. This will periodically trigger the callback, until it returns an
+    /// `Err` result.
+    pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
+        while callback(self).is_ok() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for DrmDisplay {
+    fn drop(&mut self) {
+        if self.off {
+            let _ = self.set_power(true);
+        }
+        // don't leave the last image on the screen
+        if let Some(saved) = &self.saved {
+            let _ = self.card.set_crtc(
+                self.crtc,
+                saved.framebuffer(),
+                saved.position(),
+                &[self.connector],
+                saved.mode(),
+            );
+        }
+        for (buffer, framebuffer) in self.buffers.drain(..) {
+            let _ = self.card.destroy_framebuffer(framebuffer);
+            let _ = self.card.destroy_dumb_buffer(buffer);
+        }
+    }
+}
+
+fn select_mode(modes: &[Mode], wanted: Option<&str>) -> RahmenResult<Mode> {
+    match wanted {
+        None => modes.first().cloned(),
+        Some(wanted) => modes
+            .iter()
+            .find(|mode| {
+                let (width, height) = mode.size();
+                let size = format!("{}x{}", width, height);
+                wanted == size || wanted == format!("{}@{}", size, mode.vrefresh())
+            })
+            .cloned(),
+    }
+    .ok_or_else(|| drm_err(format!("mode {} not available", wanted.unwrap_or("any"))))
+}
+
+impl Display for DrmDisplay {
+    fn render(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        img: &DynamicImage,
+    ) -> RahmenResult<()> {
+        self.image.copy_from(&img.to_bgra8(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn blank(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        x_size: u32,
+        y_size: u32,
+    ) -> RahmenResult<()> {
+        let black = image::FlatSamples::with_monocolor(&Bgra([0; 4]), x_size, y_size);
+        self.image
+            .copy_from(&black.as_view().unwrap(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn update(&mut self) -> RahmenResult<()> {
+        if self.off {
+            return Ok(());
+        }
+        let _span = debug_span!("update").entered();
+        let back = 1 - self.front;
+        let (buffer, framebuffer) = &mut self.buffers[back];
+        let pitch = buffer.pitch() as usize;
+        {
+            let mut mapping = self.card.map_dumb_buffer(buffer).map_err(drm_err)?;
+            let row_length = self.image.width() as usize * 4;
+            for (src, dst) in self
+                .image
+                .as_raw()
+                .chunks(row_length)
+                .zip(mapping.as_mut().chunks_mut(pitch))
+            {
+                dst[..row_length].copy_from_slice(src);
+            }
+        }
+        self.card
+            .page_flip(
+                self.crtc,
+                *framebuffer,
+                &[PageFlipFlags::PageFlipEvent],
+                None,
+            )
+            .map_err(drm_err)?;
+        // block until the flip happened on the next vertical blank
+        for event in self.card.receive_events().map_err(drm_err)? {
+            if let Event::PageFlip(_) = event {
+                break;
+            }
+        }
+        self.front = back;
+        Ok(())
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.mode.size();
+        (width.into(), height.into())
+    }
+
+    fn set_power(&mut self, on: bool) -> RahmenResult<()> {
+        // the values of the DPMS property for on and off
+        let value = if on { 0 } else { 3 };
+        self.card
+            .set_property(self.connector, self.dpms_property()?, value)
+            .map_err(drm_err)?;
+        self.off = !on;
+        if on {
+            self.update()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+//! Functionality to render images on e-paper displays
+//!
+//! E-paper panels are driven through their Linux framebuffer device, which EPDC controllers and
+//! SPI panel drivers (fbtft, repaper) expose. Images are dithered to the gray levels the panel can
+//! show, and only the changed region is written, except for periodic full refreshes that clear
+//! ghosting. EPDC controllers, such as those of i.MX SoCs, show nothing written until asked to
+//! update a region: changes are updated with the waveform the controller picks, and full refreshes
+//! with the flashing GC16 waveform. Drivers of SPI panels refresh on their own.
+
+#![allow(unsafe_code)]
+
+use tracing::{debug, debug_span, warn};
+
+use crate::display::Display;
+use crate::errors::{RahmenError, RahmenResult};
+use crate::pixel::{dither, PixelFormat};
+
+use framebuffer::Framebuffer;
+use image::{DynamicImage, GenericImage, GenericImageView, GrayImage, Luma};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+/// Ask an EPDC controller to update a region, after `mxcfb.h`
+const MXCFB_SEND_UPDATE: libc::c_ulong = 0x4040_462e;
+/// Wait for an update to complete
+const MXCFB_WAIT_FOR_UPDATE_COMPLETE: libc::c_ulong = 0xc008_462f;
+const WAVEFORM_MODE_GC16: u32 = 2;
+const WAVEFORM_MODE_AUTO: u32 = 257;
+const UPDATE_MODE_PARTIAL: u32 = 0;
+const UPDATE_MODE_FULL: u32 = 1;
+const TEMP_USE_AMBIENT: i32 = 0x1000;
+
+/// The bits per pixel of the framebuffers written to
+const SUPPORTED_BITS_PER_PIXEL: [u32; 4] = [8, 16, 24, 32];
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct MxcfbRect {
+    top: u32,
+    left: u32,
+    width: u32,
+    height: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct MxcfbUpdateData {
+    update_region: MxcfbRect,
+    waveform_mode: u32,
+    update_mode: u32,
+    update_marker: u32,
+    temp: i32,
+    flags: u32,
+    // the alternate buffer, which is not used
+    alt_buffer_data: [u32; 7],
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct MxcfbUpdateMarkerData {
+    update_marker: u32,
+    collision_test: u32,
+}
+
+/// A region of the display as `(x, y, width, height)`
+type Region = (u32, u32, u32, u32);
+
+/// A display driver for e-paper panels behind a Linux framebuffer
+#[derive(Debug)]
+pub struct EinkDisplay {
+    framebuffer: Framebuffer,
+    format: PixelFormat,
+    image: GrayImage,
+    dirty: Option<Region>,
+    gray_levels: u8,
+    full_refresh_every: usize,
+    updates: usize,
+    /// whether the controller takes update requests, until it turns out not to
+    epdc: bool,
+    /// the marker of the last update requested
+    marker: u32,
+}
+
+impl EinkDisplay {
+    /// Open the framebuffer `device` of an e-paper panel, see `new`
+    pub fn open(device: &str, gray_levels: u8, full_refresh_every: usize) -> RahmenResult<Self> {
+        let framebuffer = Framebuffer::new(device)
+            .map_err(|e| RahmenError::DisplayError(format!("{}: {:?}", device, e)))?;
+        Self::new(framebuffer, gray_levels, full_refresh_every)
+    }
+
+    /// Create a new e-paper display showing `gray_levels` levels of gray, doing a full refresh
+    /// every `full_refresh_every` updates
+    pub fn new(
+        framebuffer: Framebuffer,
+        gray_levels: u8,
+        full_refresh_every: usize,
+    ) -> RahmenResult<Self> {
+        let bits_per_pixel = framebuffer.var_screen_info.bits_per_pixel;
+        if !SUPPORTED_BITS_PER_PIXEL.contains(&bits_per_pixel) {
+            return Err(RahmenError::DisplayError(format!(
+                "e-paper framebuffers with {} bits per pixel are not supported, only {:?}",
+                bits_per_pixel, SUPPORTED_BITS_PER_PIXEL
+            )));
+        }
+        let (width, height) = (
+            framebuffer.var_screen_info.xres,
+            framebuffer.var_screen_info.yres,
+        );
+        Ok(Self {
+            format: PixelFormat::from_screen_info(&framebuffer.var_screen_info)?,
+            framebuffer,
+            image: GrayImage::new(width, height),
+            dirty: None,
+            gray_levels,
+            full_refresh_every,
+            updates: 0,
+            epdc: true,
+            marker: 0,
+        })
+    }
+
+    /// Ask the controller to show `region`, fully refreshed if `full`, and wait for full refreshes
+    /// to complete, so the next update doesn't cut them short
+    fn send_update(&mut self, (x, y, width, height): Region, full: bool) {
+        if !self.epdc {
+            return;
+        }
+        self.marker = self.marker.wrapping_add(1).max(1);
+        let data = MxcfbUpdateData {
+            update_region: MxcfbRect {
+                top: y,
+                left: x,
+                width,
+                height,
+            },
+            waveform_mode: if full {
+                WAVEFORM_MODE_GC16
+            } else {
+                WAVEFORM_MODE_AUTO
+            },
+            update_mode: if full {
+                UPDATE_MODE_FULL
+            } else {
+                UPDATE_MODE_PARTIAL
+            },
+            update_marker: self.marker,
+            temp: TEMP_USE_AMBIENT,
+            ..Default::default()
+        };
+        let fd = self.framebuffer.device.as_raw_fd();
+        if unsafe { libc::ioctl(fd, MXCFB_SEND_UPDATE, &data) } < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOTTY) {
+                debug!("The panel refreshes on its own");
+                self.epdc = false;
+            } else {
+                warn!("Updating the panel failed: {}", err);
+            }
+            return;
+        }
+        if full {
+            let mut marker = MxcfbUpdateMarkerData {
+                update_marker: self.marker,
+                ..Default::default()
+            };
+            if unsafe { libc::ioctl(fd, MXCFB_WAIT_FOR_UPDATE_COMPLETE, &mut marker) } < 0 {
+                warn!(
+                    "Waiting for the panel failed: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+
+    /// Enter the control loop. This will periodically trigger the callback, until it returns an
+    /// `Err` result.
+    pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
+        while callback(self).is_ok() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn mark_dirty(&mut self, (x, y, width, height): Region) {
+        self.dirty = Some(match self.dirty {
+            None => (x, y, width, height),
+            Some((dx, dy, dwidth, dheight)) => {
+                let (x0, y0) = (x.min(dx), y.min(dy));
+                let (x1, y1) = ((x + width).max(dx + dwidth), (y + height).max(dy + dheight));
+                (x0, y0, x1 - x0, y1 - y0)
+            }
+        });
+    }
+
+    /// Write a dithered region to the framebuffer, converting to its pixel layout
+    fn write_region(&mut self, (x0, y0): (u32, u32), region: &GrayImage) {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let line_length = self.framebuffer.fix_screen_info.line_length as usize;
+        let frame = &mut self.framebuffer.frame;
+        for (x, y, Luma([v])) in region.enumerate_pixels() {
+            let offset = (y0 + y) as usize * line_length + (x0 + x) as usize * bytes_per_pixel;
+            self.format
+                .pack([*v; 3], &mut frame[offset..offset + bytes_per_pixel]);
+        }
+    }
+}
+
+impl Display for EinkDisplay {
+    fn render(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        img: &DynamicImage,
+    ) -> RahmenResult<()> {
+        self.image.copy_from(&img.to_luma8(), x_offset, y_offset)?;
+        self.mark_dirty((x_offset, y_offset, img.width(), img.height()));
+        Ok(())
+    }
+
+    fn blank(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        x_size: u32,
+        y_size: u32,
+    ) -> RahmenResult<()> {
+        let black = image::FlatSamples::with_monocolor(&Luma([0]), x_size, y_size);
+        self.image
+            .copy_from(&black.as_view().unwrap(), x_offset, y_offset)?;
+        self.mark_dirty((x_offset, y_offset, x_size, y_size));
+        Ok(())
+    }
+
+    fn update(&mut self) -> RahmenResult<()> {
+        let _span = debug_span!("update").entered();
+        if let Some(dirty) = self.dirty.take() {
+            self.updates += 1;
+            // redraw everything now and then, and have the panel flash to clear ghosting
+            let full = self.updates % self.full_refresh_every.max(1) == 0;
+            let (x, y, width, height) = if full {
+                let (width, height) = self.dimensions();
+                (0, 0, width, height)
+            } else {
+                dirty
+            };
+            let region = self.image.view(x, y, width, height).to_image();
+            let dithered = dither(&region, self.gray_levels);
+            self.write_region((x, y), &dithered);
+            self.send_update((x, y, width, height), full);
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (
+            self.framebuffer.var_screen_info.xres,
+            self.framebuffer.var_screen_info.yres,
+        )
+    }
+}
@@ -1,18 +1,44 @@
 //! Functionality to render images on a FLTK window
+//!
+//! When the window is resized, the new size is reported once it stopped changing for a moment, so
+//! the image is scaled and composed again only once, rather than for every step of dragging the
+//! border. Until then, the previous frame stays centered in the window.
 
+use tracing::{debug_span, warn};
+
+use crate::control::Command;
 use crate::display::Display;
 use crate::errors::{RahmenError, RahmenResult};
 
 use fltk::{
     app::{App, Scheme},
     enums::ColorDepth,
-    enums::{Event, Key},
+    enums::{Cursor, Event, Key},
     frame::Frame,
     prelude::{GroupExt, WidgetBase, WidgetExt, WindowExt},
     window::Window,
 };
 use image::{DynamicImage, GenericImage, Rgb, RgbImage};
-use std::time::Duration;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+/// How long the size of the window has to stay the same before it is reported
+const RESIZE_SETTLE: Duration = Duration::from_millis(200);
+
+/// How to show the window, for kiosks on desktop machines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WindowOptions {
+    /// start in fullscreen mode
+    pub fullscreen: bool,
+    /// leave out the title bar and borders
+    pub borderless: bool,
+    /// ask the window manager to keep the window above all others, which needs the `x11` feature
+    pub always_on_top: bool,
+    /// the monitor to show the window on, counting from 0
+    pub monitor: Option<usize>,
+    /// hide the mouse cursor over the window
+    pub hide_cursor: bool,
+}
 
 /// A display driver rendering to a FLTK window
 #[derive(Debug)]
@@ -20,28 +46,74 @@ pub struct FltkDisplay {
     window: Window,
     frame: Frame,
     image: RgbImage,
+    /// the size reported to Rahmen, once known
+    size: Option<(u32, u32)>,
+    /// the size the window changed to, and when
+    resized: Option<((u32, u32), Instant)>,
 }
 
 impl FltkDisplay {
-    /// Create a new FLTK display
-    pub fn new() -> Self {
+    /// Create a new FLTK display shown as given by `options`, sending commands triggered by key
+    /// presses to `commands`
+    pub fn new(commands: Sender<Command>, options: WindowOptions) -> Self {
         let dim_x = 400;
         let dim_y = 300;
         let _app = App::default().with_scheme(Scheme::Gleam);
         let mut window = Window::new(100, 100, dim_x, dim_y, "Rahmen");
         let frame = Frame::new(0, 0, dim_x, dim_y, "");
         window.make_resizable(true);
+        window.set_border(!options.borderless);
+        // a window goes fullscreen on the monitor it is on
+        match options.monitor {
+            Some(monitor) if (monitor as i32) < fltk::app::screen_count() => {
+                let (x, y, width, height) = fltk::app::screen_xywh(monitor as i32);
+                window.set_pos(
+                    x + (width - dim_x).max(0) / 2,
+                    y + (height - dim_y).max(0) / 2,
+                );
+            }
+            Some(monitor) => warn!("Monitor {} not found", monitor),
+            None => {}
+        }
         window.end();
         window.show_with_env_args();
+        window.fullscreen(options.fullscreen);
+        if options.always_on_top {
+            #[cfg(feature = "x11")]
+            if let Err(e) = crate::display_x11::keep_above(window.raw_handle() as u32) {
+                warn!("Keeping the window on top failed: {}", e);
+            }
+            #[cfg(not(feature = "x11"))]
+            warn!("Keeping the window on top needs the x11 feature");
+        }
+        if options.hide_cursor {
+            window.set_cursor(Cursor::None);
+        }
 
-        let mut is_fullscreen = false;
+        let mut is_fullscreen = options.fullscreen;
+        let send = move |command| {
+            let _ = commands.send(command);
+            true
+        };
         window.handle(move |t, ev| match ev {
             Event::KeyDown => match fltk::app::event_key() {
-                Key::Enter => {
+                key if key == Key::Enter || key == Key::from_char('f') => {
                     t.fullscreen(!is_fullscreen);
                     is_fullscreen = !is_fullscreen;
                     true
                 }
+                key if key == Key::from_char(' ') => send(Command::TogglePause),
+                Key::Right => send(Command::Next),
+                Key::Left => send(Command::Previous),
+                // the status line is the information shown over the image
+                key if key == Key::from_char('i') || key == Key::from_char('c') => {
+                    send(Command::ToggleClean)
+                }
+                key if key == Key::from_char('p') => send(Command::CycleProof),
+                key if key == Key::from_char('r') => send(Command::Rotate(true)),
+                key if key == Key::from_char('l') => send(Command::Rotate(false)),
+                // stop like on a signal, saving the state of the slideshow
+                key if key == Key::from_char('q') => send(Command::Quit),
                 _ => false,
             },
             _ => false,
@@ -51,15 +123,20 @@ impl FltkDisplay {
             window,
             frame,
             image: Default::default(),
+            size: None,
+            resized: None,
         }
     }
 
     /// Main loop to handle FLTK events and call back into Rahmen's logic
     pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
-        while callback(self).is_ok() && self.window.shown() {
+        while {
+            self.track_size();
+            callback(self).is_ok() && self.window.shown()
+        } {
             match fltk::app::wait_for(Duration::from_millis(50).as_secs_f64()) {
                 Err(e) => {
-                    eprintln!("FLTK error: {}", e);
+                    warn!("FLTK error: {}", e);
                     // break;
                 }
                 _ => {}
@@ -67,6 +144,23 @@ impl FltkDisplay {
         }
     }
 
+    /// Report the size of the window once it settled
+    fn track_size(&mut self) {
+        let size = (self.frame.width() as u32, self.frame.height() as u32);
+        let now = Instant::now();
+        match (self.size, self.resized) {
+            (None, _) => self.size = Some(size),
+            (Some(current), _) if current == size => self.resized = None,
+            (_, Some((pending, at))) if pending == size => {
+                if now >= at + RESIZE_SETTLE {
+                    self.size = Some(size);
+                    self.resized = None;
+                }
+            }
+            _ => self.resized = Some((size, now)),
+        }
+    }
+
     fn match_dimensions(&mut self) -> RahmenResult<()> {
         if self.image.dimensions() != self.dimensions() {
             self.image = RgbImage::from_raw(
@@ -88,7 +182,7 @@ impl Display for FltkDisplay {
         y_offset: u32,
         img: &DynamicImage,
     ) -> RahmenResult<()> {
-        let _t = crate::Timer::new(|e| println!("Rendering {}ms", e.as_millis()));
+        let _span = debug_span!("render").entered();
         self.match_dimensions()?;
         self.image.copy_from(&img.to_rgb8(), x_offset, y_offset)?;
         Ok(())
@@ -102,7 +196,7 @@ impl Display for FltkDisplay {
         x_size: u32,
         y_size: u32,
     ) -> RahmenResult<()> {
-        let _t = crate::Timer::new(|e| println!("Rendering {}ms", e.as_millis()));
+        let _span = debug_span!("render").entered();
         self.match_dimensions()?;
         let black = image::FlatSamples::with_monocolor(&Rgb([0; 3]), x_size, y_size);
         self.image
@@ -111,6 +205,7 @@ impl Display for FltkDisplay {
     }
 
     fn update(&mut self) -> RahmenResult<()> {
+        let _span = debug_span!("update").entered();
         let (x, y) = self.image.dimensions();
         let image =
             fltk::image::RgbImage::new(self.image.as_raw(), x as _, y as _, ColorDepth::Rgb8)
@@ -121,6 +216,7 @@ impl Display for FltkDisplay {
     }
 
     fn dimensions(&self) -> (u32, u32) {
-        (self.frame.width() as _, self.frame.height() as _)
+        self.size
+            .unwrap_or((self.frame.width() as _, self.frame.height() as _))
     }
 }
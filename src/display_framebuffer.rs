@@ -1,40 +1,281 @@
 //! Functionality to render images on a Linux framebuffer
+//!
+//! Where the driver supports it, the framebuffer is twice as high as the screen, and we draw into
+//! the hidden half, then pan to it during the vertical blank. Otherwise, we draw directly into the
+//! visible buffer, right after the vertical blank.
+//!
+//! Only the regions that changed since the last update are copied to the framebuffer, so updating
+//! the status line doesn't cost a full redraw.
+//!
+//! The console is switched to graphics mode, which hides its cursor, while the display is open.
+//! When it is dropped, the console gets back its text mode and the screen mode it had, and shows
+//! what it showed before, or stays black.
 
-use crate::display::Display;
+#![allow(unsafe_code)]
+
+use tracing::{debug_span, warn};
+
+use crate::display::{Display, Rotation};
 use crate::errors::{RahmenError, RahmenResult};
+use crate::pixel::{BgraImage, PixelFormat};
+use crate::vt::{VtEvent, VtSwitcher};
 
-use framebuffer::Framebuffer;
-use image::{Bgra, DynamicImage, GenericImage, ImageBuffer};
+use framebuffer::{Framebuffer, KdMode, VarScreeninfo};
+use image::imageops::{crop_imm, rotate180, rotate270, rotate90};
+use image::{Bgra, DynamicImage, GenericImage, GenericImageView};
+use std::os::unix::io::AsRawFd;
 use std::time::Duration;
 
-type BgraImage = ImageBuffer<Bgra<u8>, Vec<u8>>;
+const FBIOPAN_DISPLAY: libc::c_ulong = 0x4606;
+const FBIO_WAITFORVSYNC: libc::c_ulong = 0x4004_4620;
+const FBIOBLANK: libc::c_ulong = 0x4611;
+const FB_BLANK_UNBLANK: libc::c_int = 0;
+const FB_BLANK_POWERDOWN: libc::c_int = 4;
+
+/// A rectangle, from the top left corner inclusive to the bottom right corner exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Region {
+    fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x0: x,
+            y0: y,
+            x1: x + width,
+            y1: y + height,
+        }
+    }
+
+    /// The smallest region containing both regions
+    fn union(self, other: Option<Region>) -> Self {
+        match other {
+            Some(other) => Self {
+                x0: self.x0.min(other.x0),
+                y0: self.y0.min(other.y0),
+                x1: self.x1.max(other.x1),
+                y1: self.y1.max(other.y1),
+            },
+            None => self,
+        }
+    }
+
+    /// The region on a screen of the given size showing this region of the content rotated by
+    /// `rotation`
+    fn rotated(self, rotation: Rotation, (width, height): (u32, u32)) -> Self {
+        let Self { x0, y0, x1, y1 } = self;
+        match rotation {
+            Rotation::None => self,
+            Rotation::Rotate90 => Self {
+                x0: height - y1,
+                y0: x0,
+                x1: height - y0,
+                y1: x1,
+            },
+            Rotation::Rotate180 => Self {
+                x0: width - x1,
+                y0: height - y1,
+                x1: width - x0,
+                y1: height - y0,
+            },
+            Rotation::Rotate270 => Self {
+                x0: y0,
+                y0: width - x1,
+                x1: y1,
+                y1: width - x0,
+            },
+        }
+    }
+}
+
+/// The state of the framebuffer before we took it over
+struct Saved {
+    info: VarScreeninfo,
+    /// the content of the visible page, if it is to be restored
+    content: Option<Vec<u8>>,
+}
+
+impl std::fmt::Debug for Saved {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Saved")
+            .field("content", &self.content.as_ref().map(Vec::len))
+            .finish()
+    }
+}
+
+impl Saved {
+    /// Save the state of `framebuffer`, with the content it shows if `content` is set
+    fn of(framebuffer: &Framebuffer, content: bool) -> Self {
+        let info = framebuffer.var_screen_info.clone();
+        let content = if content {
+            let line_length = framebuffer.fix_screen_info.line_length as usize;
+            let start = line_length * info.yoffset as usize;
+            framebuffer
+                .frame
+                .get(start..start + line_length * info.yres as usize)
+                .map(<[u8]>::to_vec)
+        } else {
+            None
+        };
+        Self { info, content }
+    }
+}
 
 /// A display driver for Linux framebuffers
 #[derive(Debug)]
 pub struct FramebufferDisplay {
     framebuffer: Framebuffer,
+    saved: Saved,
     image: BgraImage,
+    vt: Option<VtSwitcher>,
+    active: bool,
+    rotation: Rotation,
+    format: PixelFormat,
+    double_buffered: bool,
+    page: u32,
+    /// the region changed since the last update
+    dirty: Option<Region>,
+    /// the region changed in the update before, which the hidden page lacks
+    flipped: Option<Region>,
+    /// whether the screen was powered down
+    powered_down: bool,
 }
 
 impl FramebufferDisplay {
-    /// Crate a new framebuffer
-    pub fn new(mut framebuffer: Framebuffer) -> Self {
-        assert_eq!(framebuffer.var_screen_info.bits_per_pixel, 32);
+    /// Open the framebuffer `device`, enlarging it to hold two screens for page flipping. When
+    /// done, the console shows what it showed before if `restore` is set, and black otherwise.
+    pub fn open(device: &str, rotation: Rotation, restore: bool) -> RahmenResult<Self> {
+        let fb_err = |e: framebuffer::FramebufferError| {
+            RahmenError::DisplayError(format!("{}: {:?}", device, e))
+        };
+        let mut framebuffer = Framebuffer::new(device).map_err(fb_err)?;
+        let saved = Saved::of(&framebuffer, restore);
+        let mut info = framebuffer.var_screen_info.clone();
+        if info.yres_virtual < info.yres * 2 {
+            info.yres_virtual = info.yres * 2;
+            info.xres_virtual = info.xres;
+            if Framebuffer::put_var_screeninfo(&framebuffer.device, &info).is_ok() {
+                // map the enlarged framebuffer
+                framebuffer = Framebuffer::new(device).map_err(fb_err)?;
+            }
+        }
+        Self::with_saved(framebuffer, rotation, saved)
+    }
+
+    /// Create a new framebuffer, rotating the content clockwise by `rotation`, for monitors
+    /// mounted in portrait orientation. When done, the console shows what it showed before if
+    /// `restore` is set, and black otherwise.
+    pub fn new(framebuffer: Framebuffer, rotation: Rotation, restore: bool) -> RahmenResult<Self> {
+        let saved = Saved::of(&framebuffer, restore);
+        Self::with_saved(framebuffer, rotation, saved)
+    }
+
+    fn with_saved(
+        mut framebuffer: Framebuffer,
+        rotation: Rotation,
+        saved: Saved,
+    ) -> RahmenResult<Self> {
+        let format = PixelFormat::from_screen_info(&framebuffer.var_screen_info)?;
+        let _ = Framebuffer::set_kd_mode(KdMode::Graphics)
+            .map_err(|_e| warn!("Failed to set graphics mode."));
         framebuffer.frame.fill(0);
-        Self {
+        let info = &framebuffer.var_screen_info;
+        let page_size = (framebuffer.fix_screen_info.line_length * info.yres) as usize;
+        let double_buffered =
+            info.yres_virtual >= info.yres * 2 && framebuffer.frame.len() >= page_size * 2;
+        if !double_buffered {
+            warn!("Framebuffer too small for page flipping, tearing may be visible");
+        }
+        let page = if double_buffered && info.yoffset >= info.yres {
+            1
+        } else {
+            0
+        };
+        let vt = VtSwitcher::new()
+            .map_err(|e| warn!("Console switching not available: {}", e))
+            .ok();
+        Ok(Self {
             framebuffer,
+            saved,
             image: Default::default(),
+            vt,
+            active: true,
+            rotation,
+            format,
+            page,
+            double_buffered,
+            dirty: None,
+            flipped: None,
+            powered_down: false,
+        })
+    }
+
+    /// Block until the next vertical blank. Not all drivers support this, so errors are ignored.
+    fn wait_for_vsync(&self) {
+        let crtc: u32 = 0;
+        unsafe {
+            libc::ioctl(
+                self.framebuffer.device.as_raw_fd(),
+                FBIO_WAITFORVSYNC,
+                &crtc,
+            )
+        };
+    }
+
+    /// Show the given page of the framebuffer
+    fn pan_to(&mut self, page: u32) -> RahmenResult<()> {
+        let mut info = self.framebuffer.var_screen_info.clone();
+        info.xoffset = 0;
+        info.yoffset = page * info.yres;
+        if unsafe { libc::ioctl(self.framebuffer.device.as_raw_fd(), FBIOPAN_DISPLAY, &info) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
         }
+        self.framebuffer.var_screen_info = info;
+        self.page = page;
+        Ok(())
+    }
+
+    /// Power the screen down, or back up
+    fn blank_screen(&mut self, level: libc::c_int) -> RahmenResult<()> {
+        if unsafe { libc::ioctl(self.framebuffer.device.as_raw_fd(), FBIOBLANK, level) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
     }
 
     /// Enter the control loop. This will periodically trigger the callback, until it returns an
-    /// `Err` result.
+    /// `Err` result. While another console is active, the callback is not triggered.
     pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
-        while callback(self).is_ok() {
+        loop {
+            if self.poll() && callback(self).is_err() {
+                break;
+            }
             std::thread::sleep(Duration::from_millis(50));
         }
     }
 
+    fn handle_vt_switch(&mut self) {
+        match self.vt.as_ref().map(VtSwitcher::poll).transpose() {
+            Ok(Some(Some(VtEvent::Released))) => self.active = false,
+            Ok(Some(Some(VtEvent::Acquired))) => {
+                let _ = Framebuffer::set_kd_mode(KdMode::Graphics)
+                    .map_err(|_e| warn!("Failed to set graphics mode."));
+                self.active = true;
+                // the other console has drawn over our content, restore it
+                if self.image.dimensions() == self.dimensions() {
+                    self.invalidate();
+                    let _ = self.update();
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Console switch failed: {}", e),
+        }
+    }
+
     fn match_dimensions(&mut self) -> RahmenResult<()> {
         if self.image.dimensions() != self.dimensions() {
             self.image = BgraImage::from_raw(
@@ -43,9 +284,45 @@ impl FramebufferDisplay {
                 vec![0u8; (self.dimensions().0 * self.dimensions().1 * 4) as usize],
             )
             .ok_or(RahmenError::Terminate)?;
+            self.invalidate();
         }
         Ok(())
     }
+
+    /// Mark the whole screen, on both pages, as changed
+    fn invalidate(&mut self) {
+        let (width, height) = self.image.dimensions();
+        self.dirty = Some(Region::new(0, 0, width, height));
+        self.flipped = self.dirty;
+    }
+}
+
+impl Drop for FramebufferDisplay {
+    fn drop(&mut self) {
+        if self.powered_down && self.blank_screen(FB_BLANK_UNBLANK).is_err() {
+            warn!("Failed to power the screen up.");
+        }
+        // don't leave the last image burned onto the console
+        let line_length = self.framebuffer.fix_screen_info.line_length as usize;
+        let start = line_length * self.saved.info.yoffset as usize;
+        let restored = match &self.saved.content {
+            Some(content) => self
+                .framebuffer
+                .frame
+                .get_mut(start..start + content.len())
+                .map(|page| page.copy_from_slice(content))
+                .is_some(),
+            None => false,
+        };
+        if !restored {
+            self.framebuffer.frame.fill(0);
+        }
+        if Framebuffer::put_var_screeninfo(&self.framebuffer.device, &self.saved.info).is_err() {
+            warn!("Failed to restore the screen mode.");
+        }
+        let _ =
+            Framebuffer::set_kd_mode(KdMode::Text).map_err(|_e| warn!("Failed to set text mode."));
+    }
 }
 
 impl Display for FramebufferDisplay {
@@ -58,6 +335,8 @@ impl Display for FramebufferDisplay {
     ) -> RahmenResult<()> {
         self.match_dimensions()?;
         self.image.copy_from(&img.to_bgra8(), x_offset, y_offset)?;
+        self.dirty =
+            Some(Region::new(x_offset, y_offset, img.width(), img.height()).union(self.dirty));
         Ok(())
     }
 
@@ -69,26 +348,106 @@ impl Display for FramebufferDisplay {
         x_size: u32,
         y_size: u32,
     ) -> RahmenResult<()> {
-        let _t = crate::Timer::new(|e| println!("Blanking {}ms", e.as_millis()));
+        let _span = debug_span!("blank").entered();
         self.match_dimensions()?;
         let black = image::FlatSamples::with_monocolor(&Bgra([0; 4]), x_size, y_size);
         self.image
             .copy_from(&black.as_view().unwrap(), x_offset, y_offset)?;
+        self.dirty = Some(Region::new(x_offset, y_offset, x_size, y_size).union(self.dirty));
         Ok(())
     }
 
     fn dimensions(&self) -> (u32, u32) {
-        (
+        self.rotation.content_dimensions((
             self.framebuffer.var_screen_info.xres,
             self.framebuffer.var_screen_info.yres,
-        )
+        ))
+    }
+
+    fn set_power(&mut self, on: bool) -> RahmenResult<()> {
+        self.blank_screen(if on {
+            FB_BLANK_UNBLANK
+        } else {
+            FB_BLANK_POWERDOWN
+        })?;
+        self.powered_down = !on;
+        Ok(())
+    }
+
+    fn poll(&mut self) -> bool {
+        self.handle_vt_switch();
+        self.active
     }
 
     fn update(&mut self) -> RahmenResult<()> {
-        self.framebuffer
-            .frame
-            .as_mut()
-            .copy_from_slice(self.image.as_raw());
+        // changes wait while another console is shown, and the whole screen is redrawn once ours is
+        // back
+        if !self.active {
+            return Ok(());
+        }
+        // the hidden page also lacks the changes shown by the last flip
+        let dirty = match self.dirty.take() {
+            Some(dirty) => dirty,
+            None => return Ok(()),
+        };
+        let _span = debug_span!("update").entered();
+        let region = if self.double_buffered {
+            dirty.union(self.flipped)
+        } else {
+            dirty
+        };
+        let cropped = crop_imm(
+            &self.image,
+            region.x0,
+            region.y0,
+            region.x1 - region.x0,
+            region.y1 - region.y0,
+        )
+        .to_image();
+        let image = match self.rotation {
+            Rotation::None => cropped,
+            Rotation::Rotate90 => rotate90(&cropped),
+            Rotation::Rotate180 => rotate180(&cropped),
+            Rotation::Rotate270 => rotate270(&cropped),
+        };
+        let screen = region.rotated(self.rotation, self.image.dimensions());
+        let line_length = self.framebuffer.fix_screen_info.line_length as usize;
+        let page_size = line_length * self.framebuffer.var_screen_info.yres as usize;
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        // draw into the hidden page, if there is one
+        let target = if self.double_buffered {
+            1 - self.page
+        } else {
+            self.wait_for_vsync();
+            0
+        };
+        let start = target as usize * page_size;
+        let frame = &mut self.framebuffer.frame[start..start + page_size];
+        for (row, line) in image
+            .as_raw()
+            .chunks(image.width() as usize * 4)
+            .zip(frame.chunks_mut(line_length).skip(screen.y0 as usize))
+        {
+            let line = &mut line[screen.x0 as usize * bytes_per_pixel..];
+            if self.format.is_bgra() {
+                line[..row.len()].copy_from_slice(row);
+            } else {
+                for (pixel, out) in row.chunks(4).zip(line.chunks_mut(bytes_per_pixel)) {
+                    self.format.pack_bgra(pixel, out);
+                }
+            }
+        }
+        if self.double_buffered {
+            self.wait_for_vsync();
+            if let Err(e) = self.pan_to(target) {
+                warn!("Page flipping failed, drawing directly: {}", e);
+                self.double_buffered = false;
+                let _ = self.pan_to(0);
+                self.invalidate();
+                return self.update();
+            }
+            self.flipped = Some(dirty);
+        }
         Ok(())
     }
 }
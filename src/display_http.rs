@@ -0,0 +1,193 @@
+//! Functionality to serve the displayed frame over HTTP
+//!
+//! Any browser can act as the frame: `/` shows an MJPEG stream of the frame, `/refresh` a page
+//! reloading a still image periodically for browsers that can't handle MJPEG, and `/frame.jpg` the
+//! current frame. Each MJPEG client holds a connection of the shared HTTP server, which limits
+//! how many are served at the same time, and drops clients too slow to take a frame in time.
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, DynamicImage, GenericImage, Rgb, RgbImage};
+use tracing::{debug_span, info};
+
+use crate::display::Display;
+use crate::errors::RahmenResult;
+use crate::http_server::{self, Limits, Request, Response};
+
+/// Resend the current frame to MJPEG clients after this time without updates, which also detects
+/// clients that went away
+const STREAM_KEEPALIVE: Duration = Duration::from_secs(10);
+
+const STREAM_PAGE: &str = "<!DOCTYPE html>
+<html><head><title>Rahmen</title>
+<style>body { margin: 0; background: #000; } img { width: 100vw; height: 100vh; object-fit: contain; }</style>
+</head><body><img src=\"/stream\"></body></html>
+";
+
+/// The latest encoded frame along with a counter of updates
+#[derive(Debug, Default)]
+struct SharedFrame {
+    frame: Mutex<(u64, Arc<Vec<u8>>)>,
+    updated: Condvar,
+}
+
+impl SharedFrame {
+    fn publish(&self, jpeg: Vec<u8>) {
+        let mut frame = self.frame.lock().unwrap();
+        *frame = (frame.0 + 1, Arc::new(jpeg));
+        self.updated.notify_all();
+    }
+
+    fn current(&self) -> (u64, Arc<Vec<u8>>) {
+        self.frame.lock().unwrap().clone()
+    }
+
+    /// Wait for a frame newer than `generation`, but return the current one on timeout
+    fn next_after(&self, generation: u64, timeout: Duration) -> (u64, Arc<Vec<u8>>) {
+        let (frame, _) = self
+            .updated
+            .wait_timeout_while(self.frame.lock().unwrap(), timeout, |frame| {
+                frame.0 == generation
+            })
+            .unwrap();
+        frame.clone()
+    }
+}
+
+/// A display driver serving the frame over HTTP
+#[derive(Debug)]
+pub struct HttpDisplay {
+    frame: Arc<SharedFrame>,
+    image: RgbImage,
+    quality: u8,
+}
+
+impl HttpDisplay {
+    /// Start serving on `address`, such as `0.0.0.0:8080`, to at most `max_clients` at a time.
+    /// Frames have the given dimensions and are encoded as JPEG with `quality` (1-100). The
+    /// `/refresh` page reloads every `refresh` seconds.
+    pub fn new(
+        address: &str,
+        dimensions: (u32, u32),
+        quality: u8,
+        refresh: u32,
+        max_clients: usize,
+    ) -> RahmenResult<Self> {
+        let frame = Arc::new(SharedFrame::default());
+        let shared = Arc::clone(&frame);
+        let limits = Limits {
+            max_connections: max_clients.max(1),
+            ..Limits::default()
+        };
+        let address = http_server::serve(address, limits, move |request| {
+            handle(request, &shared, refresh)
+        })?;
+        info!("Serving the frame on http://{}/", address);
+        Ok(Self {
+            frame,
+            image: RgbImage::new(dimensions.0, dimensions.1),
+            quality,
+        })
+    }
+
+    /// Enter the control loop. This will periodically trigger the callback, until it returns an
+    /// `Err` result.
+    pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
+        while callback(self).is_ok() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+fn handle(request: Request, frame: &Arc<SharedFrame>, refresh: u32) -> Response {
+    match request.path.as_str() {
+        "/" => Response::new("200 OK", "text/html", STREAM_PAGE),
+        "/refresh" => {
+            let page = format!(
+                "<!DOCTYPE html>
+<html><head><title>Rahmen</title><meta http-equiv=\"refresh\" content=\"{}\">
+<style>body {{ margin: 0; background: #000; }} img {{ width: 100vw; height: 100vh; object-fit: contain; }}</style>
+</head><body><img src=\"/frame.jpg\"></body></html>
+",
+                refresh
+            );
+            Response::new("200 OK", "text/html", page)
+        }
+        "/frame.jpg" => Response::new("200 OK", "image/jpeg", frame.current().1.as_ref().clone()),
+        "/stream" => {
+            let frame = Arc::clone(frame);
+            Response::Stream(Box::new(move |stream| stream_frames(stream, &frame)))
+        }
+        _ => Response::new("404 Not Found", "text/plain", "not found\n"),
+    }
+}
+
+fn stream_frames(stream: &mut TcpStream, frame: &SharedFrame) -> std::io::Result<()> {
+    stream.write_all(
+        b"HTTP/1.0 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary=frame\r\nCache-Control: no-cache\r\n\r\n",
+    )?;
+    let (mut generation, mut jpeg) = frame.current();
+    loop {
+        if !jpeg.is_empty() {
+            write!(
+                stream,
+                "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                jpeg.len()
+            )?;
+            stream.write_all(&jpeg)?;
+            stream.write_all(b"\r\n")?;
+            stream.flush()?;
+        }
+        let (next_generation, next_jpeg) = frame.next_after(generation, STREAM_KEEPALIVE);
+        generation = next_generation;
+        jpeg = next_jpeg;
+    }
+}
+
+impl Display for HttpDisplay {
+    fn render(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        img: &DynamicImage,
+    ) -> RahmenResult<()> {
+        self.image.copy_from(&img.to_rgb8(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn blank(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        x_size: u32,
+        y_size: u32,
+    ) -> RahmenResult<()> {
+        let black = image::FlatSamples::with_monocolor(&Rgb([0; 3]), x_size, y_size);
+        self.image
+            .copy_from(&black.as_view().unwrap(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn update(&mut self) -> RahmenResult<()> {
+        let _span = debug_span!("update").entered();
+        let mut jpeg = vec![];
+        JpegEncoder::new_with_quality(&mut jpeg, self.quality).encode(
+            self.image.as_raw(),
+            self.image.width(),
+            self.image.height(),
+            ColorType::Rgb8,
+        )?;
+        self.frame.publish(jpeg);
+        Ok(())
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+}
@@ -0,0 +1,229 @@
+//! Functionality to drive several displays at once
+//!
+//! Images are composed at the size of the first display. Other displays of a different size show
+//! the composed frame scaled to fit. A framebuffer whose console is switched away from is skipped,
+//! and shows the latest frame once its console is back, while the other displays go on.
+
+use std::time::Duration;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, GenericImageView, Rgb, RgbImage};
+use tracing::warn;
+
+use crate::display::Display;
+use crate::errors::{RahmenError, RahmenResult};
+
+/// How several displays share the images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiMode {
+    /// Show the same content on all displays
+    Mirror,
+    /// Show each new image on the next display in turn
+    Alternate,
+}
+
+/// A render operation, applied to the targeted displays on update
+#[derive(Debug)]
+enum Operation {
+    Render(usize, u32, u32, RgbImage),
+    Blank(usize, u32, u32, u32, u32),
+    Text(usize, Vec<String>),
+}
+
+/// A display driver forwarding to several other displays
+pub struct MultiDisplay {
+    outputs: Vec<Box<dyn Display>>,
+    frames: Vec<RgbImage>,
+    /// whether each display is shown, as of the last poll
+    shown: Vec<bool>,
+    mode: MultiMode,
+    advance_key: usize,
+    current: usize,
+    pending: Vec<Operation>,
+}
+
+impl std::fmt::Debug for MultiDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiDisplay")
+            .field("outputs", &self.outputs.len())
+            .field("mode", &self.mode)
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl MultiDisplay {
+    /// Combine several displays. In `Alternate` mode, rendering an image with `advance_key`
+    /// switches to the next display.
+    pub fn new(
+        outputs: Vec<Box<dyn Display>>,
+        mode: MultiMode,
+        advance_key: usize,
+    ) -> RahmenResult<Self> {
+        if outputs.is_empty() {
+            return Err(RahmenError::DisplayError("no displays configured".into()));
+        }
+        Ok(Self {
+            frames: outputs.iter().map(|_| RgbImage::default()).collect(),
+            shown: outputs.iter().map(|_| true).collect(),
+            current: outputs.len() - 1,
+            outputs,
+            mode,
+            advance_key,
+            pending: vec![],
+        })
+    }
+
+    /// Enter the control loop. This will periodically trigger the callback, until it returns an
+    /// `Err` result. While none of the displays is shown, the callback is not triggered.
+    pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
+        loop {
+            if self.poll() && callback(self).is_err() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn match_dimensions(&mut self) {
+        let (width, height) = self.dimensions();
+        for frame in &mut self.frames {
+            if frame.dimensions() != (width, height) {
+                *frame = RgbImage::new(width, height);
+            }
+        }
+    }
+
+    /// Show a composed frame on one of the displays, scaled to fit if the sizes differ
+    fn show(&mut self, index: usize) -> RahmenResult<()> {
+        let frame = &self.frames[index];
+        let output = &mut self.outputs[index];
+        let (width, height) = output.dimensions();
+        if frame.dimensions() == (width, height) {
+            output.render(0, 0, 0, &DynamicImage::ImageRgb8(frame.clone()))?;
+        } else {
+            let scaled =
+                DynamicImage::ImageRgb8(frame.clone()).resize(width, height, FilterType::Triangle);
+            output.blank(0, 0, 0, width, height)?;
+            output.render(
+                0,
+                (width - scaled.width()) / 2,
+                (height - scaled.height()) / 2,
+                &scaled,
+            )?;
+        }
+        output.update()
+    }
+}
+
+impl Display for MultiDisplay {
+    fn render(
+        &mut self,
+        key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        img: &DynamicImage,
+    ) -> RahmenResult<()> {
+        self.pending
+            .push(Operation::Render(key, x_offset, y_offset, img.to_rgb8()));
+        Ok(())
+    }
+
+    fn blank(
+        &mut self,
+        key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        x_size: u32,
+        y_size: u32,
+    ) -> RahmenResult<()> {
+        self.pending
+            .push(Operation::Blank(key, x_offset, y_offset, x_size, y_size));
+        Ok(())
+    }
+
+    fn text(&mut self, key: usize, lines: &[String]) -> RahmenResult<()> {
+        self.pending.push(Operation::Text(key, lines.to_vec()));
+        Ok(())
+    }
+
+    fn update(&mut self) -> RahmenResult<()> {
+        self.match_dimensions();
+        let operations = std::mem::take(&mut self.pending);
+        let advance_key = self.advance_key;
+        // operations arrive in no particular order, so decide on the target displays first
+        let advance = self.mode == MultiMode::Alternate
+            && operations
+                .iter()
+                .any(|op| matches!(op, Operation::Render(key, ..) if *key == advance_key));
+        let targets: Vec<usize> = match self.mode {
+            MultiMode::Mirror => (0..self.outputs.len()).collect(),
+            MultiMode::Alternate => {
+                if advance {
+                    self.current = (self.current + 1) % self.outputs.len();
+                    // this display showed another image before
+                    self.frames[self.current].fill(0);
+                }
+                vec![self.current]
+            }
+        };
+
+        for operation in &operations {
+            for &index in &targets {
+                match operation {
+                    Operation::Render(_, x_offset, y_offset, img) => {
+                        self.frames[index].copy_from(img, *x_offset, *y_offset)?;
+                    }
+                    // the frame was cleared already
+                    Operation::Blank(key, ..) if advance && *key == advance_key => {}
+                    Operation::Blank(_, x_offset, y_offset, x_size, y_size) => {
+                        let black =
+                            image::FlatSamples::with_monocolor(&Rgb([0; 3]), *x_size, *y_size);
+                        self.frames[index].copy_from(
+                            &black.as_view().unwrap(),
+                            *x_offset,
+                            *y_offset,
+                        )?;
+                    }
+                    Operation::Text(key, lines) => self.outputs[index].text(*key, lines)?,
+                }
+            }
+        }
+        for index in targets {
+            if self.shown[index] {
+                self.show(index)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.outputs[0].dimensions()
+    }
+
+    fn set_power(&mut self, on: bool) -> RahmenResult<()> {
+        // switch the others even if one of them fails
+        let mut result = Ok(());
+        for output in &mut self.outputs {
+            if let Err(e) = output.set_power(on) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    fn poll(&mut self) -> bool {
+        for index in 0..self.outputs.len() {
+            let shown = self.outputs[index].poll();
+            // the display missed the frames while it wasn't shown
+            if shown && !self.shown[index] && !self.frames[index].is_empty() {
+                if let Err(e) = self.show(index) {
+                    warn!("Showing the frame again failed: {}", e);
+                }
+            }
+            self.shown[index] = shown;
+        }
+        self.shown.iter().any(|shown| *shown)
+    }
+}
@@ -0,0 +1,120 @@
+//! Functionality to render frames without any display hardware
+//!
+//! The frame is kept in memory, and each update can be written to a numbered PNG file. This
+//! exercises the whole pipeline, from the providers to the status line, in CI or on a laptop
+//! while trying a configuration, and the files show what a frame would have shown. The display
+//! can stop after a number of frames, ending the slideshow.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use image::{DynamicImage, GenericImage, Rgb, RgbImage};
+use tracing::{debug_span, info};
+
+use crate::display::Display;
+use crate::errors::RahmenResult;
+
+/// A display driver rendering to memory, and optionally to PNG files
+#[derive(Debug)]
+pub struct PngDisplay {
+    image: RgbImage,
+    /// the directory to write the frames to, if any
+    dir: Option<PathBuf>,
+    /// the number of frames to show before stopping, if limited
+    max_frames: Option<u64>,
+    frames: u64,
+    text: Vec<String>,
+}
+
+impl PngDisplay {
+    /// Create a new display of `(width, height)` pixels, writing each frame to `dir`, which is
+    /// created if needed, if given, and stopping after `max_frames`, if given
+    pub fn new(
+        dimensions: (u32, u32),
+        dir: Option<PathBuf>,
+        max_frames: Option<u64>,
+    ) -> RahmenResult<Self> {
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(Self {
+            image: RgbImage::new(dimensions.0, dimensions.1),
+            dir,
+            max_frames,
+            frames: 0,
+            text: vec![],
+        })
+    }
+
+    /// Enter the control loop. This will periodically trigger the callback, until it returns an
+    /// `Err` result, or the number of frames to show is reached.
+    pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
+        while self.max_frames.map_or(true, |max| self.frames < max) && callback(self).is_ok() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// The frame as shown after the last update
+    pub fn frame(&self) -> &RgbImage {
+        &self.image
+    }
+
+    /// The number of frames shown so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// The status line as last passed as text
+    pub fn status_line(&self) -> &[String] {
+        &self.text
+    }
+}
+
+impl Display for PngDisplay {
+    fn render(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        img: &DynamicImage,
+    ) -> RahmenResult<()> {
+        let _span = debug_span!("render").entered();
+        self.image.copy_from(&img.to_rgb8(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn blank(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        x_size: u32,
+        y_size: u32,
+    ) -> RahmenResult<()> {
+        let black = image::FlatSamples::with_monocolor(&Rgb([0; 3]), x_size, y_size);
+        self.image
+            .copy_from(&black.as_view().unwrap(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn text(&mut self, _key: usize, lines: &[String]) -> RahmenResult<()> {
+        self.text = lines.to_vec();
+        Ok(())
+    }
+
+    fn update(&mut self) -> RahmenResult<()> {
+        let _span = debug_span!("update").entered();
+        self.frames += 1;
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("frame-{:05}.png", self.frames));
+            info!("Writing {:?}", path);
+            self.image
+                .save_with_format(&path, image::ImageFormat::Png)?;
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+}
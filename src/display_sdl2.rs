@@ -0,0 +1,205 @@
+//! Functionality to render images in an SDL2 window
+//!
+//! SDL2 runs on Linux, macOS and Windows, which makes it convenient to preview a configuration on a
+//! desktop before deploying it to the frame. With a fixed resolution, Rahmen renders at the frame's
+//! resolution and SDL scales the result to the window, in hardware where available. On high-DPI
+//! screens of macOS and Windows, it renders at the pixel resolution rather than the scaled one.
+
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use image::{DynamicImage, GenericImage, Rgb, RgbImage};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::{FullscreenType, WindowContext};
+use sdl2::EventPump;
+use tracing::debug_span;
+
+use crate::control::Command;
+use crate::display::{parse_resolution, Display};
+use crate::errors::{RahmenError, RahmenResult};
+
+/// A display driver rendering to an SDL2 window
+pub struct Sdl2Display {
+    canvas: WindowCanvas,
+    texture_creator: TextureCreator<WindowContext>,
+    events: EventPump,
+    commands: Sender<Command>,
+    resolution: Option<(u32, u32)>,
+    dimensions: (u32, u32),
+    image: RgbImage,
+}
+
+impl std::fmt::Debug for Sdl2Display {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sdl2Display")
+            .field("resolution", &self.resolution)
+            .field("dimensions", &self.dimensions)
+            .finish()
+    }
+}
+
+fn sdl_err<E: std::fmt::Display>(err: E) -> RahmenError {
+    RahmenError::DisplayError(format!("SDL2: {}", err))
+}
+
+impl Sdl2Display {
+    /// Open a window, sending commands triggered by key presses to `commands`. With a
+    /// `resolution` given as `WxH`, images are rendered at that size and scaled to the window,
+    /// otherwise they are rendered at the window's size.
+    pub fn new(commands: Sender<Command>, resolution: Option<&str>) -> RahmenResult<Self> {
+        let resolution = resolution.map(parse_resolution).transpose()?;
+        // a preview window should not turn off the compositor of the desktop
+        #[cfg(target_os = "linux")]
+        sdl2::hint::set("SDL_VIDEO_X11_NET_WM_BYPASS_COMPOSITOR", "0");
+        // otherwise, Windows scales the window up and blurs it on high-DPI screens
+        #[cfg(target_os = "windows")]
+        sdl2::hint::set("SDL_WINDOWS_DPI_AWARENESS", "permonitorv2");
+        let sdl = sdl2::init().map_err(sdl_err)?;
+        let video = sdl.video().map_err(sdl_err)?;
+        video.disable_screen_saver();
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "linear");
+
+        let (width, height) = resolution.unwrap_or((800, 600));
+        let mut builder = video.window("Rahmen", width, height);
+        builder.position_centered().resizable();
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        builder.allow_highdpi();
+        let window = builder.build().map_err(sdl_err)?;
+        let mut canvas = window.into_canvas().build().map_err(sdl_err)?;
+        if let Some((width, height)) = resolution {
+            canvas.set_logical_size(width, height).map_err(sdl_err)?;
+        }
+        let texture_creator = canvas.texture_creator();
+        let events = sdl.event_pump().map_err(sdl_err)?;
+        let dimensions = canvas.output_size().map_err(sdl_err)?;
+
+        Ok(Self {
+            canvas,
+            texture_creator,
+            events,
+            commands,
+            resolution,
+            dimensions,
+            image: Default::default(),
+        })
+    }
+
+    /// Enter the control loop. This will periodically trigger the callback, until it returns an
+    /// `Err` result or the window is closed.
+    pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
+        while self.handle_events().is_ok() && callback(self).is_ok() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn handle_events(&mut self) -> RahmenResult<()> {
+        let events: Vec<_> = self.events.poll_iter().collect();
+        for event in events {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Err(RahmenError::Terminate),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return),
+                    ..
+                } => {
+                    let window = self.canvas.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off,
+                    };
+                    window.set_fullscreen(fullscreen).map_err(sdl_err)?;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => {
+                    let _ = self.commands.send(Command::CycleProof);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => {
+                    let _ = self.commands.send(Command::ToggleClean);
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => {
+                    self.dimensions = self.canvas.output_size().map_err(sdl_err)?;
+                    self.update()?;
+                }
+                Event::Window {
+                    win_event: WindowEvent::Exposed,
+                    ..
+                } => self.update()?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn match_dimensions(&mut self) -> RahmenResult<()> {
+        if self.image.dimensions() != self.dimensions() {
+            self.image = RgbImage::new(self.dimensions().0, self.dimensions().1);
+        }
+        Ok(())
+    }
+}
+
+impl Display for Sdl2Display {
+    fn render(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        img: &DynamicImage,
+    ) -> RahmenResult<()> {
+        self.match_dimensions()?;
+        self.image.copy_from(&img.to_rgb8(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn blank(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        x_size: u32,
+        y_size: u32,
+    ) -> RahmenResult<()> {
+        self.match_dimensions()?;
+        let black = image::FlatSamples::with_monocolor(&Rgb([0; 3]), x_size, y_size);
+        self.image
+            .copy_from(&black.as_view().unwrap(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn update(&mut self) -> RahmenResult<()> {
+        let _span = debug_span!("update").entered();
+        let (width, height) = self.image.dimensions();
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        if width > 0 && height > 0 {
+            let mut texture = self
+                .texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                .map_err(sdl_err)?;
+            texture
+                .update(None, self.image.as_raw(), width as usize * 3)
+                .map_err(sdl_err)?;
+            self.canvas.copy(&texture, None, None).map_err(sdl_err)?;
+        }
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.resolution.unwrap_or(self.dimensions)
+    }
+}
@@ -0,0 +1,256 @@
+//! Functionality to render images in a terminal
+//!
+//! Images are drawn using the Kitty graphics protocol or Sixel, with the status line printed as
+//! text below. This is handy to debug a frame over SSH. Beyond Unix, the size of the terminal is
+//! not known, and the classic 80 by 24 cells are assumed.
+
+#![allow(unsafe_code)]
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use image::{DynamicImage, GenericImage, Rgb, RgbImage};
+use tracing::debug_span;
+
+use crate::display::{Display, GraphicsProtocol};
+use crate::errors::RahmenResult;
+
+/// Terminal rows reserved for the status line
+const TEXT_ROWS: u32 = 2;
+
+/// Assumed cell size in pixels for terminals that don't report their size in pixels
+const FALLBACK_CELL_SIZE: (u32, u32) = (10, 20);
+
+/// Maximum size of a chunk of image data in the Kitty graphics protocol
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// A display driver rendering to the terminal on standard output
+#[derive(Debug)]
+pub struct TerminalDisplay {
+    protocol: GraphicsProtocol,
+    image: RgbImage,
+    image_changed: bool,
+    text: Vec<String>,
+    text_keys: HashSet<usize>,
+}
+
+impl TerminalDisplay {
+    /// Create a new terminal display drawing graphics using `protocol`
+    pub fn new(protocol: GraphicsProtocol) -> Self {
+        Self {
+            protocol,
+            image: Default::default(),
+            image_changed: false,
+            text: vec![],
+            text_keys: HashSet::new(),
+        }
+    }
+
+    /// Enter the control loop. This will periodically trigger the callback, until it returns an
+    /// `Err` result.
+    pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
+        while callback(self).is_ok() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn match_dimensions(&mut self) {
+        if self.image.dimensions() != self.dimensions() {
+            self.image = RgbImage::new(self.dimensions().0, self.dimensions().1);
+        }
+    }
+}
+
+/// Determine the terminal size as `(columns, rows, width, height)`, in cells and pixels
+#[cfg(unix)]
+fn window_size() -> (u16, u16, u16, u16) {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    if unsafe { libc::ioctl(io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &mut size) } < 0 {
+        // not a terminal, assume the classic size
+        size.ws_col = 80;
+        size.ws_row = 24;
+    }
+    (size.ws_col, size.ws_row, size.ws_xpixel, size.ws_ypixel)
+}
+
+/// Determine the terminal size, which is not known without `TIOCGWINSZ`
+#[cfg(not(unix))]
+fn window_size() -> (u16, u16, u16, u16) {
+    (80, 24, 0, 0)
+}
+
+/// Determine the terminal size as `(columns, rows, width, height)`, with width and height in pixels
+fn terminal_size() -> (u32, u32, u32, u32) {
+    let (columns, rows, width, height) = window_size();
+    let (columns, rows) = (u32::from(columns), u32::from(rows));
+    let (mut width, mut height) = (u32::from(width), u32::from(height));
+    if width == 0 || height == 0 {
+        width = columns * FALLBACK_CELL_SIZE.0;
+        height = rows * FALLBACK_CELL_SIZE.1;
+    }
+    (columns, rows, width, height)
+}
+
+fn write_kitty<W: Write>(out: &mut W, img: &RgbImage) -> io::Result<()> {
+    // delete previous images
+    out.write_all(b"\x1b_Ga=d,q=2\x1b\\")?;
+    let data = base64::encode(img.as_raw());
+    let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=24,s={},v={},q=2,m={};",
+                img.width(),
+                img.height(),
+                more
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        out.write_all(b"\x1b\\")?;
+    }
+    Ok(())
+}
+
+fn write_sixel<W: Write>(out: &mut W, img: &RgbImage) -> io::Result<()> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    // map each pixel to a 6x6x6 color cube
+    let level = |v: u8| (usize::from(v) * 5 + 127) / 255;
+    let colors: Vec<usize> = img
+        .pixels()
+        .map(|p| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+        .collect();
+
+    write!(out, "\x1bPq\"1;1;{};{}", width, height)?;
+    for color in 0..216 {
+        // sixel colors are given in percent
+        let (r, g, b) = (color / 36, color / 6 % 6, color % 6);
+        write!(out, "#{};2;{};{};{}", color, r * 20, g * 20, b * 20)?;
+    }
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut used = [false; 216];
+        for y in rows.clone() {
+            for color in &colors[y * width..(y + 1) * width] {
+                used[*color] = true;
+            }
+        }
+        for color in (0..216).filter(|color| used[*color]) {
+            write!(out, "#{}", color)?;
+            let mut run = (0, 0);
+            for x in 0..width {
+                let bits = rows
+                    .clone()
+                    .filter(|y| colors[y * width + x] == color)
+                    .fold(0, |bits, y| bits | 1 << (y - band));
+                if bits != run.0 && run.1 > 0 {
+                    write_sixel_run(out, run)?;
+                    run.1 = 0;
+                }
+                run = (bits, run.1 + 1);
+            }
+            write_sixel_run(out, run)?;
+            // return to the start of the band for the next color
+            out.write_all(b"$")?;
+        }
+        out.write_all(b"-")?;
+    }
+    out.write_all(b"\x1b\\")
+}
+
+fn write_sixel_run<W: Write>(out: &mut W, (bits, count): (u8, usize)) -> io::Result<()> {
+    let sixel = char::from(63 + bits);
+    if count > 3 {
+        write!(out, "!{}{}", count, sixel)
+    } else {
+        write!(out, "{}", sixel.to_string().repeat(count))
+    }
+}
+
+impl Display for TerminalDisplay {
+    fn render(
+        &mut self,
+        key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        img: &DynamicImage,
+    ) -> RahmenResult<()> {
+        // the status line is printed as text instead
+        if self.text_keys.contains(&key) {
+            return Ok(());
+        }
+        self.match_dimensions();
+        self.image.copy_from(&img.to_rgb8(), x_offset, y_offset)?;
+        self.image_changed = true;
+        Ok(())
+    }
+
+    fn blank(
+        &mut self,
+        key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        x_size: u32,
+        y_size: u32,
+    ) -> RahmenResult<()> {
+        if self.text_keys.contains(&key) {
+            return Ok(());
+        }
+        self.match_dimensions();
+        let black = image::FlatSamples::with_monocolor(&Rgb([0; 3]), x_size, y_size);
+        self.image
+            .copy_from(&black.as_view().unwrap(), x_offset, y_offset)?;
+        self.image_changed = true;
+        Ok(())
+    }
+
+    fn text(&mut self, key: usize, lines: &[String]) -> RahmenResult<()> {
+        self.text_keys.insert(key);
+        self.text = lines.to_vec();
+        Ok(())
+    }
+
+    fn update(&mut self) -> RahmenResult<()> {
+        let _span = debug_span!("update").entered();
+        let (columns, rows, _, _) = terminal_size();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        if self.image_changed {
+            self.image_changed = false;
+            out.write_all(b"\x1b[H\x1b[2J")?;
+            match self.protocol {
+                GraphicsProtocol::Kitty => write_kitty(&mut out, &self.image)?,
+                GraphicsProtocol::Sixel => write_sixel(&mut out, &self.image)?,
+            }
+        }
+        // print the status line into the rows below the image
+        write!(out, "\x1b[{};1H\x1b[J", rows.saturating_sub(TEXT_ROWS) + 1)?;
+        let lines: Vec<String> = self
+            .text
+            .iter()
+            .take(TEXT_ROWS as usize)
+            .map(|line| line.chars().take(columns as usize).collect())
+            .collect();
+        write!(out, "{}", lines.join("\r\n"))?;
+        out.flush()?;
+        Ok(())
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        let (_, rows, width, height) = terminal_size();
+        // leave room for the status line
+        let text_height = height / rows.max(1) * TEXT_ROWS;
+        (width, height.saturating_sub(text_height))
+    }
+}
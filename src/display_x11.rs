@@ -0,0 +1,253 @@
+//! Functionality to render images in a fullscreen X11 window
+
+use std::time::Duration;
+
+use image::{Bgra, DynamicImage, GenericImage};
+use tracing::{debug_span, warn};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::dpms::{ConnectionExt as _, DPMSMode};
+use x11rb::protocol::screensaver::ConnectionExt as _;
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, CreateGCAux, CreateWindowAux, EventMask,
+    Gcontext, ImageFormat, PropMode, Window, WindowClass, CLIENT_MESSAGE_EVENT,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+use crate::display::Display;
+use crate::errors::{RahmenError, RahmenResult};
+use crate::pixel::BgraImage;
+
+/// A display driver rendering to a fullscreen X11 window
+#[derive(Debug)]
+pub struct X11Display {
+    connection: RustConnection,
+    window: Window,
+    gc: Gcontext,
+    depth: u8,
+    dimensions: (u32, u32),
+    image: BgraImage,
+}
+
+fn x11_err<E: std::fmt::Display>(err: E) -> RahmenError {
+    RahmenError::DisplayError(format!("X11: {}", err))
+}
+
+impl X11Display {
+    /// Open a fullscreen window on the default screen. With `override_redirect`, the window
+    /// manager leaves the window alone, so it has no decorations and stays on top, which is what
+    /// kiosks want.
+    pub fn new(override_redirect: bool) -> RahmenResult<Self> {
+        let (connection, screen_num) = RustConnection::connect(None).map_err(x11_err)?;
+        let screen = &connection.setup().roots[screen_num];
+        let (root, depth, black) = (screen.root, screen.root_depth, screen.black_pixel);
+        let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+
+        let window = connection.generate_id().map_err(x11_err)?;
+        connection
+            .create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                root,
+                0,
+                0,
+                width,
+                height,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                0,
+                &CreateWindowAux::new()
+                    .background_pixel(black)
+                    .override_redirect(u32::from(override_redirect))
+                    .event_mask(EventMask::EXPOSURE | EventMask::STRUCTURE_NOTIFY),
+            )
+            .map_err(x11_err)?;
+        connection
+            .change_property8(
+                PropMode::REPLACE,
+                window,
+                AtomEnum::WM_NAME,
+                AtomEnum::STRING,
+                b"Rahmen",
+            )
+            .map_err(x11_err)?;
+        if !override_redirect {
+            // ask the window manager for fullscreen
+            let net_wm_state = intern_atom(&connection, b"_NET_WM_STATE")?;
+            let fullscreen = intern_atom(&connection, b"_NET_WM_STATE_FULLSCREEN")?;
+            connection
+                .change_property32(
+                    PropMode::REPLACE,
+                    window,
+                    net_wm_state,
+                    AtomEnum::ATOM,
+                    &[fullscreen],
+                )
+                .map_err(x11_err)?;
+        }
+        let gc = connection.generate_id().map_err(x11_err)?;
+        connection
+            .create_gc(gc, window, &CreateGCAux::new())
+            .map_err(x11_err)?;
+        connection.map_window(window).map_err(x11_err)?;
+        // keep the screensaver from kicking in while we're running
+        if let Err(e) = connection.screensaver_suspend(1) {
+            warn!("Failed to suspend the screensaver: {}", e);
+        }
+        connection.flush().map_err(x11_err)?;
+
+        Ok(Self {
+            connection,
+            window,
+            gc,
+            depth,
+            dimensions: (width.into(), height.into()),
+            image: Default::default(),
+        })
+    }
+
+    /// Enter the control loop. This will periodically trigger the callback, until it returns an
+    /// `Err` result or the connection to the X server fails.
+    pub fn main_loop<F: FnMut(&mut dyn Display) -> RahmenResult<()>>(&mut self, mut callback: F) {
+        while self.handle_events().is_ok() && callback(self).is_ok() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn handle_events(&mut self) -> RahmenResult<()> {
+        while let Some(event) = self.connection.poll_for_event().map_err(x11_err)? {
+            match event {
+                Event::Expose(event) if event.count == 0 => self.update()?,
+                Event::ConfigureNotify(event) => {
+                    self.dimensions = (event.width.into(), event.height.into())
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn match_dimensions(&mut self) -> RahmenResult<()> {
+        if self.image.dimensions() != self.dimensions() {
+            self.image = BgraImage::new(self.dimensions().0, self.dimensions().1);
+        }
+        Ok(())
+    }
+}
+
+/// Ask the window manager to keep `window`, which is already shown, above all others with
+/// `_NET_WM_STATE_ABOVE`, for windows of other toolkits. Unlike override-redirect windows, the
+/// window still gets the focus and can go fullscreen.
+pub fn keep_above(window: Window) -> RahmenResult<()> {
+    let (connection, screen_num) = RustConnection::connect(None).map_err(x11_err)?;
+    let root = connection.setup().roots[screen_num].root;
+    let net_wm_state = intern_atom(&connection, b"_NET_WM_STATE")?;
+    let above = intern_atom(&connection, b"_NET_WM_STATE_ABOVE")?;
+    // add the state, as requested by a normal application
+    let event = ClientMessageEvent {
+        response_type: CLIENT_MESSAGE_EVENT,
+        format: 32,
+        sequence: 0,
+        window,
+        type_: net_wm_state,
+        data: [1, above, 0, 1, 0].into(),
+    };
+    connection
+        .send_event(
+            false,
+            root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )
+        .map_err(x11_err)?;
+    connection.flush().map_err(x11_err)?;
+    Ok(())
+}
+
+fn intern_atom(connection: &RustConnection, name: &[u8]) -> RahmenResult<u32> {
+    Ok(connection
+        .intern_atom(false, name)
+        .map_err(x11_err)?
+        .reply()
+        .map_err(x11_err)?
+        .atom)
+}
+
+impl Display for X11Display {
+    fn render(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        img: &DynamicImage,
+    ) -> RahmenResult<()> {
+        self.match_dimensions()?;
+        self.image.copy_from(&img.to_bgra8(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn blank(
+        &mut self,
+        _key: usize,
+        x_offset: u32,
+        y_offset: u32,
+        x_size: u32,
+        y_size: u32,
+    ) -> RahmenResult<()> {
+        self.match_dimensions()?;
+        let black = image::FlatSamples::with_monocolor(&Bgra([0; 4]), x_size, y_size);
+        self.image
+            .copy_from(&black.as_view().unwrap(), x_offset, y_offset)?;
+        Ok(())
+    }
+
+    fn update(&mut self) -> RahmenResult<()> {
+        let _span = debug_span!("update").entered();
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        // split the image into strips fitting the maximum request size
+        let row_length = width as usize * 4;
+        let rows = ((self.connection.maximum_request_bytes() - 64) / row_length).max(1);
+        for (i, strip) in self.image.as_raw().chunks(rows * row_length).enumerate() {
+            self.connection
+                .put_image(
+                    ImageFormat::Z_PIXMAP,
+                    self.window,
+                    self.gc,
+                    width as _,
+                    (strip.len() / row_length) as _,
+                    0,
+                    (i * rows) as _,
+                    0,
+                    self.depth,
+                    strip,
+                )
+                .map_err(x11_err)?;
+        }
+        self.connection.flush().map_err(x11_err)?;
+        Ok(())
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    fn set_power(&mut self, on: bool) -> RahmenResult<()> {
+        // forcing a level needs DPMS to be enabled
+        self.connection
+            .dpms_enable()
+            .map_err(x11_err)?
+            .check()
+            .map_err(x11_err)?;
+        self.connection
+            .dpms_force_level(if on { DPMSMode::ON } else { DPMSMode::OFF })
+            .map_err(x11_err)?
+            .check()
+            .map_err(x11_err)?;
+        Ok(())
+    }
+}
@@ -1,9 +1,16 @@
 //! Rahmen error handling
+//!
+//! Errors are either transient, such as a network mount that is briefly gone, and may go away
+//! when retried, or permanent, such as a corrupt file. The `ErrorPolicy` decides what happens
+//! when loading an image or its metadata fails.
 
 use std::error::Error;
 use std::fmt;
 use std::num::ParseFloatError;
 use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::warn;
 
 /// Error types within Rahmen
 #[derive(std::fmt::Debug)]
@@ -12,40 +19,190 @@ pub enum RahmenError {
     CaseUnknown(String),
     /// Errors originating from config loading
     ConfigError(Arc<config::ConfigError>),
+    /// Errors communicating over D-Bus
+    DbusError(String),
+    /// Errors reported by a display backend
+    DisplayError(String),
+    /// Decoding an image failed, which retrying will not fix
+    DecodeError(String),
+    /// Errors loading a font
+    #[cfg(feature = "font")]
+    FontError(font_kit::error::FontLoadingError),
+    /// Errors in HTTP responses
+    HttpError(String),
     /// Errors interacting with I/O
     IoError(std::io::Error),
     /// Errors from the image library
     ImageError(Arc<image::error::ImageError>),
+    /// Errors reading buttons, keys or remote controls
+    InputError(String),
+    /// Errors from the Lua interpreter
+    LuaError(String),
+    /// Errors communicating with the MQTT broker
+    MqttError(String),
     /// Parsing a float failed
     ParseFloatError(ParseFloatError),
+    /// Parsing a time failed
+    ParseTimeError(chrono::ParseError),
     /// Errors form the Python interpreter
+    #[cfg(feature = "python")]
     PythonError(pyo3::prelude::PyErr),
     /// An error originating from regex processing
     RegexError(regex::Error),
-    /// Pseudo-error to indicate a retry condition
-    Retry,
     /// Errors from rexiv2
+    #[cfg(feature = "metadata")]
     Rexiv2Error(rexiv2::Rexiv2Error),
+    /// Errors reading a sensor
+    SensorError(String),
+    /// Errors reading or writing persistent state
+    StoreError(String),
     /// Pseudo-error to indicate program termination
     Terminate,
+    /// Transcoding an image failed
+    TranscodeError(String),
+    /// A feature that is needed was not compiled in
+    Unsupported(String),
+    /// Fetching the weather failed
+    WeatherError(String),
 }
 
 /// Result type for `RahmenError`
 pub type RahmenResult<T> = Result<T, RahmenError>;
 
+/// Whether an I/O error may go away when retried, unlike a file that was deleted
+fn is_transient_io(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    match err.kind() {
+        ErrorKind::TimedOut
+        | ErrorKind::Interrupted
+        | ErrorKind::WouldBlock
+        | ErrorKind::NotConnected
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::BrokenPipe => true,
+        _ => is_transient_os_error(err),
+    }
+}
+
+/// Whether the OS error of `err` is one of a storage or network that may come back
+#[cfg(unix)]
+fn is_transient_os_error(err: &std::io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EIO) | Some(libc::ESTALE) | Some(libc::EHOSTDOWN) | Some(libc::EHOSTUNREACH)
+    )
+}
+
+/// Whether the OS error of `err` may go away, which beyond Unix the kind tells already
+#[cfg(not(unix))]
+fn is_transient_os_error(_err: &std::io::Error) -> bool {
+    false
+}
+
+impl RahmenError {
+    /// Whether the error may go away when retried, unlike a corrupt file or a wrong setting
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RahmenError::IoError(err) => is_transient_io(err),
+            RahmenError::ImageError(err) => match err.as_ref() {
+                image::ImageError::IoError(err) => is_transient_io(err),
+                _ => false,
+            },
+            RahmenError::DbusError(_)
+            | RahmenError::MqttError(_)
+            | RahmenError::SensorError(_)
+            | RahmenError::WeatherError(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// What to do when loading an image or its metadata fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnError {
+    /// Log the error and skip the image
+    Skip,
+    /// Retry transient errors, then log the error and skip the image
+    Retry,
+    /// Stop the slideshow
+    Abort,
+}
+
+/// How to handle errors loading images and their metadata
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorPolicy {
+    /// what to do
+    pub on_error: OnError,
+    /// how often to retry transient errors
+    pub retries: u32,
+    /// how long to wait before the first retry, doubled for each further one
+    pub backoff: Duration,
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        Self {
+            on_error: OnError::Retry,
+            retries: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ErrorPolicy {
+    /// Call `attempt` until it succeeds, fails permanently, or runs out of retries
+    pub fn run<T, F: FnMut() -> RahmenResult<T>>(&self, mut attempt: F) -> RahmenResult<T> {
+        let mut backoff = self.backoff;
+        for _ in 0..self.retries {
+            match attempt() {
+                Err(err) if self.on_error == OnError::Retry && err.is_transient() => {
+                    warn!("{}, retrying in {:?}", err, backoff);
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+        attempt()
+    }
+
+    /// Whether to stop the slideshow after an error that was not retried away
+    pub fn aborts(&self) -> bool {
+        self.on_error == OnError::Abort
+    }
+}
+
 impl fmt::Display for RahmenError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
             RahmenError::CaseUnknown(err) => write!(f, "Unknown case: {}", err),
             RahmenError::ConfigError(err) => err.fmt(f),
+            RahmenError::DbusError(err) => write!(f, "D-Bus error: {}", err),
+            RahmenError::DisplayError(err) => write!(f, "Display error: {}", err),
+            RahmenError::DecodeError(err) => write!(f, "Decoding failed: {}", err),
+            #[cfg(feature = "font")]
+            RahmenError::FontError(err) => err.fmt(f),
+            RahmenError::HttpError(err) => write!(f, "HTTP error: {}", err),
             RahmenError::IoError(err) => err.fmt(f),
             RahmenError::ImageError(err) => err.fmt(f),
+            RahmenError::InputError(err) => write!(f, "Input error: {}", err),
+            RahmenError::LuaError(err) => write!(f, "Lua error: {}", err),
+            RahmenError::MqttError(err) => write!(f, "MQTT error: {}", err),
             RahmenError::ParseFloatError(err) => err.fmt(f),
+            RahmenError::ParseTimeError(err) => err.fmt(f),
+            #[cfg(feature = "python")]
             RahmenError::PythonError(err) => write!(f, "Python error: {}", err),
             RahmenError::RegexError(err) => err.fmt(f),
-            RahmenError::Retry => write!(f, "Retry"),
+            #[cfg(feature = "metadata")]
             RahmenError::Rexiv2Error(err) => err.fmt(f),
+            RahmenError::SensorError(err) => write!(f, "Sensor error: {}", err),
+            RahmenError::StoreError(err) => write!(f, "State error: {}", err),
             RahmenError::Terminate => write!(f, "Terminate"),
+            RahmenError::TranscodeError(err) => write!(f, "Transcoding failed: {}", err),
+            RahmenError::Unsupported(err) => write!(f, "Not supported: {}", err),
+            RahmenError::WeatherError(err) => write!(f, "Weather error: {}", err),
         }
     }
 }
@@ -55,14 +212,30 @@ impl Error for RahmenError {
         match self {
             RahmenError::CaseUnknown(_err) => None,
             RahmenError::ConfigError(err) => err.source(),
+            RahmenError::DbusError(_err) => None,
+            RahmenError::DisplayError(_err) => None,
+            RahmenError::DecodeError(_err) => None,
+            #[cfg(feature = "font")]
+            RahmenError::FontError(err) => err.source(),
+            RahmenError::HttpError(_err) => None,
             RahmenError::IoError(err) => err.source(),
             RahmenError::ImageError(err) => err.source(),
+            RahmenError::InputError(_err) => None,
+            RahmenError::LuaError(_err) => None,
+            RahmenError::MqttError(_err) => None,
             RahmenError::ParseFloatError(err) => err.source(),
+            RahmenError::ParseTimeError(err) => err.source(),
+            #[cfg(feature = "python")]
             RahmenError::PythonError(err) => err.source(),
             RahmenError::RegexError(err) => err.source(),
-            RahmenError::Retry => None,
+            #[cfg(feature = "metadata")]
             RahmenError::Rexiv2Error(err) => err.source(),
+            RahmenError::SensorError(_err) => None,
+            RahmenError::StoreError(_err) => None,
             RahmenError::Terminate => None,
+            RahmenError::TranscodeError(_err) => None,
+            RahmenError::Unsupported(_err) => None,
+            RahmenError::WeatherError(_err) => None,
         }
     }
 }
@@ -73,6 +246,19 @@ impl From<config::ConfigError> for RahmenError {
     }
 }
 
+impl From<glob::PatternError> for RahmenError {
+    fn from(err: glob::PatternError) -> Self {
+        RahmenError::ConfigError(Arc::new(config::ConfigError::Message(err.to_string())))
+    }
+}
+
+#[cfg(feature = "font")]
+impl From<font_kit::error::FontLoadingError> for RahmenError {
+    fn from(err: font_kit::error::FontLoadingError) -> Self {
+        RahmenError::FontError(err)
+    }
+}
+
 impl From<std::io::Error> for RahmenError {
     fn from(err: std::io::Error) -> Self {
         RahmenError::IoError(err)
@@ -91,6 +277,13 @@ impl From<ParseFloatError> for RahmenError {
     }
 }
 
+impl From<chrono::ParseError> for RahmenError {
+    fn from(err: chrono::ParseError) -> Self {
+        RahmenError::ParseTimeError(err)
+    }
+}
+
+#[cfg(feature = "python")]
 impl From<pyo3::prelude::PyErr> for RahmenError {
     fn from(err: pyo3::prelude::PyErr) -> Self {
         RahmenError::PythonError(err)
@@ -103,6 +296,7 @@ impl From<regex::Error> for RahmenError {
     }
 }
 
+#[cfg(feature = "metadata")]
 impl From<rexiv2::Rexiv2Error> for RahmenError {
     fn from(err: rexiv2::Rexiv2Error) -> Self {
         RahmenError::Rexiv2Error(err)
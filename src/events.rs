@@ -0,0 +1,98 @@
+//! Events of the slideshow, which subsystems subscribe to
+//!
+//! The dataflow, the main loop and inputs publish what happens, such as an image being shown,
+//! through a channel. The main loop dispatches the events to all subscribers in the order they
+//! were published, so integrations like statistics or hooks react to the slideshow without the
+//! parts that publish the events knowing about them.
+
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use crate::control::Command;
+
+/// Something that happened in the slideshow
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// An image was loaded, before it is shown
+    ImageLoaded(PathBuf),
+    /// A slide of images is shown, the main one first, for the duration its provider requested,
+    /// if any
+    SlideShown(Vec<PathBuf>, Option<Duration>),
+    /// Obtaining or loading an image failed
+    ProviderError(String),
+    /// An input issued a command
+    InputAction(Command),
+}
+
+/// Publishes events to a bus, from any thread
+#[derive(Debug, Clone)]
+pub struct Publisher {
+    sender: Sender<Event>,
+}
+
+impl Publisher {
+    /// Publish `event`, which is dropped if the bus is gone
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Dispatches published events to subscribers
+pub struct EventBus<'a> {
+    sender: Sender<Event>,
+    events: Receiver<Event>,
+    subscribers: Vec<Box<dyn FnMut(&Event) + 'a>>,
+}
+
+impl std::fmt::Debug for EventBus<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+impl Default for EventBus<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> EventBus<'a> {
+    /// Create a bus without subscribers
+    pub fn new() -> Self {
+        let (sender, events) = mpsc::channel();
+        Self {
+            sender,
+            events,
+            subscribers: vec![],
+        }
+    }
+
+    /// A publisher for this bus
+    pub fn publisher(&self) -> Publisher {
+        Publisher {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Publish `event`, to be dispatched with the next call to `dispatch`
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Call `subscriber` with every event dispatched from now on
+    pub fn subscribe<F: FnMut(&Event) + 'a>(&mut self, subscriber: F) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    /// Dispatch all events published so far to the subscribers
+    pub fn dispatch(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            for subscriber in &mut self.subscribers {
+                subscriber(&event);
+            }
+        }
+    }
+}
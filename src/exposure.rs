@@ -0,0 +1,90 @@
+//! The camera settings of a photo, in conventional notation
+//!
+//! EXIF records the aperture, exposure time and focal length as rationals, such as `28/10`, which
+//! read poorly. The pseudo-tags of this module format them as photographers write them, skipping
+//! settings the image does not record.
+
+use crate::metadata::Metadata;
+
+/// Prefix of the pseudo-tags provided by this module
+pub const TAG_PREFIX: &str = "Rahmen.Exposure";
+
+/// The separator between the settings in `Rahmen.Exposure`
+const SEPARATOR: &str = " · ";
+
+/// Parse an EXIF rational, such as `22/1`
+pub(crate) fn rational(value: &str) -> Option<f64> {
+    let mut parts = value.split('/');
+    let numerator: f64 = parts.next()?.trim().parse().ok()?;
+    let denominator: f64 = parts.next().map_or(Some(1.), |d| d.trim().parse().ok())?;
+    if denominator == 0. {
+        None
+    } else {
+        Some(numerator / denominator)
+    }
+}
+
+/// Format a number with at most one decimal, without a trailing zero
+fn decimal(value: f64) -> String {
+    let formatted = format!("{:.1}", value);
+    formatted
+        .strip_suffix(".0")
+        .map(str::to_string)
+        .unwrap_or(formatted)
+}
+
+fn number(metadata: &Metadata, tag: &str) -> Option<f64> {
+    rational(&metadata.get_tag_string(tag).ok()?).filter(|value| *value > 0.)
+}
+
+/// The aperture, such as `ƒ/2.8`
+pub fn aperture(metadata: &Metadata) -> Option<String> {
+    number(metadata, "Exif.Photo.FNumber").map(|f| format!("ƒ/{}", decimal(f)))
+}
+
+/// The exposure time, such as `1/250 s`, or `2 s` for long exposures
+pub fn exposure_time(metadata: &Metadata) -> Option<String> {
+    number(metadata, "Exif.Photo.ExposureTime").map(|time| {
+        if time < 0.5 {
+            format!("1/{:.0} s", 1. / time)
+        } else {
+            format!("{} s", decimal(time))
+        }
+    })
+}
+
+/// The sensitivity, such as `ISO 200`
+pub fn iso(metadata: &Metadata) -> Option<String> {
+    number(metadata, "Exif.Photo.ISOSpeedRatings").map(|iso| format!("ISO {:.0}", iso))
+}
+
+/// The focal length, such as `35 mm`
+pub fn focal_length(metadata: &Metadata) -> Option<String> {
+    number(metadata, "Exif.Photo.FocalLength").map(|length| format!("{} mm", decimal(length)))
+}
+
+/// The value of a pseudo-tag:
+///
+/// * `Rahmen.Exposure`: all settings recorded, such as `ƒ/2.8 · 1/250 s · ISO 200 · 35 mm`
+/// * `Rahmen.Exposure.Aperture`, `Rahmen.Exposure.Time`, `Rahmen.Exposure.ISO` and
+///   `Rahmen.Exposure.FocalLength`: one setting
+pub fn tag(metadata: &Metadata, name: &str) -> Option<String> {
+    match name.strip_prefix(TAG_PREFIX)? {
+        "" => {
+            let settings: Vec<_> = [aperture, exposure_time, iso, focal_length]
+                .iter()
+                .filter_map(|setting| setting(metadata))
+                .collect();
+            if settings.is_empty() {
+                None
+            } else {
+                Some(settings.join(SEPARATOR))
+            }
+        }
+        ".Aperture" => aperture(metadata),
+        ".Time" => exposure_time(metadata),
+        ".ISO" => iso(metadata),
+        ".FocalLength" => focal_length(metadata),
+        _ => None,
+    }
+}
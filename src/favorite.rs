@@ -0,0 +1,87 @@
+//! Mark photos as favorites while they are shown
+//!
+//! Marking a photo writes an XMP rating, and optionally a label, so photo managers such as
+//! digiKam, darktable or Lightroom pick the favorites up. The metadata is written into an XMP
+//! sidecar file next to the image, named like it with the extension replaced by `.xmp`, as
+//! Lightroom names them, so the images stay untouched. If configured, it is written into the image
+//! itself, unless the image cannot be written.
+
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::errors::RahmenResult;
+use crate::metadata::Metadata;
+
+/// An empty XMP packet, to start a sidecar file from
+const EMPTY_SIDECAR: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"/>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>
+";
+
+/// The XMP sidecar file of the image at `path`, named like it with the extension replaced by
+/// `.xmp`, unless there is only one named with `.xmp` appended, as darktable names them
+pub(crate) fn sidecar_path(path: &Path) -> PathBuf {
+    let sidecar = path.with_extension("xmp");
+    let mut appended = path.as_os_str().to_owned();
+    appended.push(".xmp");
+    let appended = PathBuf::from(appended);
+    if !sidecar.exists() && appended.exists() {
+        appended
+    } else {
+        sidecar
+    }
+}
+
+/// The XMP sidecar file of the image at `path`, created empty if there is none yet
+pub(crate) fn create_sidecar(path: &Path) -> RahmenResult<PathBuf> {
+    let sidecar = sidecar_path(path);
+    if !sidecar.exists() {
+        std::fs::write(&sidecar, EMPTY_SIDECAR)?;
+    }
+    Ok(sidecar)
+}
+
+/// How to mark favorites
+#[derive(Debug, Clone)]
+pub struct Favorites {
+    /// the rating to give, from 1 to 5
+    pub rating: u8,
+    /// the label to give, if any
+    pub label: Option<String>,
+    /// whether to write sidecar files, leaving images untouched, rather than the images
+    pub sidecar: bool,
+}
+
+impl Favorites {
+    fn write(&self, path: &Path) -> RahmenResult<()> {
+        let metadata = Metadata::new_from_path(path)?;
+        metadata.set_tag_numeric("Xmp.xmp.Rating", i32::from(self.rating))?;
+        if let Some(label) = &self.label {
+            metadata.set_tag_string("Xmp.xmp.Label", label)?;
+        }
+        metadata.save_to_file(path)?;
+        Ok(())
+    }
+
+    fn write_sidecar(&self, path: &Path) -> RahmenResult<PathBuf> {
+        let sidecar = create_sidecar(path)?;
+        self.write(&sidecar)?;
+        Ok(sidecar)
+    }
+
+    /// Mark the image at `path` as a favorite, and return the file written
+    pub fn mark(&self, path: &Path) -> RahmenResult<PathBuf> {
+        if !self.sidecar {
+            match self.write(path) {
+                Ok(()) => return Ok(path.to_path_buf()),
+                Err(e) => warn!("Cannot write {:?}, using a sidecar file: {}", path, e),
+            }
+        }
+        self.write_sidecar(path)
+    }
+}
@@ -1,99 +1,496 @@
 //! Utilities to rasterize fonts to images
+//!
+//! Each line is shaped with rustybuzz, which applies the ligatures and kerning of the font, joins
+//! the letters of scripts such as Arabic, and puts right-to-left text into visual order. The
+//! glyphs are then rasterized with font-kit. Without the `font` feature, which needs fontconfig and
+//! FreeType, no text is drawn.
+
+#[cfg(feature = "font")]
+use tracing::info;
+use tracing::warn;
 
 use crate::errors::RahmenResult;
+#[cfg(feature = "font")]
 use font_kit::canvas::{Canvas, Format, RasterizationOptions};
+#[cfg(feature = "font")]
 use font_kit::hinting::HintingOptions;
+#[cfg(feature = "font")]
 use font_kit::loaders::freetype::Font;
 
-use image::{DynamicImage, RgbImage};
+#[cfg(feature = "font")]
+use image::Rgba;
+use image::{DynamicImage, Rgb, RgbImage, RgbaImage};
+#[cfg(feature = "font")]
 use pathfinder_geometry::rect::RectI;
+#[cfg(feature = "font")]
 use pathfinder_geometry::transform2d::Transform2F;
+#[cfg(feature = "font")]
 use pathfinder_geometry::vector::{Vector2F, Vector2I};
+#[cfg(feature = "font")]
+use rustybuzz::UnicodeBuffer;
+#[cfg(feature = "font")]
 use std::collections::HashMap;
+use std::path::Path;
+#[cfg(feature = "font")]
+use std::path::PathBuf;
+#[cfg(feature = "font")]
+use std::sync::Arc;
+
+/// Horizontal alignment of text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Alignment {
+    /// Align to the left edge
+    Left,
+    /// Center between the edges
+    Center,
+    /// Align to the right edge
+    Right,
+}
+
+/// A background behind the text, to keep it readable over bright images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    /// A box of the given color and opacity
+    Box(Rgb<u8>, u8),
+    /// A gradient from transparent to the given color and opacity, which is reached at the bottom
+    /// if the flag is set, at the top otherwise
+    Gradient(Rgb<u8>, u8, bool),
+}
+
+/// The colors and effects to draw text with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStyle {
+    /// color of the text
+    pub color: Rgb<u8>,
+    /// width in pixels and color of an outline around the glyphs
+    pub outline: Option<(u32, Rgb<u8>)>,
+    /// offset in pixels to the bottom right and color of a drop shadow
+    pub shadow: Option<(u32, Rgb<u8>)>,
+    /// background behind the text
+    pub background: Option<Background>,
+    /// horizontal alignment of the lines
+    pub align: Alignment,
+    /// space to leave to the left and right in pixels
+    pub margin: u32,
+    /// wrap long lines, up to this many lines in total, instead of cutting them off
+    pub max_lines: Option<usize>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            color: Rgb([255; 3]),
+            outline: None,
+            shadow: None,
+            background: None,
+            align: Alignment::Left,
+            margin: 0,
+            max_lines: None,
+        }
+    }
+}
+
+/// A glyph to draw, with its metrics
+#[cfg(feature = "font")]
+#[derive(Debug, Clone, Copy)]
+struct Glyph {
+    id: u32,
+    /// the position relative to the pen, as moved by the shaper
+    offset: Vector2F,
+    /// the rightmost pixel relative to the origin
+    extent: f32,
+    /// the horizontal distance to the next glyph
+    advance: f32,
+    space: bool,
+}
+
+/// The width of a line of glyphs
+#[cfg(feature = "font")]
+fn line_width(glyphs: &[Glyph]) -> f32 {
+    glyphs.iter().map(|glyph| glyph.advance).sum()
+}
+
+/// Split glyphs into the ones that fit into `available` pixels, and the rest
+#[cfg(feature = "font")]
+fn split_fitting(glyphs: &[Glyph], available: f32) -> (&[Glyph], &[Glyph]) {
+    let mut x = 0.;
+    let fitting = glyphs
+        .iter()
+        .take_while(|glyph| {
+            let fits = x + glyph.extent <= available;
+            x += glyph.advance;
+            fits
+        })
+        .count();
+    glyphs.split_at(fitting)
+}
+
+/// Split glyphs into the first line that fits into `available` pixels, breaking between words if
+/// possible, and the rest
+#[cfg(feature = "font")]
+fn wrap(glyphs: &[Glyph], available: f32) -> (&[Glyph], &[Glyph]) {
+    let (fitting, rest) = split_fitting(glyphs, available);
+    if rest.is_empty() {
+        return (fitting, rest);
+    }
+    // break at the last space, or within the word if it doesn't fit on a line by itself
+    let end = match fitting.iter().rposition(|glyph| glyph.space) {
+        Some(space) if space > 0 => space,
+        _ => fitting.len().max(1),
+    };
+    let (line, rest) = glyphs.split_at(end);
+    let start = rest.iter().take_while(|glyph| glyph.space).count();
+    (line, &rest[start..])
+}
+
+/// Composite `color` with the given alpha over `pixel`
+#[cfg(feature = "font")]
+fn over(pixel: &mut Rgba<u8>, color: Rgb<u8>, alpha: u8) {
+    let alpha = f32::from(alpha) / 255.;
+    let below = f32::from(pixel[3]) / 255. * (1. - alpha);
+    let total = alpha + below;
+    if total > 0. {
+        for channel in 0..3 {
+            pixel[channel] =
+                ((f32::from(color[channel]) * alpha + f32::from(pixel[channel]) * below) / total)
+                    .round() as u8;
+        }
+    }
+    pixel[3] = (total * 255.).round() as u8;
+}
 
 /// A font renderer to rasterize text to images
+#[cfg(feature = "font")]
 #[derive(Debug)]
 pub struct FontRenderer {
     font: Font,
-    raster_cache: HashMap<char, Option<(u32, RectI)>>,
+    /// the font file, for the shaper
+    data: Arc<Vec<u8>>,
+    path: Option<PathBuf>,
+    style: TextStyle,
+    raster_cache: HashMap<(u32, u32), RectI>,
 }
 
+#[cfg(feature = "font")]
 impl FontRenderer {
     /// Create a new font renderer from an given font.
     pub fn with_font(font: Font) -> Self {
         Self {
+            data: font.copy_font_data().unwrap_or_default(),
             font,
+            path: None,
+            style: Default::default(),
             raster_cache: HashMap::new(),
         }
     }
 
-    /// Render a text and return an image containing the rasterized text
+    /// Create a new font renderer from a font file, which can be reloaded later
+    pub fn from_path<P: AsRef<Path>>(path: P) -> RahmenResult<Self> {
+        let mut renderer = Self::with_font(Font::from_path(&path, 0)?);
+        renderer.path = Some(path.as_ref().into());
+        Ok(renderer)
+    }
+
+    /// Set the colors and effects to draw text with
+    pub fn set_style(&mut self, style: TextStyle) {
+        self.style = style;
+    }
+
+    /// Reload the font from its file, if it was loaded from one. On failure, the current font is
+    /// kept.
+    pub fn reload(&mut self) {
+        if let Some(path) = &self.path {
+            match Font::from_path(path, 0) {
+                Ok(font) => {
+                    info!("Reloaded font {:?}", path);
+                    self.data = font.copy_font_data().unwrap_or_default();
+                    self.font = font;
+                    self.raster_cache.clear();
+                }
+                Err(e) => warn!("Failed to reload font {:?}: {}", path, e),
+            }
+        }
+    }
+
+    /// Shape a line of text into the glyphs to draw, left to right. Characters the font lacks
+    /// are left out.
+    fn shape(
+        &mut self,
+        line: &str,
+        size: f32,
+        hinting: HintingOptions,
+        rasterization: RasterizationOptions,
+    ) -> Vec<Glyph> {
+        let face = match rustybuzz::Face::from_slice(&self.data, 0) {
+            Some(face) => face,
+            None => return vec![],
+        };
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(line);
+        buffer.guess_segment_properties();
+        let shaped = rustybuzz::shape(&face, &[], buffer);
+        let font = &self.font;
+        let raster_cache = &mut self.raster_cache;
+        let scale = size / font.metrics().units_per_em as f32;
+        shaped
+            .glyph_infos()
+            .iter()
+            .zip(shaped.glyph_positions())
+            // glyph 0 stands for missing characters
+            .filter(|(info, _)| info.glyph_id != 0)
+            .map(|(info, position)| {
+                // the bounds depend on the size
+                let raster_rect = *raster_cache
+                    .entry((info.glyph_id, size.to_bits()))
+                    .or_insert_with(|| {
+                        font.raster_bounds(
+                            info.glyph_id,
+                            size,
+                            Transform2F::default(),
+                            hinting,
+                            rasterization,
+                        )
+                        .expect("Failed to determine raster bounds")
+                    });
+                let offset = Vector2F::new(
+                    position.x_offset as f32 * scale,
+                    -position.y_offset as f32 * scale,
+                );
+                Glyph {
+                    id: info.glyph_id,
+                    offset,
+                    extent: offset.x() + (raster_rect.width() + raster_rect.origin_x()) as f32,
+                    advance: position.x_advance as f32 * scale,
+                    // the cluster is the index of the first character the glyph stands for
+                    space: line[info.cluster as usize..]
+                        .chars()
+                        .next()
+                        .map_or(false, char::is_whitespace),
+                }
+            })
+            .collect()
+    }
+
+    /// The width in pixels a text needs to be rendered whole, without wrapping, with the margins
+    pub fn text_width<'a, I: Iterator<Item = &'a str>>(&mut self, text: I, size: f32) -> f32 {
+        let hinting = HintingOptions::Full(size);
+        let rasterization = RasterizationOptions::GrayscaleAa;
+        text.map(|line| line_width(&self.shape(line, size, hinting, rasterization)))
+            .fold(0., f32::max)
+            + 2. * self.style.margin as f32
+    }
+
+    /// Render a text and return an image containing the rasterized text on black
     pub fn render<'a, I: Iterator<Item = &'a str>>(
         &mut self,
         text: I,
         size: f32,
         dimensions: (u32, u32),
     ) -> RahmenResult<DynamicImage> {
+        let rgba = self.render_rgba(text, size, dimensions)?;
+        Ok(DynamicImage::ImageRgb8(RgbImage::from_fn(
+            rgba.width(),
+            rgba.height(),
+            |x, y| {
+                let pixel = rgba.get_pixel(x, y);
+                let channel = |c: usize| (u32::from(pixel[c]) * u32::from(pixel[3]) / 255) as u8;
+                Rgb([channel(0), channel(1), channel(2)])
+            },
+        )))
+    }
+
+    /// Render a text and return an image containing the rasterized text on a transparent
+    /// background, or the configured background
+    pub fn render_rgba<'a, I: Iterator<Item = &'a str>>(
+        &mut self,
+        text: I,
+        size: f32,
+        dimensions: (u32, u32),
+    ) -> RahmenResult<RgbaImage> {
         let hinting = HintingOptions::Full(size);
         let rasterization = RasterizationOptions::GrayscaleAa;
-        let format = Format::Rgb24;
+        // render the coverage only, colors and effects are applied below
+        let format = Format::A8;
+        let style = self.style;
 
-        let metrics = self.font.metrics();
-        // width of character, larger values of optional factor (eg size * 1.1) increase spacing
-        let em = size * 1.05;
         // dimensions are set in dataflow.rs
         let mut canvas = Canvas::new(Vector2I::new(dimensions.0 as _, dimensions.1 as _), format);
-        let font = &self.font;
+        let available = dimensions.0.saturating_sub(2 * style.margin) as f32;
+        // distance between lines
+        let em = size * 1.05;
 
-        for (i, line) in text.enumerate() {
-            // horizontal offset from the start of the text
-            let mut base_x = 0.;
-            for char in line.chars() {
-                if let Some((glyph_id, raster_rect)) =
-                    self.raster_cache.entry(char).or_insert_with(|| {
-                        if let Some(glyph_id) = font.glyph_for_char(char) {
-                            Some((
-                                glyph_id,
-                                font.raster_bounds(
-                                    glyph_id,
-                                    size,
-                                    Transform2F::default(),
-                                    hinting,
-                                    rasterization,
-                                )
-                                .expect("Failed to determine raster bounds"),
-                            ))
-                        } else {
-                            None
+        // lay out the lines first, to align them
+        let mut lines = vec![];
+        let mut truncated = false;
+        for line in text {
+            let glyphs = self.shape(line, size, hinting, rasterization);
+            match style.max_lines {
+                Some(max_lines) => {
+                    let mut rest = &glyphs[..];
+                    while !rest.is_empty() {
+                        if lines.len() == max_lines {
+                            truncated = true;
+                            break;
                         }
-                    })
-                {
-                    if (base_x as i32 + raster_rect.width() + raster_rect.origin_x()) as u32
-                        > dimensions.0
-                    {
-                        break;
+                        let (row, remainder) = wrap(rest, available);
+                        lines.push(row.to_vec());
+                        rest = remainder;
                     }
-                    self.font
-                        .rasterize_glyph(
-                            &mut canvas,
-                            *glyph_id,
-                            size,
-                            Transform2F::from_translation(Vector2F::new(
-                                base_x,
-                                i as f32 * em + size,
-                            )),
-                            hinting,
-                            rasterization,
-                        )
-                        .expect("Font rasterization failed");
-
-                    base_x += self.font.advance(*glyph_id).unwrap().x() * em
-                        / metrics.units_per_em as f32;
                 }
+                None => {
+                    let (row, _) = split_fitting(&glyphs, available);
+                    lines.push(row.to_vec());
+                }
+            }
+        }
+        // indicate that the text goes on
+        if truncated {
+            let mut ellipsis = self.shape("…", size, hinting, rasterization);
+            if ellipsis.is_empty() {
+                ellipsis = self.shape("...", size, hinting, rasterization);
+            }
+            if let Some(last) = lines.last_mut() {
+                let ellipsis_width: f32 = ellipsis.iter().map(|glyph| glyph.advance).sum();
+                while !last.is_empty()
+                    && (last.last().map_or(false, |glyph| glyph.space)
+                        || line_width(last) + ellipsis_width > available)
+                {
+                    last.pop();
+                }
+                last.extend(ellipsis);
+            }
+        }
+        let font = &self.font;
+
+        // without text, such as in clean mode, there is nothing to keep readable
+        let background = style
+            .background
+            .filter(|_| lines.iter().any(|glyphs| !glyphs.is_empty()));
+        for (i, glyphs) in lines.into_iter().enumerate() {
+            let space = (available - line_width(&glyphs)).max(0.);
+            let offset = style.margin as f32
+                + match style.align {
+                    Alignment::Left => 0.,
+                    Alignment::Center => (space / 2.).floor(),
+                    Alignment::Right => space,
+                };
+            let mut x = offset;
+            for glyph in glyphs {
+                font.rasterize_glyph(
+                    &mut canvas,
+                    glyph.id,
+                    size,
+                    Transform2F::from_translation(
+                        Vector2F::new(x, i as f32 * em + size) + glyph.offset,
+                    ),
+                    hinting,
+                    rasterization,
+                )
+                .expect("Font rasterization failed");
+                x += glyph.advance;
             }
         }
-        Ok(DynamicImage::ImageRgb8(
-            RgbImage::from_raw(canvas.size.x() as _, canvas.size.y() as _, canvas.pixels).unwrap(),
-        ))
+
+        let (width, height) = (canvas.size.x() as i64, canvas.size.y() as i64);
+        let coverage = |x: i64, y: i64| {
+            if x >= 0 && y >= 0 && x < width && y < height {
+                canvas.pixels[y as usize * canvas.stride + x as usize]
+            } else {
+                0
+            }
+        };
+        Ok(RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+            let mut pixel = Rgba([0; 4]);
+            match background {
+                Some(Background::Box(color, alpha)) => over(&mut pixel, color, alpha),
+                Some(Background::Gradient(color, alpha, towards_bottom)) => {
+                    let fraction = y as f32 / (height - 1).max(1) as f32;
+                    let fraction = if towards_bottom {
+                        fraction
+                    } else {
+                        1. - fraction
+                    };
+                    over(&mut pixel, color, (f32::from(alpha) * fraction) as u8);
+                }
+                None => {}
+            }
+            let (x, y) = (i64::from(x), i64::from(y));
+            if let Some((offset, color)) = style.shadow {
+                let offset = i64::from(offset);
+                over(&mut pixel, color, coverage(x - offset, y - offset));
+            }
+            if let Some((outline, color)) = style.outline {
+                let r = i64::from(outline);
+                let mut max = 0;
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        if dx * dx + dy * dy <= r * r {
+                            max = max.max(coverage(x + dx, y + dy));
+                        }
+                    }
+                }
+                over(&mut pixel, color, max);
+            }
+            over(&mut pixel, style.color, coverage(x, y));
+            pixel
+        }))
+    }
+}
+
+/// A font renderer without font support, which draws no text
+#[cfg(not(feature = "font"))]
+#[derive(Debug)]
+pub struct FontRenderer {
+    style: TextStyle,
+}
+
+#[cfg(not(feature = "font"))]
+impl FontRenderer {
+    /// Create a font renderer drawing no text, for want of font support
+    pub fn from_path<P: AsRef<Path>>(path: P) -> RahmenResult<Self> {
+        warn!(
+            "compiled without font support, not drawing text with {:?}",
+            path.as_ref()
+        );
+        Ok(Self {
+            style: Default::default(),
+        })
+    }
+
+    /// Set the colors and effects to draw text with
+    pub fn set_style(&mut self, style: TextStyle) {
+        self.style = style;
+    }
+
+    /// Reload the font from its file, which there is none of
+    pub fn reload(&mut self) {}
+
+    /// The width in pixels a text needs, which is only the margins
+    pub fn text_width<'a, I: Iterator<Item = &'a str>>(&mut self, _text: I, _size: f32) -> f32 {
+        2. * self.style.margin as f32
+    }
+
+    /// Render a text, which leaves the image black
+    pub fn render<'a, I: Iterator<Item = &'a str>>(
+        &mut self,
+        _text: I,
+        _size: f32,
+        dimensions: (u32, u32),
+    ) -> RahmenResult<DynamicImage> {
+        Ok(DynamicImage::ImageRgb8(RgbImage::new(
+            dimensions.0,
+            dimensions.1,
+        )))
+    }
+
+    /// Render a text, which leaves the image transparent
+    pub fn render_rgba<'a, I: Iterator<Item = &'a str>>(
+        &mut self,
+        _text: I,
+        _size: f32,
+        dimensions: (u32, u32),
+    ) -> RahmenResult<RgbaImage> {
+        Ok(RgbaImage::new(dimensions.0, dimensions.1))
     }
 }
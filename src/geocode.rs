@@ -0,0 +1,433 @@
+//! Name the place a photo was taken at, from its GPS position
+//!
+//! Places are looked up offline in a GeoNames dataset (<https://download.geonames.org/export/dump/>):
+//! a file of cities, such as `cities1000.txt`, and optionally the names of first-level
+//! administrative divisions (`admin1CodesASCII.txt`) and of countries (`countryInfo.txt`). Names in
+//! other languages are taken from a file of alternate names (`alternateNamesV2.txt`, or one of
+//! `alternatenames/`), keeping those in the language of the configured locale only. The cities
+//! are kept in cells of one degree, so the nearest one is found without scanning them all.
+//! Places can be cached on disk by their rounded position, so images taken close to each other
+//! are looked up once.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::errors::{RahmenError, RahmenResult};
+use crate::store::Store;
+use crate::weather::Units;
+
+/// Prefix of the pseudo-tags naming places
+pub const TAG_PREFIX: &str = "Rahmen.Geo.";
+
+/// Prefix of the pseudo-tags relating positions to home
+pub const HOME_TAG_PREFIX: &str = "Rahmen.Home.";
+
+/// Kilometers in a mile
+const MILE: f64 = 1.609344;
+
+/// The points of the compass, clockwise from north
+const COMPASS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+
+/// Mean radius of the earth in kilometers
+const EARTH_RADIUS: f64 = 6371.0;
+
+/// How many cells around the position are searched for the nearest city, in each direction
+const SEARCH_CELLS: i32 = 2;
+
+/// The levels of detail of a place
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detail {
+    /// The nearest city, town or village
+    City,
+    /// The first-level administrative division, such as a state or region
+    Admin1,
+    /// The country
+    Country,
+}
+
+/// A named place
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Place {
+    /// name of the city
+    pub city: String,
+    /// name of the first-level administrative division, if known
+    pub admin1: Option<String>,
+    /// name of the country, or its ISO code if the name is not known
+    pub country: Option<String>,
+    /// the IANA time zone, such as `Europe/Zurich`, if known
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+impl Place {
+    /// The name of the place at the level of `detail`
+    pub fn get(&self, detail: Detail) -> Option<&str> {
+        match detail {
+            Detail::City => Some(&self.city),
+            Detail::Admin1 => self.admin1.as_deref(),
+            Detail::Country => self.country.as_deref(),
+        }
+    }
+}
+
+/// The language places are named in
+#[derive(Debug, Clone, Default)]
+pub struct Language {
+    /// the locale whose language names are given in, such as `de` or `fr_CH`, if any
+    pub locale: Option<String>,
+    /// the GeoNames file of alternate names, which has the names in other languages
+    pub alternate_names: Option<PathBuf>,
+    /// spell names not known in the language in ASCII rather than locally
+    pub ascii: bool,
+}
+
+impl Language {
+    /// The names of places in the language of the locale, by their GeoNames id
+    fn names(&self) -> RahmenResult<HashMap<String, String>> {
+        let (locale, path) = match (&self.locale, &self.alternate_names) {
+            (Some(locale), Some(path)) => (locale, path),
+            _ => return Ok(HashMap::new()),
+        };
+        // `de_CH.UTF-8` is German, as are the names GeoNames has for `de`
+        let language = primary_language(locale);
+        let mut names = HashMap::new();
+        let mut preferred = HashMap::new();
+        for line in read_lines(path)? {
+            let line = line?;
+            let columns: Vec<_> = line.split('\t').collect();
+            if columns.len() < 4 {
+                return Err(malformed(path, &line));
+            }
+            // colloquial and historic names are no names to show
+            let flag = |index: usize| columns.get(index) == Some(&"1");
+            if primary_language(columns[2]) != language || flag(6) || flag(7) {
+                continue;
+            }
+            let id = columns[1];
+            if flag(4) {
+                preferred.insert(id.to_string(), columns[3].to_string());
+            } else if !names.contains_key(id) {
+                names.insert(id.to_string(), columns[3].to_string());
+            }
+        }
+        names.extend(preferred);
+        Ok(names)
+    }
+}
+
+/// The language of a locale or language code, such as `de` for `de_CH.UTF-8` or `zh` for `zh-CN`
+fn primary_language(locale: &str) -> String {
+    locale
+        .split(|c| c == '_' || c == '-' || c == '.' || c == '@')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+#[derive(Debug)]
+struct City {
+    latitude: f64,
+    longitude: f64,
+    place: Place,
+}
+
+/// Places by rounded position, kept in a store
+struct Cache {
+    store: RefCell<Box<dyn Store>>,
+    decimals: usize,
+}
+
+impl std::fmt::Debug for Cache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cache")
+            .field("decimals", &self.decimals)
+            .finish()
+    }
+}
+
+/// Looks up the nearest city to a position
+#[derive(Debug)]
+pub struct ReverseGeocoder {
+    cells: HashMap<(i32, i32), Vec<City>>,
+    detail: Vec<Detail>,
+    separator: String,
+    cache: Option<Cache>,
+}
+
+/// The great-circle distance between two positions, given as `(latitude, longitude)` in
+/// degrees, in kilometers
+pub fn distance((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let (dlat, dlon) = (lat2 - lat1, (lon2 - lon1).to_radians());
+    let a = (dlat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.).sin().powi(2);
+    2. * EARTH_RADIUS * a.sqrt().min(1.).asin()
+}
+
+/// The initial bearing from one position to another, given as `(latitude, longitude)` in
+/// degrees, in degrees clockwise from north
+pub fn bearing((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlon = (lon2 - lon1).to_radians();
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.)
+}
+
+/// The nearest of the eight points of the compass to a bearing
+pub fn compass(bearing: f64) -> &'static str {
+    COMPASS[((bearing / 45.).round() as usize) % COMPASS.len()]
+}
+
+/// A home position, to show how far from it photos were taken
+#[derive(Debug, Clone)]
+pub struct Home {
+    /// the position, as `(latitude, longitude)` in degrees
+    pub position: (f64, f64),
+    /// units to show distances in, kilometers or miles
+    pub units: Units,
+    /// decimal places of distances
+    pub decimals: usize,
+}
+
+impl Home {
+    /// The value of a pseudo-tag for a photo taken at `position`:
+    ///
+    /// * `Rahmen.Home.Distance`: the distance and direction from home, such as `1.240 km SE`
+    /// * `Rahmen.Home.Kilometers` or `Rahmen.Home.Miles`: the distance as a number
+    /// * `Rahmen.Home.Direction`: the point of the compass
+    /// * `Rahmen.Home.Bearing`: the direction in degrees clockwise from north
+    pub fn tag(&self, position: (f64, f64), name: &str) -> Option<String> {
+        let kilometers = distance(self.position, position);
+        let bearing = bearing(self.position, position);
+        match name.strip_prefix(HOME_TAG_PREFIX)? {
+            "Distance" => Some(match self.units {
+                Units::Metric => {
+                    format!("{:.*} km {}", self.decimals, kilometers, compass(bearing))
+                }
+                Units::Imperial => format!(
+                    "{:.*} mi {}",
+                    self.decimals,
+                    kilometers / MILE,
+                    compass(bearing)
+                ),
+            }),
+            "Kilometers" => Some(format!("{:.*}", self.decimals, kilometers)),
+            "Miles" => Some(format!("{:.*}", self.decimals, kilometers / MILE)),
+            "Direction" => Some(compass(bearing).to_string()),
+            "Bearing" => Some(format!("{:.0}", bearing)),
+            _ => None,
+        }
+    }
+}
+
+fn cell(latitude: f64, longitude: f64) -> (i32, i32) {
+    (
+        latitude.floor() as i32,
+        (longitude.floor() as i32 + 180).rem_euclid(360) - 180,
+    )
+}
+
+fn malformed(path: &Path, line: &str) -> RahmenError {
+    RahmenError::ConfigError(std::sync::Arc::new(config::ConfigError::Message(format!(
+        "malformed line in {:?}: {}",
+        path, line
+    ))))
+}
+
+/// Read the tab-separated lines of a GeoNames file, skipping comments
+fn read_lines(path: &Path) -> RahmenResult<impl Iterator<Item = std::io::Result<String>>> {
+    Ok(BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !line.starts_with('#'))))
+}
+
+/// Read names by code from column `name` of a GeoNames file with codes in the first column, or from
+/// `translated` by the GeoNames id in column `id`
+fn read_names(
+    path: &Path,
+    name: usize,
+    id: usize,
+    translated: &HashMap<String, String>,
+) -> RahmenResult<HashMap<String, String>> {
+    let mut names = HashMap::new();
+    for line in read_lines(path)? {
+        let line = line?;
+        let columns: Vec<_> = line.split('\t').collect();
+        match (columns.first(), columns.get(name)) {
+            (Some(code), Some(name)) => {
+                let name = columns
+                    .get(id)
+                    .and_then(|id| translated.get(*id))
+                    .map_or(*name, String::as_str);
+                names.insert(code.to_string(), name.to_string())
+            }
+            _ => return Err(malformed(path, &line)),
+        };
+    }
+    Ok(names)
+}
+
+impl ReverseGeocoder {
+    /// Load the cities with at least `min_population` inhabitants from the GeoNames file
+    /// `cities`, naming their divisions and countries after the files `admin1` and `countries`,
+    /// if given, in `language` where the names are known in it. `Rahmen.Geo.Place` shows the
+    /// levels in `detail`, joined by `separator`.
+    pub fn load(
+        cities: &Path,
+        admin1: Option<&Path>,
+        countries: Option<&Path>,
+        min_population: u64,
+        language: &Language,
+        detail: Vec<Detail>,
+        separator: String,
+    ) -> RahmenResult<Self> {
+        let ascii = language.ascii;
+        let translated = language.names()?;
+        let admin1 = admin1
+            .map(|path| read_names(path, if ascii { 2 } else { 1 }, 3, &translated))
+            .transpose()?
+            .unwrap_or_default();
+        let countries = countries
+            .map(|path| read_names(path, 4, 16, &translated))
+            .transpose()?
+            .unwrap_or_default();
+        let mut cells: HashMap<_, Vec<City>> = HashMap::new();
+        for line in read_lines(cities)? {
+            let line = line?;
+            let columns: Vec<_> = line.split('\t').collect();
+            if columns.len() < 15 {
+                return Err(malformed(cities, &line));
+            }
+            let population: u64 = columns[14].parse().unwrap_or(0);
+            if population < min_population {
+                continue;
+            }
+            let (latitude, longitude) = match (columns[4].parse(), columns[5].parse()) {
+                (Ok(latitude), Ok(longitude)) => (latitude, longitude),
+                _ => return Err(malformed(cities, &line)),
+            };
+            let country_code = columns[8];
+            let place = Place {
+                city: translated
+                    .get(columns[0])
+                    .map_or(columns[if ascii { 2 } else { 1 }], String::as_str)
+                    .to_string(),
+                admin1: admin1
+                    .get(&format!("{}.{}", country_code, columns[10]))
+                    .cloned(),
+                country: Some(
+                    countries
+                        .get(country_code)
+                        .cloned()
+                        .unwrap_or_else(|| country_code.to_string()),
+                )
+                .filter(|country| !country.is_empty()),
+                timezone: columns
+                    .get(17)
+                    .filter(|timezone| !timezone.is_empty())
+                    .map(|timezone| timezone.to_string()),
+            };
+            cells
+                .entry(cell(latitude, longitude))
+                .or_default()
+                .push(City {
+                    latitude,
+                    longitude,
+                    place,
+                });
+        }
+        Ok(Self {
+            cells,
+            detail,
+            separator,
+            cache: None,
+        })
+    }
+
+    /// Cache places in `store`, by their position rounded to `decimals` decimal places. Two
+    /// decimals are about a kilometer.
+    pub fn with_cache(mut self, store: Box<dyn Store>, decimals: usize) -> Self {
+        self.cache = Some(Cache {
+            store: RefCell::new(store),
+            decimals,
+        });
+        self
+    }
+
+    /// The city nearest to a position, if there is one within a few degrees. With a cache, the
+    /// position is rounded first.
+    pub fn lookup(&self, latitude: f64, longitude: f64) -> Option<Place> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.nearest(latitude, longitude).cloned(),
+        };
+        // look up the rounded position, which all positions of the same key share
+        let factor = 10f64.powi(cache.decimals as i32);
+        let (latitude, longitude) = (
+            (latitude * factor).round() / factor,
+            (longitude * factor).round() / factor,
+        );
+        let key = format!(
+            "{:.*},{:.*}",
+            cache.decimals, latitude, cache.decimals, longitude
+        );
+        match cache.store.borrow().get(&key) {
+            Ok(Some(cached)) => {
+                if let Ok(place) = serde_json::from_str(&cached) {
+                    return place;
+                }
+            }
+            Ok(None) => {}
+            Err(err) => warn!("Reading cached place failed: {}", err),
+        }
+        let place = self.nearest(latitude, longitude).cloned();
+        let cached = serde_json::to_string(&place).unwrap_or_default();
+        if let Err(err) = cache.store.borrow_mut().set(&key, &cached) {
+            warn!("Caching place failed: {}", err);
+        }
+        place
+    }
+
+    fn nearest(&self, latitude: f64, longitude: f64) -> Option<&Place> {
+        let (row, column) = cell(latitude, longitude);
+        let mut nearest: Option<(f64, &City)> = None;
+        for dy in -SEARCH_CELLS..=SEARCH_CELLS {
+            for dx in -SEARCH_CELLS..=SEARCH_CELLS {
+                let key = (row + dy, (column + dx + 180).rem_euclid(360) - 180);
+                for city in self.cells.get(&key).into_iter().flatten() {
+                    let d = distance((latitude, longitude), (city.latitude, city.longitude));
+                    if nearest.map_or(true, |(nearest, _)| d < nearest) {
+                        nearest = Some((d, city));
+                    }
+                }
+            }
+        }
+        nearest.map(|(_, city)| &city.place)
+    }
+
+    /// The value of a pseudo-tag for `place`:
+    ///
+    /// * `Rahmen.Geo.City`, `Rahmen.Geo.Admin1` and `Rahmen.Geo.Country`: the name at that level
+    /// * `Rahmen.Geo.Place`: the names at the configured levels
+    pub fn tag(&self, place: &Place, name: &str) -> Option<String> {
+        match name.strip_prefix(TAG_PREFIX)? {
+            "City" => place.get(Detail::City).map(str::to_string),
+            "Admin1" => place.get(Detail::Admin1).map(str::to_string),
+            "Country" => place.get(Detail::Country).map(str::to_string),
+            "Place" => Some(
+                self.detail
+                    .iter()
+                    .filter_map(|detail| place.get(*detail))
+                    .collect::<Vec<_>>()
+                    .join(&self.separator),
+            ),
+            _ => None,
+        }
+    }
+}
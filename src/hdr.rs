@@ -0,0 +1,381 @@
+//! Show HDR images on SDR displays
+//!
+//! Ultra HDR and other gain map JPEGs carry a base image, and a gain map taking it to another
+//! rendition, as described in their `hdrgm` XMP properties. Usually the base image is the SDR
+//! rendition, which is shown as it is, but when it is the HDR rendition, the gain map is applied
+//! to take it down to SDR. HDR HEIC and AVIF images are encoded with the PQ or HLG transfer
+//! function, usually with BT.2020 primaries, which converters pass on as plain 8-bit values that
+//! look washed out. They are tone mapped: decoded to linear light, converted to the BT.709
+//! primaries of sRGB, compressed into the range of SDR and encoded as sRGB again. The displays
+//! are all SDR, so HDR is never passed through.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbImage};
+use tracing::{debug, warn};
+
+use crate::pixel::into_rgb8;
+
+/// The luminance of SDR white, in nits, after ITU-R BT.2408
+const SDR_WHITE: f32 = 203.;
+
+/// The start of an XMP packet in an APP1 segment
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// How much of a HEIC or AVIF file to search for its color information
+const HEADER_SIZE: u64 = 1 << 20;
+
+/// The steps of the table encoding linear light as sRGB
+const ENCODE_STEPS: usize = 4096;
+
+/// The transfer function of an HDR image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    /// Perceptual quantizer, after SMPTE ST 2084
+    Pq,
+    /// Hybrid log-gamma, after ITU-R BT.2100
+    Hlg,
+}
+
+/// The parameters of a gain map, as log2 of the gains, from its `hdrgm` XMP properties
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GainMap {
+    min: f32,
+    max: f32,
+    gamma: f32,
+    offset_sdr: f32,
+    offset_hdr: f32,
+    base_is_hdr: bool,
+}
+
+/// The value of the `hdrgm` property `name`, as an attribute, an element, or the first item of a
+/// list of values by channel
+fn xmp_value<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    let attribute = format!("hdrgm:{}=\"", name);
+    if let Some(start) = xmp.find(&attribute) {
+        let value = &xmp[start + attribute.len()..];
+        return value.find('"').map(|end| &value[..end]);
+    }
+    let element = format!("<hdrgm:{}>", name);
+    let start = xmp.find(&element)?;
+    let mut value = &xmp[start + element.len()..];
+    if value.trim_start().starts_with('<') {
+        let item = value.find("<rdf:li>")?;
+        value = &value[item + "<rdf:li>".len()..];
+    }
+    value.find('<').map(|end| value[..end].trim())
+}
+
+impl GainMap {
+    /// The parameters in `xmp`, if it describes a gain map
+    fn from_xmp(xmp: &str) -> Option<Self> {
+        xmp_value(xmp, "Version")?;
+        let number = |name, default| {
+            xmp_value(xmp, name)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Some(Self {
+            min: number("GainMapMin", 0.),
+            max: number("GainMapMax", 1.),
+            gamma: number("Gamma", 1.),
+            offset_sdr: number("OffsetSDR", 1. / 64.),
+            offset_hdr: number("OffsetHDR", 1. / 64.),
+            base_is_hdr: xmp_value(xmp, "BaseRenditionIsHDR")
+                .map_or(false, |value| value.eq_ignore_ascii_case("true")),
+        })
+    }
+
+    /// Take `base`, the HDR rendition, to SDR with the gain map `map`
+    fn apply(&self, base: DynamicImage, map: &DynamicImage) -> DynamicImage {
+        let mut base = into_rgb8(base);
+        let (width, height) = base.dimensions();
+        let map = map
+            .resize_exact(width, height, FilterType::Triangle)
+            .to_rgb8();
+        let decode = srgb_decode_table();
+        let encode = srgb_encode_table();
+        let gamma = if self.gamma > 0. { self.gamma } else { 1. };
+        for (pixel, gain) in base.pixels_mut().zip(map.pixels()) {
+            for (value, gain) in pixel.0.iter_mut().zip(gain.0.iter()) {
+                let gain = (f32::from(*gain) / 255.).powf(1. / gamma);
+                let log_boost = self.min * (1. - gain) + self.max * gain;
+                // the gain takes SDR to HDR, so HDR is taken back by its inverse
+                let linear = (decode[usize::from(*value)] + self.offset_hdr) * (-log_boost).exp2()
+                    - self.offset_sdr;
+                *value = encode_srgb(&encode, linear);
+            }
+        }
+        DynamicImage::ImageRgb8(base)
+    }
+}
+
+/// Whether the XMP packets of a JPEG image describe a gain map
+fn has_gain_map<'a>(mut xmp: impl Iterator<Item = &'a [u8]>) -> bool {
+    xmp.any(|xmp| xmp_value(&String::from_utf8_lossy(xmp), "Version").is_some())
+}
+
+/// The XMP packets in the header of the JPEG image read from `reader`, which is read up to the
+/// image data
+fn header_xmp(reader: &mut impl Read) -> Option<Vec<Vec<u8>>> {
+    let mut byte = [0; 1];
+    let mut start = [0; 2];
+    reader.read_exact(&mut start).ok()?;
+    if start != [0xff, 0xd8] {
+        return None;
+    }
+    let mut xmp = vec![];
+    loop {
+        reader.read_exact(&mut byte).ok()?;
+        if byte[0] != 0xff {
+            return None;
+        }
+        // markers may be padded with fill bytes
+        while byte[0] == 0xff {
+            reader.read_exact(&mut byte).ok()?;
+        }
+        let marker = byte[0];
+        if marker == 0xda || marker == 0xd9 {
+            return Some(xmp);
+        }
+        let mut length = [0; 2];
+        reader.read_exact(&mut length).ok()?;
+        let length = usize::from(u16::from_be_bytes(length)).checked_sub(2)?;
+        let mut payload = vec![0; length];
+        reader.read_exact(&mut payload).ok()?;
+        if marker == 0xe1 && payload.starts_with(XMP_HEADER) {
+            xmp.push(payload.split_off(XMP_HEADER.len()));
+        }
+    }
+}
+
+/// The XMP packets of the JPEG image at the start of `data`, and where the image ends
+fn parse_jpeg(data: &[u8]) -> Option<(Vec<&[u8]>, usize)> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut xmp = vec![];
+    let mut pos = 2;
+    loop {
+        // markers may be padded with fill bytes
+        while *data.get(pos)? == 0xff && *data.get(pos + 1)? == 0xff {
+            pos += 1;
+        }
+        if *data.get(pos)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        if marker == 0xd9 {
+            return Some((xmp, pos + 2));
+        }
+        let length = usize::from(u16::from_be_bytes([
+            *data.get(pos + 2)?,
+            *data.get(pos + 3)?,
+        ]));
+        let payload = data.get(pos + 4..pos + 2 + length)?;
+        if marker == 0xe1 && payload.starts_with(XMP_HEADER) {
+            xmp.push(&payload[XMP_HEADER.len()..]);
+        }
+        pos += 2 + length;
+        if marker == 0xda {
+            // in the entropy-coded data, 0xff is followed by 0 or a restart marker
+            loop {
+                if *data.get(pos)? != 0xff {
+                    pos += 1;
+                    continue;
+                }
+                match *data.get(pos + 1)? {
+                    0 | 0xd0..=0xd7 => pos += 2,
+                    _ => break,
+                }
+            }
+        }
+    }
+}
+
+/// The gain map of the JPEG in `data`, with its parameters, if it has one
+fn gain_map(data: &[u8]) -> Option<(GainMap, DynamicImage)> {
+    let (primary_xmp, end) = parse_jpeg(data)?;
+    if !has_gain_map(primary_xmp.into_iter()) {
+        return None;
+    }
+    // the gain map is the image following the primary one
+    let start = end
+        + data[end..]
+            .windows(3)
+            .position(|w| w == [0xff, 0xd8, 0xff])?;
+    let (map_xmp, map_end) = parse_jpeg(&data[start..])?;
+    let parameters = map_xmp
+        .iter()
+        .find_map(|xmp| GainMap::from_xmp(&String::from_utf8_lossy(xmp)))?;
+    let map = image::load_from_memory_with_format(
+        &data[start..start + map_end],
+        image::ImageFormat::Jpeg,
+    )
+    .map_err(|e| warn!("Failed to decode the gain map: {}", e))
+    .ok()?;
+    Some((parameters, map))
+}
+
+/// The transfer function and whether the primaries are BT.2020, from the `nclx` color
+/// information of a HEIC or AVIF file starting with `header`, if it is HDR
+fn nclx(header: &[u8]) -> Option<(Transfer, bool)> {
+    let start = header.windows(8).position(|w| w == b"colrnclx")? + 8;
+    let field = |offset: usize| {
+        Some(u16::from_be_bytes([
+            *header.get(start + offset)?,
+            *header.get(start + offset + 1)?,
+        ]))
+    };
+    let transfer = match field(2)? {
+        16 => Transfer::Pq,
+        18 => Transfer::Hlg,
+        _ => return None,
+    };
+    Some((transfer, field(0)? == 9))
+}
+
+fn srgb_decode_table() -> Vec<f32> {
+    (0..=255u16)
+        .map(|value| {
+            let value = f32::from(value) / 255.;
+            if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        })
+        .collect()
+}
+
+fn srgb_encode_table() -> Vec<u8> {
+    (0..ENCODE_STEPS)
+        .map(|step| {
+            let value = step as f32 / (ENCODE_STEPS - 1) as f32;
+            let encoded = if value <= 0.003_130_8 {
+                value * 12.92
+            } else {
+                1.055 * value.powf(1. / 2.4) - 0.055
+            };
+            (encoded * 255.).round() as u8
+        })
+        .collect()
+}
+
+/// Encode linear light from 0 to 1 as sRGB, through `table`
+fn encode_srgb(table: &[u8], linear: f32) -> u8 {
+    let step = (linear.max(0.).min(1.) * (table.len() - 1) as f32).round();
+    table[step as usize]
+}
+
+/// The luminance in nits of the 8-bit `value` encoded with `transfer`
+fn decode(transfer: Transfer, value: u8) -> f32 {
+    let value = f32::from(value) / 255.;
+    match transfer {
+        Transfer::Pq => {
+            let (m1, m2) = (0.159_301_76, 78.843_75);
+            let (c1, c2, c3) = (0.835_937_5, 18.851_562, 18.687_5);
+            let power = value.powf(1. / m2);
+            10000. * ((power - c1).max(0.) / (c2 - c3 * power)).powf(1. / m1)
+        }
+        Transfer::Hlg => {
+            let (a, b, c) = (0.178_832_77, 0.284_668_92, 0.559_910_7);
+            let scene = if value <= 0.5 {
+                value * value / 3.
+            } else {
+                (((value - c) / a).exp() + b) / 12.
+            };
+            // the system gamma of a display of 1000 nits
+            1000. * scene.powf(1.2)
+        }
+    }
+}
+
+/// Shows HDR images as SDR
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapper {
+    /// the luminance shown as white, in nits, for HDR images without a gain map
+    pub peak: f32,
+}
+
+impl ToneMapper {
+    /// Show `img`, loaded from the image at `path`, as SDR
+    pub fn apply(&self, path: &Path, img: DynamicImage) -> DynamicImage {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("jpg") | Some("jpeg") => {
+                // most images have no gain map, which their header tells without reading them
+                let header = File::open(path)
+                    .ok()
+                    .and_then(|file| header_xmp(&mut BufReader::new(file)));
+                if !header.map_or(false, |xmp| has_gain_map(xmp.iter().map(Vec::as_slice))) {
+                    return img;
+                }
+                match std::fs::read(path).ok().and_then(|data| gain_map(&data)) {
+                    Some((parameters, map)) if parameters.base_is_hdr => {
+                        debug!("Applying the gain map of {:?}", path);
+                        parameters.apply(img, &map)
+                    }
+                    _ => img,
+                }
+            }
+            Some("heic") | Some("heif") | Some("avif") => {
+                let mut header = vec![];
+                let read = File::open(path)
+                    .and_then(|file| file.take(HEADER_SIZE).read_to_end(&mut header));
+                match read.ok().and_then(|_| nclx(&header)) {
+                    Some((transfer, bt2020)) => {
+                        debug!("Tone mapping {:?} from {:?}", path, transfer);
+                        DynamicImage::ImageRgb8(self.tone_map(into_rgb8(img), transfer, bt2020))
+                    }
+                    None => img,
+                }
+            }
+            _ => img,
+        }
+    }
+
+    /// Decode `img` with `transfer`, and compress its highlights into the range of SDR
+    fn tone_map(&self, mut img: RgbImage, transfer: Transfer, bt2020: bool) -> RgbImage {
+        let linear = (0..=255u8)
+            .map(|value| decode(transfer, value) / SDR_WHITE)
+            .collect::<Vec<_>>();
+        let encode = srgb_encode_table();
+        // extended Reinhard, reaching white at the peak
+        let white = (self.peak / SDR_WHITE).max(1.);
+        for pixel in img.pixels_mut() {
+            let [red, green, blue] = pixel.0;
+            let (red, green, blue) = (
+                linear[usize::from(red)],
+                linear[usize::from(green)],
+                linear[usize::from(blue)],
+            );
+            let (red, green, blue) = if bt2020 {
+                (
+                    1.660_491 * red - 0.587_641 * green - 0.072_850 * blue,
+                    -0.124_550 * red + 1.132_9 * green - 0.008_349 * blue,
+                    -0.018_151 * red - 0.100_579 * green + 1.118_73 * blue,
+                )
+            } else {
+                (red, green, blue)
+            };
+            let luminance = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+            let scale = if luminance > 0. {
+                (1. + luminance / (white * white)) / (1. + luminance)
+            } else {
+                1.
+            };
+            pixel.0 = [
+                encode_srgb(&encode, red * scale),
+                encode_srgb(&encode, green * scale),
+                encode_srgb(&encode, blue * scale),
+            ];
+        }
+        img
+    }
+}
@@ -0,0 +1,189 @@
+//! A minimal HTTP client, to query web services and download images
+//!
+//! Requests are HTTP/1.0, so responses are neither chunked nor kept alive, and the body ends with
+//! the connection. HTTPS is spoken with rustls, which checks certificates against the Mozilla
+//! root certificates built in, so it needs no system library or certificate store. Connecting,
+//! and the whole request, have a deadline, and the size of the response a limit, so a dead network
+//! or a huge file never stalls or exhausts the frame.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustls::{ClientConfig, ClientSession, StreamOwned};
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// The largest response head accepted
+const HEAD_LIMIT: u64 = 16 * 1024;
+
+/// A connection to a server, encrypted or not
+enum Connection {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientSession, TcpStream>>),
+}
+
+impl Connection {
+    /// The underlying socket, for its timeouts
+    fn socket(&self) -> &TcpStream {
+        match self {
+            Connection::Plain(stream) => stream,
+            Connection::Tls(stream) => &stream.sock,
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.read(buf),
+            Connection::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.write(buf),
+            Connection::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Connection::Plain(stream) => stream.flush(),
+            Connection::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Wrap `stream` to `name` in TLS
+fn tls(stream: TcpStream, name: &str, url: &str) -> RahmenResult<Connection> {
+    let mut config = ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    let name = webpki::DNSNameRef::try_from_ascii_str(name)
+        .map_err(|_| RahmenError::HttpError(format!("{}: invalid host name for TLS", url)))?;
+    let session = ClientSession::new(&Arc::new(config), name);
+    Ok(Connection::Tls(Box::new(StreamOwned::new(session, stream))))
+}
+
+/// Fetch `url`, of the form `http://host[:port]/path` or `https://host[:port]/path`, and return
+/// the body of the response. The request fails with a `TimedOut` I/O error if it takes longer
+/// than `timeout`, and with an `HttpError` if the body is larger than `limit` bytes.
+pub fn get(url: &str, timeout: Duration, limit: u64) -> RahmenResult<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+    let timed_out = || {
+        RahmenError::IoError(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("{}: no response within {:?}", url, timeout),
+        ))
+    };
+    let (rest, secure) = match (url.strip_prefix("http://"), url.strip_prefix("https://")) {
+        (Some(rest), _) => (rest, false),
+        (_, Some(rest)) => (rest, true),
+        _ => {
+            return Err(RahmenError::HttpError(format!(
+                "{}: not an http:// or https:// URL",
+                url
+            )))
+        }
+    };
+    let (host, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) => (
+            name,
+            port.parse()
+                .map_err(|_| RahmenError::HttpError(format!("{}: invalid port", url)))?,
+        ),
+        None => (host, if secure { 443 } else { 80 }),
+    };
+    let mut stream = None;
+    let mut last_error = None;
+    for address in (name, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(connected) => {
+                stream = Some(connected);
+                break;
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    let stream = match (stream, last_error) {
+        (Some(stream), _) => stream,
+        (None, Some(e)) => return Err(e.into()),
+        (None, None) => {
+            return Err(RahmenError::HttpError(format!("{}: host not found", url)));
+        }
+    };
+    // the TLS handshake happens along with the request, so it has the same timeouts
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut stream = if secure {
+        tls(stream, name, url)?
+    } else {
+        Connection::Plain(stream)
+    };
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: rahmen\r\n\r\n",
+        path, host
+    )?;
+    stream.flush()?;
+    let mut response = vec![];
+    let mut chunk = [0; 16 * 1024];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return Err(timed_out());
+        }
+        stream.socket().set_read_timeout(Some(remaining))?;
+        let read = match stream.read(&mut chunk) {
+            Ok(read) => read,
+            Err(e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                return Err(timed_out())
+            }
+            // servers often close TLS connections without saying so, which ends the body all
+            // the same
+            Err(e) if secure && e.kind() == std::io::ErrorKind::UnexpectedEof => 0,
+            Err(e) => return Err(e.into()),
+        };
+        if read == 0 {
+            break;
+        }
+        response.extend_from_slice(&chunk[..read]);
+        if response.len() as u64 > HEAD_LIMIT + limit {
+            return Err(RahmenError::HttpError(format!(
+                "{}: larger than {} bytes",
+                url, limit
+            )));
+        }
+    }
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .filter(|split| *split as u64 + 4 <= HEAD_LIMIT)
+        .ok_or_else(|| RahmenError::HttpError(format!("{}: malformed response", url)))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(RahmenError::HttpError(format!("{}: {}", url, status)));
+    }
+    let body = response.split_off(split + 4);
+    if body.len() as u64 > limit {
+        return Err(RahmenError::HttpError(format!(
+            "{}: larger than {} bytes",
+            url, limit
+        )));
+    }
+    Ok(body)
+}
@@ -0,0 +1,215 @@
+//! A minimal HTTP server, shared by the control API, the metrics and the web display
+//!
+//! Each connection is handled on a thread of its own, up to a limit, and further connections are
+//! turned away with `503 Service Unavailable` until one ends. Reading a request and writing each
+//! part of a response time out, so slow or vanished clients never hold a thread for long. Requests
+//! are answered with HTTP/1.0, one per connection.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// The largest request head accepted
+const HEAD_LIMIT: u64 = 16 * 1024;
+
+/// Limits protecting a server from clients
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// how long reading a request, and writing each part of a response, may take
+    pub timeout: Duration,
+    /// how many connections are handled at the same time
+    pub max_connections: usize,
+    /// the largest request body accepted
+    pub max_body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_connections: 8,
+            max_body: 64 * 1024,
+        }
+    }
+}
+
+/// A request received
+#[derive(Debug)]
+pub struct Request {
+    /// the method, such as `GET`
+    pub method: String,
+    /// the path requested, including any query
+    pub path: String,
+    /// the body, empty if there is none
+    pub body: Vec<u8>,
+}
+
+/// A function writing a response to a connection, such as a stream of frames
+pub type Writer = Box<dyn FnOnce(&mut TcpStream) -> std::io::Result<()> + Send>;
+
+/// The answer to a request
+pub enum Response {
+    /// A complete response, with its status, such as `200 OK`, its content type and its body
+    Full(&'static str, String, Vec<u8>),
+    /// A response written by a function, which writes the status line and headers as well
+    Stream(Writer),
+}
+
+impl Response {
+    /// A complete response
+    pub fn new<B: Into<Vec<u8>>>(status: &'static str, content_type: &str, body: B) -> Self {
+        Response::Full(status, content_type.into(), body.into())
+    }
+
+    /// A complete response with a JSON body
+    pub fn json(status: &'static str, body: &serde_json::Value) -> Self {
+        Self::new(status, "application/json", body.to_string())
+    }
+}
+
+impl std::fmt::Debug for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Response::Full(status, content_type, body) => f
+                .debug_tuple("Full")
+                .field(status)
+                .field(content_type)
+                .field(&body.len())
+                .finish(),
+            Response::Stream(_) => f.debug_tuple("Stream").finish(),
+        }
+    }
+}
+
+/// The addresses to listen on for `address`, such as `127.0.0.1:8081` or `localhost:8081`
+pub fn resolve(address: &str) -> RahmenResult<Vec<SocketAddr>> {
+    let addresses: Vec<_> = address
+        .to_socket_addrs()
+        .map_err(|e| RahmenError::HttpError(format!("{:?}: {}", address, e)))?
+        .collect();
+    if addresses.is_empty() {
+        return Err(RahmenError::HttpError(format!(
+            "{:?}: no address to listen on",
+            address
+        )));
+    }
+    Ok(addresses)
+}
+
+/// Serve requests on `address`, such as `127.0.0.1:8081`, in a background thread, answering each
+/// with `handler`. Returns the address bound, which tells the port chosen for port 0.
+pub fn serve<H>(address: &str, limits: Limits, handler: H) -> RahmenResult<SocketAddr>
+where
+    H: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(&resolve(address)?[..])?;
+    let bound = listener.local_addr()?;
+    let handler = Arc::new(handler);
+    let connections = Arc::new(AtomicUsize::new(0));
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("HTTP connection on {} failed: {}", bound, e);
+                    continue;
+                }
+            };
+            if connections.fetch_add(1, Ordering::SeqCst) >= limits.max_connections {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                let _ = stream.set_write_timeout(Some(limits.timeout));
+                let _ = respond(
+                    &mut stream,
+                    "503 Service Unavailable",
+                    "text/plain",
+                    b"busy\n",
+                );
+                continue;
+            }
+            let handler = Arc::clone(&handler);
+            let connections = Arc::clone(&connections);
+            std::thread::spawn(move || {
+                // clients going away are nothing to worry about
+                let _ = handle(stream, limits, handler.as_ref());
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    Ok(bound)
+}
+
+fn handle<H: Fn(Request) -> Response>(
+    mut stream: TcpStream,
+    limits: Limits,
+    handler: &H,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(limits.timeout))?;
+    stream.set_write_timeout(Some(limits.timeout))?;
+    let mut reader = BufReader::new((&stream).take(HEAD_LIMIT + limits.max_body as u64));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some(index) = header.find(':') {
+            if header[..index].eq_ignore_ascii_case("content-length") {
+                content_length = header[index + 1..].trim().parse().unwrap_or(0);
+            }
+        }
+        header.clear();
+    }
+    if content_length > limits.max_body {
+        return respond(
+            &mut stream,
+            "413 Payload Too Large",
+            "text/plain",
+            b"request too large\n",
+        );
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let mut parts = request_line.split_whitespace();
+    let request = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => Request {
+            method: method.into(),
+            path: path.into(),
+            body,
+        },
+        _ => {
+            return respond(
+                &mut stream,
+                "400 Bad Request",
+                "text/plain",
+                b"bad request\n",
+            )
+        }
+    };
+    match handler(request) {
+        Response::Full(status, content_type, body) => {
+            respond(&mut stream, status, &content_type, &body)
+        }
+        Response::Stream(write) => write(&mut stream),
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.0 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
@@ -0,0 +1,103 @@
+//! Count how often and how long each image is shown, for reporting on signage
+//!
+//! Impressions are kept in the persistent store, as `impressions/<path>` keys with the number of
+//! impressions and the total display time in seconds as value.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::errors::{RahmenError, RahmenResult};
+use crate::store::Store;
+
+const PREFIX: &str = "impressions/";
+
+/// The impressions of an image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Impressions {
+    /// how often the image was shown
+    pub count: u64,
+    /// the total display time in seconds
+    pub seconds: f64,
+}
+
+impl Impressions {
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(2, ',');
+        Some(Self {
+            count: parts.next()?.parse().ok()?,
+            seconds: parts.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// Record that the image at `path` was shown for `duration`
+pub fn record<S: Store + ?Sized>(
+    store: &mut S,
+    path: &Path,
+    duration: Duration,
+) -> RahmenResult<()> {
+    let key = format!("{}{}", PREFIX, path.to_string_lossy());
+    let mut impressions = store
+        .get(&key)?
+        .and_then(|value| Impressions::parse(&value))
+        .unwrap_or_default();
+    impressions.count += 1;
+    impressions.seconds += duration.as_secs_f64();
+    store.set(
+        &key,
+        &format!("{},{:.1}", impressions.count, impressions.seconds),
+    )
+}
+
+/// Read the impressions of all images, ordered by path
+pub fn all<S: Store + ?Sized>(store: &S) -> RahmenResult<Vec<(String, Impressions)>> {
+    let mut all = vec![];
+    for key in store.keys(PREFIX)? {
+        if let Some(impressions) = store
+            .get(&key)?
+            .and_then(|value| Impressions::parse(&value))
+        {
+            all.push((key[PREFIX.len()..].to_string(), impressions));
+        }
+    }
+    Ok(all)
+}
+
+/// Write the impressions of all images to `target`, as JSON if its name ends with `.json`, as CSV
+/// otherwise
+pub fn export<S: Store + ?Sized>(store: &S, target: &Path) -> RahmenResult<()> {
+    let all = all(store)?;
+    let mut writer = BufWriter::new(File::create(target)?);
+    if target
+        .extension()
+        .map_or(false, |extension| extension == "json")
+    {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            path: &'a str,
+            #[serde(flatten)]
+            impressions: &'a Impressions,
+        }
+        let entries: Vec<_> = all
+            .iter()
+            .map(|(path, impressions)| Entry { path, impressions })
+            .collect();
+        serde_json::to_writer_pretty(&mut writer, &entries)
+            .map_err(|e| RahmenError::StoreError(e.to_string()))?;
+    } else {
+        writeln!(writer, "path,count,seconds")?;
+        for (path, impressions) in &all {
+            writeln!(
+                writer,
+                "\"{}\",{},{:.1}",
+                path.replace('"', "\"\""),
+                impressions.count,
+                impressions.seconds
+            )?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
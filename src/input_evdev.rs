@@ -0,0 +1,262 @@
+//! Keyboards attached to the frame, read through evdev
+//!
+//! Displays drawing to the console, like the framebuffer, get no key events, so the keyboards can
+//! be read from their event devices in `/dev/input` instead, which needs membership in the `input`
+//! group. Devices are grabbed, so their key presses don't also reach the login prompt on the
+//! console below. Keyboards are found through `/proc/bus/input/devices` when Rahmen starts. The
+//! arrow and page keys go to the next or previous image, space pauses, `c` toggles clean mode, `p`
+//! cycles the soft proofing modes, `o` blanks the screen, `f` marks the image as a favorite, `r`
+//! and `l` turn it clockwise and counterclockwise, and delete never shows it again. Infrared
+//! receivers handled by the kernel are event devices too, and are read the same way with another
+//! keymap.
+
+use std::collections::HashMap;
+use std::fs::File;
+#[cfg(target_os = "linux")]
+use std::io::Read;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+use tracing::{info, warn};
+
+use crate::control::{Action, Command};
+use crate::errors::{RahmenError, RahmenResult};
+
+/// `_IOW('E', 0x90, int)`, to take exclusive access to an event device
+#[cfg(target_os = "linux")]
+const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+
+/// The type of key events
+#[cfg(target_os = "linux")]
+const EV_KEY: u16 = 1;
+
+/// The value of key events for a key going down
+#[cfg(target_os = "linux")]
+const KEY_PRESSED: i32 = 1;
+
+/// Actions bound to keys, by their codes
+pub type Keymap = HashMap<u16, Action>;
+
+/// Names and codes of keys, as in `linux/input-event-codes.h`
+const KEYS: &[(&str, u16)] = &[
+    ("KEY_ESC", 1),
+    ("KEY_1", 2),
+    ("KEY_2", 3),
+    ("KEY_3", 4),
+    ("KEY_4", 5),
+    ("KEY_5", 6),
+    ("KEY_6", 7),
+    ("KEY_7", 8),
+    ("KEY_8", 9),
+    ("KEY_9", 10),
+    ("KEY_0", 11),
+    ("KEY_R", 19),
+    ("KEY_O", 24),
+    ("KEY_P", 25),
+    ("KEY_ENTER", 28),
+    ("KEY_F", 33),
+    ("KEY_L", 38),
+    ("KEY_C", 46),
+    ("KEY_B", 48),
+    ("KEY_N", 49),
+    ("KEY_SPACE", 57),
+    ("KEY_UP", 103),
+    ("KEY_PAGEUP", 104),
+    ("KEY_LEFT", 105),
+    ("KEY_RIGHT", 106),
+    ("KEY_DOWN", 108),
+    ("KEY_PAGEDOWN", 109),
+    ("KEY_DELETE", 111),
+    ("KEY_MUTE", 113),
+    ("KEY_POWER", 116),
+    ("KEY_PAUSE", 119),
+    ("KEY_STOP", 128),
+    ("KEY_MENU", 139),
+    ("KEY_BACK", 158),
+    ("KEY_NEXTSONG", 163),
+    ("KEY_PLAYPAUSE", 164),
+    ("KEY_PREVIOUSSONG", 165),
+    ("KEY_REWIND", 168),
+    ("KEY_PLAY", 207),
+    ("KEY_FASTFORWARD", 208),
+    ("KEY_OK", 352),
+    ("KEY_INFO", 358),
+    ("KEY_RED", 398),
+    ("KEY_GREEN", 399),
+    ("KEY_YELLOW", 400),
+    ("KEY_BLUE", 401),
+    ("KEY_CHANNELUP", 402),
+    ("KEY_CHANNELDOWN", 403),
+    ("KEY_NEXT", 407),
+    ("KEY_PREVIOUS", 412),
+];
+
+/// The code of a key, given by its name, such as `KEY_NEXT`, or by its number
+pub fn key_code(name: &str) -> RahmenResult<u16> {
+    KEYS.iter()
+        .find(|(key, _)| *key == name)
+        .map(|(_, code)| *code)
+        .or_else(|| name.parse().ok())
+        .ok_or_else(|| RahmenError::InputError(format!("unknown key {:?}", name)))
+}
+
+/// Build a keymap from names of keys and their actions
+pub fn keymap(keys: &[(&str, Action)]) -> Keymap {
+    keys.iter()
+        .filter_map(|(name, action)| key_code(name).ok().map(|code| (code, *action)))
+        .collect()
+}
+
+/// The keys of keyboards
+pub fn keyboard_keymap() -> Keymap {
+    keymap(&[
+        ("KEY_RIGHT", Action::Next),
+        ("KEY_DOWN", Action::Next),
+        ("KEY_PAGEDOWN", Action::Next),
+        ("KEY_N", Action::Next),
+        ("KEY_LEFT", Action::Previous),
+        ("KEY_UP", Action::Previous),
+        ("KEY_PAGEUP", Action::Previous),
+        ("KEY_B", Action::Previous),
+        ("KEY_SPACE", Action::Pause),
+        ("KEY_C", Action::ToggleClean),
+        ("KEY_P", Action::CycleProof),
+        ("KEY_O", Action::ScreenOff),
+        ("KEY_F", Action::Favorite),
+        ("KEY_DELETE", Action::Blacklist),
+        ("KEY_R", Action::RotateRight),
+        ("KEY_L", Action::RotateLeft),
+    ])
+}
+
+/// The event devices listed in `/proc/bus/input/devices` whose description satisfies
+/// `predicate`. A description is a block of lines, such as `H: Handlers=sysrq kbd event0`.
+fn input_devices<F: Fn(&str) -> bool>(predicate: F) -> Vec<PathBuf> {
+    let devices = std::fs::read_to_string("/proc/bus/input/devices").unwrap_or_default();
+    devices
+        .split("\n\n")
+        .filter(|description| predicate(description))
+        .filter_map(|description| {
+            description
+                .lines()
+                .filter_map(|line| line.strip_prefix("H: Handlers="))
+                .flat_map(str::split_whitespace)
+                .find(|handler| handler.starts_with("event"))
+        })
+        .map(|event| Path::new("/dev/input").join(event))
+        .collect()
+}
+
+/// Whether a device is an infrared receiver handled by the kernel's remote control subsystem
+fn is_remote(description: &str) -> bool {
+    description
+        .lines()
+        .any(|line| line.starts_with("S: Sysfs=") && line.contains("/rc/rc"))
+}
+
+/// The event devices of the keyboards attached
+pub fn keyboards() -> Vec<PathBuf> {
+    input_devices(|description| {
+        !is_remote(description)
+            && description.lines().any(|line| {
+                line.strip_prefix("H: Handlers=").map_or(false, |handlers| {
+                    handlers.split_whitespace().any(|h| h == "kbd")
+                })
+            })
+    })
+}
+
+/// The event devices of the infrared receivers attached
+pub fn remotes() -> Vec<PathBuf> {
+    input_devices(is_remote)
+}
+
+/// Read key events from a device until it is gone, and send the commands of their actions
+#[cfg(target_os = "linux")]
+fn read(mut file: File, keymap: &Keymap, commands: &Sender<Command>) -> std::io::Result<()> {
+    // the size of `struct input_event` depends on the size of the timestamp on this platform;
+    // type, code and value follow it
+    let size = std::mem::size_of::<libc::input_event>();
+    let mut event = vec![0; size];
+    loop {
+        file.read_exact(&mut event)?;
+        let fields = &event[size - 8..];
+        let kind = u16::from_ne_bytes([fields[0], fields[1]]);
+        let code = u16::from_ne_bytes([fields[2], fields[3]]);
+        let value = i32::from_ne_bytes([fields[4], fields[5], fields[6], fields[7]]);
+        if kind == EV_KEY && value == KEY_PRESSED {
+            if let Some(action) = keymap.get(&code) {
+                if commands.send(action.command()).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Take exclusive access to the event device `file`, so no other reader gets its events
+#[cfg(target_os = "linux")]
+fn grab(file: &File) -> std::io::Result<()> {
+    // the argument of the variadic call must have the size of an int
+    let exclusive: libc::c_int = 1;
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB, exclusive) };
+    if result < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Event devices exist on Linux only
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "event devices can only be read on Linux",
+    )
+}
+
+/// Read key events from a device, which needs Linux
+#[cfg(not(target_os = "linux"))]
+fn read(_file: File, _keymap: &Keymap, _commands: &Sender<Command>) -> std::io::Result<()> {
+    Err(unsupported())
+}
+
+/// Take exclusive access to an event device, which needs Linux
+#[cfg(not(target_os = "linux"))]
+fn grab(_file: &File) -> std::io::Result<()> {
+    Err(unsupported())
+}
+
+/// Read key events from `devices`, each in a background thread, and send the commands of the
+/// actions `keymap` binds the keys to to `commands`. Devices which can't be read are skipped.
+pub fn start(devices: Vec<PathBuf>, keymap: Keymap, commands: Sender<Command>) {
+    for device in devices {
+        let file = match File::open(&device) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!(
+                    "Cannot read {:?}, is the user in the input group? {}",
+                    device, e
+                );
+                continue;
+            }
+        };
+        if let Err(e) = grab(&file) {
+            warn!(
+                "Cannot grab {:?}, keys reach the console too: {}",
+                device, e
+            );
+        }
+        info!("Reading keys from {:?}", device);
+        let (keymap, commands) = (keymap.clone(), commands.clone());
+        std::thread::spawn(move || {
+            if let Err(e) = read(file, &keymap, &commands) {
+                warn!("Reading keys from {:?} failed: {}", device, e);
+            }
+        });
+    }
+}
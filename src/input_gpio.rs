@@ -0,0 +1,168 @@
+//! Buttons connected to the GPIO pins of a Raspberry Pi
+//!
+//! Each button is bound to an action, and optionally to a second action when held. The pins are
+//! polled in a background thread, and a change only counts once the level is stable for the
+//! debounce time, so bouncing contacts do not trigger an action twice. A button with a long-press
+//! action triggers its action when released, and the long-press action once held long enough.
+//! GPIO support is behind the `gpio` feature.
+
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+#[cfg(feature = "gpio")]
+use std::time::Instant;
+
+use crate::control::{Action, Command};
+use crate::errors::{RahmenError, RahmenResult};
+
+/// The highest BCM number of a pin on the header of a Raspberry Pi
+const MAX_PIN: u8 = 27;
+
+/// How often the pins are read
+#[cfg(feature = "gpio")]
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A button connected to a pin
+#[derive(Debug, Clone)]
+pub struct Button {
+    /// the BCM number of the pin
+    pub pin: u8,
+    /// the action when pressed
+    pub action: Action,
+    /// the action when held, if any
+    pub long_action: Option<Action>,
+    /// whether the button connects the pin to ground, using the internal pull-up resistor, or
+    /// else to 3.3 V, using the pull-down resistor
+    pub active_low: bool,
+    /// how long the level must be stable to count
+    pub debounce: Duration,
+    /// how long the button must be held for the long-press action
+    pub long_press: Duration,
+}
+
+#[cfg(feature = "gpio")]
+fn gpio_err(e: rppal::gpio::Error) -> RahmenError {
+    RahmenError::InputError(format!("GPIO: {}", e))
+}
+
+/// A button being watched
+#[cfg(feature = "gpio")]
+struct WatchedButton {
+    button: Button,
+    pin: rppal::gpio::InputPin,
+    /// the level read last, and since when
+    reading: (bool, Instant),
+    /// since when the button is pressed, after debouncing, and whether the long-press action
+    /// was triggered
+    pressed: Option<(Instant, bool)>,
+}
+
+#[cfg(feature = "gpio")]
+impl WatchedButton {
+    /// Read the pin, and return the action to trigger, if any
+    fn poll(&mut self, now: Instant) -> Option<Action> {
+        let level = self.pin.is_low() == self.button.active_low;
+        if level != self.reading.0 {
+            self.reading = (level, now);
+        }
+        if now.duration_since(self.reading.1) < self.button.debounce {
+            return None;
+        }
+        match (self.pressed, self.reading.0) {
+            (None, true) => {
+                self.pressed = Some((now, false));
+                // without a long-press action, there is no need to wait for the release
+                if self.button.long_action.is_none() {
+                    return Some(self.button.action);
+                }
+                None
+            }
+            (Some((since, false)), true) => match self.button.long_action {
+                Some(action) if now.duration_since(since) >= self.button.long_press => {
+                    self.pressed = Some((since, true));
+                    Some(action)
+                }
+                _ => None,
+            },
+            (Some((_, long)), false) => {
+                self.pressed = None;
+                if self.button.long_action.is_some() && !long {
+                    Some(self.button.action)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Check that buttons can be connected to `pins`: each is on the header, and has one button only
+pub fn check_pins<I: IntoIterator<Item = u8>>(pins: I) -> RahmenResult<()> {
+    if cfg!(not(feature = "gpio")) {
+        return Err(RahmenError::Unsupported(
+            "compiled without GPIO support".into(),
+        ));
+    }
+    let mut seen = vec![];
+    for pin in pins {
+        if pin > MAX_PIN {
+            return Err(RahmenError::InputError(format!(
+                "GPIO pin {} is not on the header, which has pins 0 to {}",
+                pin, MAX_PIN
+            )));
+        }
+        if seen.contains(&pin) {
+            return Err(RahmenError::InputError(format!(
+                "GPIO pin {} has more than one button",
+                pin
+            )));
+        }
+        seen.push(pin);
+    }
+    Ok(())
+}
+
+/// Watch `buttons` in a background thread, and send the commands of their actions to `commands`
+#[cfg(feature = "gpio")]
+pub fn start(buttons: Vec<Button>, commands: Sender<Command>) -> RahmenResult<()> {
+    check_pins(buttons.iter().map(|button| button.pin))?;
+    let gpio = rppal::gpio::Gpio::new().map_err(gpio_err)?;
+    let now = Instant::now();
+    let mut watched = vec![];
+    for button in buttons {
+        let pin = gpio.get(button.pin).map_err(gpio_err)?;
+        let pin = if button.active_low {
+            pin.into_input_pullup()
+        } else {
+            pin.into_input_pulldown()
+        };
+        watched.push(WatchedButton {
+            button,
+            pin,
+            reading: (false, now),
+            pressed: None,
+        });
+    }
+    std::thread::spawn(move || loop {
+        let now = Instant::now();
+        for button in &mut watched {
+            if let Some(action) = button.poll(now) {
+                if commands.send(action.command()).is_err() {
+                    return;
+                }
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    });
+    Ok(())
+}
+
+/// Watch `buttons`, which needs GPIO support
+#[cfg(not(feature = "gpio"))]
+pub fn start(buttons: Vec<Button>, _commands: Sender<Command>) -> RahmenResult<()> {
+    Err(RahmenError::Unsupported(format!(
+        "compiled without GPIO support, cannot watch {} buttons",
+        buttons.len()
+    )))
+}
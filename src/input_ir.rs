@@ -0,0 +1,98 @@
+//! Infrared remote controls
+//!
+//! Receivers handled by the kernel's remote control subsystem, rc-core, are event devices that
+//! report the keys of the remote, such as `KEY_NEXT`, so they are read like keyboards. Receivers
+//! set up with LIRC are read through the socket of `lircd` instead, which reports the names of
+//! the buttons as configured in its `lircd.conf`. Either way, the keys are bound to actions, by
+//! default those a TV remote suggests.
+
+#[cfg(unix)]
+use std::io::{BufRead, BufReader};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+
+#[cfg(unix)]
+use tracing::{info, warn};
+
+use crate::control::{Action, Command};
+use crate::errors::{RahmenError, RahmenResult};
+#[cfg(unix)]
+use crate::input_evdev::key_code;
+use crate::input_evdev::{keymap, Keymap};
+
+/// The keys of remote controls
+pub fn default_keymap() -> Keymap {
+    keymap(&[
+        ("KEY_NEXT", Action::Next),
+        ("KEY_NEXTSONG", Action::Next),
+        ("KEY_RIGHT", Action::Next),
+        ("KEY_CHANNELUP", Action::Next),
+        ("KEY_FASTFORWARD", Action::Next),
+        ("KEY_PREVIOUS", Action::Previous),
+        ("KEY_PREVIOUSSONG", Action::Previous),
+        ("KEY_LEFT", Action::Previous),
+        ("KEY_CHANNELDOWN", Action::Previous),
+        ("KEY_REWIND", Action::Previous),
+        ("KEY_PLAYPAUSE", Action::Pause),
+        ("KEY_PLAY", Action::Pause),
+        ("KEY_PAUSE", Action::Pause),
+        ("KEY_OK", Action::Pause),
+        ("KEY_POWER", Action::ScreenOff),
+        ("KEY_INFO", Action::ToggleClean),
+    ])
+}
+
+/// The action of a line reported by `lircd`, such as `000000037ff07bef 00 KEY_NEXT remote`,
+/// ignoring repeats of held buttons
+#[cfg(unix)]
+fn lircd_action(line: &str, keymap: &Keymap) -> Option<Action> {
+    let mut fields = line.split_whitespace().skip(1);
+    let repeat = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let code = key_code(fields.next()?).ok()?;
+    if repeat == 0 {
+        keymap.get(&code).copied()
+    } else {
+        None
+    }
+}
+
+/// Read the buttons pressed from the socket of `lircd` at `socket` in a background thread, and
+/// send the commands of the actions `keymap` binds them to to `commands`
+#[cfg(unix)]
+pub fn start_lircd(socket: &Path, keymap: Keymap, commands: Sender<Command>) -> RahmenResult<()> {
+    let stream = UnixStream::connect(socket).map_err(|e| {
+        RahmenError::InputError(format!("cannot connect to lircd at {:?}: {}", socket, e))
+    })?;
+    info!("Reading remote control from {:?}", socket);
+    let socket = socket.to_path_buf();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            match line {
+                Ok(line) => {
+                    if let Some(action) = lircd_action(&line, &keymap) {
+                        if commands.send(action.command()).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Reading from lircd at {:?} failed: {}", socket, e);
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Read the buttons pressed from the socket of `lircd` at `socket`, which needs Unix domain
+/// sockets
+#[cfg(not(unix))]
+pub fn start_lircd(socket: &Path, _keymap: Keymap, _commands: Sender<Command>) -> RahmenResult<()> {
+    Err(RahmenError::InputError(format!(
+        "cannot connect to lircd at {:?} without Unix domain sockets",
+        socket
+    )))
+}
@@ -0,0 +1,75 @@
+//! A PIR motion sensor connected to a GPIO pin of a Raspberry Pi
+//!
+//! PIR sensors drive their output high while they see motion, for a hold time set on the module.
+//! The pin is polled in a background thread, and motion is reported as a command when it starts,
+//! and again every `REPEAT` while it lasts, so the slideshow stays awake while someone is around.
+//! The main loop blanks the screen when no motion was reported for a while. GPIO support is
+//! behind the `gpio` feature.
+
+use std::sync::mpsc::Sender;
+
+#[cfg(feature = "gpio")]
+use std::time::{Duration, Instant};
+
+use crate::control::Command;
+use crate::errors::{RahmenError, RahmenResult};
+
+/// How often the pin is read
+#[cfg(feature = "gpio")]
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often lasting motion is reported again
+#[cfg(feature = "gpio")]
+const REPEAT: Duration = Duration::from_secs(10);
+
+/// A motion sensor connected to a pin
+#[derive(Debug, Clone)]
+pub struct MotionSensor {
+    /// the BCM number of the pin
+    pub pin: u8,
+    /// whether the sensor pulls the pin low on motion, rather than driving it high
+    pub active_low: bool,
+}
+
+/// Watch `sensor` in a background thread, and send `Command::Motion` to `commands` on motion
+#[cfg(feature = "gpio")]
+pub fn start(sensor: MotionSensor, commands: Sender<Command>) -> RahmenResult<()> {
+    let gpio_err = |e: rppal::gpio::Error| RahmenError::InputError(format!("GPIO: {}", e));
+    let pin = rppal::gpio::Gpio::new()
+        .map_err(gpio_err)?
+        .get(sensor.pin)
+        .map_err(gpio_err)?;
+    // idle sensors leave the pin at the level of the pull resistor
+    let pin = if sensor.active_low {
+        pin.into_input_pullup()
+    } else {
+        pin.into_input_pulldown()
+    };
+    std::thread::spawn(move || {
+        let mut reported: Option<Instant> = None;
+        loop {
+            let now = Instant::now();
+            if pin.is_low() == sensor.active_low {
+                if reported.map_or(true, |at| now >= at + REPEAT) {
+                    if commands.send(Command::Motion).is_err() {
+                        return;
+                    }
+                    reported = Some(now);
+                }
+            } else {
+                reported = None;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+    Ok(())
+}
+
+/// Watch `sensor`, which needs GPIO support
+#[cfg(not(feature = "gpio"))]
+pub fn start(sensor: MotionSensor, _commands: Sender<Command>) -> RahmenResult<()> {
+    Err(RahmenError::Unsupported(format!(
+        "compiled without GPIO support, cannot watch the motion sensor on pin {}",
+        sensor.pin
+    )))
+}
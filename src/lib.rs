@@ -1,6 +1,6 @@
 //! Rahmen library
 
-#![forbid(unsafe_code)]
+#![deny(unsafe_code)]
 #![warn(
     anonymous_parameters,
     missing_copy_implementations,
@@ -19,42 +19,99 @@
 #[macro_use]
 extern crate serde_derive;
 
-use std::time::{Duration, Instant};
-
+pub mod adjust;
+pub mod backlight;
+pub mod check;
+pub mod cleanup;
+pub mod collage;
+pub mod compositor;
 pub mod config;
+pub mod control;
+pub mod control_dbus;
+pub mod control_http;
+pub mod control_socket;
 pub mod dataflow;
+pub mod date;
+pub mod dirs;
 pub mod display;
+#[cfg(feature = "drm")]
+pub mod display_drm;
+#[cfg(feature = "eink")]
+pub mod display_eink;
 #[cfg(feature = "fltk")]
 pub mod display_fltk;
+#[cfg(feature = "framebuffer")]
 pub mod display_framebuffer;
+#[cfg(feature = "http")]
+pub mod display_http;
+pub mod display_multi;
+pub mod display_png;
+#[cfg(feature = "sdl2")]
+pub mod display_sdl2;
+#[cfg(feature = "terminal")]
+pub mod display_terminal;
+#[cfg(feature = "x11")]
+pub mod display_x11;
 pub mod errors;
+pub mod events;
+pub mod exposure;
+pub mod favorite;
 pub mod font;
+pub mod geocode;
+pub mod hdr;
+pub mod http_client;
+pub mod http_server;
+pub mod impressions;
+pub mod input_evdev;
+pub mod input_gpio;
+pub mod input_ir;
+pub mod input_motion;
+pub mod light_sensor;
+#[cfg(feature = "cli")]
+pub mod logging;
+pub mod lua;
+pub mod metadata;
+pub mod metrics;
+pub mod mqtt;
+pub mod pair;
+pub mod pixel;
+pub mod pixel_shift;
+pub mod placeholder;
+pub mod player;
+pub mod power;
+pub mod prefetch;
+pub mod presence;
+pub mod progress;
 pub mod provider;
+#[cfg(feature = "tokio")]
+pub mod provider_async;
+pub mod provider_blacklist;
+pub mod provider_composite;
+pub mod provider_duplicate;
+pub mod provider_duration;
 pub mod provider_glob;
 pub mod provider_list;
-
-/// A timer to track the duration of a code span until it is dropped
-#[derive(Debug)]
-pub struct Timer<F: Fn(Duration)> {
-    start: Instant,
-    f: F,
-}
-
-impl<F: Fn(Duration)> Timer<F> {
-    /// Construct a new timer with a callback. The callback will receive the duration since creation
-    pub fn new(f: F) -> Self {
-        Self {
-            start: Instant::now(),
-            f,
-        }
-    }
-}
-
-impl<F: Fn(Duration)> Drop for Timer<F> {
-    fn drop(&mut self) {
-        let elapsed = self.start.elapsed();
-        if elapsed >= Duration::from_millis(20) {
-            (self.f)(elapsed)
-        }
-    }
-}
+pub mod provider_lookahead;
+pub mod provider_orientation;
+pub mod provider_playlist;
+pub mod provider_quality;
+pub mod provider_requeue;
+pub mod provider_resolution;
+pub mod provider_resume;
+pub mod provider_shuffle;
+pub mod python;
+pub mod qr;
+pub mod rotate;
+pub mod schedule;
+pub mod signals;
+pub mod stats;
+pub mod store;
+pub mod straighten;
+pub mod systemd;
+pub mod transcode;
+pub mod transition;
+#[cfg(feature = "framebuffer")]
+pub mod vt;
+pub mod watch;
+pub mod weather;
+pub mod xmp;
@@ -0,0 +1,228 @@
+//! Dim the images with the light in the room
+//!
+//! An ambient light sensor is read in a background thread, either through the Industrial I/O
+//! subsystem of the kernel, which has drivers for many sensors, or directly over I2C for the
+//! TSL2561 and VEML7700, which needs the `i2c` feature. The illuminance is smoothed, and mapped to
+//! a brightness factor on a logarithmic scale, as the eye perceives light: from `min_brightness`
+//! in a room as dark as `dark` lux, to full brightness in a room as bright as `bright` lux.
+
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// The weight of a new reading when smoothing
+const SMOOTHING: f32 = 0.3;
+
+/// The brightness factor changes in steps of this size, so the image isn't adjusted again for
+/// every flicker of the light
+const STEP: f32 = 0.05;
+
+/// The kind of light sensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SensorKind {
+    /// A sensor with a driver of the Industrial I/O subsystem
+    Iio,
+    /// A TSL2561 on an I2C bus
+    Tsl2561,
+    /// A VEML7700 on an I2C bus
+    Veml7700,
+}
+
+/// A light sensor to read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sensor {
+    /// The IIO device in the directory, such as `/sys/bus/iio/devices/iio:device0`
+    Iio(PathBuf),
+    /// A TSL2561 on the I2C bus, at the address
+    Tsl2561(u8, u16),
+    /// A VEML7700 on the I2C bus, at the address
+    Veml7700(u8, u16),
+}
+
+fn sensor_err<E: std::fmt::Display>(e: E) -> RahmenError {
+    RahmenError::SensorError(e.to_string())
+}
+
+/// Read a number from the file `name` in the IIO device directory `device`
+fn read_attribute(device: &std::path::Path, name: &str) -> RahmenResult<Option<f32>> {
+    match std::fs::read_to_string(device.join(name)) {
+        Ok(text) => text.trim().parse().map(Some).map_err(sensor_err),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The illuminance in lux reported by the IIO device `device`
+fn read_iio(device: &std::path::Path) -> RahmenResult<f32> {
+    if let Some(lux) = read_attribute(device, "in_illuminance_input")? {
+        return Ok(lux);
+    }
+    let raw = read_attribute(device, "in_illuminance_raw")?
+        .ok_or_else(|| sensor_err(format!("{:?} reports no illuminance", device)))?;
+    let scale = read_attribute(device, "in_illuminance_scale")?.unwrap_or(1.);
+    let offset = read_attribute(device, "in_illuminance_offset")?.unwrap_or(0.);
+    Ok((raw + offset) * scale)
+}
+
+/// The illuminance in lux for the readings of the broadband and the infrared channel of a TSL2561,
+/// at an integration time of 402 ms and a gain of 16
+#[cfg(feature = "i2c")]
+fn tsl2561_lux(broadband: f32, infrared: f32) -> f32 {
+    if broadband <= 0. {
+        return 0.;
+    }
+    let ratio = infrared / broadband;
+    let lux = if ratio <= 0.5 {
+        0.0304 * broadband - 0.062 * broadband * ratio.powf(1.4)
+    } else if ratio <= 0.61 {
+        0.0224 * broadband - 0.031 * infrared
+    } else if ratio <= 0.8 {
+        0.0128 * broadband - 0.0153 * infrared
+    } else if ratio <= 1.3 {
+        0.00146 * broadband - 0.00112 * infrared
+    } else {
+        0.
+    };
+    lux.max(0.)
+}
+
+/// An open light sensor
+enum Reader {
+    Iio(PathBuf),
+    #[cfg(feature = "i2c")]
+    Tsl2561(rppal::i2c::I2c),
+    #[cfg(feature = "i2c")]
+    Veml7700(rppal::i2c::I2c),
+}
+
+impl Reader {
+    #[cfg(feature = "i2c")]
+    fn open(sensor: &Sensor) -> RahmenResult<Self> {
+        let i2c = |bus, address| -> RahmenResult<rppal::i2c::I2c> {
+            let mut i2c = rppal::i2c::I2c::with_bus(bus).map_err(sensor_err)?;
+            i2c.set_slave_address(address).map_err(sensor_err)?;
+            Ok(i2c)
+        };
+        Ok(match sensor {
+            Sensor::Iio(device) => Reader::Iio(device.clone()),
+            Sensor::Tsl2561(bus, address) => {
+                let i2c = i2c(*bus, *address)?;
+                // power on, and integrate for 402 ms at a gain of 1
+                i2c.smbus_write_byte(0x80, 0x03).map_err(sensor_err)?;
+                i2c.smbus_write_byte(0x81, 0x02).map_err(sensor_err)?;
+                Reader::Tsl2561(i2c)
+            }
+            Sensor::Veml7700(bus, address) => {
+                let i2c = i2c(*bus, *address)?;
+                // power on, and integrate for 100 ms at a gain of 1
+                i2c.smbus_write_word(0x00, 0x0000).map_err(sensor_err)?;
+                Reader::Veml7700(i2c)
+            }
+        })
+    }
+
+    #[cfg(not(feature = "i2c"))]
+    fn open(sensor: &Sensor) -> RahmenResult<Self> {
+        match sensor {
+            Sensor::Iio(device) => Ok(Reader::Iio(device.clone())),
+            _ => Err(RahmenError::Unsupported(
+                "compiled without I2C support, cannot read the light sensor".into(),
+            )),
+        }
+    }
+
+    /// The illuminance in lux
+    fn read(&mut self) -> RahmenResult<f32> {
+        match self {
+            Reader::Iio(device) => read_iio(device),
+            #[cfg(feature = "i2c")]
+            Reader::Tsl2561(i2c) => {
+                let broadband = i2c.smbus_read_word(0xac).map_err(sensor_err)?;
+                let infrared = i2c.smbus_read_word(0xae).map_err(sensor_err)?;
+                // scale the readings from a gain of 1 to 16
+                Ok(tsl2561_lux(
+                    f32::from(broadband) * 16.,
+                    f32::from(infrared) * 16.,
+                ))
+            }
+            #[cfg(feature = "i2c")]
+            Reader::Veml7700(i2c) => {
+                let count = i2c.smbus_read_word(0x04).map_err(sensor_err)?;
+                Ok(f32::from(count) * 0.0576)
+            }
+        }
+    }
+}
+
+impl Sensor {
+    /// Read the sensor every `interval` in a background thread, and send the illuminance in lux to
+    /// `sender`. The thread stops once the receiver is gone.
+    pub fn spawn(self, interval: Duration, sender: Sender<f32>) -> RahmenResult<()> {
+        let mut reader = Reader::open(&self)?;
+        std::thread::spawn(move || loop {
+            match reader.read() {
+                Ok(lux) => {
+                    if sender.send(lux).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!("Failed to read the light sensor: {}", e),
+            }
+            std::thread::sleep(interval);
+        });
+        Ok(())
+    }
+}
+
+/// Maps the light in the room to a brightness factor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ambient {
+    /// the illuminance at and below which the brightness is least, in lux
+    dark: f32,
+    /// the illuminance at and above which the brightness is full, in lux
+    bright: f32,
+    /// the least brightness factor
+    min_brightness: f32,
+    /// the smoothed illuminance, once read
+    lux: Option<f32>,
+}
+
+impl Ambient {
+    /// Map illuminances from `dark` to `bright` lux to brightness factors from `min_brightness`
+    /// to 1
+    pub fn new(dark: f32, bright: f32, min_brightness: f32) -> Self {
+        Self {
+            dark: dark.max(0.01),
+            bright: bright.max(dark.max(0.01) * 1.01),
+            min_brightness: min_brightness.max(0.).min(1.),
+            lux: None,
+        }
+    }
+
+    /// Take a reading of `lux` into account
+    pub fn update(&mut self, lux: f32) {
+        let lux = lux.max(0.);
+        self.lux = Some(match self.lux {
+            Some(smoothed) => smoothed + SMOOTHING * (lux - smoothed),
+            None => lux,
+        });
+    }
+
+    /// The brightness factor for the light in the room, full until the sensor was read
+    pub fn brightness(&self) -> f32 {
+        let lux = match self.lux {
+            Some(lux) => lux.max(self.dark).min(self.bright),
+            None => return 1.,
+        };
+        let position = (lux.ln() - self.dark.ln()) / (self.bright.ln() - self.dark.ln());
+        let factor = self.min_brightness + (1. - self.min_brightness) * position;
+        ((factor / STEP).round() * STEP)
+            .max(self.min_brightness)
+            .min(1.)
+    }
+}
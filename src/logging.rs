@@ -0,0 +1,107 @@
+//! Set up logging
+//!
+//! What is logged is selected with the `RAHMEN_LOG` environment variable, in the syntax of
+//! `tracing_subscriber::EnvFilter`, such as `debug` or `info,rahmen::dataflow=debug`, and
+//! defaults to `info`. At the `debug` level, the time taken by each step of loading and rendering
+//! an image is logged. Setting `RAHMEN_LOG_FORMAT=json` logs one JSON object per line instead of
+//! text, for log collectors.
+//!
+//! Logs go to the standard output, or are appended to the file named by `RAHMEN_LOG_FILE`, which
+//! the daily maintenance rotates.
+
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tracing::warn;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// The environment variable selecting what is logged
+pub const FILTER_VARIABLE: &str = "RAHMEN_LOG";
+/// The environment variable selecting the format, `text` or `json`
+pub const FORMAT_VARIABLE: &str = "RAHMEN_LOG_FORMAT";
+/// The environment variable naming the file to log to, instead of the standard output
+pub const FILE_VARIABLE: &str = "RAHMEN_LOG_FILE";
+
+/// The log file, shared by all threads logging
+struct LogFile(Arc<File>);
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        (&*self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        (&*self.0).flush()
+    }
+}
+
+/// Install the global logger, configured from the environment
+pub fn init() {
+    match std::env::var_os(FILE_VARIABLE) {
+        Some(path) => match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => {
+                let file = Arc::new(file);
+                install(move || LogFile(Arc::clone(&file)), false);
+            }
+            Err(e) => {
+                install(std::io::stdout, true);
+                warn!("Cannot log to {:?}: {}", path, e);
+            }
+        },
+        None => install(std::io::stdout, true),
+    }
+}
+
+/// Install the global logger, writing to the writers made by `make_writer`, with colors if `ansi`
+/// is set
+fn install<W: MakeWriter + Send + Sync + 'static>(make_writer: W, ansi: bool) {
+    let filter =
+        EnvFilter::try_from_env(FILTER_VARIABLE).unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        // report the duration of the spans around the rendering steps when they close
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(make_writer)
+        .with_ansi(ansi);
+    match std::env::var(FORMAT_VARIABLE).as_deref() {
+        Ok("json") => builder.json().init(),
+        _ => builder.init(),
+    }
+}
+
+/// The `index`th rotated copy of the log file at `path`, such as `rahmen.log.1`
+fn rotated(path: &OsString, index: usize) -> PathBuf {
+    let mut rotated = path.clone();
+    rotated.push(format!(".{}", index));
+    rotated.into()
+}
+
+/// Rotate the log file, if logging to one: it becomes the first of `kept` older copies, the
+/// oldest of which is removed. The file is logged to until the process exits, so this is done
+/// right before restarting.
+pub fn rotate(kept: usize) -> std::io::Result<()> {
+    let path = match std::env::var_os(FILE_VARIABLE) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let ignore_missing = |result: std::io::Result<()>| match result {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    };
+    if kept == 0 {
+        return ignore_missing(std::fs::remove_file(&path));
+    }
+    ignore_missing(std::fs::remove_file(rotated(&path, kept)))?;
+    for index in (1..kept).rev() {
+        ignore_missing(std::fs::rename(
+            rotated(&path, index),
+            rotated(&path, index + 1),
+        ))?;
+    }
+    ignore_missing(std::fs::rename(&path, rotated(&path, 1)))
+}
@@ -0,0 +1,80 @@
+//! Postprocess the status line with a Lua script
+//!
+//! This is a lighter alternative to the Python hook, with the same contract: it is configured as a
+//! module, which is looked up on a search path, and which has a function `export`. That function
+//! is called once and returns a function, which is called for each image with a list of the
+//! metadata items and the separator, and returns the processed list of items. The module may
+//! return a table with `export`, or define it globally. Lua support is behind the `lua` feature;
+//! the interpreter is built in, so no runtime needs to be installed.
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// A Lua function postprocessing the items of the status line
+pub struct LuaPostprocess {
+    #[cfg(feature = "lua")]
+    lua: mlua::Lua,
+    #[cfg(feature = "lua")]
+    function: mlua::RegistryKey,
+}
+
+impl std::fmt::Debug for LuaPostprocess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LuaPostprocess").finish()
+    }
+}
+
+#[cfg(feature = "lua")]
+fn lua_err(e: mlua::Error) -> RahmenError {
+    RahmenError::LuaError(e.to_string())
+}
+
+impl LuaPostprocess {
+    /// Require `module`, looking in the directories `paths` before the default Lua search path, and
+    /// obtain the postprocessing function from its `export` function
+    #[cfg(feature = "lua")]
+    pub fn load(module: &str, paths: &[String]) -> RahmenResult<Self> {
+        let lua = mlua::Lua::new();
+        let function = {
+            let package: mlua::Table = lua.globals().get("package").map_err(lua_err)?;
+            let default_path: String = package.get("path").map_err(lua_err)?;
+            let search_path = paths
+                .iter()
+                .map(|path| format!("{}/?.lua;", path))
+                .collect::<String>()
+                + &default_path;
+            package.set("path", search_path).map_err(lua_err)?;
+            let require: mlua::Function = lua.globals().get("require").map_err(lua_err)?;
+            let loaded: mlua::Value = require.call(module).map_err(lua_err)?;
+            let export: mlua::Function = match loaded {
+                mlua::Value::Table(table) => table.get("export"),
+                _ => lua.globals().get("export"),
+            }
+            .map_err(lua_err)?;
+            let function: mlua::Function = export.call(()).map_err(lua_err)?;
+            lua.create_registry_value(function).map_err(lua_err)?
+        };
+        Ok(Self { lua, function })
+    }
+
+    /// Require `module`, which needs Lua support
+    #[cfg(not(feature = "lua"))]
+    pub fn load(module: &str, _paths: &[String]) -> RahmenResult<Self> {
+        Err(RahmenError::Unsupported(format!(
+            "compiled without Lua support, cannot require {:?}",
+            module
+        )))
+    }
+
+    /// Postprocess the items of the status line, joined with `separator` afterwards
+    #[cfg(feature = "lua")]
+    pub fn call(&self, items: &[String], separator: &str) -> RahmenResult<Vec<String>> {
+        let function: mlua::Function = self.lua.registry_value(&self.function).map_err(lua_err)?;
+        function.call((items.to_vec(), separator)).map_err(lua_err)
+    }
+
+    /// Postprocess the items of the status line, which needs Lua support
+    #[cfg(not(feature = "lua"))]
+    pub fn call(&self, items: &[String], _separator: &str) -> RahmenResult<Vec<String>> {
+        Ok(items.to_vec())
+    }
+}
@@ -0,0 +1,112 @@
+//! Access to the Exif, IPTC and XMP metadata of images
+//!
+//! With the `metadata` feature, this is rexiv2, which needs the gexiv2 and exiv2 C libraries.
+//! Without it, images read as if they had no metadata: tags are missing, sizes are zero and there
+//! is no position or thumbnail, while writing ratings or orientations fails.
+
+#[cfg(feature = "metadata")]
+pub use rexiv2::{GpsInfo, Metadata};
+
+#[cfg(not(feature = "metadata"))]
+pub use self::stub::{GpsInfo, Metadata};
+
+#[cfg(not(feature = "metadata"))]
+mod stub {
+    use std::ffi::OsStr;
+
+    use crate::errors::{RahmenError, RahmenResult};
+
+    /// The error reading or writing metadata without support for it
+    fn unsupported<T>() -> RahmenResult<T> {
+        Err(RahmenError::Unsupported(
+            "compiled without metadata support".into(),
+        ))
+    }
+
+    /// Where an image was taken
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct GpsInfo {
+        /// degrees east
+        pub longitude: f64,
+        /// degrees north
+        pub latitude: f64,
+        /// meters above sea level
+        pub altitude: f64,
+    }
+
+    /// The metadata of an image, always empty
+    #[derive(Debug)]
+    pub struct Metadata(());
+
+    impl Metadata {
+        /// Open the metadata of the image at `path`
+        pub fn new_from_path<S: AsRef<OsStr>>(_path: S) -> RahmenResult<Self> {
+            Ok(Self(()))
+        }
+
+        /// The value of `tag`, never present
+        pub fn get_tag_string(&self, _tag: &str) -> RahmenResult<String> {
+            unsupported()
+        }
+
+        /// The value of `tag` for display, never present
+        pub fn get_tag_interpreted_string(&self, _tag: &str) -> RahmenResult<String> {
+            unsupported()
+        }
+
+        /// The values of `tag`, never present
+        pub fn get_tag_multiple_strings(&self, _tag: &str) -> RahmenResult<Vec<String>> {
+            unsupported()
+        }
+
+        /// The numeric value of `tag`, always zero
+        pub fn get_tag_numeric(&self, _tag: &str) -> i32 {
+            0
+        }
+
+        /// Whether `tag` is present, which it never is
+        pub fn has_tag(&self, _tag: &str) -> bool {
+            false
+        }
+
+        /// The XMP tags present, never any
+        pub fn get_xmp_tags(&self) -> RahmenResult<Vec<String>> {
+            unsupported()
+        }
+
+        /// The width of the image, unknown and so zero
+        pub fn get_pixel_width(&self) -> i32 {
+            0
+        }
+
+        /// The height of the image, unknown and so zero
+        pub fn get_pixel_height(&self) -> i32 {
+            0
+        }
+
+        /// Where the image was taken, never known
+        pub fn get_gps_info(&self) -> Option<GpsInfo> {
+            None
+        }
+
+        /// The embedded thumbnail, never present
+        pub fn get_thumbnail(&self) -> Option<&[u8]> {
+            None
+        }
+
+        /// Set `tag` to a number, which fails
+        pub fn set_tag_numeric(&self, _tag: &str, _value: i32) -> RahmenResult<()> {
+            unsupported()
+        }
+
+        /// Set `tag` to a string, which fails
+        pub fn set_tag_string(&self, _tag: &str, _value: &str) -> RahmenResult<()> {
+            unsupported()
+        }
+
+        /// Write the metadata to the file at `path`, which fails
+        pub fn save_to_file<S: AsRef<OsStr>>(&self, _path: S) -> RahmenResult<()> {
+            unsupported()
+        }
+    }
+}
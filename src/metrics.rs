@@ -0,0 +1,208 @@
+//! Metrics for monitoring the slideshow with Prometheus
+//!
+//! The counters are shared between the dataflow, the prefetcher and the main loop, and reported in
+//! the Prometheus text format, either at `/metrics` on a separate address, or written to a file
+//! for the textfile collector of the node exporter. The file is replaced atomically, so the
+//! collector never reads it half written.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::errors::{RahmenError, RahmenResult};
+use crate::http_server::{self, Limits, Response};
+
+/// The upper bounds of the buckets of the decode duration histogram, in seconds
+const DECODE_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30.];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// the number of observations up to each bound of `DECODE_BUCKETS`
+    buckets: [u64; DECODE_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(&DECODE_BUCKETS) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    images_shown: u64,
+    decode_seconds: Histogram,
+    decode_failures: BTreeMap<&'static str, u64>,
+    provider_exhausted: u64,
+}
+
+/// The metrics of a slideshow, which can be cloned to count from several places
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl Metrics {
+    /// Count an image shown
+    pub fn image_shown(&self) {
+        self.counters.lock().unwrap().images_shown += 1;
+    }
+
+    /// Record the time it took to load and decode an image
+    pub fn image_decoded(&self, duration: Duration) {
+        self.counters
+            .lock()
+            .unwrap()
+            .decode_seconds
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Count an image that could not be loaded, by the reason of `error`
+    pub fn decode_failed(&self, error: &RahmenError) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .decode_failures
+            .entry(failure_reason(error))
+            .or_default() += 1;
+    }
+
+    /// Count a provider running out of images
+    pub fn provider_exhausted(&self) {
+        self.counters.lock().unwrap().provider_exhausted += 1;
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut text = String::new();
+        // writing to a string cannot fail
+        let _ = writeln!(
+            text,
+            "# HELP rahmen_images_shown_total Images shown.\n\
+             # TYPE rahmen_images_shown_total counter\n\
+             rahmen_images_shown_total {}",
+            counters.images_shown
+        );
+
+        let _ = writeln!(
+            text,
+            "# HELP rahmen_image_decode_seconds Time taken to load and decode an image.\n\
+             # TYPE rahmen_image_decode_seconds histogram"
+        );
+        let histogram = &counters.decode_seconds;
+        for (bound, count) in DECODE_BUCKETS.iter().zip(&histogram.buckets) {
+            let _ = writeln!(
+                text,
+                "rahmen_image_decode_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            );
+        }
+        let _ = writeln!(
+            text,
+            "rahmen_image_decode_seconds_bucket{{le=\"+Inf\"}} {}\n\
+             rahmen_image_decode_seconds_sum {}\n\
+             rahmen_image_decode_seconds_count {}",
+            histogram.count, histogram.sum, histogram.count
+        );
+
+        let _ = writeln!(
+            text,
+            "# HELP rahmen_image_decode_failures_total Images that failed to load.\n\
+             # TYPE rahmen_image_decode_failures_total counter"
+        );
+        for (reason, count) in &counters.decode_failures {
+            let _ = writeln!(
+                text,
+                "rahmen_image_decode_failures_total{{reason=\"{}\"}} {}",
+                reason, count
+            );
+        }
+
+        let _ = writeln!(
+            text,
+            "# HELP rahmen_provider_exhausted_total Times the input ran out of images.\n\
+             # TYPE rahmen_provider_exhausted_total counter\n\
+             rahmen_provider_exhausted_total {}",
+            counters.provider_exhausted
+        );
+
+        if let Some(bytes) = resident_bytes() {
+            let _ = writeln!(
+                text,
+                "# HELP rahmen_resident_memory_bytes Resident memory size.\n\
+                 # TYPE rahmen_resident_memory_bytes gauge\n\
+                 rahmen_resident_memory_bytes {}",
+                bytes
+            );
+        }
+        text
+    }
+
+    /// Write the metrics to the file at `path`, replacing it atomically
+    pub fn write_textfile(&self, path: &Path) -> RahmenResult<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, self.render())?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// The reason an image could not be loaded, as a label value
+pub fn failure_reason(error: &RahmenError) -> &'static str {
+    match error {
+        RahmenError::ImageError(error) => match error.as_ref() {
+            image::ImageError::Decoding(_) => "corrupt",
+            image::ImageError::Unsupported(_) => "unsupported",
+            image::ImageError::Limits(_) => "too_large",
+            image::ImageError::IoError(_) => "io",
+            _ => "image",
+        },
+        RahmenError::IoError(error) if error.kind() == std::io::ErrorKind::NotFound => "not_found",
+        RahmenError::IoError(_) => "io",
+        RahmenError::DecodeError(_) => "corrupt",
+        RahmenError::TranscodeError(_) => "transcode",
+        _ => "other",
+    }
+}
+
+/// The resident memory of this process, in bytes, if the kernel reports it
+fn resident_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Serve `metrics` at `/metrics` on `address`, such as `0.0.0.0:9185`, in a background thread
+pub fn serve(address: &str, metrics: Metrics) -> RahmenResult<()> {
+    let address = http_server::serve(address, Limits::default(), move |request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") => {
+                Response::new("200 OK", "text/plain; version=0.0.4", metrics.render())
+            }
+            _ => Response::new("404 Not Found", "text/plain", "not found\n"),
+        }
+    })?;
+    info!("Metrics on http://{}/metrics", address);
+    Ok(())
+}
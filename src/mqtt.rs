@@ -0,0 +1,577 @@
+//! Integrate with home automation over MQTT
+//!
+//! Rahmen publishes the image shown, its status line and whether the slideshow is paused as JSON
+//! to `<topic>/state`, and follows commands published to `<topic>/set/<command>`: `next`,
+//! `previous`, `favorite`, `blacklist`, `rotate` (`left` to turn counterclockwise), `pause` (`ON`,
+//! `OFF` or anything else to toggle), `show` (a path, or an HTTP or HTTPS URL to download),
+//! `interval` (seconds) and `brightness` (percent). With Home Assistant's MQTT discovery, the frame
+//! appears as a device with buttons, switches, sliders and sensors, without configuring anything
+//! in Home Assistant. MQTT support is behind the `mqtt` feature.
+//!
+//! Images to show are downloaded one after the other on a thread of their own, so the connection
+//! to the broker is kept alive meanwhile, into `rahmen/downloads` in the user's cache directory.
+
+use std::sync::mpsc::Sender;
+
+#[cfg(feature = "mqtt")]
+use std::collections::hash_map::DefaultHasher;
+#[cfg(feature = "mqtt")]
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "mqtt")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "mqtt")]
+use std::time::Duration;
+
+#[cfg(feature = "mqtt")]
+use rumqttc::{Client, LastWill, Packet, QoS};
+#[cfg(feature = "mqtt")]
+use tracing::{info, warn};
+
+use crate::config::MqttSettings;
+use crate::control::{Command, Status};
+use crate::errors::{RahmenError, RahmenResult};
+#[cfg(feature = "mqtt")]
+use crate::http_client;
+
+/// How long a download may take
+#[cfg(feature = "mqtt")]
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+/// The largest image downloaded
+#[cfg(feature = "mqtt")]
+const DOWNLOAD_LIMIT: u64 = 64 * 1024 * 1024;
+/// How many downloaded images are kept, so they can be shown again
+#[cfg(feature = "mqtt")]
+const DOWNLOADS_KEPT: usize = 32;
+
+/// Where to connect to, and how to announce the frame
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    /// host name of the broker
+    pub host: String,
+    /// port of the broker
+    pub port: u16,
+    /// the client id, which also identifies the device in Home Assistant
+    pub client_id: String,
+    /// user name and password, if the broker needs them
+    pub credentials: Option<(String, String)>,
+    /// the topic below which to publish the state and receive commands
+    pub topic: String,
+    /// the prefix of Home Assistant's discovery topics, if the frame is to be announced
+    pub discovery_prefix: Option<String>,
+    /// the name of the device in Home Assistant
+    pub name: String,
+}
+
+/// Check that `topic`, the one of `setting`, can be published to and subscribed below
+fn check_topic(setting: &str, topic: &str) -> RahmenResult<()> {
+    if topic.is_empty() || topic.starts_with('/') || topic.ends_with('/') {
+        return Err(RahmenError::MqttError(format!(
+            "{} {:?} must not be empty or start or end with /",
+            setting, topic
+        )));
+    }
+    if topic.contains(|c| c == '+' || c == '#') {
+        return Err(RahmenError::MqttError(format!(
+            "{} {:?} must not contain the wildcards + or #",
+            setting, topic
+        )));
+    }
+    Ok(())
+}
+
+impl MqttConfig {
+    /// The configuration given by `settings`, checked, and filled in with the defaults. Fails
+    /// without MQTT support.
+    pub fn new(settings: MqttSettings) -> RahmenResult<Self> {
+        if cfg!(not(feature = "mqtt")) {
+            return Err(RahmenError::Unsupported(format!(
+                "compiled without MQTT support, cannot connect to {}",
+                settings.host
+            )));
+        }
+        if settings.host.is_empty() {
+            return Err(RahmenError::MqttError("the host is empty".into()));
+        }
+        let credentials = match (settings.username, settings.password) {
+            (Some(user), Some(password)) => Some((user, password)),
+            (None, None) => None,
+            _ => {
+                return Err(RahmenError::MqttError(
+                    "the user name and the password must be given together".into(),
+                ))
+            }
+        };
+        let topic = settings.topic.unwrap_or_else(|| "rahmen".into());
+        check_topic("topic", &topic)?;
+        let discovery_prefix = if settings.discovery.unwrap_or(true) {
+            let prefix = settings
+                .discovery_prefix
+                .unwrap_or_else(|| "homeassistant".into());
+            check_topic("discovery_prefix", &prefix)?;
+            Some(prefix)
+        } else {
+            None
+        };
+        let client_id = settings.client_id.unwrap_or_else(|| "rahmen".into());
+        if client_id.is_empty() {
+            return Err(RahmenError::MqttError("the client id is empty".into()));
+        }
+        Ok(Self {
+            host: settings.host,
+            port: settings.port.unwrap_or(1883),
+            client_id,
+            credentials,
+            topic,
+            discovery_prefix,
+            name: settings.name.unwrap_or_else(|| "Rahmen".into()),
+        })
+    }
+}
+
+/// A connection to an MQTT broker
+pub struct Mqtt {
+    #[cfg(feature = "mqtt")]
+    client: Client,
+    #[cfg(feature = "mqtt")]
+    topic: String,
+    /// the state published last, to publish changes only
+    #[cfg(feature = "mqtt")]
+    published: Option<serde_json::Value>,
+}
+
+impl std::fmt::Debug for Mqtt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mqtt").finish()
+    }
+}
+
+impl Mqtt {
+    /// Connect to the broker in a background thread, which reconnects when the connection is
+    /// lost, and send the commands received to `commands`
+    #[cfg(feature = "mqtt")]
+    pub fn connect(config: MqttConfig, commands: Sender<Command>) -> RahmenResult<Self> {
+        let mut options = rumqttc::MqttOptions::new(&config.client_id, &config.host, config.port);
+        if let Some((user, password)) = &config.credentials {
+            options.set_credentials(user, password);
+        }
+        options.set_last_will(LastWill::new(
+            availability_topic(&config.topic),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        let (client, mut connection) = Client::new(options, 64);
+        let mut announcer = client.clone();
+        let topic = config.topic.clone();
+        let downloads = Downloads::start(commands.clone())?;
+        std::thread::spawn(move || {
+            for notification in connection.iter() {
+                match notification {
+                    // subscriptions and announcements are renewed with every connection
+                    Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to MQTT broker {}", config.host);
+                        if let Err(e) = announce(&mut announcer, &config) {
+                            warn!("MQTT announcement failed: {}", e);
+                        }
+                    }
+                    Ok(rumqttc::Event::Incoming(Packet::Publish(publish))) => {
+                        let payload = String::from_utf8_lossy(&publish.payload);
+                        match command(&config.topic, &publish.topic, &payload, &downloads) {
+                            Ok(Some(command)) => {
+                                if commands.send(command).is_err() {
+                                    break;
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => warn!("MQTT command {} failed: {}", publish.topic, e),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("MQTT connection failed: {}", e);
+                        std::thread::sleep(Duration::from_secs(5));
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            client,
+            topic,
+            published: None,
+        })
+    }
+
+    /// Connect to the broker, which needs MQTT support
+    #[cfg(not(feature = "mqtt"))]
+    pub fn connect(config: MqttConfig, _commands: Sender<Command>) -> RahmenResult<Self> {
+        Err(RahmenError::Unsupported(format!(
+            "compiled without MQTT support, cannot connect to {}",
+            config.host
+        )))
+    }
+
+    /// Publish the state of the slideshow, if it changed
+    #[cfg(feature = "mqtt")]
+    pub fn publish_state(&mut self, status: &Status) {
+        let state = serde_json::json!({
+            "path": status.path,
+            "caption": status.status_line.join(" "),
+            "paused": status.paused,
+            "interval": status.interval.as_secs_f64(),
+            "brightness": status.brightness.unwrap_or(100),
+        });
+        if self.published.as_ref() == Some(&state) {
+            return;
+        }
+        // never block the main loop; the state is published again while the broker is away
+        if self
+            .client
+            .try_publish(
+                format!("{}/state", self.topic),
+                QoS::AtLeastOnce,
+                true,
+                state.to_string(),
+            )
+            .is_ok()
+        {
+            self.published = Some(state);
+        }
+    }
+
+    /// Publish the state of the slideshow, which needs MQTT support
+    #[cfg(not(feature = "mqtt"))]
+    pub fn publish_state(&mut self, _status: &Status) {}
+}
+
+#[cfg(feature = "mqtt")]
+fn availability_topic(topic: &str) -> String {
+    format!("{}/availability", topic)
+}
+
+/// Subscribe to the commands, and announce the frame
+#[cfg(feature = "mqtt")]
+fn announce(client: &mut Client, config: &MqttConfig) -> Result<(), rumqttc::ClientError> {
+    client.subscribe(format!("{}/set/+", config.topic), QoS::AtLeastOnce)?;
+    client.publish(
+        availability_topic(&config.topic),
+        QoS::AtLeastOnce,
+        true,
+        "online",
+    )?;
+    if let Some(prefix) = &config.discovery_prefix {
+        for (component, id, entity) in entities(config) {
+            client.publish(
+                format!(
+                    "{}/{}/{}/{}/config",
+                    prefix, component, config.client_id, id
+                ),
+                QoS::AtLeastOnce,
+                true,
+                entity.to_string(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The entities of the frame for Home Assistant's discovery, with their components and ids
+#[cfg(feature = "mqtt")]
+fn entities(config: &MqttConfig) -> Vec<(&'static str, &'static str, serde_json::Value)> {
+    let topic = &config.topic;
+    let state = format!("{}/state", topic);
+    let device = serde_json::json!({
+        "identifiers": [config.client_id],
+        "name": config.name,
+        "manufacturer": "Rahmen",
+        "model": "Rahmen",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+    let entity = |id: &str, name: &str, mut fields: serde_json::Value| {
+        fields["name"] = format!("{} {}", config.name, name).into();
+        fields["unique_id"] = format!("{}_{}", config.client_id, id).into();
+        fields["availability_topic"] = availability_topic(topic).into();
+        fields["device"] = device.clone();
+        fields
+    };
+    vec![
+        (
+            "button",
+            "next",
+            entity(
+                "next",
+                "next image",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/next", topic),
+                    "icon": "mdi:skip-next",
+                }),
+            ),
+        ),
+        (
+            "button",
+            "previous",
+            entity(
+                "previous",
+                "previous image",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/previous", topic),
+                    "icon": "mdi:skip-previous",
+                }),
+            ),
+        ),
+        (
+            "button",
+            "favorite",
+            entity(
+                "favorite",
+                "favorite",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/favorite", topic),
+                    "icon": "mdi:star",
+                }),
+            ),
+        ),
+        (
+            "button",
+            "blacklist",
+            entity(
+                "blacklist",
+                "never show again",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/blacklist", topic),
+                    "icon": "mdi:image-remove",
+                }),
+            ),
+        ),
+        (
+            "button",
+            "rotate",
+            entity(
+                "rotate",
+                "rotate clockwise",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/rotate", topic),
+                    "icon": "mdi:rotate-right",
+                }),
+            ),
+        ),
+        (
+            "switch",
+            "pause",
+            entity(
+                "pause",
+                "paused",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/pause", topic),
+                    "state_topic": state,
+                    "value_template": "{{ 'ON' if value_json.paused else 'OFF' }}",
+                    "icon": "mdi:pause",
+                }),
+            ),
+        ),
+        (
+            "number",
+            "brightness",
+            entity(
+                "brightness",
+                "brightness",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/brightness", topic),
+                    "state_topic": state,
+                    "value_template": "{{ value_json.brightness }}",
+                    "min": 0,
+                    "max": 100,
+                    "unit_of_measurement": "%",
+                    "icon": "mdi:brightness-6",
+                }),
+            ),
+        ),
+        (
+            "number",
+            "interval",
+            entity(
+                "interval",
+                "interval",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/interval", topic),
+                    "state_topic": state,
+                    "value_template": "{{ value_json.interval }}",
+                    "min": 1,
+                    "max": 3600,
+                    "unit_of_measurement": "s",
+                    "icon": "mdi:timer-outline",
+                }),
+            ),
+        ),
+        (
+            "text",
+            "show",
+            entity(
+                "show",
+                "show image",
+                serde_json::json!({
+                    "command_topic": format!("{}/set/show", topic),
+                    "icon": "mdi:image-search",
+                }),
+            ),
+        ),
+        (
+            "sensor",
+            "image",
+            entity(
+                "image",
+                "image",
+                serde_json::json!({
+                    "state_topic": state,
+                    "value_template": "{{ value_json.path }}",
+                    "icon": "mdi:image",
+                }),
+            ),
+        ),
+        (
+            "sensor",
+            "caption",
+            entity(
+                "caption",
+                "caption",
+                serde_json::json!({
+                    "state_topic": state,
+                    "value_template": "{{ value_json.caption }}",
+                    "icon": "mdi:text",
+                }),
+            ),
+        ),
+    ]
+}
+
+/// The command published to `received`, if it is a command topic below `topic`. Images to
+/// download are requested from `downloads`, which shows them once they are there.
+#[cfg(feature = "mqtt")]
+fn command(
+    topic: &str,
+    received: &str,
+    payload: &str,
+    downloads: &Downloads,
+) -> RahmenResult<Option<Command>> {
+    let name = match received
+        .strip_prefix(topic)
+        .and_then(|rest| rest.strip_prefix("/set/"))
+    {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let payload = payload.trim();
+    let command = match name {
+        "next" => Command::Next,
+        "previous" => Command::Previous,
+        "favorite" => Command::Favorite,
+        "blacklist" => Command::Blacklist,
+        "rotate" => Command::Rotate(payload != "left"),
+        "pause" => match payload {
+            "ON" | "on" | "true" => Command::Pause,
+            "OFF" | "off" | "false" => Command::Resume,
+            _ => Command::TogglePause,
+        },
+        "show" if payload.starts_with("http://") || payload.starts_with("https://") => {
+            downloads.request(payload);
+            return Ok(None);
+        }
+        "show" => Command::Show(PathBuf::from(
+            payload.strip_prefix("file://").unwrap_or(payload),
+        )),
+        "interval" => {
+            let seconds: f64 = payload.parse()?;
+            if seconds <= 0. || !seconds.is_finite() {
+                return Err(RahmenError::MqttError(format!(
+                    "invalid interval {}",
+                    payload
+                )));
+            }
+            Command::SetInterval(Duration::from_secs_f64(seconds))
+        }
+        "brightness" => {
+            let percent: f64 = payload.parse()?;
+            Command::set_brightness(percent).map_err(RahmenError::MqttError)?
+        }
+        _ => {
+            return Err(RahmenError::MqttError(format!(
+                "unknown command {:?}",
+                name
+            )))
+        }
+    };
+    Ok(Some(command))
+}
+
+/// Downloads images to show, one after the other on a thread of their own
+#[cfg(feature = "mqtt")]
+struct Downloads {
+    urls: Sender<String>,
+}
+
+#[cfg(feature = "mqtt")]
+impl Downloads {
+    /// Start downloading, and send a command to show each image downloaded to `commands`
+    fn start(commands: Sender<Command>) -> RahmenResult<Self> {
+        let directory = crate::dirs::BaseDirectories::with_prefix("rahmen")
+            .map_err(|e| RahmenError::MqttError(e.to_string()))?
+            .create_cache_directory("downloads")?;
+        let (urls, requested) = std::sync::mpsc::channel::<String>();
+        std::thread::spawn(move || {
+            for url in requested {
+                info!("Downloading {}", url);
+                match download(&url, &directory) {
+                    Ok(path) => {
+                        if commands.send(Command::Show(path)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("Downloading {} failed: {}", url, e),
+                }
+            }
+        });
+        Ok(Self { urls })
+    }
+
+    /// Download the image at `url`, and show it once it is there
+    fn request(&self, url: &str) {
+        if self.urls.send(url.into()).is_err() {
+            warn!("Cannot download {}, the downloads stopped", url);
+        }
+    }
+}
+
+/// Download the image at `url` into `directory`, and return its path. The file is written under
+/// a temporary name first, so it's never read half-written, and only the latest downloads are kept.
+#[cfg(feature = "mqtt")]
+fn download(url: &str, directory: &Path) -> RahmenResult<PathBuf> {
+    let body = http_client::get(url, DOWNLOAD_TIMEOUT, DOWNLOAD_LIMIT)?;
+    // keep the name, so the format can be told by the extension, and tell URLs apart by their hash
+    let name = url
+        .split(|c| c == '?' || c == '#')
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("image");
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let name = format!("{:016x}-{}", hasher.finish(), name);
+    let file = directory.join(&name);
+    let partial = directory.join(format!(".{}.part", name));
+    std::fs::write(&partial, &body)?;
+    std::fs::rename(&partial, &file)?;
+    let mut downloads: Vec<_> = std::fs::read_dir(directory)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, entry.path()))
+        })
+        .filter(|(_, path)| !path.to_string_lossy().ends_with(".part"))
+        .collect();
+    downloads.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    for (_, old) in downloads.into_iter().skip(DOWNLOADS_KEPT) {
+        if let Err(e) = std::fs::remove_file(&old) {
+            warn!("Cannot remove the old download {:?}: {}", old, e);
+        }
+    }
+    Ok(file)
+}
@@ -0,0 +1,73 @@
+//! Show two portrait images side by side on a landscape screen
+//!
+//! A single portrait image leaves most of a landscape screen empty. When the image shown next and
+//! the one after it are both portraits, they can be joined into one frame instead, scaled to the
+//! same height, with a gap of the divider color between them.
+
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::provider_orientation::Orientation;
+use crate::rotate;
+
+/// How to join two portrait images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pairing {
+    /// the gap between the images, in pixels of the screen
+    pub gap: u32,
+    /// the color of the gap
+    pub divider: [u8; 3],
+    /// how much the sides of an image may differ for it to count as square, not portrait
+    pub tolerance: f64,
+}
+
+impl Pairing {
+    /// Whether the image at `path` is a portrait as shown, judging by its header
+    pub fn is_portrait(&self, path: &Path) -> bool {
+        rotate::dimensions(path).map_or(false, |(width, height)| {
+            Orientation::of(width, height, self.tolerance) == Orientation::Portrait
+        })
+    }
+
+    /// Whether a screen of size `screen` has room for two portraits side by side
+    pub fn fits(&self, (width, height): (u32, u32)) -> bool {
+        Orientation::of(width, height, self.tolerance) == Orientation::Landscape
+    }
+
+    /// Join `left` and `right` into one image, to be shown on a screen `screen_height` high
+    pub fn join(
+        &self,
+        left: &DynamicImage,
+        right: &DynamicImage,
+        screen_height: u32,
+    ) -> DynamicImage {
+        // scale to the lower height, so neither image is enlarged
+        let height = left.height().min(right.height()).max(1);
+        let scale = |img: &DynamicImage| {
+            if img.height() == height {
+                img.to_rgba8()
+            } else {
+                let width = (u64::from(img.width()) * u64::from(height) / u64::from(img.height()))
+                    .max(1) as u32;
+                img.resize_exact(width, height, FilterType::Triangle)
+                    .to_rgba8()
+            }
+        };
+        let (left, right) = (scale(left), scale(right));
+        // the joined image is scaled to the height of the screen, and the gap with it
+        let gap =
+            (u64::from(self.gap) * u64::from(height) / u64::from(screen_height.max(1))) as u32;
+        let [r, g, b] = self.divider;
+        let mut joined = RgbaImage::from_pixel(
+            left.width() + gap + right.width(),
+            height,
+            Rgba([r, g, b, 255]),
+        );
+        // the images fit by construction
+        let _ = joined.copy_from(&left, 0, 0);
+        let _ = joined.copy_from(&right, left.width() + gap, 0);
+        DynamicImage::ImageRgba8(joined)
+    }
+}
@@ -0,0 +1,156 @@
+//! Pixel formats of images and displays
+//!
+//! Loaders and processors work on 8-bit RGB images, or RGBA images where transparency matters,
+//! such as for the status line drawn over a photo. Displays convert images to their own layout
+//! only when drawing them: most keep a `BgraImage`, which matches common framebuffers, and write
+//! it out through a `PixelFormat`.
+
+#[cfg(feature = "framebuffer")]
+use framebuffer::VarScreeninfo;
+use image::{Bgra, DynamicImage, GrayImage, ImageBuffer, Luma, RgbImage};
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// An image with pixels in BGRA order, as kept by displays
+pub type BgraImage = ImageBuffer<Bgra<u8>, Vec<u8>>;
+
+/// Layout of a pixel in memory, with the `(offset, length)` in bits of each channel. Formats with
+/// one byte per pixel are grayscale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormat {
+    bytes_per_pixel: usize,
+    red: (u32, u32),
+    green: (u32, u32),
+    blue: (u32, u32),
+}
+
+impl PixelFormat {
+    /// 32-bit BGRA, the layout of `BgraImage`
+    pub const BGRA8: Self = Self {
+        bytes_per_pixel: 4,
+        red: (16, 8),
+        green: (8, 8),
+        blue: (0, 8),
+    };
+
+    /// 8-bit grayscale
+    pub const GRAY8: Self = Self {
+        bytes_per_pixel: 1,
+        red: (0, 8),
+        green: (0, 8),
+        blue: (0, 8),
+    };
+
+    /// Create a format from the number of bytes per pixel and the `(offset, length)` in bits of
+    /// each channel
+    pub fn new(
+        bytes_per_pixel: usize,
+        red: (u32, u32),
+        green: (u32, u32),
+        blue: (u32, u32),
+    ) -> RahmenResult<Self> {
+        let bits = bytes_per_pixel as u32 * 8;
+        if ![1, 2, 3, 4].contains(&bytes_per_pixel)
+            || [red, green, blue]
+                .iter()
+                .any(|(offset, length)| *length > 8 || offset + length > bits)
+        {
+            return Err(RahmenError::DisplayError(format!(
+                "unsupported pixel format: {} bits per pixel, RGB {:?} {:?} {:?}",
+                bits, red, green, blue
+            )));
+        }
+        Ok(Self {
+            bytes_per_pixel,
+            red,
+            green,
+            blue,
+        })
+    }
+
+    /// The format of a Linux framebuffer
+    #[cfg(feature = "framebuffer")]
+    pub fn from_screen_info(info: &VarScreeninfo) -> RahmenResult<Self> {
+        if info.bits_per_pixel == 8 {
+            return Ok(Self::GRAY8);
+        }
+        Self::new(
+            (info.bits_per_pixel / 8) as usize,
+            (info.red.offset, info.red.length),
+            (info.green.offset, info.green.length),
+            (info.blue.offset, info.blue.length),
+        )
+    }
+
+    /// The number of bytes of a pixel
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bytes_per_pixel
+    }
+
+    /// Whether the format matches `BgraImage`, so rows can be copied as they are
+    pub fn is_bgra(&self) -> bool {
+        *self == Self::BGRA8
+    }
+
+    /// Write an RGB color in this format to `out`, which holds exactly one pixel
+    pub fn pack(&self, [red, green, blue]: [u8; 3], out: &mut [u8]) {
+        if self.bytes_per_pixel == 1 {
+            out[0] = luma([red, green, blue]);
+            return;
+        }
+        let channel =
+            |value: u8, (offset, length): (u32, u32)| (u32::from(value) >> (8 - length)) << offset;
+        let packed = channel(red, self.red) | channel(green, self.green) | channel(blue, self.blue);
+        out.copy_from_slice(&packed.to_le_bytes()[..self.bytes_per_pixel]);
+    }
+
+    /// Write a BGRA pixel, as kept in `BgraImage`, in this format to `out`
+    pub fn pack_bgra(&self, pixel: &[u8], out: &mut [u8]) {
+        self.pack([pixel[2], pixel[1], pixel[0]], out)
+    }
+}
+
+/// Luma of an RGB color, after ITU-R BT.601
+pub fn luma([red, green, blue]: [u8; 3]) -> u8 {
+    ((u32::from(red) * 299 + u32::from(green) * 587 + u32::from(blue) * 114) / 1000) as u8
+}
+
+/// Obtain the pixels of an image as 8-bit RGB, without copying them if they already are
+pub fn into_rgb8(img: DynamicImage) -> RgbImage {
+    match img {
+        DynamicImage::ImageRgb8(buffer) => buffer,
+        img => img.to_rgb8(),
+    }
+}
+
+/// Dither a grayscale image to a number of evenly spaced gray levels using Floyd–Steinberg error
+/// diffusion
+pub fn dither(img: &GrayImage, gray_levels: u8) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    let step = 255. / (gray_levels.max(2) - 1) as f32;
+    let mut values: Vec<f32> = img.as_raw().iter().map(|v| *v as f32).collect();
+    let mut dithered = GrayImage::new(width, height);
+    for y in 0..h {
+        for x in 0..w {
+            let i = y * w + x;
+            let old = values[i];
+            let new = ((old / step).round() * step).max(0.).min(255.);
+            dithered.put_pixel(x as u32, y as u32, Luma([new as u8]));
+            let error = old - new;
+            if x + 1 < w {
+                values[i + 1] += error * 7. / 16.;
+            }
+            if y + 1 < h {
+                if x > 0 {
+                    values[i + w - 1] += error * 3. / 16.;
+                }
+                values[i + w] += error * 5. / 16.;
+                if x + 1 < w {
+                    values[i + w + 1] += error / 16.;
+                }
+            }
+        }
+    }
+    dithered
+}
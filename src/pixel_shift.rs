@@ -0,0 +1,47 @@
+//! Move the frame by a few pixels over time, against burn-in
+//!
+//! OLED and plasma panels wear unevenly when they show the same pixels for hours, which the status
+//! line and the clock do. The frame is moved by one pixel every interval, walking back and forth
+//! over all offsets up to `radius` pixels in each direction, row by row, so no edge stays put and
+//! no step is large enough to notice.
+
+use std::time::Duration;
+
+/// Moves the frame in small steps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelShift {
+    /// the most pixels to move the frame in each direction
+    pub radius: u32,
+    /// how long to keep the frame at each offset
+    pub interval: Duration,
+}
+
+impl PixelShift {
+    /// The offset of the frame at `now`
+    pub fn offset(&self, now: Duration) -> (i32, i32) {
+        let side = u64::from(2 * self.radius + 1);
+        let positions = side * side;
+        if positions == 1 || self.interval == Duration::default() {
+            return (0, 0);
+        }
+        let step = (now.as_millis() / self.interval.as_millis()) as u64 % (2 * (positions - 1));
+        // walk back the same way after reaching the last position
+        let index = if step < positions {
+            step
+        } else {
+            2 * (positions - 1) - step
+        };
+        let (row, column) = (index / side, index % side);
+        // every other row runs the other way, so each step moves by one pixel
+        let column = if row % 2 == 0 {
+            column
+        } else {
+            side - 1 - column
+        };
+        let radius = i64::from(self.radius);
+        (
+            (column as i64 - radius) as i32,
+            (row as i64 - radius) as i32,
+        )
+    }
+}
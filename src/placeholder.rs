@@ -0,0 +1,108 @@
+//! Show what went wrong, in place of an image that failed to load
+//!
+//! An image that can't be loaded is usually skipped, which goes unnoticed on a frame nobody watches
+//! the logs of. Instead, a placeholder can be shown for as long as the image would have been: either
+//! the name of the file and the error, or a fallback image. It is shown once several images in a
+//! row failed, so a single broken file is still skipped.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use image::{DynamicImage, GenericImage, RgbImage};
+use tracing::{info, warn};
+
+use crate::errors::{RahmenError, RahmenResult};
+use crate::font::{Alignment, FontRenderer, TextStyle};
+use crate::provider::load_image_from_path;
+
+/// The size of the frame drawn before the screen size is known
+const DEFAULT_SIZE: (u32, u32) = (1280, 720);
+
+/// The most lines of text drawn, after wrapping long errors
+const MAX_LINES: usize = 6;
+
+/// What to show in place of an image that failed to load
+#[derive(Debug)]
+pub enum Placeholder {
+    /// Draw the name of the file and the error
+    Text(FontRenderer),
+    /// Show a fallback image, loaded from `path` and limited to `max_size`
+    Image {
+        /// the file the image is loaded from
+        path: PathBuf,
+        /// the largest size to load the image at
+        max_size: Option<usize>,
+        /// the image loaded
+        image: Arc<DynamicImage>,
+    },
+}
+
+impl Placeholder {
+    /// Show the image at `path`, limited to `max_size` like the images of the slideshow
+    pub fn image<P: Into<PathBuf>>(path: P, max_size: Option<usize>) -> RahmenResult<Self> {
+        let path = path.into();
+        let image = Arc::new(load_image_from_path(&path, max_size)?);
+        Ok(Placeholder::Image {
+            path,
+            max_size,
+            image,
+        })
+    }
+
+    /// Load the font or the image again from its file. On failure, the current one is kept.
+    pub fn reload(&mut self) {
+        match self {
+            Placeholder::Text(renderer) => renderer.reload(),
+            Placeholder::Image {
+                path,
+                max_size,
+                image,
+            } => match load_image_from_path(path.as_path(), *max_size) {
+                Ok(loaded) => {
+                    info!("Reloaded placeholder {:?}", path);
+                    *image = Arc::new(loaded);
+                }
+                Err(e) => warn!("Failed to reload placeholder {:?}: {}", path, e),
+            },
+        }
+    }
+
+    /// The frame to show in place of the image at `path`, which failed to load with `error`, on
+    /// a screen of `screen` size, if known
+    pub fn frame(
+        &mut self,
+        path: &Path,
+        error: &RahmenError,
+        screen: Option<(u32, u32)>,
+    ) -> Arc<DynamicImage> {
+        let renderer = match self {
+            Placeholder::Text(renderer) => renderer,
+            Placeholder::Image { image, .. } => return Arc::clone(image),
+        };
+        let (width, height) = screen.unwrap_or(DEFAULT_SIZE);
+        let size = (height as f32 / 20.).max(12.);
+        let name = path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy());
+        let lines = [
+            "Cannot show".to_string(),
+            name.into_owned(),
+            error.to_string(),
+        ];
+        let text_height = (size * 1.05 * MAX_LINES as f32).ceil() as u32;
+        renderer.set_style(TextStyle {
+            align: Alignment::Center,
+            margin: width / 20,
+            max_lines: Some(MAX_LINES),
+            ..TextStyle::default()
+        });
+        let mut canvas = RgbImage::new(width, height);
+        if let Ok(text) =
+            renderer.render(lines.iter().map(String::as_str), size, (width, text_height))
+        {
+            // the text starts a third down the screen
+            let _ = canvas.copy_from(&text.to_rgb8(), 0, height / 3);
+        }
+        Arc::new(DynamicImage::ImageRgb8(canvas))
+    }
+}
@@ -0,0 +1,570 @@
+//! The playback of the slideshow
+//!
+//! The player decides when to advance to the next image, following the commands of all inputs,
+//! such as keys, buttons, the control socket or MQTT. It is a state machine: it plays, advancing
+//! after each interval, is paused, shows a single image requested by a command for a full
+//! interval before playing on, or waits for a transition to finish. It does not load or draw
+//! images itself, but returns effects for the main loop to apply, and reports its state to the
+//! shared status read by the control interfaces.
+//!
+//! Providers only go forward, so the player keeps a bounded history of the slides shown to go
+//! back through, whatever their order. A slide is all images shown at a time, such as a pair of
+//! portraits or a collage, the main one first. After going back, going forward, by command or when the
+//! interval passes, first retraces the history before the slideshow continues.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::control::{Command, SharedStatus};
+
+/// The state of the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerState {
+    /// Advancing after each interval
+    Playing,
+    /// Showing the current image until resumed
+    Paused,
+    /// Showing an image requested by a command for a full interval, then playing on
+    ShowingSingle,
+    /// Waiting for the transition to the current image to finish before advancing
+    Transitioning,
+}
+
+/// What the main loop must do for the player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    /// Show the next image of the slideshow
+    Advance,
+    /// Show this image next, and continue with the slideshow after it
+    Requeue(PathBuf),
+}
+
+/// Plays the slideshow
+#[derive(Debug)]
+pub struct Player {
+    state: PlayerState,
+    /// the state to return to once a transition finishes
+    after_transition: PlayerState,
+    /// how long images are shown
+    delay: Duration,
+    /// when to advance to the next image, relative to the start
+    next_image_at: Duration,
+    /// the images of the slide shown, the main one first, and since when
+    slide: Vec<PathBuf>,
+    slide_since: Duration,
+    /// the slides shown, the latest last
+    history: VecDeque<Vec<PathBuf>>,
+    /// the number of slides to keep in the history
+    capacity: usize,
+    /// the position in the history of the slide shown after going back, if any
+    position: Option<usize>,
+    /// the main image of the slide of the history requested, until it is shown
+    revisiting: Option<PathBuf>,
+    /// the image requested by `Show`, until it is shown
+    requested: Option<PathBuf>,
+    status: SharedStatus,
+}
+
+impl Player {
+    /// Create a player showing images for `delay`, advancing to the first image right away, and
+    /// remembering `capacity` slides to go back to. `status` reports the state of the player.
+    pub fn new(delay: Duration, capacity: usize, now: Duration, status: SharedStatus) -> Self {
+        Self {
+            state: PlayerState::Playing,
+            after_transition: PlayerState::Playing,
+            delay,
+            next_image_at: now,
+            slide: vec![],
+            slide_since: now,
+            history: VecDeque::new(),
+            capacity,
+            position: None,
+            revisiting: None,
+            requested: None,
+            status,
+        }
+    }
+
+    /// The state of the player
+    pub fn state(&self) -> PlayerState {
+        self.state
+    }
+
+    /// The state of the player, disregarding a transition in progress
+    fn settled(&self) -> PlayerState {
+        if self.state == PlayerState::Transitioning {
+            self.after_transition
+        } else {
+            self.state
+        }
+    }
+
+    /// Enter `state`, or enter it after a transition in progress
+    fn settle(&mut self, state: PlayerState) {
+        if self.settled() != state {
+            info!("Player: {:?}", state);
+        }
+        if self.state == PlayerState::Transitioning {
+            self.after_transition = state;
+        } else {
+            self.state = state;
+        }
+        self.status.lock().unwrap().paused = state == PlayerState::Paused;
+    }
+
+    /// The main image of the slide shown, if any
+    pub fn slide(&self) -> Option<&Path> {
+        self.slide.first().map(PathBuf::as_path)
+    }
+
+    /// All images of the slide shown, the main one first
+    pub fn slide_paths(&self) -> &[PathBuf] {
+        &self.slide
+    }
+
+    /// The slides shown, the latest last, each with its main image first
+    pub fn history(&self) -> impl Iterator<Item = &[PathBuf]> {
+        self.history.iter().map(Vec::as_slice)
+    }
+
+    /// Take up the `history` of a previous run, the latest slide last, to go back to
+    pub fn restore_history(&mut self, history: Vec<Vec<PathBuf>>) {
+        self.history = history
+            .into_iter()
+            .filter(|slide| !slide.is_empty())
+            .collect();
+        while self.history.len() > self.capacity.max(1) {
+            self.history.pop_front();
+        }
+    }
+
+    /// How much of the time to show the current image has passed, from 0 to 1
+    pub fn progress(&self, now: Duration) -> f32 {
+        let elapsed = now.checked_sub(self.slide_since).unwrap_or_default();
+        let total = self
+            .next_image_at
+            .checked_sub(self.slide_since)
+            .unwrap_or_default();
+        if !self.slide.is_empty() && !total.is_zero() {
+            elapsed.as_secs_f32() / total.as_secs_f32()
+        } else {
+            0.
+        }
+    }
+
+    /// Show the slide at `position` in the history
+    fn revisit(&mut self, position: usize) -> Vec<Effect> {
+        let paths = self.history[position].clone();
+        // the latest slide of the history is where the slideshow continues from
+        self.position = if position + 1 < self.history.len() {
+            Some(position)
+        } else {
+            None
+        };
+        self.revisiting = paths.first().cloned();
+        // requeued together, the images make up the same slide again
+        paths
+            .into_iter()
+            .map(Effect::Requeue)
+            .chain(Some(Effect::Advance))
+            .collect()
+    }
+
+    /// Go forward, through the history after going back, or else in the slideshow
+    fn forward(&mut self) -> Vec<Effect> {
+        match self.position {
+            Some(position) => self.revisit(position + 1),
+            None => vec![Effect::Advance],
+        }
+    }
+
+    /// Advance to the next image if its time has come
+    pub fn poll(&mut self, now: Duration) -> Vec<Effect> {
+        match self.state {
+            PlayerState::Playing | PlayerState::ShowingSingle if self.next_image_at < now => {
+                self.settle(PlayerState::Playing);
+                self.next_image_at = now + self.delay;
+                self.forward()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Follow a playback command. Other commands are ignored.
+    pub fn handle(&mut self, command: Command, now: Duration) -> Vec<Effect> {
+        match command {
+            Command::Next => {
+                if self.settled() == PlayerState::ShowingSingle {
+                    self.settle(PlayerState::Playing);
+                }
+                self.next_image_at = now + self.delay;
+                self.forward()
+            }
+            Command::Previous => {
+                let position = self
+                    .position
+                    .unwrap_or_else(|| self.history.len().saturating_sub(1));
+                if position == 0 {
+                    // nothing to go back to
+                    return vec![];
+                }
+                if self.settled() == PlayerState::ShowingSingle {
+                    self.settle(PlayerState::Playing);
+                }
+                self.next_image_at = now + self.delay;
+                self.revisit(position - 1)
+            }
+            Command::Show(path) => {
+                // a paused slideshow stays paused on the requested image
+                if self.settled() != PlayerState::Paused {
+                    self.settle(PlayerState::ShowingSingle);
+                }
+                self.requested = Some(path.clone());
+                self.next_image_at = now + self.delay;
+                vec![Effect::Requeue(path), Effect::Advance]
+            }
+            Command::Pause | Command::Resume | Command::TogglePause => {
+                let paused = self.settled() == PlayerState::Paused;
+                let pause = match command {
+                    Command::Pause => true,
+                    Command::Resume => false,
+                    _ => !paused,
+                };
+                if pause != paused {
+                    self.settle(if pause {
+                        PlayerState::Paused
+                    } else {
+                        PlayerState::Playing
+                    });
+                    // show the current image for the full delay after resuming
+                    self.next_image_at = now + self.delay;
+                }
+                vec![]
+            }
+            Command::SetInterval(delay) => {
+                info!("Delay: {:?}", delay);
+                self.delay = delay;
+                self.next_image_at = self.slide_since + delay;
+                self.status.lock().unwrap().interval = delay;
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// A slide of `paths`, the main image first, is shown, for the duration its provider
+    /// requested, if any. Returns whether it differs from the slide shown before.
+    pub fn slide_shown(
+        &mut self,
+        paths: &[PathBuf],
+        duration: Option<Duration>,
+        now: Duration,
+    ) -> bool {
+        let path = match paths.first() {
+            Some(path) => path.as_path(),
+            None => return false,
+        };
+        let new = self.slide != paths;
+        if new {
+            self.slide = paths.to_vec();
+            self.status.lock().unwrap().path = Some(path.to_path_buf());
+        }
+        if self.revisiting.as_deref() == Some(path) {
+            self.revisiting = None;
+        } else if new {
+            // a new slide of the slideshow ends going back
+            self.position = None;
+            self.history.push_back(paths.to_vec());
+            while self.history.len() > self.capacity.max(1) {
+                self.history.pop_front();
+            }
+        }
+        self.slide_since = now;
+        if self.requested.as_deref() == Some(path) {
+            // the requested image is shown for a full interval from when it appears
+            self.requested = None;
+            self.next_image_at = now + duration.unwrap_or(self.delay);
+        } else if let Some(duration) = duration {
+            // the provider asked to show this slide longer or shorter
+            self.next_image_at = now + duration;
+        }
+        new
+    }
+
+    /// Forget `paths`, which are never to be shown again, and if one of them is shown, go forward
+    pub fn forget(&mut self, paths: &[PathBuf], now: Duration) -> Vec<Effect> {
+        let forgotten = |slide: &Vec<PathBuf>| slide.iter().any(|path| paths.contains(path));
+        // the position of the slide shown, once those with `paths` are gone from the history
+        // before it
+        let position = self.position.map(|position| {
+            position
+                - self
+                    .history
+                    .iter()
+                    .take(position)
+                    .filter(|entry| forgotten(entry))
+                    .count()
+        });
+        self.history.retain(|entry| !forgotten(entry));
+        if !forgotten(&self.slide) {
+            self.position = position;
+            return vec![];
+        }
+        if self.settled() == PlayerState::ShowingSingle {
+            self.settle(PlayerState::Playing);
+        }
+        self.next_image_at = now + self.delay;
+        // the slide after the one shown has moved to its position
+        match position {
+            Some(position) if position < self.history.len() => self.revisit(position),
+            _ => {
+                self.position = None;
+                vec![Effect::Advance]
+            }
+        }
+    }
+
+    /// A transition to the current image started
+    pub fn transition_started(&mut self) {
+        if self.state != PlayerState::Transitioning {
+            self.after_transition = self.state;
+            self.state = PlayerState::Transitioning;
+        }
+    }
+
+    /// The transition to the current image finished
+    pub fn transition_finished(&mut self) {
+        if self.state == PlayerState::Transitioning {
+            self.state = self.after_transition;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::control::Status;
+
+    const DELAY: Duration = Duration::from_secs(10);
+
+    fn seconds(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    fn slide(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    fn player() -> Player {
+        let status = Arc::new(Mutex::new(Status::new(DELAY)));
+        Player::new(DELAY, 10, seconds(0), status)
+    }
+
+    /// A player which has shown the slides `names` one after the other, a second apart
+    fn player_after(names: &[&str]) -> Player {
+        let mut player = player();
+        for (index, name) in names.iter().enumerate() {
+            player.slide_shown(&slide(&[name]), None, seconds(index as u64));
+        }
+        player
+    }
+
+    fn requeue(name: &str) -> Effect {
+        Effect::Requeue(PathBuf::from(name))
+    }
+
+    #[test]
+    fn advances_once_the_delay_passed() {
+        let mut player = player();
+        assert_eq!(player.poll(seconds(1)), vec![Effect::Advance]);
+        assert!(player.slide_shown(&slide(&["a"]), None, seconds(1)));
+        assert_eq!(player.poll(seconds(5)), vec![]);
+        assert_eq!(player.poll(seconds(12)), vec![Effect::Advance]);
+    }
+
+    #[test]
+    fn shows_a_slide_for_the_duration_its_provider_requested() {
+        let mut player = player();
+        player.slide_shown(&slide(&["a"]), Some(seconds(30)), seconds(0));
+        assert_eq!(player.poll(seconds(20)), vec![]);
+        assert_eq!(player.poll(seconds(31)), vec![Effect::Advance]);
+    }
+
+    #[test]
+    fn pausing_stops_advancing_until_resumed() {
+        let mut player = player_after(&["a"]);
+        assert_eq!(player.handle(Command::Pause, seconds(1)), vec![]);
+        assert_eq!(player.state(), PlayerState::Paused);
+        assert!(player.status.lock().unwrap().paused);
+        assert_eq!(player.poll(seconds(60)), vec![]);
+
+        // the image is shown for the full delay after resuming
+        assert_eq!(player.handle(Command::Resume, seconds(60)), vec![]);
+        assert_eq!(player.state(), PlayerState::Playing);
+        assert!(!player.status.lock().unwrap().paused);
+        assert_eq!(player.poll(seconds(65)), vec![]);
+        assert_eq!(player.poll(seconds(71)), vec![Effect::Advance]);
+    }
+
+    #[test]
+    fn toggling_pauses_and_resumes() {
+        let mut player = player_after(&["a"]);
+        player.handle(Command::TogglePause, seconds(1));
+        assert_eq!(player.state(), PlayerState::Paused);
+        player.handle(Command::TogglePause, seconds(2));
+        assert_eq!(player.state(), PlayerState::Playing);
+    }
+
+    #[test]
+    fn next_while_paused_advances_and_stays_paused() {
+        let mut player = player_after(&["a"]);
+        player.handle(Command::Pause, seconds(1));
+        assert_eq!(
+            player.handle(Command::Next, seconds(2)),
+            vec![Effect::Advance]
+        );
+        player.slide_shown(&slide(&["b"]), None, seconds(2));
+        assert_eq!(player.state(), PlayerState::Paused);
+        assert_eq!(player.poll(seconds(60)), vec![]);
+    }
+
+    #[test]
+    fn previous_goes_back_through_the_history_and_next_retraces_it() {
+        let mut player = player_after(&["a", "b", "c"]);
+        assert_eq!(
+            player.handle(Command::Previous, seconds(3)),
+            vec![requeue("b"), Effect::Advance]
+        );
+        player.slide_shown(&slide(&["b"]), None, seconds(3));
+        assert_eq!(
+            player.handle(Command::Previous, seconds(4)),
+            vec![requeue("a"), Effect::Advance]
+        );
+        player.slide_shown(&slide(&["a"]), None, seconds(4));
+        // nothing before the first slide
+        assert_eq!(player.handle(Command::Previous, seconds(5)), vec![]);
+
+        assert_eq!(
+            player.handle(Command::Next, seconds(5)),
+            vec![requeue("b"), Effect::Advance]
+        );
+        player.slide_shown(&slide(&["b"]), None, seconds(5));
+        // after the latest slide of the history, the interval passing goes back to it, too
+        assert_eq!(
+            player.poll(seconds(16)),
+            vec![requeue("c"), Effect::Advance]
+        );
+        player.slide_shown(&slide(&["c"]), None, seconds(16));
+        // and then the slideshow continues
+        assert_eq!(
+            player.handle(Command::Next, seconds(17)),
+            vec![Effect::Advance]
+        );
+    }
+
+    #[test]
+    fn previous_shows_all_images_of_a_slide_again() {
+        let mut player = player();
+        player.slide_shown(&slide(&["a", "b"]), None, seconds(0));
+        player.slide_shown(&slide(&["c"]), None, seconds(1));
+        assert_eq!(
+            player.handle(Command::Previous, seconds(2)),
+            vec![requeue("a"), requeue("b"), Effect::Advance]
+        );
+    }
+
+    #[test]
+    fn previous_goes_back_to_a_restored_history() {
+        let mut player = player();
+        player.restore_history(vec![slide(&["a"]), slide(&["b", "c"])]);
+        player.slide_shown(&slide(&["d"]), None, seconds(0));
+        assert_eq!(
+            player.history().collect::<Vec<_>>(),
+            vec![
+                &slide(&["a"])[..],
+                &slide(&["b", "c"])[..],
+                &slide(&["d"])[..]
+            ]
+        );
+        assert_eq!(
+            player.handle(Command::Previous, seconds(1)),
+            vec![requeue("b"), requeue("c"), Effect::Advance]
+        );
+    }
+
+    #[test]
+    fn previous_while_paused_stays_paused() {
+        let mut player = player_after(&["a", "b"]);
+        player.handle(Command::Pause, seconds(2));
+        assert_eq!(
+            player.handle(Command::Previous, seconds(3)),
+            vec![requeue("a"), Effect::Advance]
+        );
+        assert_eq!(player.state(), PlayerState::Paused);
+    }
+
+    #[test]
+    fn shows_a_requested_image_for_a_full_interval_then_plays_on() {
+        let mut player = player_after(&["a"]);
+        assert_eq!(
+            player.handle(Command::Show(PathBuf::from("x")), seconds(5)),
+            vec![requeue("x"), Effect::Advance]
+        );
+        assert_eq!(player.state(), PlayerState::ShowingSingle);
+        // appearing later, it is shown for a full interval from then
+        player.slide_shown(&slide(&["x"]), None, seconds(8));
+        assert_eq!(player.poll(seconds(16)), vec![]);
+        assert_eq!(player.poll(seconds(19)), vec![Effect::Advance]);
+        assert_eq!(player.state(), PlayerState::Playing);
+    }
+
+    #[test]
+    fn a_requested_image_keeps_the_slideshow_paused() {
+        let mut player = player_after(&["a"]);
+        player.handle(Command::Pause, seconds(1));
+        player.handle(Command::Show(PathBuf::from("x")), seconds(2));
+        assert_eq!(player.state(), PlayerState::Paused);
+    }
+
+    #[test]
+    fn waits_for_transitions_to_finish() {
+        let mut player = player_after(&["a"]);
+        player.transition_started();
+        assert_eq!(player.state(), PlayerState::Transitioning);
+        assert_eq!(player.poll(seconds(60)), vec![]);
+        // pausing meanwhile takes effect once the transition finished
+        player.handle(Command::Pause, seconds(60));
+        assert_eq!(player.state(), PlayerState::Transitioning);
+        player.transition_finished();
+        assert_eq!(player.state(), PlayerState::Paused);
+    }
+
+    #[test]
+    fn forgetting_the_slide_shown_goes_forward() {
+        let mut player = player_after(&["a", "b"]);
+        assert_eq!(
+            player.forget(&slide(&["b"]), seconds(2)),
+            vec![Effect::Advance]
+        );
+        // going back skips it
+        player.slide_shown(&slide(&["c"]), None, seconds(2));
+        assert_eq!(
+            player.handle(Command::Previous, seconds(3)),
+            vec![requeue("a"), Effect::Advance]
+        );
+    }
+
+    #[test]
+    fn forgetting_another_image_only_drops_it_from_the_history() {
+        let mut player = player_after(&["a", "b", "c"]);
+        assert_eq!(player.forget(&slide(&["a"]), seconds(3)), vec![]);
+        player.handle(Command::Previous, seconds(3));
+        player.slide_shown(&slide(&["b"]), None, seconds(3));
+        assert_eq!(player.handle(Command::Previous, seconds(4)), vec![]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Switch the screen off and on
+//!
+//! A black screen still glows, and its backlight draws power, so while the screen is blank, it can
+//! be powered off. The display can do so itself: DRM/KMS and X11 through DPMS, and framebuffers
+//! through the blanking ioctl. HDMI TVs are better switched by the Raspberry Pi firmware with
+//! `vcgencmd`, or over HDMI-CEC with `cec-client` from libcec, which puts the TV into standby.
+//! Anything else can be switched by a pair of shell commands. The external commands may take a
+//! few seconds, so they run one after the other in a background thread, which skips to the latest
+//! state requested, and a quick switch off and on again never ends with the screen off.
+
+use std::process::Stdio;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use tracing::{info, warn};
+
+use crate::display::Display;
+use crate::errors::RahmenResult;
+
+/// How to switch the screen off and on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerMethod {
+    /// Ask the display, through DPMS or the framebuffer's blanking
+    Display,
+    /// Switch the HDMI output of a Raspberry Pi with `vcgencmd display_power`
+    Vcgencmd,
+    /// Put the TV into standby and wake it over HDMI-CEC with `cec-client`
+    Cec,
+    /// Run shell commands
+    Command,
+}
+
+/// Switches the screen off and on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Power {
+    /// Ask the display
+    Display,
+    /// Run `vcgencmd display_power`
+    Vcgencmd,
+    /// Send to the CEC device with the logical address
+    Cec(u8),
+    /// Run the first shell command to switch on, and the second to switch off
+    Command(String, String),
+}
+
+impl Power {
+    /// The program to run to switch the screen on or off, its arguments and its input, unless the
+    /// display switches it
+    fn command(&self, on: bool) -> Option<(&'static str, Vec<String>, Option<String>)> {
+        Some(match self {
+            Power::Display => return None,
+            Power::Vcgencmd => (
+                "vcgencmd",
+                vec!["display_power".to_string(), (on as u8).to_string()],
+                None,
+            ),
+            Power::Cec(address) => (
+                "cec-client",
+                vec!["-s".to_string(), "-d".to_string(), "1".to_string()],
+                Some(format!(
+                    "{} {}\n",
+                    if on { "on" } else { "standby" },
+                    address
+                )),
+            ),
+            Power::Command(on_command, off_command) => (
+                "sh",
+                vec![
+                    "-c".to_string(),
+                    if on { on_command } else { off_command }.clone(),
+                ],
+                None,
+            ),
+        })
+    }
+}
+
+/// Switches the screen off and on, running external commands in a background thread
+#[derive(Debug)]
+pub struct PowerSwitch {
+    /// the states requested of the background thread, if it runs external commands
+    states: Option<Sender<bool>>,
+}
+
+impl PowerSwitch {
+    /// Create a new `PowerSwitch` switching by `power`
+    pub fn new(power: Power) -> Self {
+        let states = if power == Power::Display {
+            None
+        } else {
+            let (sender, receiver) = channel();
+            std::thread::spawn(move || work(&power, &receiver));
+            Some(sender)
+        };
+        Self { states }
+    }
+
+    /// Switch the screen shown by `display` on or off
+    pub fn set(&self, on: bool, display: &mut dyn Display) -> RahmenResult<()> {
+        info!("Screen power: {}", on);
+        match &self.states {
+            // the thread only ends along with the switch
+            Some(states) => {
+                let _ = states.send(on);
+                Ok(())
+            }
+            None => display.set_power(on),
+        }
+    }
+}
+
+/// Switch the screen by `power` to each state received from `states`, skipping to the latest,
+/// until the sender is gone
+fn work(power: &Power, states: &Receiver<bool>) {
+    // the state switched to last, unless unknown
+    let mut switched = None;
+    while let Ok(mut on) = states.recv() {
+        while let Ok(later) = states.try_recv() {
+            on = later;
+        }
+        if switched == Some(on) {
+            continue;
+        }
+        if let Some((program, args, input)) = power.command(on) {
+            match run(program, &args, input) {
+                Ok(()) => switched = Some(on),
+                Err(e) => {
+                    warn!("Failed to switch the screen with {}: {}", program, e);
+                    switched = None;
+                }
+            }
+        }
+    }
+}
+
+/// Run `program` with `args`, writing `input` to it, and wait for it to succeed
+fn run(program: &str, args: &[String], input: Option<String>) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("exited with {}", status),
+        ))
+    }
+}
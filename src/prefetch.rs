@@ -0,0 +1,110 @@
+//! Load upcoming images in the background
+//!
+//! Loading an image can take a while, especially for large images or images that need to be
+//! transcoded. The prefetcher loads the upcoming images in background threads, so they are ready
+//! by the time they are shown. Transient errors are retried there too, so waiting for a network
+//! mount to come back doesn't freeze the display.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+
+use image::DynamicImage;
+use tracing::warn;
+
+use crate::errors::{ErrorPolicy, RahmenError, RahmenResult};
+
+type Loader = dyn Fn(&Path) -> RahmenResult<DynamicImage> + Send + Sync;
+
+enum Slot {
+    Loading,
+    Loaded(Arc<DynamicImage>),
+    Failed(RahmenError),
+}
+
+/// Loads images ahead of time
+#[derive(Clone)]
+pub struct Prefetcher {
+    loader: Arc<Loader>,
+    policy: ErrorPolicy,
+    slots: Arc<(Mutex<HashMap<PathBuf, Slot>>, Condvar)>,
+}
+
+impl std::fmt::Debug for Prefetcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Prefetcher").finish()
+    }
+}
+
+impl Prefetcher {
+    /// Create a new prefetcher loading images with `loader`, retrying in the background as
+    /// `policy` says
+    pub fn new<F: Fn(&Path) -> RahmenResult<DynamicImage> + Send + Sync + 'static>(
+        loader: F,
+        policy: ErrorPolicy,
+    ) -> Self {
+        Self {
+            loader: Arc::new(loader),
+            policy,
+            slots: Default::default(),
+        }
+    }
+
+    /// Start loading the upcoming images in the background, and forget any other images loaded
+    /// before, except the `current` ones about to be loaded
+    pub fn prefetch(&self, current: &[PathBuf], upcoming: &[PathBuf]) {
+        let mut slots = self.slots.0.lock().unwrap();
+        slots.retain(|path, _| current.contains(path) || upcoming.contains(path));
+        for path in upcoming {
+            if slots.contains_key(path) {
+                continue;
+            }
+            slots.insert(path.clone(), Slot::Loading);
+            let (loader, policy, shared, path) = (
+                Arc::clone(&self.loader),
+                self.policy,
+                Arc::clone(&self.slots),
+                path.clone(),
+            );
+            std::thread::spawn(move || {
+                let result = policy.run(|| loader(&path));
+                let mut slots = shared.0.lock().unwrap();
+                match result {
+                    Ok(img) => {
+                        // the image might not be upcoming anymore
+                        if let Some(slot) = slots.get_mut(&path) {
+                            *slot = Slot::Loaded(Arc::new(img));
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Failed to prefetch {:?}: {}", path, e);
+                        // the error is reported when the image is requested
+                        if let Some(slot) = slots.get_mut(&path) {
+                            *slot = Slot::Failed(e);
+                        }
+                    }
+                }
+                shared.1.notify_all();
+            });
+        }
+    }
+
+    /// Obtain an image, waiting for it if it is being loaded in the background, or loading it
+    /// right away if it isn't. Loading right away is not retried, as it blocks the caller.
+    pub fn load(&self, path: &Path) -> RahmenResult<Arc<DynamicImage>> {
+        let mut slots = self.slots.0.lock().unwrap();
+        loop {
+            match slots.remove(path) {
+                Some(Slot::Loaded(img)) => return Ok(img),
+                Some(Slot::Failed(e)) => return Err(e),
+                Some(Slot::Loading) => {
+                    slots.insert(path.to_owned(), Slot::Loading);
+                    slots = self.slots.1.wait(slots).unwrap();
+                }
+                None => break,
+            }
+        }
+        drop(slots);
+        (self.loader)(path).map(Arc::new)
+    }
+}
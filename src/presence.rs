@@ -0,0 +1,119 @@
+//! Notice whether anyone is home, by their phones
+//!
+//! Phones are looked for in a background thread, either by pinging them on the network, or by
+//! pinging their Bluetooth address with `l2ping` from BlueZ. That is classic Bluetooth, not a
+//! Bluetooth Low Energy scan: it needs the `CAP_NET_RAW` capability, and only finds phones paired
+//! with the frame and connectable. Phones sleep and drop off the network, so nobody counts as home
+//! only after none was seen for the grace period. Changes are reported as commands to the main
+//! loop, which starts out assuming someone is home.
+
+use std::process::Stdio;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::config::PresenceSettings;
+use crate::control::Command;
+use crate::errors::{RahmenError, RahmenResult};
+
+/// What to do while nobody is home
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Away {
+    /// Pause the slideshow
+    Pause,
+    /// Blank the screen
+    Blank,
+}
+
+/// A device whose presence means someone is home
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Device {
+    /// A host on the network, by name or address, answering pings
+    Host(String),
+    /// A Bluetooth device, by address, answering `l2ping`
+    Bluetooth(String),
+}
+
+impl Device {
+    /// Whether the device answers now
+    fn answers(&self) -> bool {
+        let mut command = match self {
+            Device::Host(host) => {
+                let mut command = std::process::Command::new("ping");
+                command.args(&["-c", "1", "-W", "2", host]);
+                command
+            }
+            Device::Bluetooth(address) => {
+                let mut command = std::process::Command::new("l2ping");
+                command.args(&["-c", "1", "-t", "5", address]);
+                command
+            }
+        };
+        match command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+        {
+            Ok(status) => status.success(),
+            Err(e) => {
+                warn!("Failed to look for {:?}: {}", self, e);
+                false
+            }
+        }
+    }
+}
+
+/// The devices to look for by `settings`, which must name at least one, as nobody would ever be
+/// home otherwise
+pub fn devices(settings: &PresenceSettings) -> RahmenResult<Vec<Device>> {
+    let devices: Vec<_> = settings
+        .hosts
+        .iter()
+        .flatten()
+        .cloned()
+        .map(Device::Host)
+        .chain(
+            settings
+                .bluetooth
+                .iter()
+                .flatten()
+                .cloned()
+                .map(Device::Bluetooth),
+        )
+        .collect();
+    if devices.is_empty() {
+        return Err(RahmenError::ConfigError(Arc::new(
+            config::ConfigError::Message("presence needs hosts or bluetooth addresses".into()),
+        )));
+    }
+    Ok(devices)
+}
+
+/// Look for `devices` every `interval` in a background thread, and send `Command::Presence` to
+/// `commands` when someone comes home, or none of the devices was seen for `grace`. The thread
+/// stops once the receiver is gone.
+pub fn start(devices: Vec<Device>, interval: Duration, grace: Duration, commands: Sender<Command>) {
+    std::thread::spawn(move || {
+        let mut seen_at = Instant::now();
+        let mut present = true;
+        loop {
+            let now = Instant::now();
+            if devices.iter().any(Device::answers) {
+                seen_at = now;
+            }
+            let home = now < seen_at + grace;
+            if home != present {
+                present = home;
+                info!("Someone home: {}", present);
+                if commands.send(Command::Presence(present)).is_err() {
+                    return;
+                }
+            }
+            std::thread::sleep(interval);
+        }
+    });
+}
@@ -0,0 +1,158 @@
+//! Show how far the slideshow has progressed
+//!
+//! A thin bar along an edge of the screen fills up while an image is shown, so viewers can tell
+//! when the next one comes, and a counter shows the position of the image in the slideshow. Both
+//! are layers of the compositor, updated independently of the image.
+
+use std::sync::Arc;
+
+use image::{DynamicImage, Rgb, Rgba, RgbaImage};
+
+use crate::compositor::Compositor;
+use crate::config::Corner;
+use crate::dataflow::StatusPosition;
+use crate::errors::RahmenResult;
+use crate::font::FontRenderer;
+
+/// The bar for an image of which `fraction` of its display time has passed, `width` pixels wide
+/// when complete. There is no bar before any time has passed.
+pub fn bar(width: u32, height: u32, fraction: f32, color: Rgb<u8>) -> Option<RgbaImage> {
+    let filled = (width as f32 * fraction.max(0.).min(1.)).round() as u32;
+    if filled == 0 || height == 0 {
+        return None;
+    }
+    let Rgb([red, green, blue]) = color;
+    Some(RgbaImage::from_pixel(
+        filled,
+        height,
+        Rgba([red, green, blue, 255]),
+    ))
+}
+
+/// The counter for the image at `index`, counting from 1, out of `total` images if known
+pub fn counter(index: usize, total: Option<usize>) -> String {
+    match total {
+        Some(total) => format!("{} / {}", index, total),
+        None => index.to_string(),
+    }
+}
+
+/// Placement of the progress bar
+#[derive(Debug, Clone, Copy)]
+pub struct BarStyle {
+    /// height in pixels
+    pub height: u32,
+    /// the edge of the screen to show the bar at
+    pub position: StatusPosition,
+    /// color of the bar
+    pub color: Rgb<u8>,
+}
+
+/// Placement of the counter, which is drawn with its own font renderer
+#[derive(Debug)]
+pub struct CounterStyle {
+    /// renderer drawing the counter, aligned to the side of the corner
+    pub renderer: FontRenderer,
+    /// font size in pixels
+    pub font_size: f32,
+    /// height of the canvas the text is drawn on, in pixels
+    pub canvas_height: u32,
+    /// the corner to show the counter in
+    pub corner: Corner,
+    /// distance from the edge of the screen in pixels
+    pub margin: u32,
+}
+
+/// Progress bar and counter, shown as layers of a compositor
+#[derive(Debug)]
+pub struct ProgressIndicator {
+    bar: Option<BarStyle>,
+    counter: Option<CounterStyle>,
+    filled: Option<u32>,
+}
+
+impl ProgressIndicator {
+    /// Compositor layer of the bar
+    pub const BAR_KEY: usize = 5;
+    /// Compositor layer of the counter
+    pub const COUNTER_KEY: usize = 6;
+
+    /// Create an indicator showing a bar and a counter, if given
+    pub fn new(bar: Option<BarStyle>, counter: Option<CounterStyle>) -> Self {
+        Self {
+            bar,
+            counter,
+            filled: None,
+        }
+    }
+
+    /// Show the bar for an image of which `fraction` of its display time has passed, or hide it
+    /// in clean mode
+    pub fn show_bar(
+        &mut self,
+        compositor: &mut Compositor,
+        (width, height): (u32, u32),
+        fraction: f32,
+        clean: bool,
+    ) {
+        let style = match &self.bar {
+            Some(style) => style,
+            None => return,
+        };
+        let img = bar(width, style.height, fraction, style.color).filter(|_| !clean);
+        let filled = img.as_ref().map(|img| img.width());
+        // only draw again when the bar grew by a pixel, or shrank for the next image
+        if filled == self.filled {
+            return;
+        }
+        self.filled = filled;
+        match img {
+            Some(img) => {
+                let y = match style.position {
+                    StatusPosition::Top => 0,
+                    StatusPosition::Bottom => height.saturating_sub(style.height),
+                };
+                compositor.set(Self::BAR_KEY, 0, y, Arc::new(DynamicImage::ImageRgba8(img)));
+            }
+            None => compositor.clear(Self::BAR_KEY),
+        }
+    }
+
+    /// Show the counter for the image at `index` out of `total`, or hide it in clean mode
+    pub fn show_counter(
+        &mut self,
+        compositor: &mut Compositor,
+        (width, height): (u32, u32),
+        index: usize,
+        total: Option<usize>,
+        clean: bool,
+    ) -> RahmenResult<()> {
+        let style = match &mut self.counter {
+            Some(style) => style,
+            None => return Ok(()),
+        };
+        if clean || index == 0 {
+            compositor.clear(Self::COUNTER_KEY);
+            return Ok(());
+        }
+        let text = counter(index, total);
+        let img = style.renderer.render_rgba(
+            std::iter::once(text.as_str()),
+            style.font_size,
+            (width, style.canvas_height),
+        )?;
+        let y = match style.corner {
+            Corner::TopLeft | Corner::TopRight => style.margin,
+            Corner::BottomLeft | Corner::BottomRight => {
+                height.saturating_sub(style.canvas_height + style.margin)
+            }
+        };
+        compositor.set(
+            Self::COUNTER_KEY,
+            0,
+            y,
+            Arc::new(DynamicImage::ImageRgba8(img)),
+        );
+        Ok(())
+    }
+}
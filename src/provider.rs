@@ -1,19 +1,30 @@
 //! Utilities to provide images, and other abstractions
 
 use std::convert::{TryFrom, TryInto};
-use std::io::BufReader;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::path::Path;
+use std::time::Duration;
 
+use chrono::{DateTime, FixedOffset, Locale};
+use chrono_tz::Tz;
 use convert_case::{Case, Casing};
-use image::{DynamicImage, Pixel};
+use image::DynamicImage;
 use itertools::Itertools;
-use pyo3::prelude::*;
-use pyo3::types::PyList;
 use regex::Regex;
-use rexiv2::Metadata;
+use tracing::{debug_span, info, warn};
 
-use crate::config::{Element, Replacement};
+use crate::config::{Condition, Element, Replacement};
+use crate::date;
 use crate::errors::{RahmenError, RahmenResult};
+use crate::exposure;
+use crate::geocode::{self, Home, Place, ReverseGeocoder};
+use crate::lua::LuaPostprocess;
+use crate::metadata::Metadata;
+use crate::pixel::into_rgb8;
+use crate::python::PyPostprocess;
+use crate::stats::{self, ImageStats};
+use crate::xmp;
 
 /// Provider trait to produce images, or other types
 pub trait Provider<D> {
@@ -22,16 +33,160 @@ pub trait Provider<D> {
     /// Ok(Some(T)) -> Process T
     /// Ok(None) -> Exhausted
     fn next_image(&mut self) -> RahmenResult<Option<D>>;
+
+    /// The display duration requested for the element returned last, if it should differ from
+    /// the configured delay
+    fn duration(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Look at up to `count` upcoming elements without consuming them. Providers that can't look
+    /// ahead return fewer elements.
+    fn peek(&mut self, _count: usize) -> RahmenResult<Vec<D>>
+    where
+        D: Clone,
+    {
+        Ok(vec![])
+    }
+
+    /// The total number of elements, including those returned already, if known
+    fn total(&self) -> Option<usize> {
+        None
+    }
 }
 
 impl<D> Provider<D> for Box<dyn Provider<D>> {
     fn next_image(&mut self) -> RahmenResult<Option<D>> {
         (**self).next_image()
     }
+
+    fn duration(&self) -> Option<Duration> {
+        (**self).duration()
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<D>>
+    where
+        D: Clone,
+    {
+        (**self).peek(count)
+    }
+
+    fn total(&self) -> Option<usize> {
+        (**self).total()
+    }
 }
 
+/// Load a JPEG image. Syncing tools often leave partial files behind, so for truncated files the
+/// decoded part is shown, with the rest filled in gray, while progressive JPEGs are shown with the
+/// scans that arrived completely. If decoding fails altogether, the embedded thumbnail is shown
+/// instead, if there is one.
 fn load_jpeg<P: AsRef<Path>>(path: P, max_size: Option<usize>) -> RahmenResult<DynamicImage> {
-    let mut d = mozjpeg::Decompress::with_markers(mozjpeg::ALL_MARKERS).from_path(&path)?;
+    let mut data = std::fs::read(&path)?;
+    if !data.ends_with(&[0xff, 0xd9]) {
+        warn!("Image is truncated: {:?}", path.as_ref());
+        // a scan cut short would leave the rest of the image at the quality of the scans before
+        if let Some(end) = progressive_scans(&data).and_then(|ends| ends.last().copied()) {
+            data.truncate(end);
+            data.extend_from_slice(&[0xff, 0xd9]);
+        }
+    }
+    // mozjpeg reports fatal decoding errors by panicking
+    match std::panic::catch_unwind(|| decode_jpeg(&data, max_size)) {
+        Ok(Ok(img)) => Ok(img),
+        _ => {
+            warn!("Failed to decode image: {:?}", path.as_ref());
+            load_thumbnail(&path)
+        }
+    }
+}
+
+/// The offsets at which the complete scans of a progressive JPEG end, each refining the whole
+/// image, or `None` for a baseline JPEG, which is decoded from top to bottom instead
+pub fn progressive_scans(data: &[u8]) -> Option<Vec<usize>> {
+    let mut progressive = false;
+    let mut ends = vec![];
+    // skip the start of image marker
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xff {
+        let marker = data[pos + 1];
+        match marker {
+            // fill bytes before a marker
+            0xff => {
+                pos += 1;
+                continue;
+            }
+            // end of image
+            0xd9 => break,
+            // markers without a segment
+            0x01 | 0xd0..=0xd8 => {
+                pos += 2;
+                continue;
+            }
+            // start of frame of a progressive JPEG
+            0xc2 | 0xc6 | 0xca | 0xce => progressive = true,
+            _ => {}
+        }
+        let end = pos + 2 + usize::from(u16::from_be_bytes([data[pos + 2], data[pos + 3]]));
+        pos = end;
+        if marker == 0xda {
+            // the entropy-coded data of the scan runs until the next marker, other than a
+            // stuffed zero byte or a restart marker
+            match (pos..data.len().saturating_sub(1))
+                .find(|&i| data[i] == 0xff && !matches!(data[i + 1], 0x00 | 0xd0..=0xd7 | 0xff))
+            {
+                Some(next) => {
+                    ends.push(next);
+                    pos = next;
+                }
+                None => break,
+            }
+        }
+    }
+    Some(ends).filter(|_| progressive)
+}
+
+/// How much there is to show of a JPEG file still being written: the number of complete scans of
+/// a progressive JPEG, or else the size of the file, whose rows are shown as they arrive
+pub fn arrived<P: AsRef<Path>>(path: P) -> Option<u64> {
+    let data = std::fs::read(path).ok()?;
+    Some(match progressive_scans(&data) {
+        Some(ends) => ends.len() as u64,
+        None => data.len() as u64,
+    })
+}
+
+/// Whether a JPEG file lacks its end marker, as happens while it is still being written
+pub fn is_truncated<P: AsRef<Path>>(path: P) -> bool {
+    if image::ImageFormat::from_path(&path).ok() != Some(image::ImageFormat::Jpeg) {
+        return false;
+    }
+    let mut end = [0; 2];
+    File::open(path)
+        .and_then(|mut file| {
+            file.seek(SeekFrom::End(-2))?;
+            file.read_exact(&mut end)
+        })
+        .is_ok()
+        && end != [0xff, 0xd9]
+}
+
+/// Load the thumbnail embedded in an image's metadata
+fn load_thumbnail<P: AsRef<Path>>(path: P) -> RahmenResult<DynamicImage> {
+    let metadata = Metadata::new_from_path(path.as_ref())?;
+    let thumbnail = metadata
+        .get_thumbnail()
+        .ok_or_else(|| RahmenError::DecodeError("no embedded thumbnail".into()))?;
+    info!("Showing the embedded thumbnail instead");
+    Ok(image::load_from_memory_with_format(
+        thumbnail,
+        image::ImageFormat::Jpeg,
+    )?)
+}
+
+/// Decode a JPEG image with mozjpeg, scaled down while decoding to about `max_size` pixels
+#[cfg(feature = "mozjpeg")]
+fn decode_jpeg(data: &[u8], max_size: Option<usize>) -> RahmenResult<DynamicImage> {
+    let mut d = mozjpeg::Decompress::with_markers(mozjpeg::ALL_MARKERS).from_mem(data)?;
 
     if let Some(max_size) = max_size {
         let mut scale = 8;
@@ -41,36 +196,43 @@ fn load_jpeg<P: AsRef<Path>>(path: P, max_size: Option<usize>) -> RahmenResult<D
         }
         d.scale(scale);
     }
-    let mut decompress_started = d.to_colorspace(mozjpeg::ColorSpace::JCS_EXT_BGR)?;
-    let height = decompress_started.height();
-    let mut img = DynamicImage::new_bgr8(decompress_started.width() as _, height as _);
+    let mut decompress_started = d.to_colorspace(mozjpeg::ColorSpace::JCS_EXT_RGB)?;
+    let (width, height) = (decompress_started.width(), decompress_started.height());
     let buffer: Option<Vec<[u8; 3]>> = decompress_started.read_scanlines();
-    let rgb_img = img.as_mut_bgr8().unwrap();
-    if let Some(buffer) = buffer {
-        for (row, row_buffer) in buffer.chunks(buffer.len() / height).enumerate() {
-            for (col, pixel) in row_buffer.iter().enumerate() {
-                *rgb_img.get_pixel_mut(col as _, row as _) = *image::Bgr::from_slice(pixel);
-            }
-        }
-        Ok(img)
-    } else {
-        eprintln!("Failed to decode image: {:?}", path.as_ref());
-        Err(RahmenError::Retry)
-    }
+    buffer
+        .and_then(|buffer| {
+            let raw = buffer
+                .iter()
+                .flat_map(|pixel| pixel.iter().copied())
+                .collect();
+            image::RgbImage::from_raw(width as _, height as _, raw)
+        })
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| RahmenError::DecodeError("incomplete JPEG data".into()))
 }
 
-/// Load an image from a path
+/// Decode a JPEG image with the image library, which is slower and decodes at full size
+#[cfg(not(feature = "mozjpeg"))]
+fn decode_jpeg(data: &[u8], _max_size: Option<usize>) -> RahmenResult<DynamicImage> {
+    Ok(image::load_from_memory_with_format(
+        data,
+        image::ImageFormat::Jpeg,
+    )?)
+}
+
+/// Load an image from a path, as 8-bit RGB
 pub fn load_image_from_path<P: AsRef<Path>>(
     path: P,
     max_size: Option<usize>,
 ) -> RahmenResult<DynamicImage> {
-    let _t = crate::Timer::new(|e| println!("Loading {}ms", e.as_millis()));
-    println!("Loading {:?}", path.as_ref());
+    let _span = debug_span!("load").entered();
+    info!("Loading {:?}", path.as_ref());
     match image::ImageFormat::from_path(&path)? {
         image::ImageFormat::Jpeg => load_jpeg(path, max_size),
         format => {
             image::io::Reader::with_format(BufReader::new(std::fs::File::open(&path)?), format)
                 .decode()
+                .map(|img| DynamicImage::ImageRgb8(into_rgb8(img)))
                 .map_err(Into::into)
         }
     }
@@ -104,15 +266,20 @@ enum StatusLineTransformation {
     RegexReplace(Box<(Regex, String)>),
     Capitalize,
     ChangeCase(Case, Case),
+    FormatDate(String, Locale),
 }
 
 /// runtime transformation ops for the metadata values (the parameters are gathered in the try_from function)
 impl StatusLineTransformation {
-    fn transform<S: AsRef<str>>(&self, input: S) -> String {
+    /// transform a value, with `offset` that of the camera's clock for dates without one
+    fn transform<S: AsRef<str>>(&self, input: S, offset: Option<FixedOffset>) -> String {
         match self {
             Self::RegexReplace(re) => re.0.replace_all(input.as_ref(), re.1.as_str()).into_owned(),
             Self::Capitalize => input.as_ref().from_case(Case::Upper).to_case(Case::Title),
             Self::ChangeCase(f, t) => input.as_ref().from_case(*f).to_case(*t),
+            Self::FormatDate(format, locale) => {
+                date::format(input.as_ref(), format, *locale, offset)
+            }
         }
     }
 }
@@ -132,11 +299,63 @@ impl TryFrom<Replacement> for StatusLineTransformation {
     }
 }
 
+/// a test on the value of a tag, which is `None` if the tag is missing
+#[derive(Debug)]
+enum StatusLineTest {
+    Empty(bool),
+    Equals(String),
+    NotEquals(String),
+    Matches(Regex),
+}
+
+impl StatusLineTest {
+    fn holds(&self, value: Option<&str>) -> bool {
+        match self {
+            Self::Empty(empty) => value.map_or(true, str::is_empty) == *empty,
+            Self::Equals(expected) => value == Some(expected.as_str()),
+            Self::NotEquals(unexpected) => value != Some(unexpected.as_str()),
+            Self::Matches(re) => value.map_or(false, |value| re.is_match(value)),
+        }
+    }
+}
+
+/// a condition to show an element: tests on a tag, all of which have to hold
+#[derive(Debug)]
+struct StatusLineCondition {
+    tag: String,
+    tests: Vec<StatusLineTest>,
+}
+
+/// prepare the tests of a condition
+impl TryFrom<Condition> for StatusLineCondition {
+    type Error = RahmenError;
+    fn try_from(value: Condition) -> Result<Self, Self::Error> {
+        let mut tests = vec![];
+        if let Some(empty) = value.empty {
+            tests.push(StatusLineTest::Empty(empty));
+        }
+        if let Some(expected) = value.equals {
+            tests.push(StatusLineTest::Equals(expected));
+        }
+        if let Some(unexpected) = value.not_equals {
+            tests.push(StatusLineTest::NotEquals(unexpected));
+        }
+        if let Some(regex) = value.matches {
+            tests.push(StatusLineTest::Matches(Regex::new(&regex)?));
+        }
+        Ok(Self {
+            tag: value.tag,
+            tests,
+        })
+    }
+}
+
 /// a status line meta data element: a string and transformations to perform on it
 #[derive(Debug)]
 struct StatusLineElement {
     tags: Vec<String>,
     transformations: Vec<StatusLineTransformation>,
+    conditions: Vec<StatusLineCondition>,
 }
 
 /// prepare the ops for the processing of an element
@@ -146,6 +365,15 @@ impl TryFrom<Element> for StatusLineElement {
     fn try_from(value: Element) -> Result<Self, Self::Error> {
         let mut transformations = vec![];
         // collect the transformation ops and store their parameters
+        // the date format, applied to the value as read
+        if let Some(format) = value.date_format {
+            date::parse_format(&format)?;
+            let locale = match value.locale {
+                Some(locale) => date::parse_locale(&locale)?,
+                None => Locale::en_US,
+            };
+            transformations.push(StatusLineTransformation::FormatDate(format, locale));
+        }
         // the case conversion to apply
         if let Some(case_conversion) = value.case_conversion {
             transformations.push(StatusLineTransformation::ChangeCase(
@@ -165,18 +393,45 @@ impl TryFrom<Element> for StatusLineElement {
             ))));
         }
 
+        // the conditions to show the element
+        let mut conditions = vec![];
+        for condition in value.when.into_iter().flat_map(Vec::into_iter) {
+            conditions.push(condition.try_into()?);
+        }
+
         // return the transformations and the tags vector
         Ok(Self {
             transformations,
             tags: value.exif_tags,
+            conditions,
         })
     }
 }
 
 /// the status line meta data element
 impl StatusLineElement {
+    /// whether this element shows or tests pseudo-tags starting with `prefix`, such as image
+    /// statistics, which are expensive to compute
+    fn uses(&self, prefix: &str) -> bool {
+        self.tags
+            .iter()
+            .chain(self.conditions.iter().map(|condition| &condition.tag))
+            .any(|tag| tag.starts_with(prefix))
+    }
+
     /// this processes each metadata tag and subordinate instructions from the config file
-    fn process(&self, metadata: &Metadata) -> Option<String> {
+    fn process(&self, sources: &TagSources<'_>) -> Option<String> {
+        // show nothing unless all conditions hold
+        for condition in &self.conditions {
+            let value = sources.get(&condition.tag);
+            if !condition
+                .tests
+                .iter()
+                .all(|test| test.holds(value.as_deref()))
+            {
+                return None;
+            }
+        }
         // metadata processor: get the metadata value of the given meta tag (self.tag, from try_from above)
         // so we have three values here, self.tag (the tag), metadata (the data for this tag),
         // and value (the processed and later transformed metadata)
@@ -184,15 +439,16 @@ impl StatusLineElement {
         if let Some(mut value) = self
             .tags
             .iter()
-            // ...get tag as string...
-            .map(|f| metadata.get_tag_interpreted_string(f).ok())
+            // ...get tag as string, or the image statistics for our pseudo-tags...
+            .map(|f| sources.get(f))
             // ...if it is s/th,...
             .find(Option::is_some)
             .flatten()
         // ...process that value using the pushed transformation ops and return the transformed value
         {
+            let offset = date::recorded_offset(&sources.metadata);
             for transformation in &self.transformations {
-                value = transformation.transform(value);
+                value = transformation.transform(value, offset);
             }
             Some(value)
         } else {
@@ -201,6 +457,45 @@ impl StatusLineElement {
     }
 }
 
+/// the sources of the values of tags for an image: its metadata, and what our pseudo-tags are
+/// computed from
+struct TagSources<'a> {
+    metadata: Metadata,
+    stats: Option<ImageStats>,
+    geocoder: Option<&'a ReverseGeocoder>,
+    place: Option<Place>,
+    home: Option<&'a Home>,
+    // where the image was taken, as `(latitude, longitude)`
+    position: Option<(f64, f64)>,
+    // when the image was taken, in the time zone of the place
+    date: Option<DateTime<FixedOffset>>,
+}
+
+impl TagSources<'_> {
+    /// get the value of a tag as string, or of our pseudo-tags
+    fn get(&self, tag: &str) -> Option<String> {
+        if tag.starts_with(stats::TAG_PREFIX) {
+            self.stats.as_ref().and_then(|stats| stats.tag(tag))
+        } else if tag.starts_with(exposure::TAG_PREFIX) {
+            exposure::tag(&self.metadata, tag)
+        } else if tag.starts_with(date::TAG_PREFIX) {
+            self.date.as_ref().and_then(|time| date::tag(time, tag))
+        } else if tag.starts_with(geocode::HOME_TAG_PREFIX) {
+            self.home
+                .zip(self.position)
+                .and_then(|(home, position)| home.tag(position, tag))
+        } else if tag.starts_with(geocode::TAG_PREFIX) {
+            self.geocoder
+                .zip(self.place.as_ref())
+                .and_then(|(geocoder, place)| geocoder.tag(place, tag))
+        } else if xmp::handles(tag) {
+            xmp::tag(&self.metadata, tag)
+        } else {
+            self.metadata.get_tag_interpreted_string(tag).ok()
+        }
+    }
+}
+
 /// A status line formatter formats meta data tags according to configured elements into a string
 /// and then processes that string using regexes/replacements as configured
 #[derive(Debug)]
@@ -210,7 +505,23 @@ pub struct StatusLineFormatter {
     // the separator string for the line output
     separator: String,
     // the Python code used to postprocess the metadata items
-    py_postprocess_fn: Option<Py<PyAny>>,
+    py_postprocess: Option<PyPostprocess>,
+    // the Lua code used to postprocess the metadata items
+    lua_postprocess: Option<LuaPostprocess>,
+    // names the places images were taken at
+    geocoder: Option<ReverseGeocoder>,
+    // relates the places images were taken at to home
+    home: Option<Home>,
+    // the time zone the camera's clock is set to, if not the local one
+    camera_timezone: Option<Tz>,
+    // whether any element shows image statistics
+    uses_stats: bool,
+    // whether any element shows the place an image was taken at
+    uses_places: bool,
+    // whether any element shows how far from home an image was taken
+    uses_home: bool,
+    // whether any element shows when an image was taken
+    uses_dates: bool,
 }
 
 impl StatusLineFormatter {
@@ -219,34 +530,114 @@ impl StatusLineFormatter {
         // we get the arguments when we're called
         statusline_elements_iter: I,
         py_postprocess: Option<String>,
+        lua_postprocess: Option<String>,
+        lua_path: &[String],
         separator: String,
     ) -> RahmenResult<Self> {
         // read the metadata config entries and store them to the elements vector
-        let mut elements = vec![];
+        let mut elements: Vec<StatusLineElement> = vec![];
         for element in statusline_elements_iter {
             elements.push(element.try_into()?);
         }
         // read and store the Python code (if it exists)
-        let py_postprocess_fn = if let Some(postprocess_path) = py_postprocess {
-            Some(Python::with_gil(|py| {
-                let module = py.import(postprocess_path.as_ref())?;
-                module.call0("export").map(|obj| obj.into_py(py))
-            })?)
-        } else {
-            None
-        };
+        let py_postprocess = py_postprocess
+            .map(|module| PyPostprocess::load(&module))
+            .transpose()?;
+        // require the Lua module (if it exists)
+        let lua_postprocess = lua_postprocess
+            .map(|module| LuaPostprocess::load(&module, lua_path))
+            .transpose()?;
+
+        let uses_stats = elements.iter().any(|e| e.uses(stats::TAG_PREFIX));
+        let uses_places = elements.iter().any(|e| e.uses(geocode::TAG_PREFIX));
+        let uses_home = elements.iter().any(|e| e.uses(geocode::HOME_TAG_PREFIX));
+        let uses_dates = elements.iter().any(|e| e.uses(date::TAG_PREFIX));
 
         Ok(Self {
             elements,
-            py_postprocess_fn,
+            py_postprocess,
+            lua_postprocess,
             separator,
+            geocoder: None,
+            home: None,
+            camera_timezone: None,
+            uses_stats,
+            uses_places,
+            uses_home,
+            uses_dates,
         })
     }
 
-    /// Format the meta data from the given path (called as receiver to the status line formatter)
-    pub fn format<P: AsRef<std::ffi::OsStr>>(&self, path: P) -> RahmenResult<String> {
+    /// Name the places images were taken at with `geocoder`, for the place pseudo-tags
+    pub fn with_geocoder(mut self, geocoder: ReverseGeocoder) -> Self {
+        self.geocoder = Some(geocoder);
+        self
+    }
+
+    /// Relate the places images were taken at to `home`, for the home pseudo-tags
+    pub fn with_home(mut self, home: Home) -> Self {
+        self.home = Some(home);
+        self
+    }
+
+    /// Take the camera's clock to be set to `timezone` rather than the local time zone, if the
+    /// images record no offset
+    pub fn with_camera_timezone(mut self, timezone: Tz) -> Self {
+        self.camera_timezone = Some(timezone);
+        self
+    }
+
+    /// Format the meta data from the given path (called as receiver to the status line formatter).
+    /// The image is needed for the statistics pseudo-tags.
+    pub fn format<P: AsRef<std::ffi::OsStr>>(
+        &self,
+        path: P,
+        img: &DynamicImage,
+    ) -> RahmenResult<String> {
         let metadata = Metadata::new_from_path(path)?;
-        let mut line_elements = self
+        let stats = if self.uses_stats {
+            Some(ImageStats::from_image(img))
+        } else {
+            None
+        };
+        let position = if self.uses_places || self.uses_home || self.uses_dates {
+            metadata
+                .get_gps_info()
+                .map(|gps| (gps.latitude, gps.longitude))
+        } else {
+            None
+        };
+        let geocoder = self
+            .geocoder
+            .as_ref()
+            .filter(|_| self.uses_places || self.uses_dates);
+        let place = geocoder
+            .zip(position)
+            .and_then(|(geocoder, (latitude, longitude))| geocoder.lookup(latitude, longitude));
+        // the time zone of the place is that of the nearest city, or else looked up offline, so
+        // it is known without geocoding too
+        let date = if self.uses_dates {
+            let timezone = place
+                .as_ref()
+                .and_then(|place| place.timezone.clone())
+                .or_else(|| {
+                    position
+                        .and_then(|(latitude, longitude)| date::timezone_at(latitude, longitude))
+                });
+            date::local_time(&metadata, self.camera_timezone, timezone.as_deref())
+        } else {
+            None
+        };
+        let sources = TagSources {
+            metadata,
+            stats,
+            geocoder,
+            place,
+            home: self.home.as_ref(),
+            position,
+            date,
+        };
+        let mut line_elements: Vec<String> = self
             .elements
             .iter()
             // process each metadata section (element) using the associated transformation instructions
@@ -255,7 +646,7 @@ impl StatusLineFormatter {
             // being sure that parameters stay at their position.
             // This produces a Vec<String> of all the metadata found (empty strings if no data).
             .flat_map(move |element| {
-                if let Some(v) = element.process(&metadata) {
+                if let Some(v) = element.process(&sources) {
                     Some(v)
                 } else {
                     Some("".to_string())
@@ -267,16 +658,13 @@ impl StatusLineFormatter {
         // this takes a Vec<String> of all the metadata found (empty strings if no data)
         // and produces a Vec<String> of either the items returned from the Python code (if there's some code),
         // or just the input
-        line_elements = if let Some(code) = &self.py_postprocess_fn {
-            Python::with_gil(|py| -> PyResult<Vec<String>> {
-                let tags = PyList::new(py, &line_elements);
-                code.call1(py, (tags, &self.separator))?.extract(py)
-            })
-            .unwrap()
-        } else {
-            // do nothing when there's no Python code
-            line_elements
-        };
+        if let Some(code) = &self.py_postprocess {
+            line_elements = code.call(&line_elements, &self.separator)?;
+        }
+        // the same with the Lua function, if there is one
+        if let Some(lua) = &self.lua_postprocess {
+            line_elements = lua.call(&line_elements, &self.separator)?;
+        }
 
         // unconditionally filter out the empty items we received from above and
         // deduplicate them, and join them with the separator, producing the final status line
@@ -0,0 +1,409 @@
+//! Providers doing their work asynchronously, such as fetching images over the network
+//!
+//! A provider waiting for a server blocks the main loop for as long as the server takes to answer.
+//! An `AsyncProvider` returns a future instead, which can be raced against a timeout and is
+//! cancelled by dropping it. The main loop asks for elements synchronously, so `BlockingProvider`
+//! drives an async provider on its own Tokio runtime, giving up on elements taking longer than a
+//! timeout. The other way around, `SpawnedProvider` moves a synchronous provider to Tokio's
+//! blocking threads, to combine it with async providers.
+//!
+//! Both look ahead, so upcoming images are prefetched while the current one is shown, which
+//! matters most for images coming over the network.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::control::Command;
+use crate::errors::{RahmenError, RahmenResult};
+use crate::provider::Provider;
+
+/// The future of the next element of an `AsyncProvider`
+pub type ProviderFuture<'a, D> = Pin<Box<dyn Future<Output = RahmenResult<Option<D>>> + Send + 'a>>;
+
+/// The future of the upcoming elements of an `AsyncProvider`
+pub type PeekFuture<'a, D> = Pin<Box<dyn Future<Output = RahmenResult<Vec<D>>> + Send + 'a>>;
+
+/// Provider trait to produce images, or other types, asynchronously
+pub trait AsyncProvider<D> {
+    /// Obtain the next element, with the same meaning as `Provider::next_image`. Dropping the
+    /// future cancels the request.
+    fn next_image(&mut self) -> ProviderFuture<'_, D>;
+
+    /// The display duration requested for the element returned last, if it should differ from
+    /// the configured delay
+    fn duration(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Look at up to `count` upcoming elements without consuming them, with the same meaning as
+    /// `Provider::peek`
+    fn peek(&mut self, _count: usize) -> PeekFuture<'_, D>
+    where
+        D: Clone + Send + 'static,
+    {
+        Box::pin(async { Ok(vec![]) })
+    }
+
+    /// The total number of elements, including those returned already, if known
+    fn total(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<D> AsyncProvider<D> for Box<dyn AsyncProvider<D> + Send> {
+    fn next_image(&mut self) -> ProviderFuture<'_, D> {
+        (**self).next_image()
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        (**self).duration()
+    }
+
+    fn peek(&mut self, count: usize) -> PeekFuture<'_, D>
+    where
+        D: Clone + Send + 'static,
+    {
+        (**self).peek(count)
+    }
+
+    fn total(&self) -> Option<usize> {
+        (**self).total()
+    }
+}
+
+/// Stops a `BlockingProvider` from another thread, such as when quitting
+#[derive(Debug, Clone, Default)]
+pub struct Cancel {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Cancel {
+    /// Cancel the request in progress, and fail all later ones with `RahmenError::Terminate`
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        // stores a permit if no request is waiting
+        self.notify.notify_one();
+    }
+
+    /// Whether `cancel` was called
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Pass on the commands received from `commands`, cancelling once `Command::Quit` comes
+    /// through, so a request in progress does not keep the main loop from quitting. All senders
+    /// of commands, such as the signal handler and the control interfaces, stop this way.
+    pub fn on_quit(&self, commands: Receiver<Command>) -> Receiver<Command> {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = self.clone();
+        std::thread::spawn(move || {
+            for command in commands {
+                if command == Command::Quit {
+                    cancel.cancel();
+                }
+                if sender.send(command).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+}
+
+/// Provider obtaining the elements of an async provider, waiting for at most a timeout each
+#[derive(Debug)]
+pub struct BlockingProvider<P> {
+    inner: P,
+    runtime: Runtime,
+    timeout: Option<Duration>,
+    cancel: Cancel,
+}
+
+impl<P> BlockingProvider<P> {
+    /// Create a new `BlockingProvider` running `inner` on a runtime of its own, failing requests
+    /// taking longer than `timeout` with a `TimedOut` I/O error, which counts as transient
+    pub fn new(inner: P, timeout: Option<Duration>) -> RahmenResult<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            inner,
+            runtime,
+            timeout,
+            cancel: Cancel::default(),
+        })
+    }
+
+    /// A handle to cancel requests from another thread
+    pub fn cancel_handle(&self) -> Cancel {
+        self.cancel.clone()
+    }
+}
+
+/// Run `request` on `runtime` until it completes, fails after `timeout`, or is cancelled
+fn wait<T, F: Future<Output = RahmenResult<T>>>(
+    runtime: &Runtime,
+    timeout: Option<Duration>,
+    cancel: &Cancel,
+    request: F,
+) -> RahmenResult<T> {
+    if cancel.is_cancelled() {
+        return Err(RahmenError::Terminate);
+    }
+    runtime.block_on(async move {
+        let request = async move {
+            match timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, request).await {
+                    Ok(result) => result,
+                    Err(_) => Err(RahmenError::IoError(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("no element within {:?}", timeout),
+                    ))),
+                },
+                None => request.await,
+            }
+        };
+        tokio::select! {
+            result = request => result,
+            _ = cancel.notify.notified() => Err(RahmenError::Terminate),
+        }
+    })
+}
+
+impl<D: Send + 'static, P: AsyncProvider<D>> Provider<D> for BlockingProvider<P> {
+    fn next_image(&mut self) -> RahmenResult<Option<D>> {
+        let next = self.inner.next_image();
+        wait(&self.runtime, self.timeout, &self.cancel, next)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.inner.duration()
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<D>>
+    where
+        D: Clone,
+    {
+        let upcoming = self.inner.peek(count);
+        wait(&self.runtime, self.timeout, &self.cancel, upcoming)
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
+
+/// The outcome of a request to the provider of a `SpawnedProvider`, with its duration and total
+type Requested<D> = (RahmenResult<Option<D>>, Option<Duration>, Option<usize>);
+
+/// Async provider obtaining the elements of a synchronous provider on Tokio's blocking threads
+///
+/// A request given up on, such as after a timeout, keeps running, and the next one waits for it
+/// rather than asking the provider again, so no element is lost.
+#[derive(Debug)]
+pub struct SpawnedProvider<D, P> {
+    inner: Arc<Mutex<P>>,
+    /// the request still running, if it was given up on
+    pending: Option<JoinHandle<Requested<D>>>,
+    /// the outcome of a request, once looked at but not returned yet
+    ready: Option<Requested<D>>,
+    duration: Option<Duration>,
+    total: Option<usize>,
+}
+
+impl<D, P> SpawnedProvider<D, P> {
+    /// Create a new `SpawnedProvider` wrapping `inner`
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+            pending: None,
+            ready: None,
+            duration: None,
+            total: None,
+        }
+    }
+}
+
+impl<D, P> SpawnedProvider<D, P>
+where
+    D: Send + 'static,
+    P: Provider<D> + Send + 'static,
+{
+    /// Wait for the outcome of the request running, or of a new one. Dropping the future leaves
+    /// the request running, for the next call to wait for.
+    async fn request(&mut self) -> RahmenResult<Requested<D>> {
+        if let Some(requested) = self.ready.take() {
+            return Ok(requested);
+        }
+        let inner = Arc::clone(&self.inner);
+        let pending = self.pending.get_or_insert_with(|| {
+            tokio::task::spawn_blocking(move || {
+                let mut inner = inner.lock().unwrap();
+                let next = inner.next_image();
+                (next, inner.duration(), inner.total())
+            })
+        });
+        let requested = pending.await;
+        self.pending = None;
+        Ok(requested.map_err(std::io::Error::from)?)
+    }
+}
+
+impl<D, P> AsyncProvider<D> for SpawnedProvider<D, P>
+where
+    D: Send + 'static,
+    P: Provider<D> + Send + 'static,
+{
+    fn next_image(&mut self) -> ProviderFuture<'_, D> {
+        Box::pin(async move {
+            let (next, duration, total) = self.request().await?;
+            self.duration = duration;
+            self.total = total;
+            next
+        })
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn peek(&mut self, count: usize) -> PeekFuture<'_, D>
+    where
+        D: Clone + Send + 'static,
+    {
+        Box::pin(async move {
+            // the element of a request given up on comes first
+            if self.pending.is_some() {
+                let requested = self.request().await?;
+                self.ready = Some(requested);
+            }
+            let mut upcoming = vec![];
+            match &self.ready {
+                Some((Ok(Some(element)), _, _)) => upcoming.push(element.clone()),
+                // nothing comes after the end or an error
+                Some(_) => return Ok(upcoming),
+                None => {}
+            }
+            if upcoming.len() < count {
+                let inner = Arc::clone(&self.inner);
+                let rest = count - upcoming.len();
+                let peeked = tokio::task::spawn_blocking(move || inner.lock().unwrap().peek(rest))
+                    .await
+                    .map_err(std::io::Error::from)??;
+                upcoming.extend(peeked);
+            }
+            Ok(upcoming)
+        })
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::provider_lookahead::LookaheadProvider;
+
+    /// A provider returning the numbers from 0 on, each after `delay`
+    struct Slow {
+        delay: Duration,
+        next: u32,
+    }
+
+    impl Provider<u32> for Slow {
+        fn next_image(&mut self) -> RahmenResult<Option<u32>> {
+            std::thread::sleep(self.delay);
+            self.next += 1;
+            Ok(Some(self.next - 1))
+        }
+    }
+
+    /// A provider that never returns an element
+    struct Stuck;
+
+    impl AsyncProvider<u32> for Stuck {
+        fn next_image(&mut self) -> ProviderFuture<'_, u32> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    fn slow(delay: Duration) -> SpawnedProvider<u32, LookaheadProvider<u32, Slow>> {
+        SpawnedProvider::new(LookaheadProvider::new(Slow { delay, next: 0 }))
+    }
+
+    #[test]
+    fn times_out_and_returns_the_element_later() {
+        let mut provider = BlockingProvider::new(
+            slow(Duration::from_millis(200)),
+            Some(Duration::from_millis(20)),
+        )
+        .unwrap();
+        match provider.next_image() {
+            Err(RahmenError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        std::thread::sleep(Duration::from_millis(300));
+        // the request given up on finished meanwhile, and its element comes first
+        let start = Instant::now();
+        assert_eq!(provider.next_image().unwrap(), Some(0));
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn cancelling_stops_the_request_in_progress_and_all_later_ones() {
+        let mut provider = BlockingProvider::new(Stuck, None).unwrap();
+        let cancel = provider.cancel_handle();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            cancel.cancel();
+        });
+        assert!(matches!(provider.next_image(), Err(RahmenError::Terminate)));
+        assert!(matches!(provider.next_image(), Err(RahmenError::Terminate)));
+    }
+
+    #[test]
+    fn quitting_cancels() {
+        let cancel = Cancel::default();
+        let (sender, commands) = mpsc::channel();
+        let commands = cancel.on_quit(commands);
+        sender.send(Command::Next).unwrap();
+        assert_eq!(commands.recv().unwrap(), Command::Next);
+        assert!(!cancel.is_cancelled());
+        sender.send(Command::Quit).unwrap();
+        assert_eq!(commands.recv().unwrap(), Command::Quit);
+        assert!(cancel.is_cancelled());
+    }
+
+    #[test]
+    fn peeks_through_to_the_inner_provider() {
+        let mut provider = BlockingProvider::new(slow(Duration::from_millis(0)), None).unwrap();
+        assert_eq!(provider.peek(2).unwrap(), vec![0, 1]);
+        assert_eq!(provider.next_image().unwrap(), Some(0));
+        assert_eq!(provider.peek(2).unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn peeking_waits_for_a_request_given_up_on() {
+        let mut provider = slow(Duration::from_millis(100));
+        let next = provider.next_image();
+        assert!(tokio::time::timeout(Duration::from_millis(10), next)
+            .await
+            .is_err());
+        assert_eq!(provider.peek(2).await.unwrap(), vec![0, 1]);
+        assert_eq!(provider.next_image().await.unwrap(), Some(0));
+        assert_eq!(provider.next_image().await.unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,123 @@
+//! Never show blacklisted elements of another provider
+//!
+//! The blacklist is a plain text file with one path per line, so it can also be edited by hand.
+//! Lines that are empty or start with `#` are ignored. Paths are compared as the providers return
+//! them, so a path blacklisted through one input pattern may show up again through another.
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::errors::RahmenResult;
+use crate::provider::Provider;
+
+/// A persistent list of paths never to show, which can be shared with the control inputs
+#[derive(Debug, Clone)]
+pub struct Blacklist {
+    path: PathBuf,
+    entries: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl Blacklist {
+    /// Open the blacklist in the file at `path`, which is created on the first addition
+    pub fn open<P: Into<PathBuf>>(path: P) -> RahmenResult<Self> {
+        let path = path.into();
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(text) => text
+                .lines()
+                .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                .map(PathBuf::from)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            entries: Arc::new(Mutex::new(entries)),
+        })
+    }
+
+    /// Whether `element` is blacklisted
+    pub fn contains(&self, element: &Path) -> bool {
+        self.entries.lock().unwrap().contains(element)
+    }
+
+    /// Blacklist `element`, appending it to the file
+    pub fn add(&self, element: &Path) -> RahmenResult<()> {
+        if self.entries.lock().unwrap().insert(element.to_path_buf()) {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            writeln!(file, "{}", element.to_string_lossy())?;
+        }
+        Ok(())
+    }
+
+    /// The number of blacklisted paths
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether nothing is blacklisted
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Provider skipping the elements of another provider which are blacklisted
+#[derive(Debug)]
+pub struct BlacklistProvider<P: Provider<PathBuf>> {
+    inner: P,
+    blacklist: Blacklist,
+}
+
+impl<P: Provider<PathBuf>> BlacklistProvider<P> {
+    /// Create a new `BlacklistProvider` wrapping `inner`, skipping the paths in `blacklist`
+    pub fn new(inner: P, blacklist: Blacklist) -> Self {
+        Self { inner, blacklist }
+    }
+}
+
+impl<P: Provider<PathBuf>> Provider<PathBuf> for BlacklistProvider<P> {
+    fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
+        // a looping provider repeats its elements at the latest after twice as many blacklisted
+        // elements in a row as there are, and then has nothing else left to show
+        let max_skipped = 2 * self.blacklist.len();
+        let mut skipped = 0;
+        while let Some(element) = self.inner.next_image()? {
+            if !self.blacklist.contains(&element) {
+                return Ok(Some(element));
+            }
+            skipped += 1;
+            if skipped > max_skipped {
+                warn!("Every image is blacklisted");
+                return Ok(None);
+            }
+        }
+        Ok(None)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.inner.duration()
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<PathBuf>> {
+        // blacklisted elements are left out, so fewer than `count` may be returned
+        Ok(self
+            .inner
+            .peek(count)?
+            .into_iter()
+            .filter(|element| !self.blacklist.contains(element))
+            .collect())
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
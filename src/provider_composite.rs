@@ -0,0 +1,106 @@
+//! Combine the elements of several providers
+
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::errors::RahmenResult;
+use crate::provider::Provider;
+
+/// A provider combined with others, with its scheduling state
+#[derive(Debug)]
+struct Source<P> {
+    provider: P,
+    weight: u32,
+    current: i64,
+    since: usize,
+}
+
+/// Provider interleaving the elements of several providers according to their weights.
+///
+/// Sources are picked by smooth weighted round-robin, so next to a source of weight 9, a source of
+/// weight 1 is picked once every ten elements, evenly spread. On top of that, a source that wasn't
+/// picked for `max_gap` elements is picked next regardless of its weight, so a huge album can't
+/// starve a small folder. Exhausted sources are dropped; once all are exhausted, so is this
+/// provider.
+#[derive(Debug)]
+pub struct CompositeProvider<D, P: Provider<D>> {
+    sources: Vec<Source<P>>,
+    max_gap: Option<usize>,
+    duration: Option<Duration>,
+    _element: PhantomData<fn() -> D>,
+}
+
+impl<D, P: Provider<D>> CompositeProvider<D, P> {
+    /// Create a new `CompositeProvider` from providers and their weights
+    pub fn new<I: IntoIterator<Item = (P, u32)>>(sources: I, max_gap: Option<usize>) -> Self {
+        Self {
+            sources: sources
+                .into_iter()
+                .map(|(provider, weight)| Source {
+                    provider,
+                    weight,
+                    current: 0,
+                    since: 0,
+                })
+                .collect(),
+            max_gap,
+            duration: None,
+            _element: PhantomData,
+        }
+    }
+
+    /// Pick the source to take the next element from
+    fn pick(&mut self) -> usize {
+        let total: i64 = self.sources.iter().map(|s| i64::from(s.weight)).sum();
+        for source in &mut self.sources {
+            source.current += i64::from(source.weight);
+            source.since += 1;
+        }
+        // the source waiting longest, if it waited too long
+        let starving = self.max_gap.and_then(|max_gap| {
+            self.sources
+                .iter()
+                .enumerate()
+                .filter(|(_, source)| source.since > max_gap)
+                .max_by_key(|(_, source)| source.since)
+                .map(|(index, _)| index)
+        });
+        let index = starving.unwrap_or_else(|| {
+            self.sources
+                .iter()
+                .enumerate()
+                .max_by_key(|(index, source)| (source.current, std::cmp::Reverse(*index)))
+                .map(|(index, _)| index)
+                .unwrap_or_default()
+        });
+        self.sources[index].current -= total;
+        self.sources[index].since = 0;
+        index
+    }
+}
+
+impl<D, P: Provider<D>> Provider<D> for CompositeProvider<D, P> {
+    fn next_image(&mut self) -> RahmenResult<Option<D>> {
+        while !self.sources.is_empty() {
+            let index = self.pick();
+            let source = &mut self.sources[index].provider;
+            match source.next_image()? {
+                Some(element) => {
+                    self.duration = source.duration();
+                    return Ok(Some(element));
+                }
+                None => {
+                    info!("Source {} is exhausted", index);
+                    self.sources.remove(index);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+}
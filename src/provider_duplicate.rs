@@ -0,0 +1,366 @@
+//! Skip near-duplicates of images shown shortly before
+//!
+//! Bursts, bracketed exposures and re-exports of the same photo make for a dull slideshow. Each
+//! image gets a perceptual hash, a difference hash of 64 bits computed from a tiny grayscale
+//! version of it, which barely changes with exposure, size or compression. An image is skipped if
+//! its hash differs in at most `threshold` bits from the hash of one of the last `window` images.
+//!
+//! Hashing decodes the image, although at a small size, so it happens on a thread of its own,
+//! which indexes the upcoming images ahead of the slideshow. An image that isn't hashed yet when it
+//! comes up is shown, and compared with the images after it once its hash is known. The hashes are
+//! cached in a file, with a line `hash size mtime path` per image, in which backslashes and line
+//! breaks of the path are escaped. An image is hashed again when its size or modification time
+//! changes; the line appended last wins, and the file is compacted when it is opened.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+#[cfg(unix)]
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+use tracing::{debug, info, warn};
+
+use crate::errors::RahmenResult;
+use crate::provider::{load_image_from_path, Provider};
+use crate::provider_lookahead::LookaheadProvider;
+
+/// Images are decoded with at most this many pixels for hashing
+const HASH_SOURCE_PIXELS: usize = 256 * 256;
+
+/// How many upcoming images are hashed ahead of the slideshow
+const INDEX_AHEAD: usize = 20;
+
+/// After skipping this many images in a row, the next one is shown anyway, so a short playlist of
+/// similar images does not skip forever
+const MAX_SKIPPED: usize = 100;
+
+/// The difference hash of `img`: one bit per pair of horizontally adjacent pixels of a 9x8
+/// grayscale version, set if the left one is darker
+pub fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let darker = small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(darker);
+        }
+    }
+    hash
+}
+
+/// The number of bits in which two hashes differ
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// A cached hash, valid while the image keeps its size and modification time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    size: u64,
+    mtime: u64,
+    hash: u64,
+}
+
+/// The size and modification time of the image at `image`
+fn stamp(image: &Path) -> std::io::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(image)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    Ok((metadata.len(), mtime))
+}
+
+/// The bytes of `path`, which can be any on Unix
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+/// The bytes of `path` in UTF-8, which paths that are not Unicode lose characters in
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+/// The path of `bytes`, as given by `path_bytes`
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    Some(OsString::from_vec(bytes).into())
+}
+
+/// The path of `bytes`, as given by `path_bytes`, if they are UTF-8
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(Into::into)
+}
+
+/// `path` with backslashes and line breaks escaped, so it fits on a line of the cache file
+fn escape(path: &Path) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(path.as_os_str().len());
+    for byte in path_bytes(path).iter() {
+        match *byte {
+            b'\\' => escaped.extend_from_slice(b"\\\\"),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            b'\r' => escaped.extend_from_slice(b"\\r"),
+            byte => escaped.push(byte),
+        }
+    }
+    escaped
+}
+
+/// The path escaped by `escape`, if the escapes are valid
+fn unescape(escaped: &[u8]) -> Option<PathBuf> {
+    let mut path = Vec::with_capacity(escaped.len());
+    let mut bytes = escaped.iter().copied();
+    while let Some(byte) = bytes.next() {
+        path.push(match byte {
+            b'\\' => match bytes.next()? {
+                b'\\' => b'\\',
+                b'n' => b'\n',
+                b'r' => b'\r',
+                _ => return None,
+            },
+            byte => byte,
+        });
+    }
+    path_from_bytes(path)
+}
+
+/// The line of the cache file for the hash of `image`
+fn line(image: &Path, entry: &Entry) -> Vec<u8> {
+    let mut line = format!("{:016x} {} {} ", entry.hash, entry.size, entry.mtime).into_bytes();
+    line.extend(escape(image));
+    line.push(b'\n');
+    line
+}
+
+/// The image and the hash of a line of the cache file
+fn parse_line(line: &[u8]) -> Option<(PathBuf, Entry)> {
+    let mut fields = line.splitn(4, |byte| *byte == b' ');
+    let mut field = || std::str::from_utf8(fields.next()?).ok();
+    let hash = u64::from_str_radix(field()?, 16).ok()?;
+    let size = field()?.parse().ok()?;
+    let mtime = field()?.parse().ok()?;
+    let image = unescape(fields.next()?)?;
+    Some((image, Entry { size, mtime, hash }))
+}
+
+/// The hashes of images, persisted in a file
+#[derive(Debug)]
+pub struct HashCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, Entry>,
+}
+
+impl HashCache {
+    /// Open the cache in the file at `path`, which is created on the first addition, and compact
+    /// it if it holds outdated lines
+    pub fn open<P: Into<PathBuf>>(path: P) -> RahmenResult<Self> {
+        let path = path.into();
+        let (entries, lines) = match std::fs::read(&path) {
+            Ok(bytes) => {
+                let lines: Vec<_> = bytes
+                    .split(|byte| *byte == b'\n')
+                    .filter(|line| !line.is_empty())
+                    .collect();
+                let entries: HashMap<_, _> =
+                    lines.iter().filter_map(|line| parse_line(line)).collect();
+                (entries, lines.len())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (HashMap::new(), 0),
+            Err(e) => return Err(e.into()),
+        };
+        let cache = Self { path, entries };
+        if lines > cache.entries.len() {
+            match cache.compact() {
+                Ok(()) => debug!(
+                    "Compacted the hash cache from {} to {} lines",
+                    lines,
+                    cache.entries.len()
+                ),
+                Err(e) => warn!("Cannot compact the hash cache {:?}: {}", cache.path, e),
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Write the file anew with a line per image, replacing it atomically
+    fn compact(&self) -> RahmenResult<()> {
+        let mut temporary = self.path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let mut text = vec![];
+        for (image, entry) in &self.entries {
+            text.extend(line(image, entry));
+        }
+        std::fs::write(&temporary, &text)?;
+        std::fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+
+    /// The number of cached hashes
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no hashes are cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The cached hash of the image at `image`, unless the image changed since it was hashed
+    pub fn get(&self, image: &Path) -> Option<u64> {
+        let entry = self.entries.get(image)?;
+        match stamp(image) {
+            Ok((size, mtime)) if entry.size == size && entry.mtime == mtime => Some(entry.hash),
+            _ => None,
+        }
+    }
+
+    /// Cache `hash` for the image at `image`, which had `size` and `mtime` when it was hashed
+    fn insert(&mut self, image: &Path, size: u64, mtime: u64, hash: u64) -> RahmenResult<()> {
+        let entry = Entry { size, mtime, hash };
+        self.entries.insert(image.to_path_buf(), entry);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line(image, &entry))?;
+        Ok(())
+    }
+}
+
+/// Hash the image at `image` into `cache`, unless it is cached already. The lock is not held while
+/// hashing, so looking up hashes never waits for an image to be decoded.
+fn index(cache: &Mutex<HashCache>, image: &Path) -> RahmenResult<()> {
+    if cache.lock().unwrap().get(image).is_some() {
+        return Ok(());
+    }
+    let (size, mtime) = stamp(image)?;
+    let hash = dhash(&load_image_from_path(image, Some(HASH_SOURCE_PIXELS))?);
+    cache.lock().unwrap().insert(image, size, mtime, hash)
+}
+
+/// Provider skipping the elements of another provider which look almost like a recent one
+#[derive(Debug)]
+pub struct DuplicateProvider<P: Provider<PathBuf>> {
+    inner: LookaheadProvider<PathBuf, P>,
+    cache: Arc<Mutex<HashCache>>,
+    /// sends images to hash to the indexing thread
+    indexer: Sender<PathBuf>,
+    /// the images sent to the indexing thread last, so they're not sent again
+    indexing: VecDeque<PathBuf>,
+    /// the last elements returned, newest last
+    recent: VecDeque<PathBuf>,
+    window: usize,
+    threshold: u32,
+}
+
+impl<P: Provider<PathBuf>> DuplicateProvider<P> {
+    /// Create a new `DuplicateProvider` wrapping `inner`, skipping elements whose hash differs in
+    /// at most `threshold` bits from one of the last `window` elements, with hashes cached in
+    /// `cache`. The upcoming elements are hashed on a thread of its own.
+    pub fn new(inner: P, cache: HashCache, window: usize, threshold: u32) -> Self {
+        let cache = Arc::new(Mutex::new(cache));
+        let (indexer, images) = std::sync::mpsc::channel::<PathBuf>();
+        let shared = Arc::clone(&cache);
+        std::thread::spawn(move || {
+            for image in images {
+                if let Err(e) = index(&shared, &image) {
+                    // loading it will fail too, and report the error
+                    debug!("Cannot hash {:?}: {}", image, e);
+                }
+            }
+        });
+        Self {
+            inner: LookaheadProvider::new(inner),
+            cache,
+            indexer,
+            indexing: VecDeque::with_capacity(2 * INDEX_AHEAD),
+            recent: VecDeque::with_capacity(window),
+            window,
+            threshold,
+        }
+    }
+
+    /// Have `image` hashed, unless it was requested recently
+    fn index(&mut self, image: PathBuf) {
+        if self.indexing.contains(&image) {
+            return;
+        }
+        if self.indexing.len() >= 2 * INDEX_AHEAD {
+            self.indexing.pop_front();
+        }
+        self.indexing.push_back(image.clone());
+        // the thread only stops with the provider
+        let _ = self.indexer.send(image);
+    }
+
+    /// Whether `hash` is close to the hash of a recent element, as far as they're hashed
+    fn is_duplicate(&self, hash: u64) -> bool {
+        let cache = self.cache.lock().unwrap();
+        self.recent
+            .iter()
+            .filter_map(|recent| cache.get(recent))
+            .any(|recent| distance(recent, hash) <= self.threshold)
+    }
+}
+
+impl<P: Provider<PathBuf>> Provider<PathBuf> for DuplicateProvider<P> {
+    fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
+        let mut skipped = 0;
+        while let Some(element) = self.inner.next_image()? {
+            // hash the images coming up before they're needed
+            for upcoming in self.inner.peek(INDEX_AHEAD)? {
+                self.index(upcoming);
+            }
+            let hash = self.cache.lock().unwrap().get(&element);
+            match hash {
+                Some(hash) if self.is_duplicate(hash) && skipped < MAX_SKIPPED => {
+                    info!(
+                        "Skipping {:?}, which looks like an image shown recently",
+                        element
+                    );
+                    skipped += 1;
+                    continue;
+                }
+                Some(_) => {}
+                // it's compared with later images once it's hashed
+                None => self.index(element.clone()),
+            }
+            if self.recent.len() >= self.window {
+                self.recent.pop_front();
+            }
+            if self.window > 0 {
+                self.recent.push_back(element.clone());
+            }
+            return Ok(Some(element));
+        }
+        Ok(None)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.inner.duration()
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<PathBuf>> {
+        // duplicates are not known before hashing, so some of these may be skipped
+        self.inner.peek(count)
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
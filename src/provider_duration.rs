@@ -0,0 +1,128 @@
+//! Show some elements of another provider longer or shorter than the configured delay
+//!
+//! Durations requested by the inner provider, such as those of playlist entries, come first.
+//! Otherwise, the duration is read from a sidecar file next to the image, named like it with
+//! `.duration` appended and holding the number of seconds, and then from a metadata tag of the
+//! image, or of its XMP sidecar file, named like the image with the extension replaced by `.xmp`
+//! or with `.xmp` appended.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::errors::RahmenResult;
+use crate::favorite::sidecar_path;
+use crate::metadata::Metadata;
+use crate::provider::Provider;
+use crate::xmp;
+
+/// Parse a number of seconds, optionally followed by `s`, such as `30` or `2.5s`
+pub fn parse_seconds(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let seconds: f64 = text.strip_suffix('s').unwrap_or(text).trim().parse().ok()?;
+    if seconds.is_finite() && seconds >= 0. {
+        Some(Duration::from_secs_f64(seconds))
+    } else {
+        None
+    }
+}
+
+/// `path` with `extension` appended, keeping its own extension
+fn with_appended(path: &Path, extension: &str) -> PathBuf {
+    let mut appended = OsString::from(path.as_os_str());
+    appended.push(extension);
+    appended.into()
+}
+
+/// Provider reading the durations of the elements of another provider from their sidecar files
+/// or metadata
+#[derive(Debug)]
+pub struct DurationProvider<P: Provider<PathBuf>> {
+    inner: P,
+    /// whether to read `.duration` sidecar files
+    sidecar: bool,
+    /// the tag holding the duration, if any
+    tag: Option<String>,
+    duration: Option<Duration>,
+}
+
+impl<P: Provider<PathBuf>> DurationProvider<P> {
+    /// Create a new `DurationProvider` wrapping `inner`, reading `.duration` sidecar files if
+    /// `sidecar` is set, and the metadata tag `tag`, if any
+    pub fn new(inner: P, sidecar: bool, tag: Option<String>) -> Self {
+        Self {
+            inner,
+            sidecar,
+            tag,
+            duration: None,
+        }
+    }
+
+    /// The duration stored in a sidecar file of `path`, if any
+    fn from_sidecar(&self, path: &Path) -> Option<Duration> {
+        let sidecar = with_appended(path, ".duration");
+        let text = std::fs::read_to_string(&sidecar).ok()?;
+        let duration = parse_seconds(&text);
+        if duration.is_none() {
+            warn!("Invalid duration in {:?}: {}", sidecar, text.trim());
+        }
+        duration
+    }
+
+    /// The duration stored in the metadata of `path`, or of its XMP sidecar file, if any
+    fn from_tag(&self, path: &Path) -> Option<Duration> {
+        let tag = self.tag.as_deref()?;
+        let value = std::iter::once(path.to_path_buf())
+            .chain(std::iter::once(sidecar_path(path)).filter(|xmp| xmp.exists()))
+            .filter_map(|file| Metadata::new_from_path(file).ok())
+            .find_map(|metadata| {
+                if xmp::handles(tag) {
+                    xmp::tag(&metadata, tag)
+                } else if metadata.has_tag(tag) {
+                    metadata.get_tag_interpreted_string(tag).ok()
+                } else {
+                    None
+                }
+            })?;
+        let duration = parse_seconds(&value);
+        if duration.is_none() {
+            warn!("Invalid duration in {} of {:?}: {}", tag, path, value);
+        }
+        duration
+    }
+}
+
+impl<P: Provider<PathBuf>> Provider<PathBuf> for DurationProvider<P> {
+    fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
+        let next = self.inner.next_image()?;
+        self.duration = match &next {
+            Some(path) => self
+                .inner
+                .duration()
+                .or_else(|| {
+                    if self.sidecar {
+                        self.from_sidecar(path)
+                    } else {
+                        None
+                    }
+                })
+                .or_else(|| self.from_tag(path)),
+            None => None,
+        };
+        Ok(next)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<PathBuf>> {
+        self.inner.peek(count)
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
@@ -9,19 +9,33 @@ use std::path::PathBuf;
 #[derive(Debug)]
 pub struct GlobProvider<I: Iterator<Item = PathBuf>> {
     path_iter: I,
+    returned: usize,
 }
 
 /// Create a new `GlobProvider`
 pub fn create(pattern: &str) -> RahmenResult<GlobProvider<impl Iterator<Item = PathBuf>>> {
+    // list all matches up front, so their number is known
+    let paths: Vec<_> = glob(pattern)
+        .expect("Incorrect pattern")
+        .filter_map(Result::ok)
+        .collect();
     Ok(GlobProvider {
-        path_iter: glob(pattern)
-            .expect("Incorrect pattern")
-            .filter_map(Result::ok),
+        path_iter: paths.into_iter(),
+        returned: 0,
     })
 }
 
 impl<I: Iterator<Item = PathBuf>> Provider<PathBuf> for GlobProvider<I> {
     fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
-        Ok(self.path_iter.next())
+        let next = self.path_iter.next();
+        self.returned += next.is_some() as usize;
+        Ok(next)
+    }
+
+    fn total(&self) -> Option<usize> {
+        match self.path_iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(self.returned + lower),
+            _ => None,
+        }
     }
 }
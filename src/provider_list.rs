@@ -1,35 +1,95 @@
 //! Provide a list of files from a file input
 
+use tracing::warn;
+
 use crate::errors::RahmenResult;
 use crate::provider::Provider;
+use crate::transition::TransitionOverride;
+use std::collections::HashMap;
 use std::io::BufRead;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The transitions given by the entries of file lists, by path, shared with the main loop
+#[derive(Debug, Clone, Default)]
+pub struct Transitions(Arc<Mutex<HashMap<PathBuf, TransitionOverride>>>);
+
+impl Transitions {
+    /// The transition given for the image at `path`, if any
+    pub fn of(&self, path: &Path) -> Option<TransitionOverride> {
+        self.0.lock().unwrap().get(path).cloned()
+    }
+
+    /// Whether no entry read so far gave a transition
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+}
 
 /// Provider to read paths line-by-line from a reader, which can be backed by an input stream or
-/// file
+/// file.
+///
+/// Each line can carry options after the path, separated by tabs, for example
+/// `holidays.jpg<TAB>duration=30` to show this image for 30 seconds, or
+/// `title.jpg<TAB>transition=3,linear` to fade to it in 3 seconds, at constant speed.
 #[derive(Debug)]
 pub struct ListProvider<R: BufRead> {
     reader: R,
     buffer: String,
+    duration: Option<Duration>,
+    transitions: Transitions,
 }
 
 impl<R: BufRead> ListProvider<R> {
-    /// Create a new `ListProvider`, passing in a reader
-    pub fn new(reader: R) -> Self {
+    /// Create a new `ListProvider`, passing in a reader, recording the transitions of its entries
+    /// in `transitions`
+    pub fn new(reader: R, transitions: Transitions) -> Self {
         Self {
             reader,
             buffer: String::new(),
+            duration: None,
+            transitions,
         }
     }
 }
+
 impl<R: BufRead> Provider<PathBuf> for ListProvider<R> {
     fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
         self.buffer.clear();
+        self.duration = None;
         if self.reader.read_line(&mut self.buffer)? == 0 {
             Ok(None)
         } else {
-            let trimmed = &self.buffer.trim();
-            Ok(Some(PathBuf::from(trimmed)))
+            let mut fields = self.buffer.trim_end_matches(&['\r', '\n'][..]).split('\t');
+            let path = PathBuf::from(fields.next().unwrap_or_default().trim());
+            let mut transition = None;
+            for option in fields {
+                match option.trim().split_once('=') {
+                    Some(("duration", seconds)) => match seconds.parse::<f64>() {
+                        Ok(seconds) if seconds.is_finite() && seconds >= 0. => {
+                            self.duration = Some(Duration::from_secs_f64(seconds))
+                        }
+                        _ => warn!("Invalid duration for {:?}: {}", path, seconds),
+                    },
+                    Some(("transition", value)) => match value.parse() {
+                        Ok(value) => transition = Some(value),
+                        Err(e) => warn!("Invalid transition for {:?}: {}", path, e),
+                    },
+                    _ => warn!("Ignoring unsupported option for {:?}: {}", path, option),
+                }
+            }
+            // an entry listed again without a transition gets the configured one
+            let mut transitions = self.transitions.0.lock().unwrap();
+            match transition {
+                Some(transition) => transitions.insert(path.clone(), transition),
+                None => transitions.remove(&path),
+            };
+            Ok(Some(path))
         }
     }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
 }
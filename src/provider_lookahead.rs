@@ -0,0 +1,66 @@
+//! Look ahead at the upcoming elements of another provider
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::errors::RahmenResult;
+use crate::provider::Provider;
+
+/// Provider buffering elements of another provider, so they can be peeked at before they are
+/// requested
+#[derive(Debug)]
+pub struct LookaheadProvider<D, P: Provider<D>> {
+    inner: P,
+    buffer: VecDeque<(D, Option<Duration>)>,
+    duration: Option<Duration>,
+}
+
+impl<D, P: Provider<D>> LookaheadProvider<D, P> {
+    /// Create a new `LookaheadProvider` wrapping `inner`
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            buffer: VecDeque::new(),
+            duration: None,
+        }
+    }
+}
+
+impl<D: Clone, P: Provider<D>> Provider<D> for LookaheadProvider<D, P> {
+    fn next_image(&mut self) -> RahmenResult<Option<D>> {
+        let next = match self.buffer.pop_front() {
+            Some(next) => Some(next),
+            None => self
+                .inner
+                .next_image()?
+                .map(|element| (element, self.inner.duration())),
+        };
+        Ok(next.map(|(element, duration)| {
+            self.duration = duration;
+            element
+        }))
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<D>> {
+        while self.buffer.len() < count {
+            match self.inner.next_image()? {
+                Some(element) => self.buffer.push_back((element, self.inner.duration())),
+                None => break,
+            }
+        }
+        Ok(self
+            .buffer
+            .iter()
+            .take(count)
+            .map(|(element, _)| element.clone())
+            .collect())
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
@@ -0,0 +1,129 @@
+//! Select images of the same orientation as the screen
+//!
+//! On a frame mounted in portrait, a landscape image fills only a band across the middle, and the
+//! other way round. Images can either be required to match the orientation of the screen, or only
+//! preferred to, in which case an image of the other orientation is shown after skipping
+//! `MAX_SKIPPED` in a row, so a collection mostly of the other orientation still plays. Images
+//! within the tolerance of square, and all images on a square screen, match either way.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::debug;
+
+use crate::display::ScreenSize;
+use crate::errors::RahmenResult;
+use crate::provider::Provider;
+use crate::rotate;
+
+/// After skipping this many images in a row, the next one is shown anyway when only preferring
+const MAX_SKIPPED: usize = 20;
+
+/// How strictly to select images by orientation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OrientationMode {
+    /// Skip images of the other orientation, unless too many were skipped in a row
+    Prefer,
+    /// Skip all images of the other orientation
+    Require,
+}
+
+/// The orientation of an image or a screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// wider than high
+    Landscape,
+    /// higher than wide
+    Portrait,
+    /// about as wide as high
+    Square,
+}
+
+impl Orientation {
+    /// The orientation of something `width` wide and `height` high, which is square if its longer
+    /// side is at most `1 + tolerance` times its shorter one
+    pub fn of(width: u32, height: u32, tolerance: f64) -> Self {
+        let (long, short) = (width.max(height), width.min(height).max(1));
+        if f64::from(long) <= f64::from(short) * (1. + tolerance) {
+            Orientation::Square
+        } else if width > height {
+            Orientation::Landscape
+        } else {
+            Orientation::Portrait
+        }
+    }
+
+    /// Whether an image of this orientation matches a screen of orientation `screen`
+    pub fn matches(self, screen: Orientation) -> bool {
+        self == screen || self == Orientation::Square || screen == Orientation::Square
+    }
+}
+
+/// Provider skipping the elements of another provider whose orientation differs from the screen's
+#[derive(Debug)]
+pub struct OrientationProvider<P: Provider<PathBuf>> {
+    inner: P,
+    mode: OrientationMode,
+    tolerance: f64,
+    screen: ScreenSize,
+}
+
+impl<P: Provider<PathBuf>> OrientationProvider<P> {
+    /// Create a new `OrientationProvider` wrapping `inner`, selecting images by the orientation of
+    /// the screen of size `screen` as `mode` says, taking those whose sides differ by at most
+    /// `tolerance` as square
+    pub fn new(inner: P, mode: OrientationMode, tolerance: f64, screen: ScreenSize) -> Self {
+        Self {
+            inner,
+            mode,
+            tolerance,
+            screen,
+        }
+    }
+
+    /// Whether `element` has the orientation of the screen, or either is unknown
+    fn matches(&self, element: &Path) -> bool {
+        let (screen_width, screen_height) = match self.screen.get() {
+            Some(size) => size,
+            None => return true,
+        };
+        match rotate::dimensions(element) {
+            Ok((width, height)) => Orientation::of(width, height, self.tolerance)
+                .matches(Orientation::of(screen_width, screen_height, self.tolerance)),
+            Err(_) => true,
+        }
+    }
+}
+
+impl<P: Provider<PathBuf>> Provider<PathBuf> for OrientationProvider<P> {
+    fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
+        let mut skipped = 0;
+        while let Some(element) = self.inner.next_image()? {
+            if self.matches(&element)
+                || (self.mode == OrientationMode::Prefer && skipped >= MAX_SKIPPED)
+            {
+                return Ok(Some(element));
+            }
+            debug!("Skipping {:?}, which does not fit the screen", element);
+            skipped += 1;
+        }
+        Ok(None)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.inner.duration()
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<PathBuf>> {
+        let upcoming = self.inner.peek(count)?;
+        Ok(upcoming
+            .into_iter()
+            .filter(|element| self.matches(element))
+            .collect())
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
@@ -0,0 +1,162 @@
+//! Switch between named playlists
+//!
+//! The playlist shown is selected through a handle, usually following a schedule. Each playlist
+//! continues where it left off when selected again, and starts over once it runs out, so it can
+//! be shown for hours. Without a selection, or if the selected playlist is empty, the elements of
+//! the main provider are shown.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::errors::RahmenResult;
+use crate::provider::Provider;
+
+/// Opens the provider of a playlist, given its input
+pub type Opener = Box<dyn Fn(&str) -> RahmenResult<Box<dyn Provider<PathBuf>>>>;
+
+/// Handle to select the playlist shown, which can be used from outside the dataflow
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    selected: Arc<Mutex<Option<String>>>,
+}
+
+impl Selection {
+    /// Show the playlist `name` from the next element on, or the main provider for `None`
+    pub fn select(&self, name: Option<String>) {
+        *self.selected.lock().unwrap() = name;
+    }
+}
+
+/// A playlist, and its provider once opened
+struct Playlist {
+    input: String,
+    provider: Option<Box<dyn Provider<PathBuf>>>,
+}
+
+/// Provider emitting the elements of the selected playlist
+pub struct PlaylistProvider<P: Provider<PathBuf>> {
+    main: P,
+    playlists: BTreeMap<String, Playlist>,
+    open: Opener,
+    selection: Selection,
+    /// the playlist shown, for logging changes
+    current: Option<String>,
+    duration: Option<Duration>,
+    total: Option<usize>,
+}
+
+impl<P: Provider<PathBuf>> std::fmt::Debug for PlaylistProvider<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PlaylistProvider")
+            .field("playlists", &self.playlists.keys().collect::<Vec<_>>())
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl<P: Provider<PathBuf>> PlaylistProvider<P> {
+    /// Create a new `PlaylistProvider` showing `main`, or one of `playlists`, given by their names
+    /// and inputs, which are opened with `open` when first selected
+    pub fn new<I: IntoIterator<Item = (String, String)>>(
+        main: P,
+        playlists: I,
+        open: Opener,
+    ) -> Self {
+        Self {
+            main,
+            playlists: playlists
+                .into_iter()
+                .map(|(name, input)| {
+                    (
+                        name,
+                        Playlist {
+                            input,
+                            provider: None,
+                        },
+                    )
+                })
+                .collect(),
+            open,
+            selection: Selection::default(),
+            current: None,
+            duration: None,
+            total: None,
+        }
+    }
+
+    /// Obtain a handle to select the playlist shown
+    pub fn selection(&self) -> Selection {
+        self.selection.clone()
+    }
+
+    /// The name of the selected playlist, if any
+    fn selected(&mut self) -> Option<String> {
+        let selected = self.selection.selected.lock().unwrap().clone();
+        if selected != self.current {
+            info!("Playlist: {}", selected.as_deref().unwrap_or("(input)"));
+            self.current = selected.clone();
+        }
+        selected
+    }
+}
+
+impl<P: Provider<PathBuf>> Provider<PathBuf> for PlaylistProvider<P> {
+    fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
+        if let Some(name) = self.selected() {
+            if let Some(playlist) = self.playlists.get_mut(&name) {
+                let mut started = false;
+                loop {
+                    if playlist.provider.is_none() {
+                        playlist.provider = Some((self.open)(&playlist.input)?);
+                        started = true;
+                    }
+                    if let Some(provider) = &mut playlist.provider {
+                        if let Some(next) = provider.next_image()? {
+                            self.duration = provider.duration();
+                            self.total = provider.total();
+                            return Ok(Some(next));
+                        }
+                    }
+                    // start over once the playlist runs out
+                    playlist.provider = None;
+                    if started {
+                        warn!("Playlist {} is empty, showing the input", name);
+                        break;
+                    }
+                }
+            }
+        }
+        let next = self.main.next_image()?;
+        self.duration = self.main.duration();
+        self.total = self.main.total();
+        Ok(next)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<PathBuf>> {
+        let playlist = match self.selected() {
+            Some(name) => self.playlists.get_mut(&name),
+            None => None,
+        };
+        match playlist {
+            Some(Playlist {
+                provider: Some(provider),
+                ..
+            }) => provider.peek(count),
+            // not opened yet
+            Some(_) => Ok(vec![]),
+            None => self.main.peek(count),
+        }
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.total
+    }
+}
@@ -0,0 +1,166 @@
+//! Skip blurry and nearly black or white images
+//!
+//! Camera rolls are full of accidental shots: blurred by motion, taken in a pocket, or pointed at
+//! the sky. Each image is measured on a copy at most `MEASURE_SIZE` pixels wide and high, so the
+//! measures don't depend on its resolution. The sharpness is the variance of the Laplacian of the
+//! grayscale copy, which is low for images without edges, and the brightness is the mean gray
+//! value, from 0 to 255. Measures are logged at the debug level, to find thresholds that suit a
+//! collection.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+use tracing::{debug, info, warn};
+
+use crate::errors::RahmenResult;
+use crate::provider::{load_image_from_path, Provider};
+use crate::stats;
+
+/// Images are measured on a copy at most this many pixels wide and high
+const MEASURE_SIZE: u32 = 512;
+
+/// After skipping this many images in a row, the next one is shown anyway, so a looping collection
+/// without an image passing the gate does not skip forever
+const MAX_SKIPPED: usize = 100;
+
+/// How sharp and bright an image is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// the variance of the Laplacian
+    pub sharpness: f64,
+    /// the mean gray value, from 0 to 255
+    pub brightness: f64,
+}
+
+/// Measure the quality of `img`
+pub fn measure(img: &DynamicImage) -> Quality {
+    let gray = if img.width() > MEASURE_SIZE || img.height() > MEASURE_SIZE {
+        img.resize(MEASURE_SIZE, MEASURE_SIZE, FilterType::Triangle)
+            .to_luma8()
+    } else {
+        img.to_luma8()
+    };
+    let (width, height) = gray.dimensions();
+    let brightness = gray.pixels().map(|pixel| f64::from(pixel[0])).sum::<f64>()
+        / f64::from((width * height).max(1));
+    Quality {
+        sharpness: f64::from(stats::sharpness(&gray)),
+        brightness,
+    }
+}
+
+/// The least quality an image needs to be shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityGate {
+    /// the least sharpness
+    pub min_sharpness: f64,
+    /// the least brightness
+    pub min_brightness: f64,
+    /// the most brightness
+    pub max_brightness: f64,
+}
+
+impl QualityGate {
+    /// Why an image of `quality` is not shown, if it isn't
+    pub fn reject(&self, quality: Quality) -> Option<&'static str> {
+        if quality.brightness < self.min_brightness {
+            Some("too dark")
+        } else if quality.brightness > self.max_brightness {
+            Some("too bright")
+        } else if quality.sharpness < self.min_sharpness {
+            Some("blurry")
+        } else {
+            None
+        }
+    }
+}
+
+/// Provider skipping the elements of another provider which don't pass a `QualityGate`
+#[derive(Debug)]
+pub struct QualityProvider<P: Provider<PathBuf>> {
+    inner: P,
+    gate: QualityGate,
+    /// whether each element measured so far passed
+    passed: HashMap<PathBuf, bool>,
+}
+
+impl<P: Provider<PathBuf>> QualityProvider<P> {
+    /// Create a new `QualityProvider` wrapping `inner`, skipping the elements `gate` rejects
+    pub fn new(inner: P, gate: QualityGate) -> Self {
+        Self {
+            inner,
+            gate,
+            passed: HashMap::new(),
+        }
+    }
+
+    /// Whether `element` passes the gate, measuring it unless measured before
+    fn passes(&mut self, element: &Path) -> bool {
+        if let Some(passed) = self.passed.get(element) {
+            return *passed;
+        }
+        let max_pixels = (MEASURE_SIZE * MEASURE_SIZE) as usize;
+        let passed = match load_image_from_path(element, Some(max_pixels)) {
+            Ok(img) => {
+                let quality = measure(&img);
+                debug!(
+                    "Sharpness {:.0}, brightness {:.0}: {:?}",
+                    quality.sharpness, quality.brightness, element
+                );
+                match self.gate.reject(quality) {
+                    Some(reason) => {
+                        info!("Skipping {:?}, which is {}", element, reason);
+                        false
+                    }
+                    None => true,
+                }
+            }
+            Err(e) => {
+                // loading it will fail too, and report the error
+                warn!("Cannot measure {:?}: {}", element, e);
+                true
+            }
+        };
+        self.passed.insert(element.to_path_buf(), passed);
+        passed
+    }
+}
+
+impl<P: Provider<PathBuf>> Provider<PathBuf> for QualityProvider<P> {
+    fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
+        let mut skipped = 0;
+        while let Some(element) = self.inner.next_image()? {
+            if self.passes(&element) {
+                return Ok(Some(element));
+            }
+            skipped += 1;
+            if skipped >= MAX_SKIPPED {
+                warn!("Skipped {} images in a row, showing {:?}", skipped, element);
+                return Ok(Some(element));
+            }
+        }
+        Ok(None)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.inner.duration()
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<PathBuf>> {
+        // only the elements measured already are known to be skipped
+        let passed = &self.passed;
+        Ok(self
+            .inner
+            .peek(count)?
+            .into_iter()
+            .filter(|element| passed.get(element).copied().unwrap_or(true))
+            .collect())
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
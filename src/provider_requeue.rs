@@ -0,0 +1,142 @@
+//! Show elements of another provider again on request
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::errors::RahmenResult;
+use crate::provider::Provider;
+
+/// How many elements handed out again are remembered, until asked about with `take_requeued`
+const REMEMBERED: usize = 64;
+
+/// The elements requested to be shown again, and those handed out again recently
+#[derive(Debug)]
+struct Queue<D> {
+    waiting: VecDeque<D>,
+    handed_out: VecDeque<D>,
+}
+
+/// Handle to request elements to be shown again, which can be used from outside the dataflow
+#[derive(Debug)]
+pub struct Requeue<D> {
+    queue: Arc<Mutex<Queue<D>>>,
+}
+
+impl<D> Clone for Requeue<D> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: Arc::clone(&self.queue),
+        }
+    }
+}
+
+impl<D> Requeue<D> {
+    /// Request an element to be returned by the next call to `next_image`, ahead of any
+    /// requested before
+    pub fn push(&self, element: D) {
+        self.push_all(Some(element));
+    }
+
+    /// Request `elements` to be returned, in order, by the next calls to `next_image`, ahead of
+    /// any requested before
+    pub fn push_all<I: IntoIterator<Item = D>>(&self, elements: I) {
+        let waiting = &mut self.queue.lock().unwrap().waiting;
+        for (index, element) in elements.into_iter().enumerate() {
+            waiting.insert(index, element);
+        }
+    }
+
+    /// Whether any of `elements` was handed out again on request rather than by the wrapped
+    /// provider, which is forgotten once asked
+    pub fn take_requeued(&self, elements: &[D]) -> bool
+    where
+        D: PartialEq,
+    {
+        let handed_out = &mut self.queue.lock().unwrap().handed_out;
+        let before = handed_out.len();
+        handed_out.retain(|element| !elements.contains(element));
+        handed_out.len() < before
+    }
+}
+
+/// Provider emitting requeued elements before the elements of another provider
+#[derive(Debug)]
+pub struct RequeueProvider<D, P: Provider<D>> {
+    inner: P,
+    requeue: Requeue<D>,
+    requeued: bool,
+}
+
+impl<D, P: Provider<D>> RequeueProvider<D, P> {
+    /// Create a new `RequeueProvider` wrapping `inner`
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            requeue: Requeue {
+                queue: Arc::new(Mutex::new(Queue {
+                    waiting: VecDeque::new(),
+                    handed_out: VecDeque::new(),
+                })),
+            },
+            requeued: false,
+        }
+    }
+
+    /// Obtain a handle to requeue elements
+    pub fn requeue(&self) -> Requeue<D> {
+        self.requeue.clone()
+    }
+}
+
+impl<D: Clone, P: Provider<D>> Provider<D> for RequeueProvider<D, P> {
+    fn next_image(&mut self) -> RahmenResult<Option<D>> {
+        let mut queue = self.requeue.queue.lock().unwrap();
+        let requeued = queue.waiting.pop_front();
+        self.requeued = requeued.is_some();
+        match requeued {
+            Some(element) => {
+                queue.handed_out.push_back(element.clone());
+                if queue.handed_out.len() > REMEMBERED {
+                    queue.handed_out.pop_front();
+                }
+                Ok(Some(element))
+            }
+            None => {
+                drop(queue);
+                self.inner.next_image()
+            }
+        }
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        // requeued elements don't change the schedule
+        if self.requeued {
+            None
+        } else {
+            self.inner.duration()
+        }
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<D>>
+    where
+        D: Clone,
+    {
+        let mut upcoming: Vec<D> = self
+            .requeue
+            .queue
+            .lock()
+            .unwrap()
+            .waiting
+            .iter()
+            .cloned()
+            .collect();
+        upcoming.truncate(count);
+        upcoming.extend(self.inner.peek(count - upcoming.len())?);
+        Ok(upcoming)
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
@@ -0,0 +1,105 @@
+//! Skip images too small to be shown well
+//!
+//! Thumbnails and images saved from the web look blocky when enlarged to fill the screen. Images
+//! can be skipped if they have fewer pixels than a minimum, or if they would have to be enlarged by
+//! more than a factor to fit the screen. Only the header of an image is read to learn its size,
+//! and images whose size can't be read that way, such as those needing transcoding, are shown.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::display::ScreenSize;
+use crate::errors::RahmenResult;
+use crate::provider::Provider;
+use crate::rotate;
+
+/// After skipping this many images in a row, the next one is shown anyway, so a looping collection
+/// without an image large enough does not skip forever
+const MAX_SKIPPED: usize = 100;
+
+/// Provider skipping the elements of another provider which are too small
+#[derive(Debug)]
+pub struct ResolutionProvider<P: Provider<PathBuf>> {
+    inner: P,
+    min_pixels: Option<u64>,
+    max_upscale: Option<f64>,
+    screen: ScreenSize,
+}
+
+impl<P: Provider<PathBuf>> ResolutionProvider<P> {
+    /// Create a new `ResolutionProvider` wrapping `inner`, skipping images with fewer than
+    /// `min_pixels` pixels, or which would have to be enlarged by more than `max_upscale` to fit
+    /// the screen of size `screen`
+    pub fn new(
+        inner: P,
+        min_pixels: Option<u64>,
+        max_upscale: Option<f64>,
+        screen: ScreenSize,
+    ) -> Self {
+        Self {
+            inner,
+            min_pixels,
+            max_upscale,
+            screen,
+        }
+    }
+
+    /// Why `element` is too small, if it is
+    fn too_small(&self, element: &Path) -> Option<String> {
+        let (width, height) = rotate::dimensions(element).ok()?;
+        let pixels = u64::from(width) * u64::from(height);
+        if let Some(min_pixels) = self.min_pixels {
+            if pixels < min_pixels {
+                return Some(format!("has only {}x{} pixels", width, height));
+            }
+        }
+        if let (Some(max_upscale), Some((screen_width, screen_height))) =
+            (self.max_upscale, self.screen.get())
+        {
+            let upscale = f64::min(
+                f64::from(screen_width) / f64::from(width.max(1)),
+                f64::from(screen_height) / f64::from(height.max(1)),
+            );
+            if upscale > max_upscale {
+                return Some(format!("would be enlarged {:.1} times", upscale));
+            }
+        }
+        None
+    }
+}
+
+impl<P: Provider<PathBuf>> Provider<PathBuf> for ResolutionProvider<P> {
+    fn next_image(&mut self) -> RahmenResult<Option<PathBuf>> {
+        let mut skipped = 0;
+        while let Some(element) = self.inner.next_image()? {
+            match self.too_small(&element) {
+                Some(_) if skipped >= MAX_SKIPPED => {
+                    warn!("Skipped {} images in a row, showing {:?}", skipped, element);
+                    return Ok(Some(element));
+                }
+                Some(reason) => info!("Skipping {:?}, which {}", element, reason),
+                None => return Ok(Some(element)),
+            }
+            skipped += 1;
+        }
+        Ok(None)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.inner.duration()
+    }
+
+    fn peek(&mut self, count: usize) -> RahmenResult<Vec<PathBuf>> {
+        let upcoming = self.inner.peek(count)?;
+        Ok(upcoming
+            .into_iter()
+            .filter(|element| self.too_small(element).is_none())
+            .collect())
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
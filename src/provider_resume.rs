@@ -0,0 +1,161 @@
+//! Resume showing the elements of another provider where a previous run left off
+//!
+//! The state to resume from is the position of the image shown last among the elements of the
+//! provider, along with the seed they were shuffled with, and the slides shown before it. Skipping
+//! to the position only reads as many elements as were shown before, unlike looking for the image,
+//! which may never turn up in a provider without end.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::errors::{RahmenError, RahmenResult};
+use crate::provider::Provider;
+use crate::store::Store;
+
+/// The key of the resume state in the persistent store
+const KEY: &str = "resume";
+
+/// How many of the latest elements returned remember their position
+const POSITIONS_KEPT: usize = 256;
+
+/// The state to resume from, kept in the persistent store
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResumeState {
+    /// the seed the images were shuffled with, if they were
+    pub seed: Option<u64>,
+    /// how many elements the provider returned up to and including the image shown last
+    pub position: usize,
+    /// the image shown last
+    pub image: Option<PathBuf>,
+    /// the slides shown before, the latest last, each with its main image first
+    pub history: Vec<Vec<PathBuf>>,
+}
+
+impl ResumeState {
+    /// Load the state from `store`, or the default state if there is none
+    pub fn load<S: Store + ?Sized>(store: &S) -> RahmenResult<Self> {
+        match store.get(KEY)? {
+            Some(state) => serde_json::from_str(&state)
+                .map_err(|e| RahmenError::StoreError(format!("resume state: {}", e))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Save the state to `store`
+    pub fn save<S: Store + ?Sized>(&self, store: &mut S) -> RahmenResult<()> {
+        let state =
+            serde_json::to_string(self).map_err(|e| RahmenError::StoreError(e.to_string()))?;
+        store.set(KEY, &state)
+    }
+}
+
+/// The positions of the latest elements returned by a `ResumeProvider`, shared with the main loop
+#[derive(Debug)]
+pub struct Positions<D>(Arc<Mutex<VecDeque<(D, usize)>>>);
+
+impl<D> Clone for Positions<D> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<D: PartialEq> Positions<D> {
+    /// How many elements were returned up to and including the latest `element`, if it was
+    /// returned recently
+    pub fn of(&self, element: &D) -> Option<usize> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|(returned, _)| returned == element)
+            .map(|(_, position)| *position)
+    }
+}
+
+/// Provider skipping the elements of another provider up to a given position. If the provider
+/// runs out before, nothing is skipped.
+#[derive(Debug)]
+pub struct ResumeProvider<D, P: Provider<D>> {
+    inner: P,
+    /// the position to skip to, and the element expected there
+    resume_at: Option<(usize, Option<D>)>,
+    skipped: VecDeque<(D, Option<Duration>)>,
+    /// the number of elements returned or skipped
+    returned: usize,
+    positions: Positions<D>,
+    duration: Option<Duration>,
+}
+
+impl<D, P: Provider<D>> ResumeProvider<D, P> {
+    /// Create a new `ResumeProvider` wrapping `inner`, continuing after `position` elements, the
+    /// last of which was `element`, if known
+    pub fn new(inner: P, position: usize, element: Option<D>) -> Self {
+        Self {
+            inner,
+            resume_at: Some((position, element)).filter(|(position, _)| *position > 0),
+            skipped: VecDeque::new(),
+            returned: 0,
+            positions: Positions(Arc::new(Mutex::new(VecDeque::new()))),
+            duration: None,
+        }
+    }
+
+    /// The positions of the latest elements returned
+    pub fn positions(&self) -> Positions<D> {
+        self.positions.clone()
+    }
+}
+
+impl<D: Clone + PartialEq + std::fmt::Debug, P: Provider<D>> Provider<D> for ResumeProvider<D, P> {
+    fn next_image(&mut self) -> RahmenResult<Option<D>> {
+        if let Some((position, expected)) = self.resume_at.take() {
+            while self.skipped.len() < position {
+                match self.inner.next_image()? {
+                    Some(element) => self.skipped.push_back((element, self.inner.duration())),
+                    None => break,
+                }
+            }
+            if self.skipped.len() == position {
+                let last = self.skipped.back().map(|(element, _)| element);
+                if expected.is_some() && last != expected.as_ref() {
+                    info!("The images changed, resuming at position {}", position);
+                } else {
+                    info!("Resuming after {:?}", last);
+                }
+                self.skipped.clear();
+                self.returned = position;
+            }
+        }
+        // the provider ran out, so show what was skipped
+        let next = match self.skipped.pop_front() {
+            Some(next) => Some(next),
+            None => self
+                .inner
+                .next_image()?
+                .map(|element| (element, self.inner.duration())),
+        };
+        Ok(next.map(|(element, duration)| {
+            self.duration = duration;
+            self.returned += 1;
+            let mut positions = self.positions.0.lock().unwrap();
+            positions.push_back((element.clone(), self.returned));
+            while positions.len() > POSITIONS_KEPT {
+                positions.pop_front();
+            }
+            element
+        }))
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.inner.total()
+    }
+}
@@ -0,0 +1,107 @@
+//! Shuffle the elements of another provider
+
+use std::time::Duration;
+
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::errors::RahmenResult;
+use crate::provider::Provider;
+
+/// Provider emitting the elements of another provider in random order. The inner provider is
+/// read completely on the first request, so the first element takes as long as listing all of
+/// them, and an inner provider without end never returns one. Shuffling only part of the
+/// elements at a time would not mix the last elements with the first ones.
+///
+/// The order only depends on the seed and the inner provider's elements, also across platforms,
+/// so a seed reproduces a slideshow exactly. The tests pin the order down, so that updating the
+/// random number generators cannot change it unnoticed.
+#[derive(Debug)]
+pub struct ShuffleProvider<D, P: Provider<D>> {
+    inner: Option<P>,
+    elements: Vec<(D, Option<Duration>)>,
+    total: Option<usize>,
+    duration: Option<Duration>,
+    rng: ChaCha8Rng,
+}
+
+impl<D, P: Provider<D>> ShuffleProvider<D, P> {
+    /// Create a new `ShuffleProvider` shuffling the elements of `inner` using `seed`
+    pub fn new(inner: P, seed: u64) -> Self {
+        Self {
+            inner: Some(inner),
+            elements: vec![],
+            total: None,
+            duration: None,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<D, P: Provider<D>> Provider<D> for ShuffleProvider<D, P> {
+    fn next_image(&mut self) -> RahmenResult<Option<D>> {
+        if let Some(mut inner) = self.inner.take() {
+            while let Some(element) = inner.next_image()? {
+                self.elements.push((element, inner.duration()));
+            }
+            self.elements.shuffle(&mut self.rng);
+            self.total = Some(self.elements.len());
+        }
+        Ok(self.elements.pop().map(|(element, duration)| {
+            self.duration = duration;
+            element
+        }))
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn total(&self) -> Option<usize> {
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A provider returning the numbers from 0 up to, but not including, its end
+    struct Numbers(std::ops::Range<u32>);
+
+    impl Provider<u32> for Numbers {
+        fn next_image(&mut self) -> RahmenResult<Option<u32>> {
+            Ok(self.0.next())
+        }
+    }
+
+    fn shuffled(seed: u64, count: u32) -> Vec<u32> {
+        let mut provider = ShuffleProvider::new(Numbers(0..count), seed);
+        let mut order = vec![];
+        while let Some(number) = provider.next_image().unwrap() {
+            order.push(number);
+        }
+        assert_eq!(provider.total(), Some(count as usize));
+        order
+    }
+
+    #[test]
+    fn a_seed_gives_the_same_order_every_time() {
+        assert_eq!(shuffled(0, 10), vec![6, 9, 4, 0, 1, 7, 2, 5, 8, 3]);
+        assert_eq!(shuffled(42, 10), vec![2, 6, 1, 8, 4, 9, 7, 5, 3, 0]);
+        assert_eq!(shuffled(7, 10), vec![1, 9, 8, 7, 6, 3, 0, 2, 4, 5]);
+    }
+
+    #[test]
+    fn returns_every_element_once() {
+        let mut order = shuffled(1234, 100);
+        order.sort_unstable();
+        assert_eq!(order, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn returns_nothing_for_an_empty_provider() {
+        assert!(shuffled(42, 0).is_empty());
+    }
+}
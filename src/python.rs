@@ -0,0 +1,119 @@
+//! Postprocess the status line with Python code
+//!
+//! The module configured defines a function `export`, which is called once and returns a
+//! callable. That callable is called for each image with a list of the metadata items and the
+//! separator, and returns the processed list of items. Python support is behind the `python`
+//! feature, which needs the Python development headers to build.
+//!
+//! Another module can register easing curves for transitions: its function `easings` returns a
+//! dict from names to callables, which take the elapsed fraction of a transition and return its
+//! progress.
+
+use crate::errors::{RahmenError, RahmenResult};
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyList;
+#[cfg(feature = "python")]
+use std::collections::HashMap;
+#[cfg(feature = "python")]
+use std::sync::Arc;
+#[cfg(feature = "python")]
+use tracing::warn;
+
+#[cfg(feature = "python")]
+use crate::transition;
+
+/// A Python callable postprocessing the items of the status line
+#[derive(Debug)]
+pub struct PyPostprocess {
+    #[cfg(feature = "python")]
+    function: Py<PyAny>,
+}
+
+/// Prepend `paths` to the Python search path
+#[cfg(feature = "python")]
+pub fn prepend_path(paths: &[String]) -> RahmenResult<()> {
+    Python::with_gil(|py| -> PyResult<()> {
+        let syspath: &PyList = py.import("sys")?.get("path")?.downcast()?;
+        for path in paths {
+            syspath.insert(0, path)?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Prepend `paths` to the Python search path, which has no effect without Python support
+#[cfg(not(feature = "python"))]
+pub fn prepend_path(_paths: &[String]) -> RahmenResult<()> {
+    Ok(())
+}
+
+/// Import `module`, and register the easing curves returned by its `easings` function
+#[cfg(feature = "python")]
+pub fn register_easings(module: &str) -> RahmenResult<()> {
+    let curves = Python::with_gil(|py| -> PyResult<HashMap<String, Py<PyAny>>> {
+        py.import(module)?.call0("easings")?.extract()
+    })?;
+    for (name, function) in curves {
+        let curve_name = name.clone();
+        let curve = move |t: f32| {
+            Python::with_gil(|py| function.call1(py, (t,))?.extract(py)).unwrap_or_else(
+                |e: PyErr| {
+                    warn!("Easing curve {} failed: {}", curve_name, e);
+                    t
+                },
+            )
+        };
+        transition::register_easing(&name, Arc::new(curve));
+    }
+    Ok(())
+}
+
+/// Import `module`, which needs Python support
+#[cfg(not(feature = "python"))]
+pub fn register_easings(module: &str) -> RahmenResult<()> {
+    Err(RahmenError::Unsupported(format!(
+        "compiled without Python support, cannot import {:?}",
+        module
+    )))
+}
+
+impl PyPostprocess {
+    /// Import `module`, and obtain the postprocessing callable from its `export` function
+    #[cfg(feature = "python")]
+    pub fn load(module: &str) -> RahmenResult<Self> {
+        let function = Python::with_gil(|py| -> PyResult<_> {
+            let module = py.import(module)?;
+            module.call0("export").map(|obj| obj.into_py(py))
+        })?;
+        Ok(Self { function })
+    }
+
+    /// Import `module`, which needs Python support
+    #[cfg(not(feature = "python"))]
+    pub fn load(module: &str) -> RahmenResult<Self> {
+        Err(RahmenError::Unsupported(format!(
+            "compiled without Python support, cannot import {:?}",
+            module
+        )))
+    }
+
+    /// Postprocess the items of the status line, joined with `separator` afterwards
+    #[cfg(feature = "python")]
+    pub fn call(&self, items: &[String], separator: &str) -> RahmenResult<Vec<String>> {
+        Python::with_gil(|py| -> PyResult<Vec<String>> {
+            let tags = PyList::new(py, items);
+            self.function.call1(py, (tags, separator))?.extract(py)
+        })
+        .map_err(RahmenError::from)
+    }
+
+    /// Postprocess the items of the status line, which needs Python support
+    #[cfg(not(feature = "python"))]
+    pub fn call(&self, items: &[String], _separator: &str) -> RahmenResult<Vec<String>> {
+        Ok(items.to_vec())
+    }
+}
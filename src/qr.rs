@@ -0,0 +1,81 @@
+//! QR codes linking to the image shown, so viewers can open it on their phone
+//!
+//! The link is formed from a template, such as the address of the image in a photo library,
+//! with placeholders for the path of the image.
+
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use qrcode::{Color, QrCode};
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// Modules of light border around the code, which scanners need to find it, as the standard
+/// requires
+const QUIET_ZONE: u32 = 4;
+
+/// Percent-encode a string for use in a URL, keeping slashes
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Form the link to an image from `template`, replacing `{path}` with its path, `{name}` with
+/// its file name, `{stem}` with its file name without extension, and `{dir}` with its directory.
+/// The path is taken relative to `strip_prefix`, if given. All values are percent-encoded.
+pub fn link(template: &str, path: &Path, strip_prefix: Option<&Path>) -> String {
+    let path = strip_prefix
+        .and_then(|prefix| path.strip_prefix(prefix).ok())
+        .unwrap_or(path);
+    let lossy = |value: Option<&std::ffi::OsStr>| {
+        encode(
+            &value
+                .map(|value| value.to_string_lossy())
+                .unwrap_or_default(),
+        )
+    };
+    template
+        .replace("{path}", &encode(&path.to_string_lossy()))
+        .replace("{name}", &lossy(path.file_name()))
+        .replace("{stem}", &lossy(path.file_stem()))
+        .replace(
+            "{dir}",
+            &encode(
+                &path
+                    .parent()
+                    .map(|dir| dir.to_string_lossy())
+                    .unwrap_or_default(),
+            ),
+        )
+}
+
+/// Render `data` as a QR code, with each module `module_size` pixels wide, dark on a light
+/// background
+pub fn render(data: &str, module_size: u32) -> RahmenResult<RgbaImage> {
+    let code = QrCode::new(data.as_bytes())
+        .map_err(|e| RahmenError::DisplayError(format!("QR code for {:?}: {}", data, e)))?;
+    let width = code.width() as u32;
+    let colors = code.to_colors();
+    let module_size = module_size.max(1);
+    let size = (width + 2 * QUIET_ZONE) * module_size;
+    Ok(RgbaImage::from_fn(size, size, |x, y| {
+        let (x, y) = (x / module_size, y / module_size);
+        let dark = x >= QUIET_ZONE
+            && y >= QUIET_ZONE
+            && x - QUIET_ZONE < width
+            && y - QUIET_ZONE < width
+            && colors[((y - QUIET_ZONE) * width + x - QUIET_ZONE) as usize] == Color::Dark;
+        if dark {
+            Rgba([0, 0, 0, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    }))
+}
@@ -0,0 +1,118 @@
+//! Turn images upright, and remember how
+//!
+//! Images are shown turned and mirrored as their EXIF orientation says. Old scans often have no
+//! orientation, or a wrong one, so the image shown can be turned by quarter turns, and its new
+//! orientation is written back: into an XMP sidecar file next to the image, named like it with the
+//! extension replaced by `.xmp`, or if configured into the image itself, unless it cannot be
+//! written. The orientation in a sidecar file takes precedence over the one in the image.
+
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, ImageResult};
+use tracing::warn;
+
+use crate::errors::RahmenResult;
+use crate::favorite::{create_sidecar, sidecar_path};
+use crate::metadata::Metadata;
+
+/// The orientation in EXIF
+const EXIF_ORIENTATION: &str = "Exif.Image.Orientation";
+/// The orientation in XMP, as in sidecar files
+const XMP_ORIENTATION: &str = "Xmp.tiff.Orientation";
+
+/// The transformations of the EXIF orientations 1 to 8, as whether to mirror the image
+/// horizontally, and the quarter turns clockwise after that
+const TRANSFORMS: [(bool, u8); 8] = [
+    (false, 0),
+    (true, 0),
+    (false, 2),
+    (true, 2),
+    (true, 3),
+    (false, 1),
+    (true, 1),
+    (false, 3),
+];
+
+fn transform(orientation: i32) -> (bool, u8) {
+    match orientation {
+        1..=8 => TRANSFORMS[orientation as usize - 1],
+        _ => (false, 0),
+    }
+}
+
+fn read(path: &Path, tags: &[&str]) -> Option<i32> {
+    let metadata = Metadata::new_from_path(path).ok()?;
+    tags.iter()
+        .map(|tag| metadata.get_tag_numeric(tag))
+        .find(|orientation| (1..=8).contains(orientation))
+}
+
+/// The orientation of the image at `path`, from its sidecar file or itself, 1 if it has none
+pub fn orientation(path: &Path) -> i32 {
+    read(&sidecar_path(path), &[XMP_ORIENTATION])
+        .or_else(|| read(path, &[EXIF_ORIENTATION, XMP_ORIENTATION]))
+        .unwrap_or(1)
+}
+
+/// The size of the image at `path` as shown, read from its header, with width and height swapped
+/// if its orientation turns it by a quarter
+pub fn dimensions(path: &Path) -> ImageResult<(u32, u32)> {
+    let (width, height) = image::image_dimensions(path)?;
+    Ok(match transform(orientation(path)) {
+        (_, 1) | (_, 3) => (height, width),
+        _ => (width, height),
+    })
+}
+
+/// Turn and mirror `img` as the EXIF `orientation` says
+pub fn upright(img: DynamicImage, orientation: i32) -> DynamicImage {
+    let (mirrored, turns) = transform(orientation);
+    let img = if mirrored { img.fliph() } else { img };
+    match turns {
+        1 => img.rotate90(),
+        2 => img.rotate180(),
+        3 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// How to turn images
+#[derive(Debug, Clone)]
+pub struct Rotations {
+    /// whether to write sidecar files, leaving images untouched, rather than the images
+    pub sidecar: bool,
+}
+
+impl Rotations {
+    fn write(path: &Path, tag: &str, orientation: i32) -> RahmenResult<()> {
+        let metadata = Metadata::new_from_path(path)?;
+        metadata.set_tag_numeric(tag, orientation)?;
+        // keep both in step, so all readers agree
+        if tag == EXIF_ORIENTATION && metadata.has_tag(XMP_ORIENTATION) {
+            metadata.set_tag_numeric(XMP_ORIENTATION, orientation)?;
+        }
+        metadata.save_to_file(path)?;
+        Ok(())
+    }
+
+    /// Turn the image at `path` by a quarter turn, clockwise or counterclockwise, and return the
+    /// file written
+    pub fn turn(&self, path: &Path, clockwise: bool) -> RahmenResult<PathBuf> {
+        let (mirrored, turns) = transform(orientation(path));
+        let turns = (turns + if clockwise { 1 } else { 3 }) % 4;
+        let turned = TRANSFORMS
+            .iter()
+            .position(|&transform| transform == (mirrored, turns))
+            .map_or(1, |index| index as i32 + 1);
+        // a sidecar file with an orientation overrides the image, so it has to be updated
+        if !self.sidecar && read(&sidecar_path(path), &[XMP_ORIENTATION]).is_none() {
+            match Self::write(path, EXIF_ORIENTATION, turned) {
+                Ok(()) => return Ok(path.to_path_buf()),
+                Err(e) => warn!("Cannot write {:?}, using a sidecar file: {}", path, e),
+            }
+        }
+        let sidecar = create_sidecar(path)?;
+        Self::write(&sidecar, XMP_ORIENTATION, turned)?;
+        Ok(sidecar)
+    }
+}
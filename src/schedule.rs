@@ -0,0 +1,159 @@
+//! Time-of-day schedules
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::config::{DailyPeriod, ScheduleBlock};
+use crate::errors::{RahmenError, RahmenResult};
+
+/// A time of each day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DailyTime {
+    at: NaiveTime,
+}
+
+impl DailyTime {
+    /// Parse a time of day given as `HH:MM`
+    pub fn new(at: &str) -> RahmenResult<Self> {
+        Ok(Self {
+            at: NaiveTime::parse_from_str(at, "%H:%M")?,
+        })
+    }
+
+    /// Whether this time was reached after `before`, up to and including `now`. Handles passing
+    /// midnight in between.
+    pub fn passed(&self, before: NaiveTime, now: NaiveTime) -> bool {
+        if before <= now {
+            before < self.at && self.at <= now
+        } else {
+            before < self.at || self.at <= now
+        }
+    }
+}
+
+/// A period of each day, which may span midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    from: NaiveTime,
+    to: NaiveTime,
+}
+
+impl TimeRange {
+    /// Construct a time range from a configured period. Times are given as `HH:MM`.
+    pub fn new(period: &DailyPeriod) -> RahmenResult<Self> {
+        Ok(Self {
+            from: NaiveTime::parse_from_str(&period.from, "%H:%M")?,
+            to: NaiveTime::parse_from_str(&period.to, "%H:%M")?,
+        })
+    }
+
+    /// Whether a time of day falls into this range. The start is inclusive, the end exclusive.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.from <= self.to {
+            self.from <= time && time < self.to
+        } else {
+            time >= self.from || time < self.to
+        }
+    }
+}
+
+/// A block of a schedule, changing the slideshow during a period on some days of the week
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    range: TimeRange,
+    /// the days the block starts on, or `None` for every day
+    days: Option<Vec<Weekday>>,
+    /// whether to turn the screen off
+    pub screen_off: bool,
+    /// the playlist to show, or `None` for the input
+    pub playlist: Option<String>,
+    /// how long to show each image, or `None` for the configured delay
+    pub interval: Option<Duration>,
+}
+
+impl Block {
+    /// Construct a block from its configuration. Days are given like `mon` or `saturday`.
+    pub fn new(block: &ScheduleBlock) -> RahmenResult<Self> {
+        let range = TimeRange::new(&DailyPeriod {
+            from: block.from.clone(),
+            to: block.to.clone(),
+        })?;
+        let days = block
+            .days
+            .as_ref()
+            .map(|days| {
+                days.iter()
+                    .map(|day| {
+                        day.parse::<Weekday>().map_err(|_| {
+                            RahmenError::ConfigError(Arc::new(config::ConfigError::Message(
+                                format!("unknown day of the week {:?}", day),
+                            )))
+                        })
+                    })
+                    .collect::<RahmenResult<Vec<_>>>()
+            })
+            .transpose()?;
+        let interval = match block.interval {
+            Some(seconds) if seconds > 0. && seconds.is_finite() => {
+                Some(Duration::from_secs_f64(seconds))
+            }
+            Some(seconds) => {
+                return Err(RahmenError::ConfigError(Arc::new(
+                    config::ConfigError::Message(format!("invalid interval {}", seconds)),
+                )))
+            }
+            None => None,
+        };
+        Ok(Self {
+            range,
+            days,
+            screen_off: block.screen_off.unwrap_or(false),
+            playlist: block.playlist.clone(),
+            interval,
+        })
+    }
+
+    /// Whether the block applies at a point in time. A period spanning midnight belongs to the
+    /// day it starts on.
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let time = at.time();
+        if !self.range.contains(time) {
+            return false;
+        }
+        let day = if self.range.from > self.range.to && time < self.range.to {
+            at.date().pred()
+        } else {
+            at.date()
+        };
+        self.days
+            .as_ref()
+            .map_or(true, |days| days.contains(&day.weekday()))
+    }
+}
+
+/// A weekly schedule of blocks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schedule {
+    blocks: Vec<Block>,
+}
+
+impl Schedule {
+    /// Construct a schedule from the configured blocks
+    pub fn new(blocks: &[ScheduleBlock]) -> RahmenResult<Self> {
+        Ok(Self {
+            blocks: blocks.iter().map(Block::new).collect::<RahmenResult<_>>()?,
+        })
+    }
+
+    /// The blocks of the schedule
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// The block applying at a point in time, the first one listed if several do
+    pub fn active(&self, at: NaiveDateTime) -> Option<&Block> {
+        self.blocks.iter().find(|block| block.contains(at))
+    }
+}
@@ -0,0 +1,62 @@
+//! Control the slideshow with POSIX signals
+//!
+//! Signals need no setup, so cron jobs and scripts can control the frame with `kill` or
+//! `pkill`: `SIGUSR1` shows the next image, `SIGUSR2` pauses or resumes the slideshow, and
+//! `SIGHUP` reloads the configuration, if it passes the checks. `SIGTERM` and `SIGINT` stop the
+//! slideshow through the main loop, which saves the state and restores the console. If it hangs,
+//! a second one exits right away. Beyond Unix, there are no such signals to handle.
+
+use std::sync::mpsc::Sender;
+
+#[cfg(unix)]
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
+#[cfg(unix)]
+use signal_hook::iterator::Signals;
+#[cfg(unix)]
+use tracing::{info, warn};
+
+use crate::control::Command;
+use crate::errors::RahmenResult;
+
+/// The command a signal issues
+#[cfg(unix)]
+fn command(signal: i32) -> Option<Command> {
+    match signal {
+        SIGUSR1 => Some(Command::Next),
+        SIGUSR2 => Some(Command::TogglePause),
+        SIGHUP => Some(Command::Reload),
+        SIGTERM | SIGINT => Some(Command::Quit),
+        _ => None,
+    }
+}
+
+/// Handle the signals in a background thread, and send their commands to `commands`
+#[cfg(unix)]
+pub fn start(commands: Sender<Command>) -> RahmenResult<()> {
+    let mut signals = Signals::new(&[SIGUSR1, SIGUSR2, SIGHUP, SIGTERM, SIGINT])?;
+    std::thread::spawn(move || {
+        let mut stopping = false;
+        for signal in signals.forever() {
+            if signal == SIGTERM || signal == SIGINT {
+                if stopping {
+                    warn!("Stopping right away");
+                    std::process::exit(128 + signal);
+                }
+                info!("Stopping");
+                stopping = true;
+            }
+            if let Some(command) = command(signal) {
+                if commands.send(command).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Handle the signals, of which there are none to handle beyond Unix
+#[cfg(not(unix))]
+pub fn start(_commands: Sender<Command>) -> RahmenResult<()> {
+    Ok(())
+}
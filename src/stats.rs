@@ -0,0 +1,106 @@
+//! Statistics computed from the pixels of an image
+//!
+//! They are offered as pseudo-tags of the status line. They measure brightness, colors and
+//! sharpness only, and tell nothing about what an image shows, so they are no automatic tagging.
+
+use std::collections::HashMap;
+
+use image::{DynamicImage, GrayImage};
+use itertools::Itertools;
+
+/// Prefix of the pseudo-tags exposing image statistics in the status line
+pub const TAG_PREFIX: &str = "Rahmen.Stats.";
+
+/// Statistics are computed on a copy downscaled to fit this size
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Statistics of an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageStats {
+    /// Mean luminance, from 0 (black) to 1 (white)
+    pub mean_luminance: f32,
+    /// The most frequent colors as RGB, most frequent first
+    pub dominant_colors: Vec<[u8; 3]>,
+    /// Variance of the Laplacian of the luminance; the higher, the sharper the image
+    pub sharpness: f32,
+}
+
+impl ImageStats {
+    /// Compute statistics on a downscaled copy of an image
+    pub fn from_image(img: &DynamicImage) -> Self {
+        let thumbnail = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+        let luma = thumbnail.to_luma8();
+        let mean_luminance =
+            luma.iter().map(|v| *v as f32).sum::<f32>() / (luma.len().max(1) as f32 * 255.);
+        Self {
+            mean_luminance,
+            dominant_colors: dominant_colors(&thumbnail, 3),
+            sharpness: sharpness(&luma),
+        }
+    }
+
+    /// Look up a pseudo-tag, such as `Rahmen.Stats.MeanLuminance`
+    pub fn tag(&self, name: &str) -> Option<String> {
+        match name.strip_prefix(TAG_PREFIX)? {
+            "MeanLuminance" => Some(format!("{:.2}", self.mean_luminance)),
+            "DominantColor" => self.dominant_colors.first().map(hex),
+            "DominantColors" => Some(self.dominant_colors.iter().map(hex).join(" ")),
+            "Sharpness" => Some(format!("{:.0}", self.sharpness)),
+            _ => None,
+        }
+    }
+}
+
+fn hex(color: &[u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// Determine the `count` most frequent colors, after reducing each channel to 3 bits. Each color
+/// is the average of the pixels falling into its bin.
+pub fn dominant_colors(img: &DynamicImage, count: usize) -> Vec<[u8; 3]> {
+    let mut bins: HashMap<[u8; 3], (u32, [u32; 3])> = HashMap::new();
+    for pixel in img.to_rgb8().pixels() {
+        let bin = bins
+            .entry([pixel[0] >> 5, pixel[1] >> 5, pixel[2] >> 5])
+            .or_default();
+        bin.0 += 1;
+        for channel in 0..3 {
+            bin.1[channel] += pixel[channel] as u32;
+        }
+    }
+    bins.values()
+        .sorted_by_key(|(n, _)| std::cmp::Reverse(*n))
+        .take(count)
+        .map(|(n, sum)| [(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8])
+        .collect()
+}
+
+/// Determine the average color of all pixels
+pub fn average_color(img: &DynamicImage) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    let rgb = img.to_rgb8();
+    for pixel in rgb.pixels() {
+        for channel in 0..3 {
+            sum[channel] += u64::from(pixel[channel]);
+        }
+    }
+    let n = u64::from(rgb.width() * rgb.height()).max(1);
+    [(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8]
+}
+
+/// Compute the variance of the Laplacian of a grayscale image, a common measure of sharpness
+pub fn sharpness(luma: &GrayImage) -> f32 {
+    let (width, height) = luma.dimensions();
+    if width < 3 || height < 3 {
+        return 0.;
+    }
+    let at = |x: u32, y: u32| luma.get_pixel(x, y)[0] as f32;
+    let mut values = Vec::with_capacity(((width - 2) * (height - 2)) as usize);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            values.push(at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4. * at(x, y));
+        }
+    }
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32
+}
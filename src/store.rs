@@ -0,0 +1,265 @@
+//! Persistent state, such as the image to resume from after a restart
+//!
+//! State is kept as string values under string keys, behind the `Store` trait. The JSON backend
+//! rewrites a single small file, only when a value changed, and at most once per
+//! `WRITE_INTERVAL`, which suits SD cards and other storage that should see few writes. Changes
+//! since the last write are lost if the process is killed. The SQLite backend, behind the `sqlite`
+//! feature, scales to larger amounts of state.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// How often the JSON backend writes its file at most
+pub const WRITE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Available persistence backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    /// A JSON file, rewritten on changes
+    Json,
+    /// An SQLite database
+    Sqlite,
+}
+
+/// A persistent key-value store
+pub trait Store: Send {
+    /// Look up the value of `key`
+    fn get(&self, key: &str) -> RahmenResult<Option<String>>;
+
+    /// Set `key` to `value`
+    fn set(&mut self, key: &str, value: &str) -> RahmenResult<()>;
+
+    /// Remove `key`, if present
+    fn remove(&mut self, key: &str) -> RahmenResult<()>;
+
+    /// List the keys starting with `prefix`, in order
+    fn keys(&self, prefix: &str) -> RahmenResult<Vec<String>>;
+
+    /// Write changes held back, such as before exiting
+    fn flush(&mut self) -> RahmenResult<()> {
+        Ok(())
+    }
+}
+
+impl<S: Store + ?Sized> Store for Box<S> {
+    fn get(&self, key: &str) -> RahmenResult<Option<String>> {
+        (**self).get(key)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> RahmenResult<()> {
+        (**self).set(key, value)
+    }
+
+    fn remove(&mut self, key: &str) -> RahmenResult<()> {
+        (**self).remove(key)
+    }
+
+    fn keys(&self, prefix: &str) -> RahmenResult<Vec<String>> {
+        (**self).keys(prefix)
+    }
+
+    fn flush(&mut self) -> RahmenResult<()> {
+        (**self).flush()
+    }
+}
+
+/// Open the store at `path` with the given backend
+pub fn open(backend: StoreBackend, path: &Path) -> RahmenResult<Box<dyn Store>> {
+    match backend {
+        StoreBackend::Json => Ok(Box::new(JsonStore::open(path)?)),
+        #[cfg(feature = "sqlite")]
+        StoreBackend::Sqlite => Ok(Box::new(SqliteStore::open(path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        StoreBackend::Sqlite => Err(RahmenError::Unsupported(
+            "compiled without SQLite support".into(),
+        )),
+    }
+}
+
+/// A store keeping all values in memory, and in a JSON file
+#[derive(Debug)]
+pub struct JsonStore {
+    path: PathBuf,
+    values: BTreeMap<String, String>,
+    /// whether the values changed since they were written
+    dirty: bool,
+    written_at: Option<Instant>,
+}
+
+impl JsonStore {
+    /// Open the store in the file at `path`, which is created on the first change
+    pub fn open<P: Into<PathBuf>>(path: P) -> RahmenResult<Self> {
+        let path = path.into();
+        let values = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))
+                .map_err(|e| RahmenError::StoreError(format!("{:?}: {}", path, e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            values,
+            dirty: false,
+            written_at: None,
+        })
+    }
+
+    /// Note a change, and write it unless the file was written within `WRITE_INTERVAL`
+    fn changed(&mut self) -> RahmenResult<()> {
+        self.dirty = true;
+        if self
+            .written_at
+            .map_or(true, |written_at| written_at.elapsed() >= WRITE_INTERVAL)
+        {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self) -> RahmenResult<()> {
+        // write to a temporary file first, so a crash never leaves a truncated file behind
+        let partial = self.path.with_extension("partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer_pretty(&mut writer, &self.values)
+            .map_err(|e| RahmenError::StoreError(e.to_string()))?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        std::fs::rename(&partial, &self.path)?;
+        self.dirty = false;
+        self.written_at = Some(Instant::now());
+        Ok(())
+    }
+}
+
+impl Drop for JsonStore {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("Saving state to {:?} failed: {}", self.path, e);
+        }
+    }
+}
+
+impl Store for JsonStore {
+    fn get(&self, key: &str) -> RahmenResult<Option<String>> {
+        Ok(self.values.get(key).cloned())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> RahmenResult<()> {
+        if self.values.get(key).map(String::as_str) != Some(value) {
+            self.values.insert(key.to_string(), value.to_string());
+            self.changed()?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> RahmenResult<()> {
+        if self.values.remove(key).is_some() {
+            self.changed()?;
+        }
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str) -> RahmenResult<Vec<String>> {
+        Ok(self
+            .values
+            .range(prefix.to_string()..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn flush(&mut self) -> RahmenResult<()> {
+        if self.dirty {
+            self.write()?;
+        }
+        Ok(())
+    }
+}
+
+/// A store in an SQLite database
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    connection: rusqlite::Connection,
+}
+
+#[cfg(feature = "sqlite")]
+impl std::fmt::Debug for SqliteStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteStore").finish()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_err(e: rusqlite::Error) -> RahmenError {
+    RahmenError::StoreError(format!("SQLite: {}", e))
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    /// Open the database at `path`, creating it if needed
+    pub fn open(path: &Path) -> RahmenResult<Self> {
+        let connection = rusqlite::Connection::open(path).map_err(sqlite_err)?;
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS state (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+                [],
+            )
+            .map_err(sqlite_err)?;
+        Ok(Self { connection })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Store for SqliteStore {
+    fn get(&self, key: &str) -> RahmenResult<Option<String>> {
+        use rusqlite::OptionalExtension;
+        self.connection
+            .query_row("SELECT value FROM state WHERE key = ?", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(sqlite_err)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> RahmenResult<()> {
+        self.connection
+            .execute(
+                "INSERT INTO state (key, value) VALUES (?1, ?2) \
+                 ON CONFLICT (key) DO UPDATE SET value = ?2 WHERE value != ?2",
+                [key, value],
+            )
+            .map_err(sqlite_err)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> RahmenResult<()> {
+        self.connection
+            .execute("DELETE FROM state WHERE key = ?", [key])
+            .map_err(sqlite_err)?;
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str) -> RahmenResult<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT key FROM state WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key")
+            .map_err(sqlite_err)?;
+        let keys = statement
+            .query_map([prefix], |row| row.get(0))
+            .map_err(sqlite_err)?
+            .collect::<Result<_, _>>()
+            .map_err(sqlite_err)?;
+        Ok(keys)
+    }
+}
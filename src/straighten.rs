@@ -0,0 +1,138 @@
+//! Straighten slightly tilted images, such as casual phone photos with a crooked horizon
+//!
+//! The tilt is estimated from the directions of strong edges in a downscaled copy: edges close to
+//! horizontal or vertical are collected in a histogram of angles, and a clear peak off zero is
+//! taken as the tilt. The image is then rotated back, and cropped to hide the corners the rotation
+//! uncovers.
+
+use image::{DynamicImage, GenericImageView, GrayImage, Rgb, RgbImage};
+use tracing::info;
+
+/// The tilt is estimated on a copy downscaled to fit this size
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Width of a histogram bin in degrees
+const BIN_DEGREES: f32 = 0.1;
+
+/// Tilts smaller than this many degrees are left alone
+const MIN_DEGREES: f32 = 0.2;
+
+/// Gradients weaker than this are not considered edges
+const MIN_GRADIENT: f32 = 64.;
+
+/// Estimate the tilt of an image in degrees, clockwise, if it is at most `max_degrees` and the
+/// edges agree on it
+pub fn estimate_tilt(img: &DynamicImage, max_degrees: f32) -> Option<f32> {
+    let luma = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_luma8();
+    let bins = (max_degrees / BIN_DEGREES).ceil() as usize;
+    let mut histogram = vec![0f32; 2 * bins + 1];
+    for (gx, gy) in gradients(&luma) {
+        let magnitude = (gx * gx + gy * gy).sqrt();
+        if magnitude < MIN_GRADIENT {
+            continue;
+        }
+        // the gradient is perpendicular to the edge: a horizontal edge tilted by `angle` has a
+        // gradient of (-sin, cos), a vertical one of (cos, sin)
+        let angle = if gy.abs() > gx.abs() {
+            (-gx / gy).atan()
+        } else {
+            (gy / gx).atan()
+        }
+        .to_degrees();
+        if angle.abs() <= max_degrees {
+            let bin = ((angle / BIN_DEGREES).round() as isize + bins as isize) as usize;
+            histogram[bin.min(2 * bins)] += magnitude;
+        }
+    }
+    let total: f32 = histogram.iter().sum();
+    if total <= 0. {
+        return None;
+    }
+    // smooth over neighboring bins, as edges in a small image are noisy
+    let smoothed: Vec<f32> = (0..histogram.len())
+        .map(|bin| {
+            histogram[bin.saturating_sub(1)..(bin + 2).min(histogram.len())]
+                .iter()
+                .sum()
+        })
+        .collect();
+    let (peak, weight) = smoothed
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal))?;
+    // the peak must stand out clearly from edges in random directions
+    if *weight < total * 9. / histogram.len() as f32 {
+        return None;
+    }
+    let tilt = (peak as f32 - bins as f32) * BIN_DEGREES;
+    if tilt.abs() < MIN_DEGREES {
+        None
+    } else {
+        Some(tilt)
+    }
+}
+
+/// Sobel gradients of the inner pixels of an image
+fn gradients(luma: &GrayImage) -> impl Iterator<Item = (f32, f32)> + '_ {
+    let (width, height) = luma.dimensions();
+    let at = move |x: u32, y: u32| f32::from(luma.get_pixel(x, y)[0]);
+    (1..height.saturating_sub(1)).flat_map(move |y| {
+        (1..width.saturating_sub(1)).map(move |x| {
+            let gx = at(x + 1, y - 1) + 2. * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2. * at(x - 1, y)
+                - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2. * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2. * at(x, y - 1)
+                - at(x + 1, y - 1);
+            (gx, gy)
+        })
+    })
+}
+
+/// Rotate an image counterclockwise by `degrees` around its center, and crop it to the largest
+/// rectangle of the same aspect ratio that the rotated image covers
+pub fn rotate_and_crop(img: &DynamicImage, degrees: f32) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (w, h) = (width as f32, height as f32);
+    // the crop, rotated back into the image, must fit into it
+    let scale = (w / (w * cos + h * sin.abs())).min(h / (w * sin.abs() + h * cos));
+    let (crop_width, crop_height) = ((w * scale).floor() as u32, (h * scale).floor() as u32);
+    let source = img.to_rgb8();
+    let sample = |x: f32, y: f32| {
+        let (x, y) = (x.max(0.).min(w - 1.), y.max(0.).min(h - 1.));
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let mut pixel = [0u8; 3];
+        for (channel, value) in pixel.iter_mut().enumerate() {
+            let at = |x, y| f32::from(source.get_pixel(x, y)[channel]);
+            let top = at(x0, y0) * (1. - fx) + at(x1, y0) * fx;
+            let bottom = at(x0, y1) * (1. - fx) + at(x1, y1) * fx;
+            *value = (top * (1. - fy) + bottom * fy).round() as u8;
+        }
+        Rgb(pixel)
+    };
+    let straightened = RgbImage::from_fn(crop_width, crop_height, |x, y| {
+        let dx = x as f32 + 0.5 - crop_width as f32 / 2.;
+        let dy = y as f32 + 0.5 - crop_height as f32 / 2.;
+        sample(
+            w / 2. + cos * dx - sin * dy - 0.5,
+            h / 2. + sin * dx + cos * dy - 0.5,
+        )
+    });
+    DynamicImage::ImageRgb8(straightened)
+}
+
+/// Straighten an image if it is tilted by at most `max_degrees`, otherwise return it unchanged
+pub fn straighten(img: DynamicImage, max_degrees: f32) -> DynamicImage {
+    match estimate_tilt(&img, max_degrees) {
+        Some(tilt) => {
+            info!("Straightening by {:.1}°", tilt);
+            rotate_and_crop(&img, tilt)
+        }
+        None => img,
+    }
+}
@@ -0,0 +1,127 @@
+//! Tell systemd how the slideshow is doing
+//!
+//! Run as a service of `Type=notify`, Rahmen reports being ready once the first image is shown,
+//! and the image it shows as the status of the service. With `WatchdogSec=` set, the main loop
+//! pings the watchdog, so systemd restarts a frame that hangs. Outside of systemd,
+//! `NOTIFY_SOCKET` is not set, and nothing is sent. Neither is it beyond Unix.
+
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::info;
+#[cfg(unix)]
+use tracing::warn;
+
+/// There is no service manager to notify without Unix domain sockets
+#[cfg(not(unix))]
+type UnixDatagram = std::convert::Infallible;
+
+/// Sends notifications to the service manager
+#[derive(Debug)]
+pub struct Notifier {
+    /// the socket to send from, and the address of systemd's socket
+    socket: Option<(UnixDatagram, PathBuf)>,
+    /// how often to ping the watchdog
+    watchdog: Option<Duration>,
+    pinged_at: Option<Duration>,
+    ready: bool,
+}
+
+impl Notifier {
+    /// The socket to notify the service manager given by the environment, if any
+    #[cfg(unix)]
+    fn socket_from_env() -> Option<(UnixDatagram, PathBuf)> {
+        std::env::var_os("NOTIFY_SOCKET").and_then(|path| {
+            let path = PathBuf::from(path);
+            if path.to_string_lossy().starts_with('@') {
+                warn!("Abstract notification sockets are not supported");
+                return None;
+            }
+            match UnixDatagram::unbound() {
+                Ok(socket) => Some((socket, path)),
+                Err(e) => {
+                    warn!("Cannot notify systemd: {}", e);
+                    None
+                }
+            }
+        })
+    }
+
+    /// The socket to notify the service manager, which needs Unix domain sockets
+    #[cfg(not(unix))]
+    fn socket_from_env() -> Option<(UnixDatagram, PathBuf)> {
+        None
+    }
+
+    /// Create a notifier for the service manager given by the environment, if any
+    pub fn from_env() -> Self {
+        let socket = Self::socket_from_env();
+        // the watchdog may be meant for another process of the service
+        let for_us = std::env::var("WATCHDOG_PID").map_or(true, |pid| {
+            pid.parse::<u32>().ok() == Some(std::process::id())
+        });
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .filter(|_| socket.is_some() && for_us)
+            .and_then(|usec| usec.parse().ok())
+            .filter(|usec| *usec > 0)
+            // ping twice per interval, so a late ping does not trigger the watchdog
+            .map(|usec| Duration::from_micros(usec) / 2);
+        if let Some(interval) = watchdog {
+            info!("Pinging the systemd watchdog every {:?}", interval);
+        }
+        Self {
+            socket,
+            watchdog,
+            pinged_at: None,
+            ready: false,
+        }
+    }
+
+    /// Send `state`, lines of `KEY=value`, to the service manager
+    #[cfg(unix)]
+    fn notify(&self, state: &str) {
+        if let Some((socket, path)) = &self.socket {
+            if let Err(e) = socket.send_to(state.as_bytes(), path) {
+                warn!("Notifying systemd failed: {}", e);
+            }
+        }
+    }
+
+    /// Send `state` to the service manager, of which there is none without Unix domain sockets
+    #[cfg(not(unix))]
+    fn notify(&self, _state: &str) {
+        if let Some((socket, _)) = &self.socket {
+            match *socket {}
+        }
+    }
+
+    /// Report the image shown, and that the service is ready if it is the first one
+    pub fn slide_shown(&mut self, path: &Path) {
+        // a line break would start another assignment
+        let status = format!("STATUS=Showing {}", path.display()).replace('\n', " ");
+        if self.ready {
+            self.notify(&status);
+        } else {
+            self.ready = true;
+            self.notify(&format!("READY=1\n{}", status));
+        }
+    }
+
+    /// Ping the watchdog, if enabled and due at `now`
+    pub fn watchdog(&mut self, now: Duration) {
+        if let Some(interval) = self.watchdog {
+            if self.pinged_at.map_or(true, |at| now >= at + interval) {
+                self.pinged_at = Some(now);
+                self.notify("WATCHDOG=1");
+            }
+        }
+    }
+
+    /// Report that the service is stopping
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+}
@@ -0,0 +1,269 @@
+//! Cache of images in formats Rahmen can't decode itself, or only slowly
+//!
+//! Images such as RAW files, HEIC images or PDF documents are transcoded once by an external
+//! command, downscaled to fit the screen, and stored as JPEG. Until the display is open and its
+//! size known, the display buffer size limits the copies instead. Later requests are served from
+//! the cache. When the source changes, the stale copy is served while a fresh one is
+//! generated in the background.
+//!
+//! Transcodings hold a shared lock on the cache directory, and compacting it an exclusive one, so
+//! compacting never removes the files of a transcoding in progress, in this or another process.
+//! Beyond Unix, the cache is not locked, and only one process should use it.
+
+#![allow(unsafe_code)]
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage, GenericImageView};
+use tracing::{debug_span, info, warn};
+
+use crate::config::Converter;
+use crate::display::ScreenSize;
+use crate::errors::{RahmenError, RahmenResult};
+use crate::provider::load_image_from_path;
+
+/// The file locked in a cache directory
+const LOCK_FILE: &str = ".lock";
+
+/// A lock on a cache directory, held until it is dropped
+struct CacheLock {
+    /// the lock file, whose lock is released when it is closed
+    _file: File,
+}
+
+impl CacheLock {
+    /// Lock the cache in `dir`, exclusively or shared with others, waiting for the lock
+    fn new(dir: &Path, exclusive: bool) -> RahmenResult<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(dir.join(LOCK_FILE))?;
+        Self::lock(&file, exclusive)?;
+        Ok(Self { _file: file })
+    }
+
+    /// Lock `file` with `flock`, which releases the lock when the file is closed
+    #[cfg(unix)]
+    fn lock(file: &File, exclusive: bool) -> RahmenResult<()> {
+        let operation = if exclusive {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_SH
+        };
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    /// Lock `file`, which is left unlocked without `flock`
+    #[cfg(not(unix))]
+    fn lock(_file: &File, _exclusive: bool) -> RahmenResult<()> {
+        Ok(())
+    }
+}
+
+/// A cache of transcoded images
+#[derive(Debug)]
+pub struct TranscodeCache {
+    dir: PathBuf,
+    converters: Vec<Converter>,
+    screen_size: ScreenSize,
+    max_size: usize,
+    regenerating: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl TranscodeCache {
+    /// Create a cache storing images in `dir`, downscaled to fit `screen_size` once it is known,
+    /// and to at most `max_size` pixels
+    pub fn new(
+        dir: PathBuf,
+        converters: Vec<Converter>,
+        screen_size: ScreenSize,
+        max_size: usize,
+    ) -> RahmenResult<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            converters,
+            screen_size,
+            max_size,
+            regenerating: Default::default(),
+        })
+    }
+
+    /// Determine the path to load an image from. This is the path itself if no converter handles
+    /// its extension, otherwise the transcoded copy, which is generated if needed.
+    pub fn resolve(&self, path: &Path) -> RahmenResult<PathBuf> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        let converter = match self.converters.iter().find(|converter| {
+            converter
+                .extensions
+                .iter()
+                .any(|e| Some(e.to_lowercase()) == extension)
+        }) {
+            Some(converter) => converter,
+            None => return Ok(path.to_owned()),
+        };
+
+        let cached = cached_path(&self.dir, path);
+        let source_modified = std::fs::metadata(path)?.modified()?;
+        match std::fs::metadata(&cached).and_then(|metadata| metadata.modified()) {
+            Ok(cached_modified) if cached_modified >= source_modified => {}
+            Ok(_) => {
+                // serve the stale copy, and regenerate it in the background
+                if self.regenerating.lock().unwrap().insert(path.to_owned()) {
+                    let (command, source) = (converter.command.clone(), path.to_owned());
+                    let (screen, max_size) = (self.screen_size.get(), self.max_size);
+                    let regenerating = Arc::clone(&self.regenerating);
+                    let (dir, target) = (self.dir.clone(), cached.clone());
+                    std::thread::spawn(move || {
+                        if let Err(e) =
+                            transcode(&command, &source, &dir, &target, screen, max_size)
+                        {
+                            warn!("Failed to transcode {:?}: {}", source, e);
+                        }
+                        regenerating.lock().unwrap().remove(&source);
+                    });
+                }
+            }
+            Err(_) => transcode(
+                &converter.command,
+                path,
+                &self.dir,
+                &cached,
+                self.screen_size.get(),
+                self.max_size,
+            )?,
+        }
+        Ok(cached)
+    }
+}
+
+/// Determine where the derived copy of the image at `path` is kept in the cache in `dir`
+pub(crate) fn cached_path(dir: &Path, path: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    // FNV-1a, which unlike the standard library's hasher is stable across releases
+    let hash = path
+        .to_string_lossy()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    dir.join(format!("{}-{:016x}.jpg", stem, hash))
+}
+
+/// Store an image as JPEG at `target`, writing to a temporary file first, so a partial copy is
+/// never served
+pub(crate) fn write_jpeg(img: &DynamicImage, target: &Path) -> RahmenResult<()> {
+    let partial = target.with_extension("partial");
+    let rgb = img.to_rgb8();
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    JpegEncoder::new_with_quality(&mut writer, 90).encode(
+        rgb.as_raw(),
+        rgb.width(),
+        rgb.height(),
+        ColorType::Rgb8,
+    )?;
+    writer.flush()?;
+    std::fs::rename(&partial, target)?;
+    Ok(())
+}
+
+/// Reduce the size of the cache in `dir` to at most `max_bytes`, by removing the least recently
+/// generated images. Leftovers of interrupted transcodings are removed, too. Waits for the
+/// transcodings in progress to finish.
+pub fn compact(dir: &Path, max_bytes: u64) -> RahmenResult<()> {
+    let _lock = CacheLock::new(dir, true)?;
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if entry.file_name() == LOCK_FILE {
+            continue;
+        }
+        if path
+            .extension()
+            .map_or(false, |extension| extension == "jpg")
+            && !path.to_string_lossy().ends_with(".tmp.jpg")
+        {
+            files.push((metadata.modified()?, metadata.len(), path));
+        } else if metadata.is_file() {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    // newest first
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut total = 0;
+    for (_, len, path) in files {
+        total += len;
+        if total > max_bytes {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run a converter, then downscale its output to fit `screen`, if known, and to at most `max_size`
+/// pixels, and store it at `target` in the cache in `dir`
+fn transcode(
+    command: &[String],
+    source: &Path,
+    dir: &Path,
+    target: &Path,
+    screen: Option<(u32, u32)>,
+    max_size: usize,
+) -> RahmenResult<()> {
+    let _lock = CacheLock::new(dir, false)?;
+    let _span = debug_span!("transcode").entered();
+    info!("Transcoding {:?}", source);
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| RahmenError::TranscodeError("empty converter command".into()))?;
+    let output = target.with_extension("tmp.jpg");
+    let status = Command::new(program)
+        .args(args.iter().map(|arg| {
+            arg.replace("{input}", &source.to_string_lossy())
+                .replace("{output}", &output.to_string_lossy())
+        }))
+        .status()?;
+    if !status.success() {
+        return Err(RahmenError::TranscodeError(format!(
+            "{} failed with {}",
+            program, status
+        )));
+    }
+
+    let mut img = load_image_from_path(&output, Some(max_size))?;
+    std::fs::remove_file(&output)?;
+    if let Some((width, height)) = screen {
+        if img.width() > width || img.height() > height {
+            img = img.resize(width, height, FilterType::Triangle);
+        }
+    }
+    let size = img.width() as usize * img.height() as usize;
+    if size > max_size {
+        let scale = (max_size as f64 / size as f64).sqrt();
+        img = img.resize(
+            (img.width() as f64 * scale) as u32,
+            (img.height() as f64 * scale) as u32,
+            FilterType::Triangle,
+        );
+    }
+    write_jpeg(&img, target)
+}
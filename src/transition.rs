@@ -0,0 +1,262 @@
+//! Transitions between images
+//!
+//! A new image fades in over the previous one, with the pace given by an easing curve, either a
+//! built-in one or one registered by a plugin. Entries of file lists can override the configured
+//! transition with one of their own.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use image::{DynamicImage, RgbImage};
+
+use crate::errors::{RahmenError, RahmenResult};
+
+/// A curve mapping the elapsed fraction of a transition to its progress, both from 0 to 1
+pub type Curve = Arc<dyn Fn(f32) -> f32 + Send + Sync>;
+
+/// The easing curves registered by plugins, by name
+static CUSTOM_EASINGS: Mutex<BTreeMap<String, Curve>> = Mutex::new(BTreeMap::new());
+
+/// Make `curve` available as the easing curve `name`, replacing an earlier one of that name.
+/// Built-in curves take precedence.
+pub fn register_easing(name: &str, curve: Curve) {
+    CUSTOM_EASINGS.lock().unwrap().insert(name.into(), curve);
+}
+
+/// An easing curve registered by a plugin, compared by its name
+#[derive(Clone)]
+pub struct CustomEasing {
+    name: String,
+    curve: Curve,
+}
+
+impl fmt::Debug for CustomEasing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CustomEasing").field(&self.name).finish()
+    }
+}
+
+impl PartialEq for CustomEasing {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for CustomEasing {}
+
+/// How the progress of a transition develops over time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Easing {
+    /// Constant speed
+    Linear,
+    /// Start slowly, then accelerate
+    EaseIn,
+    /// Start fast, then decelerate
+    EaseOut,
+    /// Start and end slowly
+    EaseInOut,
+    /// Like `EaseInOut`, but more pronounced
+    Cubic,
+    /// A curve registered by a plugin
+    Custom(CustomEasing),
+}
+
+impl FromStr for Easing {
+    type Err = String;
+
+    /// Parse the name of a curve, as in the configuration, such as `ease-in-out`, or the name a
+    /// plugin registered a curve under
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "linear" => Ok(Easing::Linear),
+            "ease-in" => Ok(Easing::EaseIn),
+            "ease-out" => Ok(Easing::EaseOut),
+            "ease-in-out" => Ok(Easing::EaseInOut),
+            "cubic" => Ok(Easing::Cubic),
+            _ => CUSTOM_EASINGS
+                .lock()
+                .unwrap()
+                .get(name)
+                .map(|curve| {
+                    Easing::Custom(CustomEasing {
+                        name: name.into(),
+                        curve: Arc::clone(curve),
+                    })
+                })
+                .ok_or_else(|| format!("unknown easing curve {:?}", name)),
+        }
+    }
+}
+
+impl Easing {
+    /// Determine the progress after the fraction `t` of the transition has elapsed
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.max(0.).min(1.);
+        let progress = match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2. - t),
+            Easing::EaseInOut if t < 0.5 => 2. * t * t,
+            Easing::EaseInOut => 1. - (2. - 2. * t).powi(2) / 2.,
+            Easing::Cubic if t < 0.5 => 4. * t * t * t,
+            Easing::Cubic => 1. - (2. - 2. * t).powi(3) / 2.,
+            Easing::Custom(custom) => (custom.curve)(t),
+        };
+        progress.max(0.).min(1.)
+    }
+}
+
+/// The duration of a crossfade configured to take `seconds`, which must not be negative
+pub fn duration(seconds: f64) -> RahmenResult<Duration> {
+    if seconds.is_finite() && seconds >= 0. {
+        Ok(Duration::from_secs_f64(seconds))
+    } else {
+        Err(RahmenError::ConfigError(Arc::new(
+            config::ConfigError::Message(format!("invalid transition duration {}", seconds)),
+        )))
+    }
+}
+
+/// The easing curve configured as `name`
+pub fn easing(name: &str) -> RahmenResult<Easing> {
+    name.parse()
+        .map_err(|e| RahmenError::ConfigError(Arc::new(config::ConfigError::Message(e))))
+}
+
+/// The transition to an image given by an entry of a file list, overriding the configured one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionOverride {
+    /// how long the crossfade takes, replacing the image at once if zero
+    pub duration: Duration,
+    /// the easing curve, if not the configured one
+    pub easing: Option<Easing>,
+}
+
+impl TransitionOverride {
+    /// The duration and easing curve of the crossfade, filled in from `configured`, if there is
+    /// to be one
+    pub fn crossfade(&self, configured: Option<&(Duration, Easing)>) -> Option<(Duration, Easing)> {
+        let easing = self
+            .easing
+            .clone()
+            .or_else(|| configured.map(|(_, easing)| easing.clone()))
+            .unwrap_or(Easing::EaseInOut);
+        Some((self.duration, easing)).filter(|(duration, _)| *duration > Duration::from_secs(0))
+    }
+}
+
+impl FromStr for TransitionOverride {
+    type Err = String;
+
+    /// Parse the seconds the crossfade takes, optionally followed by a comma and the easing
+    /// curve, such as `2.5,linear`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (seconds, easing) = match value.split_once(',') {
+            Some((seconds, easing)) => (seconds, Some(easing.trim().parse()?)),
+            None => (value, None),
+        };
+        match seconds.trim().parse::<f64>() {
+            Ok(seconds) if seconds.is_finite() && seconds >= 0. => Ok(Self {
+                duration: Duration::from_secs_f64(seconds),
+                easing,
+            }),
+            _ => Err(format!("invalid transition duration {:?}", seconds)),
+        }
+    }
+}
+
+/// A crossfade from one image to another, each with its position on the screen
+#[derive(Debug)]
+pub struct Crossfade {
+    from: Option<(u32, u32, RgbImage)>,
+    to: (u32, u32, RgbImage),
+    region: (u32, u32, u32, u32),
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Crossfade {
+    /// Create a crossfade from the image `from`, if any, to the image `to`, given with their
+    /// offsets
+    pub fn new(
+        from: Option<(u32, u32, &DynamicImage)>,
+        to: (u32, u32, &DynamicImage),
+        duration: Duration,
+        easing: Easing,
+    ) -> Self {
+        let from = from.map(|(x, y, img)| (x, y, img.to_rgb8()));
+        let to = (to.0, to.1, to.2.to_rgb8());
+        Self {
+            region: Self::region(from.as_ref(), &to),
+            from,
+            to,
+            duration,
+            easing,
+        }
+    }
+
+    /// The region covering both images
+    fn region(
+        from: Option<&(u32, u32, RgbImage)>,
+        to: &(u32, u32, RgbImage),
+    ) -> (u32, u32, u32, u32) {
+        let bounds =
+            |(x, y, img): &(u32, u32, RgbImage)| (*x, *y, x + img.width(), y + img.height());
+        let mut region = bounds(to);
+        if let Some(from) = from {
+            let (x0, y0, x1, y1) = bounds(from);
+            region = (
+                region.0.min(x0),
+                region.1.min(y0),
+                region.2.max(x1),
+                region.3.max(y1),
+            );
+        }
+        region
+    }
+
+    /// Fade to `to` instead, such as the same image at another brightness, keeping the progress
+    pub fn retarget(&mut self, to: (u32, u32, &DynamicImage)) {
+        self.to = (to.0, to.1, to.2.to_rgb8());
+        self.region = Self::region(self.from.as_ref(), &self.to);
+    }
+
+    /// Whether the transition is complete after `elapsed`
+    pub fn is_finished(&self, elapsed: Duration) -> bool {
+        elapsed >= self.duration
+    }
+
+    /// The progress of the transition after `elapsed`, from 0 to 1
+    pub fn progress(&self, elapsed: Duration) -> f32 {
+        let t = if self.duration.as_secs_f32() > 0. {
+            elapsed.as_secs_f32() / self.duration.as_secs_f32()
+        } else {
+            1.
+        };
+        self.easing.apply(t)
+    }
+
+    /// Compose the frame after `elapsed`, returning its offset and image. The frame covers both
+    /// images, and is black where neither is.
+    pub fn frame(&self, elapsed: Duration) -> (u32, u32, DynamicImage) {
+        let weight = self.progress(elapsed);
+        let (x0, y0, x1, y1) = self.region;
+        let mut canvas = RgbImage::new(x1 - x0, y1 - y0);
+        let mut draw = |(x, y, img): &(u32, u32, RgbImage), weight: f32| {
+            for (px, py, pixel) in img.enumerate_pixels() {
+                let target = canvas.get_pixel_mut(x - x0 + px, y - y0 + py);
+                for (channel, value) in target.0.iter_mut().zip(pixel.0.iter()) {
+                    *channel = channel.saturating_add((f32::from(*value) * weight).round() as u8);
+                }
+            }
+        };
+        if let Some(from) = &self.from {
+            draw(from, 1. - weight);
+        }
+        draw(&self.to, weight);
+        (x0, y0, DynamicImage::ImageRgb8(canvas))
+    }
+}
@@ -0,0 +1,113 @@
+//! Virtual terminal switching for the framebuffer display
+//!
+//! By default, the kernel switches consoles behind our back and we keep drawing into the
+//! framebuffer, garbling the other console. Instead, we ask the kernel to signal us on console
+//! switches, stop drawing while another console is active, and redraw once we're back.
+
+#![allow(unsafe_code)]
+
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::errors::RahmenResult;
+
+const VT_SETMODE: libc::c_ulong = 0x5602;
+const VT_RELDISP: libc::c_ulong = 0x5605;
+const VT_PROCESS: libc::c_char = 1;
+const VT_ACKACQ: libc::c_int = 2;
+
+/// Mirror of the kernel's `struct vt_mode`
+#[repr(C)]
+#[derive(Debug, Default)]
+struct VtMode {
+    mode: libc::c_char,
+    waitv: libc::c_char,
+    relsig: libc::c_short,
+    acqsig: libc::c_short,
+    frsig: libc::c_short,
+}
+
+/// A console switch the kernel asked us to perform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtEvent {
+    /// Another console is about to become active, stop drawing
+    Released,
+    /// Our console is active again, redraw
+    Acquired,
+}
+
+/// Handles console switch requests for the controlling terminal
+#[derive(Debug)]
+pub struct VtSwitcher {
+    tty: File,
+    release: Arc<AtomicBool>,
+    acquire: Arc<AtomicBool>,
+}
+
+impl VtSwitcher {
+    /// Take over console switching for the controlling terminal. Fails if it isn't a virtual
+    /// console, for example when running over SSH.
+    pub fn new() -> RahmenResult<Self> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let release = Arc::new(AtomicBool::new(false));
+        let acquire = Arc::new(AtomicBool::new(false));
+        // SIGUSR1/SIGUSR2 are the usual choice, but we keep them free for other purposes
+        let relsig = libc::SIGRTMIN();
+        let acqsig = relsig + 1;
+        signal_hook::flag::register(relsig, Arc::clone(&release))?;
+        signal_hook::flag::register(acqsig, Arc::clone(&acquire))?;
+        set_mode(
+            &tty,
+            &VtMode {
+                mode: VT_PROCESS,
+                relsig: relsig as _,
+                acqsig: acqsig as _,
+                ..Default::default()
+            },
+        )?;
+        Ok(Self {
+            tty,
+            release,
+            acquire,
+        })
+    }
+
+    /// Check for a pending console switch and acknowledge it to the kernel. The caller must not
+    /// draw after receiving `Released` until it receives `Acquired`.
+    pub fn poll(&self) -> RahmenResult<Option<VtEvent>> {
+        if self.release.swap(false, Ordering::SeqCst) {
+            release_display(&self.tty, 1)?;
+            Ok(Some(VtEvent::Released))
+        } else if self.acquire.swap(false, Ordering::SeqCst) {
+            release_display(&self.tty, VT_ACKACQ)?;
+            Ok(Some(VtEvent::Acquired))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Drop for VtSwitcher {
+    fn drop(&mut self) {
+        // hand console switching back to the kernel
+        let _ = set_mode(&self.tty, &VtMode::default());
+    }
+}
+
+fn set_mode(tty: &File, mode: &VtMode) -> RahmenResult<()> {
+    // Safety: VT_SETMODE reads a `struct vt_mode`, which `VtMode` mirrors
+    if unsafe { libc::ioctl(tty.as_raw_fd(), VT_SETMODE, mode) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+fn release_display(tty: &File, arg: libc::c_int) -> RahmenResult<()> {
+    // Safety: VT_RELDISP takes an integer argument
+    if unsafe { libc::ioctl(tty.as_raw_fd(), VT_RELDISP, arg) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! Detect changes to files while running
+//!
+//! The font of the status line, the placeholder image and the texture filling the bars beside
+//! images are watched, and reloaded when they change. The configuration file is read once at
+//! start, so changes to it take effect with the next restart, such as the daily one, while
+//! scheduled playlists are read again whenever they start over.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Watches files for modifications by polling their modification times, which is cheap enough
+/// to do once a second and works on any file system
+#[derive(Debug, Default)]
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    /// Create a watcher without any files
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Start watching a file. It's fine if it doesn't exist yet.
+    pub fn watch<P: Into<PathBuf>>(&mut self, path: P) {
+        let path = path.into();
+        let modified = modified(&path);
+        self.files.push((path, modified));
+    }
+
+    /// Return the files that were modified, created or removed since the last call
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec![];
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
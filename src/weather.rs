@@ -0,0 +1,189 @@
+//! Show the current weather in a corner of the screen
+//!
+//! The weather is fetched from Open-Meteo, which needs no account, or from OpenWeatherMap, which
+//! needs an API key, in a background thread. Both services are queried over HTTPS, which keeps
+//! the API key secret, and with a timeout, so a dead network never holds up the weather for long.
+
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::errors::{RahmenError, RahmenResult};
+use crate::http_client;
+
+/// How long to wait for a service to answer
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// The largest response accepted
+const RESPONSE_LIMIT: u64 = 1024 * 1024;
+
+/// Services to obtain the weather from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WeatherService {
+    /// Open-Meteo, <https://open-meteo.com>
+    OpenMeteo,
+    /// OpenWeatherMap, <https://openweathermap.org>, which needs an API key
+    #[serde(rename = "openweathermap")]
+    OpenWeatherMap,
+}
+
+/// Units to show temperatures in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// Degrees Celsius
+    Metric,
+    /// Degrees Fahrenheit
+    Imperial,
+}
+
+/// Where and how to obtain the weather
+#[derive(Debug, Clone)]
+pub struct WeatherSource {
+    /// the service to query
+    pub service: WeatherService,
+    /// latitude of the place to show the weather of
+    pub latitude: f64,
+    /// longitude of the place to show the weather of
+    pub longitude: f64,
+    /// API key, needed for OpenWeatherMap
+    pub api_key: Option<String>,
+    /// units to show temperatures in
+    pub units: Units,
+}
+
+/// Current weather conditions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weather {
+    /// a short description of the conditions, such as "Light rain"
+    pub conditions: String,
+    /// the temperature, in the units requested
+    pub temperature: f64,
+}
+
+impl WeatherSource {
+    /// Query the current weather
+    pub fn fetch(&self) -> RahmenResult<Weather> {
+        match self.service {
+            WeatherService::OpenMeteo => {
+                let body = http_get(
+                    "api.open-meteo.com",
+                    &format!(
+                        "/v1/forecast?latitude={}&longitude={}&current_weather=true{}",
+                        self.latitude,
+                        self.longitude,
+                        match self.units {
+                            Units::Metric => "",
+                            Units::Imperial => "&temperature_unit=fahrenheit",
+                        }
+                    ),
+                )?;
+                let response: serde_json::Value = parse(&body)?;
+                let current = &response["current_weather"];
+                Ok(Weather {
+                    conditions: weather_code(current["weathercode"].as_u64().unwrap_or(0)).into(),
+                    temperature: number(&current["temperature"])?,
+                })
+            }
+            WeatherService::OpenWeatherMap => {
+                let api_key = self.api_key.as_deref().ok_or_else(|| {
+                    RahmenError::WeatherError("OpenWeatherMap needs an API key".into())
+                })?;
+                let body = http_get(
+                    "api.openweathermap.org",
+                    &format!(
+                        "/data/2.5/weather?lat={}&lon={}&units={}&appid={}",
+                        self.latitude,
+                        self.longitude,
+                        match self.units {
+                            Units::Metric => "metric",
+                            Units::Imperial => "imperial",
+                        },
+                        api_key
+                    ),
+                )?;
+                let response: serde_json::Value = parse(&body)?;
+                let mut conditions: String = response["weather"][0]["description"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .into();
+                // descriptions are lowercase
+                if let Some(first) = conditions.get_mut(..1) {
+                    first.make_ascii_uppercase();
+                }
+                Ok(Weather {
+                    conditions,
+                    temperature: number(&response["main"]["temp"])?,
+                })
+            }
+        }
+    }
+
+    /// Format the weather for display
+    pub fn format(&self, weather: &Weather) -> String {
+        format!(
+            "{} {:.0}°{}",
+            weather.conditions,
+            weather.temperature,
+            match self.units {
+                Units::Metric => "C",
+                Units::Imperial => "F",
+            }
+        )
+    }
+
+    /// Fetch the weather every `interval` in a background thread, and send it formatted to
+    /// `sender`. The thread stops once the receiver is gone.
+    pub fn spawn(self, interval: Duration, sender: Sender<Vec<String>>) {
+        std::thread::spawn(move || loop {
+            match self.fetch() {
+                Ok(weather) => {
+                    if sender.send(vec![self.format(&weather)]).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!("Failed to fetch the weather: {}", e),
+            }
+            std::thread::sleep(interval);
+        });
+    }
+}
+
+fn parse(body: &str) -> RahmenResult<serde_json::Value> {
+    serde_json::from_str(body).map_err(|e| RahmenError::WeatherError(e.to_string()))
+}
+
+fn number(value: &serde_json::Value) -> RahmenResult<f64> {
+    value
+        .as_f64()
+        .ok_or_else(|| RahmenError::WeatherError(format!("not a number: {}", value)))
+}
+
+/// Describe a WMO weather code, as reported by Open-Meteo
+fn weather_code(code: u64) -> &'static str {
+    match code {
+        0 => "Clear",
+        1 => "Mostly clear",
+        2 => "Partly cloudy",
+        3 => "Overcast",
+        45 | 48 => "Fog",
+        51..=57 => "Drizzle",
+        61..=67 => "Rain",
+        71..=77 => "Snow",
+        80..=82 => "Showers",
+        85 | 86 => "Snow showers",
+        95..=99 => "Thunderstorm",
+        _ => "",
+    }
+}
+
+/// Fetch `path` from `host` over HTTPS, and return the body of the response
+fn http_get(host: &str, path: &str) -> RahmenResult<String> {
+    let body = http_client::get(
+        &format!("https://{}{}", host, path),
+        TIMEOUT,
+        RESPONSE_LIMIT,
+    )?;
+    String::from_utf8(body).map_err(|_| RahmenError::WeatherError("malformed response".into()))
+}
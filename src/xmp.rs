@@ -0,0 +1,109 @@
+//! XMP properties for the status line
+//!
+//! Curated captions, keywords and the names of people are usually written to XMP, by Lightroom or
+//! digiKam, as structured values: alternatives by language, lists, and regions of the image. XMP
+//! tags are read like EXIF and IPTC tags, but of alternatives by language only the default is
+//! shown, and lists are joined with commas. Pseudo-tags collect values spread over several
+//! properties.
+
+use itertools::Itertools;
+
+use crate::metadata::Metadata;
+
+/// Prefix of the pseudo-tags provided by this module
+pub const TAG_PREFIX: &str = "Rahmen.Xmp.";
+
+/// The properties naming the people shown in regions of the image, as the start and end of their
+/// paths around the index of the region, after the Metadata Working Group and Microsoft Photo
+const PERSON_PROPERTIES: &[(&str, &str)] = &[
+    ("Xmp.mwg-rs.Regions/mwg-rs:RegionList[", "]/mwg-rs:Name"),
+    (
+        "Xmp.MP.RegionInfo/MPRI:Regions[",
+        "]/MPReg:PersonDisplayName",
+    ),
+];
+
+/// The properties listing hierarchical keywords, with the separator of their levels
+const HIERARCHICAL_PROPERTIES: &[(&str, char)] = &[
+    ("Xmp.lr.hierarchicalSubject", '|'),
+    ("Xmp.digiKam.TagsList", '/'),
+];
+
+/// Whether `tag` is read by this module
+pub fn handles(tag: &str) -> bool {
+    tag.starts_with("Xmp.") || tag.starts_with(TAG_PREFIX)
+}
+
+/// The value of an XMP tag or pseudo-tag, if the image has it:
+///
+/// * `Rahmen.Xmp.Persons`: the names of the people in regions of the image
+/// * `Rahmen.Xmp.Keywords`: the most specific level of the hierarchical keywords
+pub fn tag(metadata: &Metadata, name: &str) -> Option<String> {
+    let values = match name.strip_prefix(TAG_PREFIX) {
+        Some("Persons") => persons(metadata),
+        Some("Keywords") => keywords(metadata),
+        Some(_) => return None,
+        None => values(metadata, name),
+    };
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(", "))
+    }
+}
+
+/// Split the language qualifier, as in `lang="x-default" Caption`, from a value
+fn language(value: &str) -> Option<(&str, &str)> {
+    let rest = value.strip_prefix("lang=\"")?;
+    let end = rest.find('"')?;
+    Some((&rest[..end], rest[end + 1..].trim_start()))
+}
+
+/// The values of a property: the default of alternatives by language, or all items of a list
+fn values(metadata: &Metadata, tag: &str) -> Vec<String> {
+    if !metadata.has_tag(tag) {
+        return vec![];
+    }
+    let values = metadata.get_tag_multiple_strings(tag).unwrap_or_default();
+    let alternatives: Vec<_> = values.iter().filter_map(|v| language(v)).collect();
+    if !alternatives.is_empty() {
+        return alternatives
+            .iter()
+            .find(|(lang, _)| *lang == "x-default")
+            .or_else(|| alternatives.first())
+            .map(|(_, text)| text.to_string())
+            .into_iter()
+            .collect();
+    }
+    values.into_iter().filter(|v| !v.is_empty()).collect()
+}
+
+fn persons(metadata: &Metadata) -> Vec<String> {
+    metadata
+        .get_xmp_tags()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|tag| {
+            PERSON_PROPERTIES.iter().any(|(start, end)| {
+                tag.strip_prefix(start)
+                    .and_then(|rest| rest.strip_suffix(end))
+                    .map_or(false, |index| index.bytes().all(|b| b.is_ascii_digit()))
+            })
+        })
+        .flat_map(|tag| metadata.get_tag_string(&tag).ok())
+        .filter(|name| !name.is_empty())
+        .unique()
+        .collect()
+}
+
+fn keywords(metadata: &Metadata) -> Vec<String> {
+    HIERARCHICAL_PROPERTIES
+        .iter()
+        .flat_map(|(tag, separator)| {
+            values(metadata, tag)
+                .into_iter()
+                .filter_map(move |value| value.rsplit(*separator).next().map(str::to_string))
+        })
+        .unique()
+        .collect()
+}
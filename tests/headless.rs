@@ -0,0 +1,84 @@
+//! Run the whole slideshow on the headless display, from a directory of images to the frames it
+//! writes
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use image::{Rgb, RgbImage};
+
+/// The font of the status line, which Rahmen needs even without one
+const FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf";
+
+/// The number of frames to render
+const FRAMES: usize = 3;
+
+/// A fresh directory for a test
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rahmen-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn renders_the_frames_of_a_directory() {
+    if !Path::new(FONT).exists() {
+        eprintln!("Skipping, as {} is missing", FONT);
+        return;
+    }
+    let dir = scratch_dir("headless");
+    let images = dir.join("images");
+    std::fs::create_dir(&images).unwrap();
+    for (index, color) in [[255, 0, 0], [0, 255, 0], [0, 0, 255]].iter().enumerate() {
+        RgbImage::from_pixel(64, 48, Rgb(*color))
+            .save(images.join(format!("{}.jpg", index)))
+            .unwrap();
+    }
+    let config = dir.join("rahmen.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "status_line = []\n\n[png]\nresolution = \"320x240\"\nframes = {}\n",
+            FRAMES
+        ),
+    )
+    .unwrap();
+    let frames = dir.join("frames");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_rahmen"))
+        .args(&["--display", "png", "--output"])
+        .arg(&frames)
+        .arg("--config")
+        .arg(&config)
+        .args(&["--font", FONT, "--time", "0.2"])
+        .arg(images.join("*.jpg"))
+        // keep the state and caches of the slideshow out of the home directory
+        .env("HOME", &dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // one file for each frame, up to the number of frames to render
+    let mut written = std::fs::read_dir(&frames)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    written.sort();
+    let expected = (1..=FRAMES)
+        .map(|frame| OsString::from(format!("frame-{:05}.png", frame)))
+        .collect::<Vec<_>>();
+    assert_eq!(written, expected);
+
+    // the frames show the images, scaled to the screen
+    let last = image::open(frames.join(&expected[FRAMES - 1]))
+        .unwrap()
+        .to_rgb8();
+    assert_eq!(last.dimensions(), (320, 240));
+    assert!(last.get_pixel(160, 120).0.iter().any(|value| *value > 128));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}